@@ -1,7 +1,294 @@
+use std::collections::HashSet;
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+///Rust type of a runtime export to the C type it has across the FFI boundary. ToyPtr is a Toy
+///string, array, map or struct passed as a 64 bit int and ToyValue a value of any Toy type,
+///both keep their own names so declarations can be told apart from plain ints
+fn rust_type_to_c(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Tuple(t) if t.elems.is_empty() => "void".to_string(),
+        syn::Type::Never(_) => "void".to_string(),
+        syn::Type::Ptr(p) => match rust_type_to_c(&p.elem).as_str() {
+            "int8_t" | "char" => "char*".to_string(),
+            "void" | "c_void" => "void*".to_string(),
+            elem => format!("{}*", elem),
+        },
+        syn::Type::BareFn(_) => "void*".to_string(),
+        syn::Type::Path(p) => {
+            let name = p.path.segments.last().unwrap().ident.to_string();
+            match name.as_str() {
+                "i64" | "isize" | "ToyType" => "int64_t",
+                "ToyPtr" => "ToyPtr",
+                "ToyValue" => "ToyValue",
+                "u64" | "usize" => "uint64_t",
+                "i32" | "c_int" => "int32_t",
+                "u32" => "uint32_t",
+                "i16" => "int16_t",
+                "u16" => "uint16_t",
+                "i8" => "int8_t",
+                "u8" => "uint8_t",
+                "c_char" => "char",
+                "bool" => "bool",
+                "f64" => "double",
+                "f32" => "float",
+                other => other,
+            }
+            .to_string()
+        }
+        syn::Type::Paren(p) => rust_type_to_c(&p.elem),
+        _ => panic!(
+            "runtime export with a type runtime_exports does not know: {:?}",
+            ty
+        ),
+    }
+}
+
+///The #[unsafe(no_mangle)] functions among items, also inside inline modules
+fn exported_fns<'a>(items: &'a [syn::Item], out: &mut Vec<&'a syn::ItemFn>) {
+    for item in items {
+        match item {
+            syn::Item::Fn(f) if f.attrs.iter().any(|a| is_no_mangle(a)) => out.push(f),
+            syn::Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    exported_fns(items, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_no_mangle(attr: &syn::Attribute) -> bool {
+    if attr.path().is_ident("no_mangle") {
+        return true;
+    }
+    let mut no_mangle = false;
+    if attr.path().is_ident("unsafe") {
+        let _ = attr.parse_nested_meta(|meta| {
+            no_mangle |= meta.path.is_ident("no_mangle");
+            Ok(())
+        });
+    }
+    no_mangle
+}
+
+///Parses the runtime sources and lists every #[unsafe(no_mangle)] function, as its name and its
+///json entry {"name": ..., "params": [c types], "ret": c type} taken from its signature. Sources do
+///not say which cfg a function is under, see runtime_exports_json
+fn runtime_exports(runtime_src: &Path) -> Vec<(String, String)> {
+    let mut files = vec![];
+    let mut dirs = vec![runtime_src.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).unwrap().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "rs") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let mut functions = vec![];
+    for file in files {
+        let text = fs::read_to_string(&file).unwrap();
+        let parsed = syn::parse_file(&text)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", file.display(), e));
+        let mut fns = vec![];
+        exported_fns(&parsed.items, &mut fns);
+        for f in fns {
+            let name = f.sig.ident.to_string();
+            let params: Vec<String> = f
+                .sig
+                .inputs
+                .iter()
+                .map(|arg| match arg {
+                    syn::FnArg::Typed(t) => format!("\"{}\"", rust_type_to_c(&t.ty)),
+                    syn::FnArg::Receiver(_) => panic!("{} is a method", name),
+                })
+                .collect();
+            let ret = match &f.sig.output {
+                syn::ReturnType::Default => "void".to_string(),
+                syn::ReturnType::Type(_, ty) => rust_type_to_c(ty),
+            };
+            let json = format!(
+                "    {{\"name\": \"{}\", \"params\": [{}], \"ret\": \"{}\"}}",
+                name,
+                params.join(", "),
+                ret
+            );
+            functions.push((name, json));
+        }
+    }
+    functions
+}
+
+///{"functions": [...]} with the exports a built runtime defines, so functions compiled out for its
+///features or target are left out. Every export is listed when its symbols are unknown
+fn runtime_exports_json(exports: &[(String, String)], symbols: Option<&HashSet<String>>) -> String {
+    let functions: Vec<&str> = exports
+        .iter()
+        .filter(|(name, _)| symbols.is_none_or(|symbols| symbols.contains(name)))
+        .map(|(_, json)| json.as_str())
+        .collect();
+    format!("{{\"functions\": [\n{}\n]}}\n", functions.join(",\n"))
+}
+
+///llvm-nm of the LLVM the compiler is built against, or the one on the PATH
+fn llvm_nm() -> PathBuf {
+    env::var("LLVM_SYS_211_PREFIX")
+        .map(|prefix| Path::new(&prefix).join("bin").join("llvm-nm"))
+        .ok()
+        .filter(|nm| nm.exists())
+        .unwrap_or(PathBuf::from("llvm-nm"))
+}
+
+///The global symbols a static library defines, as llvm-nm lists them. llvm-nm reads archives of
+///every target the runtime is built for, --no-llvm-bc reads the machine code of objects that also
+///carry bitcode from a newer LLVM. None when it is not installed or fails
+fn archive_symbols(archive: &Path) -> Option<HashSet<String>> {
+    let output = Command::new(llvm_nm())
+        .args([
+            "--no-llvm-bc",
+            "--defined-only",
+            "--extern-only",
+            "--format=just-symbols",
+        ])
+        .arg(archive)
+        .output()
+        .ok()
+        .filter(|o| o.status.success());
+    let Some(output) = output else {
+        println!(
+            "cargo:warning=llvm-nm could not read {}, its runtime_exports.json lists every export",
+            archive.display()
+        );
+        return None;
+    };
+    return Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty() && !l.ends_with(':'))
+            .collect(),
+    );
+}
+
+///Ships the exports of the runtime in dir next to it so other tools can check their declarations too
+fn write_runtime_exports(exports: &[(String, String)], dir: &Path) -> String {
+    let json = runtime_exports_json(exports, archive_symbols(&dir.join("libruntime.a")).as_ref());
+    fs::write(dir.join("runtime_exports.json"), &json).unwrap_or_else(|e| {
+        panic!(
+            "Failed to write runtime_exports.json to {}: {}",
+            dir.display(),
+            e
+        )
+    });
+    return json;
+}
+
+///Targets `--target` can build for besides the host, their runtimes are built when the rust target is installed
+const CROSS_TARGETS: [&str; 3] = [
+    "x86_64-unknown-linux-musl",
+    "aarch64-unknown-linux-gnu",
+    "wasm32-wasip1",
+];
+
+///Where the C runtime files a cross target links come from and which ones it needs
+fn cross_support_files(target: &str, sysroot: &Path) -> (Vec<PathBuf>, Vec<&'static str>) {
+    let self_contained = sysroot
+        .join("lib")
+        .join("rustlib")
+        .join(target)
+        .join("lib")
+        .join("self-contained");
+    match target {
+        //rust ships musl's libc.a and C runtime objects with the target
+        "x86_64-unknown-linux-musl" => (
+            vec![self_contained],
+            vec!["crt1.o", "crti.o", "crtn.o", "libc.a", "libunwind.a"],
+        ),
+        //and wasi-libc with the _start of command modules
+        "wasm32-wasip1" => (vec![self_contained], vec!["crt1-command.o", "libc.a"]),
+        //glibc from the cross toolchain (gcc-aarch64-linux-gnu), libgcc_eh.a unwinds the runtime
+        "aarch64-unknown-linux-gnu" => {
+            let mut dirs = vec![PathBuf::from("/usr/aarch64-linux-gnu/lib")];
+            if let Ok(versions) = fs::read_dir("/usr/lib/gcc-cross/aarch64-linux-gnu") {
+                dirs.extend(versions.filter_map(|v| v.ok()).map(|v| v.path()));
+            }
+            (
+                dirs,
+                vec![
+                    "crt1.o",
+                    "crti.o",
+                    "crtn.o",
+                    "libc.so.6",
+                    "libm.so.6",
+                    "libgcc.a",
+                    "libgcc_eh.a",
+                ],
+            )
+        }
+        _ => unreachable!(),
+    }
+}
+
+///Builds the runtime for a cross target into lib/<target> and copies in the C runtime files it links.
+///Skipped with a warning when the rust target or the files are not installed
+fn build_cross_runtime(
+    target: &str,
+    runtime_dir: &Path,
+    manifest_dir: &Path,
+    exports: &[(String, String)],
+) {
+    let sysroot = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .output()
+        .expect("Failed to run rustc --print sysroot");
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&sysroot.stdout).trim());
+    let (dirs, files) = cross_support_files(target, &sysroot);
+    let find = |file: &str| dirs.iter().map(|d| d.join(file)).find(|p| p.exists());
+    let missing: Vec<&str> = files
+        .iter()
+        .copied()
+        .filter(|f| find(f).is_none())
+        .collect();
+    if !sysroot.join("lib").join("rustlib").join(target).exists() || !missing.is_empty() {
+        println!(
+            "cargo:warning=--target {} is unavailable, run `rustup target add {}` and install its C library (missing {:?})",
+            target, target, missing
+        );
+        return;
+    }
+
+    for trigger in ["build", "copy"] {
+        let cross_status = Command::new("cargo")
+            .args(["build", "--release", "--target", target])
+            .current_dir(runtime_dir)
+            .env("TOY_COMP_ROOT", manifest_dir)
+            .env("TOY_RUNTIME_COPY_TRIGGER", trigger)
+            .status()
+            .expect("Failed to build cross runtimeRS crate");
+        if !cross_status.success() {
+            panic!(
+                "{} runtimeRS {} failed with status: {}",
+                target, trigger, cross_status
+            );
+        }
+    }
+
+    let target_dir = manifest_dir.join("lib").join(target);
+    for file in files {
+        fs::copy(find(file).unwrap(), target_dir.join(file))
+            .unwrap_or_else(|e| panic!("Failed copying {} for {}: {}", file, target, e));
+    }
+    write_runtime_exports(exports, &target_dir);
+}
+
 fn main() {
     cc::Build::new()
         .file("llvm_stubs.c")
@@ -9,6 +296,14 @@ fn main() {
     println!("cargo:rustc-link-arg=-Wl,--whole-archive");
     println!("cargo:rustc-link-arg=-ltemp_llvm_thing");
     println!("cargo:rustc-link-arg=-Wl,--no-whole-archive");
+
+    //the compiler embeds this to check std extern declarations, see runtime_manifest. It lists
+    //everything until the runtime is built
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let runtime_exports = runtime_exports(&manifest_dir.join("src").join("runtimeRS").join("src"));
+    let exports_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("runtime_exports.json");
+    fs::write(&exports_path, runtime_exports_json(&runtime_exports, None))
+        .expect("Failed to write runtime_exports.json");
     if env::var("CARGO_CFG_RUST_ANALYZER").is_ok() {
         return;
     }
@@ -27,7 +322,6 @@ fn main() {
             env::set_var("TOY_DEBUG", "FALSE");
         }
     }
-    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
     let runtime_dir = manifest_dir.join("src").join("runtimeRS");
 
     let runtime_build_status = Command::new("cargo")
@@ -55,6 +349,48 @@ fn main() {
         );
     }
 
+    //instrumented runtimes for --sanitize=memory and --profile=heap and the entry point free one for --lib, each kept in its own target dir so they never clobber the normal one
+    for flavor in ["sanitize", "profile", "library"] {
+        let flavor_target_dir = format!("target/{}", flavor);
+        //the library runtime is built shared too, the JIT loads it into the compiler
+        let (subcommand, crate_types): (&str, &[&str]) = if flavor == "library" {
+            ("rustc", &["--crate-type", "staticlib,cdylib"])
+        } else {
+            ("build", &[])
+        };
+        for trigger in ["build", "copy"] {
+            let flavor_status = Command::new("cargo")
+                .arg(subcommand)
+                .args([
+                    "--release",
+                    "--target",
+                    &target,
+                    "--features",
+                    flavor,
+                    "--target-dir",
+                    &flavor_target_dir,
+                ])
+                .args(crate_types)
+                .current_dir(&runtime_dir)
+                .env("TOY_COMP_ROOT", &manifest_dir)
+                .env("TOY_RUNTIME_COPY_TRIGGER", trigger)
+                .status()
+                .expect("Failed to build instrumented runtimeRS crate");
+            if !flavor_status.success() {
+                panic!(
+                    "{} runtimeRS {} failed with status: {}",
+                    flavor, trigger, flavor_status
+                );
+            }
+        }
+    }
+
+    if !cfg!(target_os = "windows") {
+        for cross_target in CROSS_TARGETS {
+            build_cross_runtime(cross_target, &runtime_dir, &manifest_dir, &runtime_exports);
+        }
+    }
+
     let out_dir = manifest_dir.join("lib").join(&target);
 
     if !out_dir.exists() {
@@ -70,6 +406,29 @@ fn main() {
         panic!("Missing runtime library: {}", runtime.display());
     }
 
+    for flavor in ["sanitize", "profile", "library"] {
+        let flavor_runtime = out_dir.join(flavor).join("libruntime.a");
+
+        if !flavor_runtime.exists() {
+            panic!("Missing {} runtime library: {}", flavor, flavor_runtime.display());
+        }
+    }
+    let shared_runtime = out_dir.join("library").join(format!(
+        "{}runtime{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    ));
+    if !shared_runtime.exists() {
+        panic!("Missing shared runtime library: {}", shared_runtime.display());
+    }
+
+    //std is checked against the runtime programs link by default
+    let default_exports = write_runtime_exports(&runtime_exports, &out_dir);
+    fs::write(&exports_path, default_exports).expect("Failed to write runtime_exports.json");
+    for flavor in ["sanitize", "profile", "library"] {
+        write_runtime_exports(&runtime_exports, &out_dir.join(flavor));
+    }
+
     println!("cargo:rustc-link-search=native={}", out_dir.display());
 
     if cfg!(target_os = "windows") {
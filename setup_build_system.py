@@ -222,6 +222,32 @@ elif os_name == "Linux":
             shell=True,
             check=True,
         )
+    # runtimes for --target, musl also backs --static
+    subprocess.run(
+        [
+            os.path.expanduser("~/.cargo/bin/rustup"),
+            "target",
+            "add",
+            "x86_64-unknown-linux-musl",
+            "aarch64-unknown-linux-gnu",
+            "wasm32-wasip1",
+            "--toolchain",
+            "nightly",
+        ],
+        check=True,
+    )
+    # C library for aarch64-linux-gnu and qemu to run its executables
+    if shutil.which("aarch64-linux-gnu-gcc") is None:
+        apt_install("gcc-aarch64-linux-gnu")
+    if shutil.which("qemu-aarch64") is None:
+        apt_install("qemu-user")
+    # runs wasm32-wasi output
+    if shutil.which("wasmtime") is None:
+        subprocess.run(
+            "curl https://wasmtime.dev/install.sh -sSf | bash",
+            shell=True,
+            check=True,
+        )
 
     subprocess.run(
         "sudo wget -qO /etc/apt/trusted.gpg.d/apt.llvm.org.asc "
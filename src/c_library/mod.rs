@@ -0,0 +1,234 @@
+//! `--lib=static` and `--lib=shared` builds. Exported functions of the main program get a C ABI
+//! wrapper under their plain name, this module describes them, writes the header C code includes
+//! and packs the static library archive.
+use crate::{
+    driver::Driver,
+    errors::{Span, ToyError, ToyErrorType},
+    token::{ExternType, TypeTok},
+};
+use std::collections::BTreeMap;
+
+///Runtime entry points every library exports next to the Toy functions
+pub const RUNTIME_ENTRY_POINTS: [&str; 3] = ["toy_init", "toy_shutdown", "toy_release"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LibraryKind {
+    Static,
+    Shared,
+}
+
+///`--lib=static` or `--lib=shared`, None when building an executable
+pub fn library_kind() -> Result<Option<LibraryKind>, ToyError> {
+    let args: Vec<String> = Driver::args();
+    let Some(value) = args.iter().find_map(|a| a.strip_prefix("--lib=")) else {
+        return Ok(None);
+    };
+    let kind = match value {
+        "static" => LibraryKind::Static,
+        "shared" => LibraryKind::Shared,
+        _ => {
+            return Err(ToyError::new(
+                ToyErrorType::InvalidFlag(format!("--lib={}, expected static or shared", value)),
+                Span::null_span(),
+            ));
+        }
+    };
+    if cfg!(target_os = "windows") {
+        return Err(ToyError::new(
+            ToyErrorType::UnsupportedPlatform(
+                "--lib builds are only supported on linux".to_string(),
+            ),
+            Span::null_span(),
+        ));
+    }
+    return Ok(Some(kind));
+}
+
+///An `export fn` of the main program as C sees it
+#[derive(Debug, Clone)]
+pub struct CExport {
+    ///C symbol, the plain function name unless it is overloaded
+    pub symbol: String,
+    ///mangled name of the Toy function the wrapper calls
+    pub toy_name: String,
+    pub params: Vec<(String, TypeTok)>,
+    pub ret: TypeTok,
+}
+impl CExport {
+    ///The C signature the wrapper is declared with, structs and arrays are passed as pointers
+    pub fn c_signature(&self) -> ExternType {
+        let params = self
+            .params
+            .iter()
+            .map(|(_, ty)| ExternType::from_type_tok(ty))
+            .collect();
+        let ret = match self.ret {
+            TypeTok::Void => ExternType::c_void(0),
+            ref ty => ExternType::from_type_tok(ty),
+        };
+        return ExternType::c_fn(params, Box::new(ret));
+    }
+}
+
+///Builds the exports from `(mangled name, base name, params, return type)`, overloads keep their mangled names so every symbol is unique
+pub fn c_exports(funcs: Vec<(String, String, Vec<(String, TypeTok)>, TypeTok)>) -> Vec<CExport> {
+    let mut base_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for (_, base, _, _) in &funcs {
+        *base_counts.entry(base.clone()).or_default() += 1;
+    }
+    return funcs
+        .into_iter()
+        .map(|(toy_name, base, params, ret)| CExport {
+            symbol: if base_counts[&base] == 1 {
+                base
+            } else {
+                toy_name.clone()
+            },
+            toy_name,
+            params,
+            ret,
+        })
+        .collect();
+}
+
+fn struct_name<'a>(
+    fields: &BTreeMap<String, Box<TypeTok>>,
+    structs: &'a [(String, BTreeMap<String, Box<TypeTok>>)],
+) -> Option<&'a str> {
+    return structs
+        .iter()
+        .find(|(_, f)| f == fields)
+        .map(|(name, _)| name.as_str());
+}
+
+///C type of a parameter or return value
+fn c_value_type(ty: &TypeTok, structs: &[(String, BTreeMap<String, Box<TypeTok>>)]) -> String {
+    return match ty {
+        TypeTok::Int => "int64_t".to_string(),
+        TypeTok::Float => "double".to_string(),
+        TypeTok::Bool => "bool".to_string(),
+        TypeTok::Str => "const char*".to_string(),
+        TypeTok::Void => "void".to_string(),
+        TypeTok::Struct(fields) => match struct_name(fields, structs) {
+            Some(name) => format!("{}*", name),
+            None => "void*".to_string(),
+        },
+        //arrays, maps and any are opaque to C
+        _ => "void*".to_string(),
+    };
+}
+
+///C type of a struct field, every field is 8 bytes so bools are int64_t
+fn c_field_type(ty: &TypeTok, structs: &[(String, BTreeMap<String, Box<TypeTok>>)]) -> String {
+    return match ty {
+        TypeTok::Bool => "int64_t".to_string(),
+        t => c_value_type(t, structs),
+    };
+}
+
+///The header for a library, `structs` are the struct interfaces of the main program
+pub fn header(
+    lib_name: &str,
+    structs: &[(String, BTreeMap<String, Box<TypeTok>>)],
+    exports: &[CExport],
+) -> String {
+    let guard: String = lib_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let mut out = format!(
+        "//generated by the Toy compiler for {}, do not edit\n#ifndef TOY_{}_H\n#define TOY_{}_H\n\n",
+        lib_name, guard, guard
+    );
+    out.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+
+    //Toy lays struct fields out in alphabetical order, the same order the BTreeMap keeps them in
+    for (name, _) in structs {
+        out.push_str(&format!("typedef struct {} {};\n", name, name));
+    }
+    for (name, fields) in structs {
+        out.push_str(&format!("struct {} {{\n", name));
+        for (field, ty) in fields {
+            out.push_str(&format!("    {} {};\n", c_field_type(ty, structs), field));
+        }
+        out.push_str("};\n");
+    }
+    if !structs.is_empty() {
+        out.push('\n');
+    }
+
+    out.push_str("//call once before any other function of the library\nvoid toy_init(void);\n");
+    out.push_str("//frees what toy_init set up\nvoid toy_shutdown(void);\n");
+    out.push_str(
+        "//frees a string or struct a function returned, free the strings a struct holds first\nvoid toy_release(const void* ptr);\n\n",
+    );
+    for export in exports {
+        let params: Vec<String> = export
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{} {}", c_value_type(ty, structs), name))
+            .collect();
+        out.push_str(&format!(
+            "{} {}({});\n",
+            c_value_type(&export.ret, structs),
+            export.symbol,
+            if params.is_empty() {
+                "void".to_string()
+            } else {
+                params.join(", ")
+            }
+        ));
+    }
+
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+    return out;
+}
+
+///A System V `ar` archive holding a single object, with the symbol index linkers use to decide to pull it in
+pub fn archive(member_name: &str, object: &[u8], symbols: &[String]) -> Vec<u8> {
+    fn member_header(name: &str, size: usize) -> Vec<u8> {
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name, 0, 0, 0, 644, size
+        );
+        return header.into_bytes();
+    }
+    let pad = |len: usize| len % 2;
+
+    let mut names: Vec<u8> = vec![];
+    for s in symbols {
+        names.extend_from_slice(s.as_bytes());
+        names.push(0);
+    }
+    let index_size = 4 + 4 * symbols.len() + names.len();
+    //every symbol lives in the one object, which follows the index
+    let object_offset = 8 + 60 + index_size + pad(index_size);
+
+    let mut out = b"!<arch>\n".to_vec();
+    out.extend(member_header("/", index_size));
+    out.extend((symbols.len() as u32).to_be_bytes());
+    for _ in symbols {
+        out.extend((object_offset as u32).to_be_bytes());
+    }
+    out.extend(names);
+    if pad(index_size) == 1 {
+        out.push(b'\n');
+    }
+    //short GNU names end in a slash
+    out.extend(member_header(&format!("{}/", member_name), object.len()));
+    out.extend_from_slice(object);
+    if pad(object.len()) == 1 {
+        out.push(b'\n');
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,97 @@
+use super::*;
+
+fn point() -> BTreeMap<String, Box<TypeTok>> {
+    BTreeMap::from([
+        ("y".to_string(), Box::new(TypeTok::Float)),
+        ("x".to_string(), Box::new(TypeTok::Float)),
+        ("visible".to_string(), Box::new(TypeTok::Bool)),
+    ])
+}
+
+#[test]
+fn test_c_library_overloads_keep_mangled_names() {
+    let exports = c_exports(vec![
+        (
+            "area_int".to_string(),
+            "area".to_string(),
+            vec![("a".to_string(), TypeTok::Int)],
+            TypeTok::Int,
+        ),
+        (
+            "area_float".to_string(),
+            "area".to_string(),
+            vec![("a".to_string(), TypeTok::Float)],
+            TypeTok::Float,
+        ),
+        (
+            "scale_float".to_string(),
+            "scale".to_string(),
+            vec![("f".to_string(), TypeTok::Float)],
+            TypeTok::Void,
+        ),
+    ]);
+    let symbols: Vec<&str> = exports.iter().map(|e| e.symbol.as_str()).collect();
+    assert_eq!(symbols, vec!["area_int", "area_float", "scale"]);
+    assert_eq!(
+        exports[2].c_signature(),
+        ExternType::c_fn(
+            vec![ExternType::c_double(0)],
+            Box::new(ExternType::c_void(0))
+        )
+    );
+}
+
+#[test]
+fn test_c_library_header() {
+    let structs = vec![("Point".to_string(), point())];
+    let exports = c_exports(vec![
+        (
+            "move_Point_float".to_string(),
+            "move".to_string(),
+            vec![
+                ("p".to_string(), TypeTok::Struct(point())),
+                ("dx".to_string(), TypeTok::Float),
+            ],
+            TypeTok::Struct(point()),
+        ),
+        (
+            "greeting".to_string(),
+            "greeting".to_string(),
+            vec![],
+            TypeTok::Str,
+        ),
+        (
+            "is_even_int".to_string(),
+            "is_even".to_string(),
+            vec![("n".to_string(), TypeTok::Int)],
+            TypeTok::Bool,
+        ),
+    ]);
+    let h = header("geometry", &structs, &exports);
+    assert!(h.contains("#ifndef TOY_GEOMETRY_H"));
+    assert!(h.contains("typedef struct Point Point;"));
+    //fields are 8 bytes each in alphabetical order
+    assert!(h.contains("struct Point {\n    int64_t visible;\n    double x;\n    double y;\n};"));
+    assert!(h.contains("void toy_init(void);"));
+    assert!(h.contains("void toy_release(const void* ptr);"));
+    assert!(h.contains("Point* move(Point* p, double dx);"));
+    assert!(h.contains("const char* greeting(void);"));
+    assert!(h.contains("bool is_even(int64_t n);"));
+}
+
+#[test]
+fn test_c_library_archive_index() {
+    let object = b"not really an object".to_vec();
+    let symbols = vec!["toy_init".to_string(), "area".to_string()];
+    let bytes = archive("toy_lib.o", &object, &symbols);
+    assert!(bytes.starts_with(b"!<arch>\n/               "));
+    //index: count then one offset per symbol, big endian
+    assert_eq!(&bytes[68..72], &2u32.to_be_bytes());
+    let offset = u32::from_be_bytes(bytes[72..76].try_into().unwrap()) as usize;
+    assert_eq!(&bytes[offset..offset + 10], b"toy_lib.o/");
+    assert_eq!(&bytes[offset + 48..offset + 58], b"20        ");
+    assert_eq!(
+        &bytes[offset + 60..offset + 60 + object.len()],
+        object.as_slice()
+    );
+}
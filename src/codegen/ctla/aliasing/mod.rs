@@ -342,16 +342,10 @@ impl AliasAndEncapsulationTracker {
                                     }
                                 }
                             }
-                            TIR::CallExternFunction(_, callee_name, params, _, _, _)
-                                if callee_name.as_ref() == "toy_write_to_arr" =>
-                            {
-                                if params.len() >= 2
-                                    && alias_values
-                                        .contains(&(function_name.clone(), params[1].val))
-                                {
-                                    if new_encapsulators
-                                        .insert((function_name.clone(), params[0].val))
-                                    {
+                            TIR::ArrStore(_, arr, _, new_value, _)
+                            | TIR::ArrPush(_, arr, new_value, _) => {
+                                if alias_values.contains(&(function_name.clone(), new_value.val)) {
+                                    if new_encapsulators.insert((function_name.clone(), arr.val)) {
                                         changed = true;
                                     }
                                 }
@@ -405,11 +399,10 @@ impl AliasAndEncapsulationTracker {
                                         callee.body.iter().flat_map(|b| b.ins.iter()).any(|ins| {
                                             matches!(
                                                 ins,
-                                                TIR::CallExternFunction(_, name, write_params, _, _, _)
-                                                    if name.as_ref() == "toy_write_to_arr"
-                                                        && write_params.len() >= 2
-                                                        && returned_values.contains(&write_params[0].val)
-                                                        && alias_values.contains(&((*callee.name).clone(), write_params[1].val))
+                                                TIR::ArrStore(_, arr, _, new_value, _)
+                                                | TIR::ArrPush(_, arr, new_value, _)
+                                                    if returned_values.contains(&arr.val)
+                                                        && alias_values.contains(&((*callee.name).clone(), new_value.val))
                                             )
                                         })
                                     });
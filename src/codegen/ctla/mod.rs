@@ -7,6 +7,7 @@ use crate::{
     },
     driver::Driver,
     errors::ToyError,
+    token::TypeTok,
 };
 use std::cell::RefCell;
 use std::collections::{BTreeSet, HashMap, HashSet};
@@ -313,10 +314,19 @@ impl CTLA {
                 uses(struct_value) || uses(new_value)
             }
             TIR::Not(_, value) => uses(value),
+            TIR::AllocArr(_, len, _, _) => uses(len),
+            TIR::ArrLoad(_, arr, idx, _) => uses(arr) || uses(idx),
+            TIR::ArrStore(_, arr, idx, value, _) => uses(arr) || uses(idx) || uses(value),
+            TIR::ArrLen(_, arr) => uses(arr),
+            TIR::ArrPush(_, arr, value, _) => uses(arr) || uses(value),
+            TIR::MapLoad(_, map, key, _) => uses(map) || uses(key),
+            TIR::MapStore(_, map, key, value) => uses(map) || uses(key) || uses(value),
             TIR::IConst(_, _, _)
             | TIR::FConst(_, _, _)
             | TIR::JumpBlockUnCond(_, _)
             | TIR::CreateStructInterface(_, _, _)
+            | TIR::AllocMap(_, _, _)
+            | TIR::FuncAddr(_, _, _)
             | TIR::GlobalString(_, _) => false,
         };
     }
@@ -564,7 +574,7 @@ impl CTLA {
             if self.block_returns_allocation_or_alias(func, origin_block_id, alloc) {
                 return;
             }
-            let insertion_idx = if free_func == "toy_free_arr" {
+            let insertion_idx = if free_func == "toy_free_arr" || free_func == "toy_free_map" {
                 func.body
                     .iter()
                     .find(|b| b.id == origin_block_id)
@@ -590,11 +600,12 @@ impl CTLA {
                     if self.block_returns_allocation_or_alias(func, block.id, alloc) {
                         continue;
                     }
-                    let insertion_idx = if free_func == "toy_free_arr" {
-                        block.ins.len().saturating_sub(1)
-                    } else {
-                        self.free_insertion_index_for_block(func, block.id, alloc)
-                    };
+                    let insertion_idx =
+                        if free_func == "toy_free_arr" || free_func == "toy_free_map" {
+                            block.ins.len().saturating_sub(1)
+                        } else {
+                            self.free_insertion_index_for_block(func, block.id, alloc)
+                        };
 
                     insertion_points.push((
                         *func.name.clone(),
@@ -633,11 +644,26 @@ impl CTLA {
             .and_then(|b| b.ins.iter().find(|ins| ins.get_id() == value_id))
             .cloned()
     }
-    /// crude doubleplusungood function that tests if a given function name matches 3 known to return arrays
-    fn is_array_allocation_call_name(&self, name: &str) -> bool {
-        return name == "toy_malloc_arr" || name == "std::sys::argv" || name == "toy_sys_get_argv";
+    /// asks the TirBuilder if an extern function was declared as returning an array
+    fn extern_returns_array(&self, name: &str) -> bool {
+        return self
+            .builder
+            .borrow()
+            .extern_funcs
+            .get(name)
+            .is_some_and(|(_, ret_type, _, _)| {
+                matches!(
+                    ret_type,
+                    TypeTok::StrArr(_)
+                        | TypeTok::IntArr(_)
+                        | TypeTok::FloatArr(_)
+                        | TypeTok::BoolArr(_)
+                        | TypeTok::AnyArr(_)
+                        | TypeTok::StructArr(_, _)
+                )
+            });
     }
-    /// tries to determine if a function returns an array by name, this is bad however and should in future just ask the TIRBuilder
+    /// determines if a local function returns a freshly allocated array
     fn function_returns_array_allocation(&self, function_name: &str) -> bool {
         let builder = self.builder.borrow();
         let Some(func) = builder.funcs.iter().find(|f| *f.name == function_name) else {
@@ -646,18 +672,51 @@ impl CTLA {
 
         for block in &func.body {
             if let Some(TIR::Ret(_, ret_ssa)) = block.ins.last() {
-                if let Some(ins) = block.ins.iter().find(|i| i.get_id() == ret_ssa.val) {
-                    if let TIR::CallExternFunction(_, f_box, _, _, _, _) = ins {
-                        if self.is_array_allocation_call_name(f_box) {
-                            return true;
-                        }
+                match block.ins.iter().find(|i| i.get_id() == ret_ssa.val) {
+                    Some(TIR::AllocArr(..)) => return true,
+                    Some(TIR::CallExternFunction(_, f_box, _, _, _, _))
+                        if self.extern_returns_array(f_box) =>
+                    {
+                        return true;
                     }
+                    _ => {}
                 }
             }
         }
         return false;
     }
-    /// matches the allocation type to the type of free needed (regular or array), returns that function name
+    /// asks the TirBuilder if an extern function was declared as returning a map
+    fn extern_returns_map(&self, name: &str) -> bool {
+        return self
+            .builder
+            .borrow()
+            .extern_funcs
+            .get(name)
+            .is_some_and(|(_, ret_type, _, _)| matches!(ret_type, TypeTok::Map(_, _)));
+    }
+    /// determines if a local function returns a freshly allocated map
+    fn function_returns_map_allocation(&self, function_name: &str) -> bool {
+        let builder = self.builder.borrow();
+        let Some(func) = builder.funcs.iter().find(|f| *f.name == function_name) else {
+            return false;
+        };
+
+        for block in &func.body {
+            if let Some(TIR::Ret(_, ret_ssa)) = block.ins.last() {
+                match block.ins.iter().find(|i| i.get_id() == ret_ssa.val) {
+                    Some(TIR::AllocMap(..)) => return true,
+                    Some(TIR::CallExternFunction(_, f_box, _, _, _, _))
+                        if self.extern_returns_map(f_box) =>
+                    {
+                        return true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        return false;
+    }
+    /// matches the allocation type to the type of free needed (regular, array or map), returns that function name
     fn alloc_type_to_free_func(&self, alloc: &HeapAllocation) -> String {
         let alloc_ins = self.get_alloc_ins(&alloc.function, alloc.block, alloc.alloc_ins.val);
 
@@ -666,11 +725,23 @@ impl CTLA {
         };
 
         match alloc_ins {
+            TIR::AllocArr(..) => {
+                return "toy_free_arr".to_string();
+            }
+            TIR::AllocMap(..) => {
+                return "toy_free_map".to_string();
+            }
+            //only string values are allocations, they are copies the map handed out
+            TIR::MapLoad(..) => {
+                return "toy_free".to_string();
+            }
             TIR::CallExternFunction(_, f_box, _, _, _, _) => {
-                //that argv thing is hacky but I dont know how to say under the hood it calls toy_malloc_arr
-                if self.is_array_allocation_call_name(f_box.as_ref()) {
+                if self.extern_returns_array(f_box.as_ref()) {
                     return "toy_free_arr".to_string();
                 }
+                if self.extern_returns_map(f_box.as_ref()) {
+                    return "toy_free_map".to_string();
+                }
                 return "toy_free".to_string();
             }
             // For local function calls that return heap-allocated values (strings),
@@ -678,6 +749,9 @@ impl CTLA {
                 if self.function_returns_array_allocation(callee_name.as_ref()) {
                     return "toy_free_arr".to_string();
                 }
+                if self.function_returns_map_allocation(callee_name.as_ref()) {
+                    return "toy_free_map".to_string();
+                }
                 return "toy_free".to_string();
             }
             _ => unreachable!(),
@@ -892,7 +966,7 @@ impl CTLA {
             .collect();
 
         let free_sort_rank = |free_name: &str| {
-            if free_name == "toy_free_arr" {
+            if free_name == "toy_free_arr" || free_name == "toy_free_map" {
                 1usize
             } else {
                 0usize
@@ -0,0 +1,487 @@
+//! Lowering of extern calls to the C calling convention. Toy keeps every value in an i64, a double
+//! or a struct pointer while a C function takes whatever its extern declaration says, the
+//! conversions between the two and the attributes a C compiler puts on narrow ints and on structs
+//! passed by value live here.
+use inkwell::{
+    AddressSpace, IntPredicate,
+    attributes::{Attribute, AttributeLoc},
+    builder::Builder,
+    module::Linkage,
+    types::{AnyType, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, ValueKind},
+};
+
+use super::LlvmGenerator;
+use crate::{
+    codegen::{SSAValue, TirType},
+    compile_target::{Arch, CompileTarget, LinkerFlavor},
+    driver::Driver,
+    errors::ToyError,
+    token::{ExternType, TypeTok},
+};
+
+///Calling convention of the target the module is compiled for
+#[derive(Clone, Copy, PartialEq)]
+enum CallingConv {
+    ///x86-64 System V, linux
+    SysV,
+    ///Microsoft x64
+    Win64,
+    ///AAPCS64, aarch64 linux
+    Aapcs64,
+    ///the basic C ABI of wasm32
+    Wasm,
+}
+
+///How one C argument is passed
+#[derive(Clone)]
+enum ParamLowering<'a> {
+    ///a single value, ints narrower than 32 bits carry the signext or zeroext C compilers give them
+    Direct(BasicTypeEnum<'a>, Option<&'static str>),
+    ///a struct passed in registers, one value per field (Toy fields are all 8 bytes)
+    Split(StructType<'a>, Vec<BasicTypeEnum<'a>>),
+    ///pointer to the struct marked byval so the callee gets its own copy, SysV structs over 16 bytes and
+    ///wasm structs of more than one field
+    ByVal(StructType<'a>),
+    ///pointer to a copy made by the caller, Win64 structs that are not 8 bytes and AAPCS64 ones over 16
+    Indirect(StructType<'a>),
+    ///the struct loaded as a single array, AAPCS64 passes structs of up to 4 floats as `[n x double]`
+    ///and other structs up to 16 bytes as `[n x i64]`
+    Coerced(BasicTypeEnum<'a>),
+}
+impl<'a> ParamLowering<'a> {
+    ///how many LLVM params the C param turns into
+    fn width(&self) -> u32 {
+        return match self {
+            ParamLowering::Split(_, parts) => parts.len() as u32,
+            _ => 1,
+        };
+    }
+}
+
+impl<'a> LlvmGenerator<'a> {
+    fn calling_conv() -> CallingConv {
+        let target = CompileTarget::current();
+        return match (target.arch, target.linker) {
+            (Arch::AArch64, _) => CallingConv::Aapcs64,
+            (Arch::Wasm32, _) => CallingConv::Wasm,
+            (_, LinkerFlavor::MinGw) => CallingConv::Win64,
+            _ => CallingConv::SysV,
+        };
+    }
+    ///Externs declared in the module follow the C ABI, runtime builtins keep the signatures declare_builtin_functions gives them
+    pub(super) fn uses_c_abi(&self, name: &str) -> bool {
+        return self.extern_abis.contains_key(name) && !self.func_map.contains_key(name);
+    }
+    fn enum_attribute(&self, name: &str, val: u64) -> Attribute {
+        return self
+            .ctx
+            .create_enum_attribute(Attribute::get_named_enum_kind_id(name), val);
+    }
+    ///The LLVM type a C scalar is passed as and the extension attribute it needs
+    fn c_scalar_type(&self, ty: &ExternType) -> (BasicTypeEnum<'a>, Option<&'static str>) {
+        let int = |bits: u32| -> BasicTypeEnum<'a> { self.ctx.custom_width_int_type(bits).into() };
+        return match ty {
+            ExternType::c_int8_t(0) | ExternType::c_char(0) => (int(8), Some("signext")),
+            ExternType::c_uint8_t(0) => (int(8), Some("zeroext")),
+            ExternType::c_int16_t(0) => (int(16), Some("signext")),
+            ExternType::c_uint16_t(0) => (int(16), Some("zeroext")),
+            ExternType::c_int32_t(0) | ExternType::c_uint32_t(0) => (int(32), None),
+            ExternType::c_bool(0) => (int(1), Some("zeroext")),
+            ExternType::c_float(0) => (self.ctx.f32_type().into(), None),
+            ExternType::c_double(0) => (self.ctx.f64_type().into(), None),
+            ExternType::c_int64_t(0) | ExternType::c_uint64_t(0) | ExternType::c_void(0) => {
+                (int(64), None)
+            }
+            //pointers, struct pointers and callbacks
+            _ => (self.ctx.ptr_type(AddressSpace::default()).into(), None),
+        };
+    }
+    ///The LLVM type Toy keeps a value of the given type in
+    fn toy_value_type(&self, ty: &TypeTok) -> BasicTypeEnum<'a> {
+        return match ty {
+            TypeTok::Float => self.ctx.f64_type().into(),
+            TypeTok::Struct(_) => self.ctx.ptr_type(AddressSpace::default()).into(),
+            _ => self.ctx.i64_type().into(),
+        };
+    }
+    fn c_param_lowering(&self, ty: &ExternType, arg: &SSAValue) -> ParamLowering<'a> {
+        let ExternType::c_struct(_, 0) = ty else {
+            let (llvm_ty, ext) = self.c_scalar_type(ty);
+            return ParamLowering::Direct(llvm_ty, ext);
+        };
+        let Some(TirType::StructInterface(fields)) = &arg.ty else {
+            unreachable!() // parser validated
+        };
+        let field_types: Vec<BasicTypeEnum<'a>> = fields
+            .iter()
+            .map(|f| self._tir_to_llvm_type(f.clone()))
+            .collect();
+        let struct_type = self.ctx.struct_type(&field_types, false);
+        return match LlvmGenerator::calling_conv() {
+            //each 8 byte field is its own eightbyte, doubles go in SSE registers and the rest in integer ones
+            CallingConv::SysV if fields.len() <= 2 => {
+                ParamLowering::Split(struct_type, field_types)
+            }
+            CallingConv::SysV => ParamLowering::ByVal(struct_type),
+            CallingConv::Win64 if fields.len() == 1 => {
+                ParamLowering::Split(struct_type, vec![self.ctx.i64_type().into()])
+            }
+            CallingConv::Win64 => ParamLowering::Indirect(struct_type),
+            //a homogeneous floating point aggregate goes in SIMD registers, one per field
+            CallingConv::Aapcs64
+                if fields.len() <= 4 && field_types.iter().all(|t| t.is_float_type()) =>
+            {
+                ParamLowering::Coerced(self.ctx.f64_type().array_type(fields.len() as u32).into())
+            }
+            CallingConv::Aapcs64 if fields.len() <= 2 => {
+                ParamLowering::Coerced(self.ctx.i64_type().array_type(fields.len() as u32).into())
+            }
+            CallingConv::Aapcs64 => ParamLowering::Indirect(struct_type),
+            //a single field struct is passed as its field, anything bigger by value in memory
+            CallingConv::Wasm if fields.len() == 1 => {
+                ParamLowering::Split(struct_type, field_types)
+            }
+            CallingConv::Wasm => ParamLowering::ByVal(struct_type),
+        };
+    }
+    ///(LLVM param index, attribute) for every attribute the lowered params need
+    fn param_attributes(&self, lowerings: &[ParamLowering<'a>]) -> Vec<(u32, Attribute)> {
+        let mut attributes = vec![];
+        let mut idx = 0;
+        for lowering in lowerings {
+            match lowering {
+                ParamLowering::Direct(_, Some(ext)) => {
+                    attributes.push((idx, self.enum_attribute(ext, 0)));
+                }
+                ParamLowering::ByVal(struct_type) => {
+                    let byval = self.ctx.create_type_attribute(
+                        Attribute::get_named_enum_kind_id("byval"),
+                        struct_type.as_any_type_enum(),
+                    );
+                    attributes.push((idx, byval));
+                    attributes.push((idx, self.enum_attribute("align", 8)));
+                }
+                _ => {}
+            }
+            idx += lowering.width();
+        }
+        return attributes;
+    }
+    fn toy_fn_type(
+        &self,
+        ret_type: &TirType,
+        params: &[BasicMetadataTypeEnum<'a>],
+    ) -> FunctionType<'a> {
+        return match ret_type {
+            TirType::Void => self.ctx.void_type().fn_type(params, false),
+            t => self._tir_to_llvm_type(t.clone()).fn_type(params, false),
+        };
+    }
+    ///Declares an extern with its C param types, the return keeps the Toy type unless a C return type was declared
+    fn declare_c_function(
+        &self,
+        name: &str,
+        lowerings: &[ParamLowering<'a>],
+        c_ret: Option<&ExternType>,
+        ret_type: &TirType,
+    ) -> FunctionValue<'a> {
+        let mut llvm_params: Vec<BasicMetadataTypeEnum<'a>> = vec![];
+        for lowering in lowerings {
+            match lowering {
+                ParamLowering::Direct(ty, _) | ParamLowering::Coerced(ty) => {
+                    llvm_params.push((*ty).into())
+                }
+                ParamLowering::Split(_, parts) => {
+                    llvm_params.extend(parts.iter().map(|p| BasicMetadataTypeEnum::from(*p)))
+                }
+                ParamLowering::ByVal(_) | ParamLowering::Indirect(_) => {
+                    llvm_params.push(self.ctx.ptr_type(AddressSpace::default()).into())
+                }
+            }
+        }
+        let fn_type = match c_ret {
+            Some(c_ret) => self.c_scalar_type(c_ret).0.fn_type(&llvm_params, false),
+            None => self.toy_fn_type(ret_type, &llvm_params),
+        };
+        let func = self
+            .main_module
+            .add_function(name, fn_type, Some(Linkage::External));
+        for (idx, attribute) in self.param_attributes(lowerings) {
+            func.add_attribute(AttributeLoc::Param(idx), attribute);
+        }
+        if let Some((_, Some(ext))) = c_ret.map(|r| self.c_scalar_type(r)) {
+            func.add_attribute(AttributeLoc::Return, self.enum_attribute(ext, 0));
+        }
+        return func;
+    }
+    ///Converts a Toy value to a C scalar of the given type
+    fn toy_to_c(
+        &self,
+        builder: &Builder<'a>,
+        val: BasicValueEnum<'a>,
+        ty: BasicTypeEnum<'a>,
+    ) -> Result<BasicValueEnum<'a>, ToyError> {
+        return Ok(match (val, ty) {
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::IntType(t)) => {
+                let (from, to) = (i.get_type().get_bit_width(), t.get_bit_width());
+                if to == 1 && from > 1 {
+                    builder
+                        .build_int_compare(
+                            IntPredicate::NE,
+                            i,
+                            i.get_type().const_zero(),
+                            "to_c_bool",
+                        )?
+                        .into()
+                } else if from > to {
+                    builder.build_int_truncate(i, t, "to_c_int")?.into()
+                } else if from < to {
+                    builder.build_int_z_extend(i, t, "to_c_int")?.into()
+                } else {
+                    i.into()
+                }
+            }
+            (BasicValueEnum::FloatValue(f), BasicTypeEnum::FloatType(t))
+                if t == self.ctx.f32_type() =>
+            {
+                builder.build_float_trunc(f, t, "to_c_float")?.into()
+            }
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::FloatType(t)) => {
+                let bits = builder.build_bit_cast(i, self.ctx.f64_type(), "to_c_bits")?;
+                self.toy_to_c(builder, bits, t.into())?
+            }
+            (BasicValueEnum::FloatValue(f), BasicTypeEnum::IntType(t)) => {
+                builder.build_bit_cast(f, t, "to_c_bits")?
+            }
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::PointerType(t)) => {
+                builder.build_int_to_ptr(i, t, "to_c_ptr")?.into()
+            }
+            (BasicValueEnum::PointerValue(p), BasicTypeEnum::IntType(t)) => {
+                builder.build_ptr_to_int(p, t, "to_c_int")?.into()
+            }
+            (v, _) => v,
+        });
+    }
+    ///Converts a C scalar of the given extern type back to the value Toy keeps it in
+    fn c_to_toy(
+        &self,
+        builder: &Builder<'a>,
+        val: BasicValueEnum<'a>,
+        ty: &ExternType,
+        toy_ty: BasicTypeEnum<'a>,
+    ) -> Result<BasicValueEnum<'a>, ToyError> {
+        let signed = matches!(
+            ty,
+            ExternType::c_int8_t(0)
+                | ExternType::c_int16_t(0)
+                | ExternType::c_int32_t(0)
+                | ExternType::c_char(0)
+        );
+        return Ok(match (val, toy_ty) {
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::IntType(t))
+                if i.get_type().get_bit_width() < t.get_bit_width() =>
+            {
+                if signed {
+                    builder.build_int_s_extend(i, t, "from_c_int")?.into()
+                } else {
+                    builder.build_int_z_extend(i, t, "from_c_int")?.into()
+                }
+            }
+            (BasicValueEnum::FloatValue(f), BasicTypeEnum::FloatType(t)) if f.get_type() != t => {
+                builder.build_float_ext(f, t, "from_c_float")?.into()
+            }
+            (BasicValueEnum::PointerValue(p), BasicTypeEnum::IntType(t)) => {
+                builder.build_ptr_to_int(p, t, "from_c_ptr")?.into()
+            }
+            (BasicValueEnum::IntValue(i), BasicTypeEnum::PointerType(t)) => {
+                builder.build_int_to_ptr(i, t, "from_c_ptr")?.into()
+            }
+            (v, _) => v,
+        });
+    }
+    ///Turns a Toy argument into the LLVM args of its lowering, a struct can become several args
+    fn lower_c_arg(
+        &self,
+        builder: &Builder<'a>,
+        val: BasicValueEnum<'a>,
+        lowering: &ParamLowering<'a>,
+    ) -> Result<Vec<BasicMetadataValueEnum<'a>>, ToyError> {
+        return Ok(match lowering {
+            ParamLowering::Direct(ty, _) => vec![self.toy_to_c(builder, val, *ty)?.into()],
+            ParamLowering::Split(struct_type, parts) => {
+                let ptr = self.value_to_ptr(builder, val)?;
+                let mut args = vec![];
+                for (i, part) in parts.iter().enumerate() {
+                    let field_ptr =
+                        builder.build_struct_gep(*struct_type, ptr, i as u32, "c_field_ptr")?;
+                    let field_type = struct_type.get_field_type_at_index(i as u32).unwrap();
+                    let field = builder.build_load(field_type, field_ptr, "c_field")?;
+                    args.push(self.toy_to_c(builder, field, *part)?.into());
+                }
+                args
+            }
+            ParamLowering::ByVal(_) => vec![self.value_to_ptr(builder, val)?.into()],
+            ParamLowering::Indirect(struct_type) => {
+                let ptr = self.value_to_ptr(builder, val)?;
+                let copy = builder.build_alloca(*struct_type, "c_struct_copy")?;
+                let loaded = builder.build_load(*struct_type, ptr, "c_struct")?;
+                builder.build_store(copy, loaded)?;
+                vec![copy.into()]
+            }
+            //every field is 8 bytes so the struct and the array share a layout
+            ParamLowering::Coerced(ty) => {
+                let ptr = self.value_to_ptr(builder, val)?;
+                vec![builder.build_load(*ty, ptr, "c_struct")?.into()]
+            }
+        });
+    }
+    ///Calls an extern through the C ABI, the result comes back as the Toy value ret_type describes
+    pub(super) fn build_c_call(
+        &self,
+        builder: &Builder<'a>,
+        curr_func_name: &str,
+        name: &str,
+        params: &[SSAValue],
+        ret_type: &TirType,
+    ) -> Result<BasicValueEnum<'a>, ToyError> {
+        let (c_params, c_ret) = self.extern_abis[name].clone();
+        let lowerings: Vec<ParamLowering<'a>> = c_params
+            .iter()
+            .zip(params)
+            .map(|(ty, arg)| self.c_param_lowering(ty, arg))
+            .collect();
+        let func = match self.main_module.get_function(name) {
+            Some(f) => f,
+            None => self.declare_c_function(name, &lowerings, c_ret.as_ref(), ret_type),
+        };
+        let mut args: Vec<BasicMetadataValueEnum<'a>> = vec![];
+        for (lowering, p) in lowerings.iter().zip(params) {
+            let val = self.get_ssa_val(curr_func_name, p.clone());
+            args.extend(self.lower_c_arg(builder, val, lowering)?);
+        }
+        let call = builder.build_call(func, &args, name)?;
+        for (idx, attribute) in self.param_attributes(&lowerings) {
+            call.add_attribute(AttributeLoc::Param(idx), attribute);
+        }
+        let ret = match call.try_as_basic_value() {
+            ValueKind::Basic(v) => v,
+            //TIR-Gen will make sure a void result is never used
+            _ => return Ok(self.ctx.i64_type().const_zero().into()),
+        };
+        return match &c_ret {
+            Some(c_ret) => self.c_to_toy(
+                builder,
+                ret,
+                c_ret,
+                self._tir_to_llvm_type(ret_type.clone()),
+            ),
+            None => Ok(ret),
+        };
+    }
+    ///A C callable wrapper around a Toy function that is passed as a callback
+    pub(super) fn callback_trampoline(
+        &self,
+        name: &str,
+        callback: &ExternType,
+    ) -> Result<FunctionValue<'a>, ToyError> {
+        let trampoline_name = format!("{}.callback.{}", name, callback.to_str());
+        if let Some(f) = self.main_module.get_function(&trampoline_name) {
+            return Ok(f);
+        }
+        return self.c_wrapper(name, &trampoline_name, callback, Linkage::Internal);
+    }
+    ///The public C entry point of an `export fn` in a `--lib` build
+    pub(super) fn export_wrapper(
+        &self,
+        toy_name: &str,
+        symbol: &str,
+        signature: &ExternType,
+    ) -> Result<FunctionValue<'a>, ToyError> {
+        let mut toy_name = toy_name.to_string();
+        //a function without params is mangled to its plain name, move it out of the way of the wrapper
+        if let Some(f) = self.main_module.get_function(symbol) {
+            toy_name = format!("{}.toy", symbol);
+            f.as_global_value().set_name(&toy_name);
+        }
+        return self.c_wrapper(&toy_name, symbol, signature, Linkage::External);
+    }
+    ///Defines `wrapper_name` with the C signature, it converts the C args to the Toy params, calls the
+    ///Toy function and converts the result back
+    fn c_wrapper(
+        &self,
+        name: &str,
+        wrapper_name: &str,
+        signature: &ExternType,
+        linkage: Linkage,
+    ) -> Result<FunctionValue<'a>, ToyError> {
+        let ExternType::c_fn(c_params, c_ret) = signature else {
+            unreachable!() // parser validated
+        };
+        let returns_void = **c_ret == ExternType::c_void(0);
+        let toy_params: Vec<TypeTok> = c_params
+            .iter()
+            .map(|p| Driver::extern_type_to_type_tok(p.clone()))
+            .collect();
+
+        let toy_func = match self.main_module.get_function(name) {
+            Some(f) => f,
+            None => {
+                //declared here when the callback is taken before the function is compiled
+                let param_types: Vec<BasicMetadataTypeEnum<'a>> = toy_params
+                    .iter()
+                    .map(|p| self.toy_value_type(p).into())
+                    .collect();
+                let fn_type = if returns_void {
+                    self.ctx.void_type().fn_type(&param_types, false)
+                } else {
+                    let ret = Driver::extern_type_to_type_tok(*c_ret.clone());
+                    self.toy_value_type(&ret).fn_type(&param_types, false)
+                };
+                self.main_module
+                    .add_function(name, fn_type, Some(Linkage::External))
+            }
+        };
+
+        let c_param_types: Vec<BasicMetadataTypeEnum<'a>> = c_params
+            .iter()
+            .map(|p| self.c_scalar_type(p).0.into())
+            .collect();
+        let fn_type = if returns_void {
+            self.ctx.void_type().fn_type(&c_param_types, false)
+        } else {
+            self.c_scalar_type(c_ret).0.fn_type(&c_param_types, false)
+        };
+        let wrapper = self
+            .main_module
+            .add_function(wrapper_name, fn_type, Some(linkage));
+        for (idx, p) in c_params.iter().enumerate() {
+            if let (_, Some(ext)) = self.c_scalar_type(p) {
+                wrapper.add_attribute(AttributeLoc::Param(idx as u32), self.enum_attribute(ext, 0));
+            }
+        }
+        if let (false, (_, Some(ext))) = (returns_void, self.c_scalar_type(c_ret)) {
+            wrapper.add_attribute(AttributeLoc::Return, self.enum_attribute(ext, 0));
+        }
+
+        let builder = self.ctx.create_builder();
+        builder.position_at_end(self.ctx.append_basic_block(wrapper, "entry"));
+        let mut args: Vec<BasicMetadataValueEnum<'a>> = vec![];
+        for (i, (c_param, toy_param)) in c_params.iter().zip(&toy_params).enumerate() {
+            let val = wrapper.get_nth_param(i as u32).unwrap();
+            let toy_ty = self.toy_value_type(toy_param);
+            args.push(self.c_to_toy(&builder, val, c_param, toy_ty)?.into());
+        }
+        let call = builder.build_call(toy_func, &args, "wrapped")?;
+        match call.try_as_basic_value() {
+            ValueKind::Basic(v) if !returns_void => {
+                let ret = self.toy_to_c(&builder, v, self.c_scalar_type(c_ret).0)?;
+                builder.build_return(Some(&ret))?;
+            }
+            _ => {
+                builder.build_return(None)?;
+            }
+        }
+        return Ok(wrapper);
+    }
+}
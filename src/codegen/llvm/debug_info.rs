@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use inkwell::{
+    builder::Builder,
+    context::Context,
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DISubprogram,
+        DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder, debug_metadata_version,
+    },
+    module::{FlagBehavior, Module},
+    values::{BasicValueEnum, FunctionValue, PointerValue},
+};
+
+use super::source_map::SourceMap;
+use crate::{
+    codegen::{SSAValue, TirType, tir::ir::ValueId},
+    errors::Span,
+};
+
+//DW_ATE_* encodings from the DWARF spec
+const DW_ATE_ADDRESS: u32 = 0x01;
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+
+///Everything needed to attach DWARF debug info to the module being generated, only exists when compiling with `-g`
+pub struct DebugInfo<'a> {
+    dibuilder: DebugInfoBuilder<'a>,
+    compile_unit: DICompileUnit<'a>,
+    file: DIFile<'a>,
+    ///(func_name, ssa value id) -> variables bound to that value, from the TirBuilder
+    var_bindings: HashMap<(String, ValueId), Vec<(String, Span)>>,
+    subprogram: Option<DISubprogram<'a>>,
+    ///variable name -> stack slot that mirrors its current value, reset for every function
+    var_slots: HashMap<String, PointerValue<'a>>,
+    ///(func_name, value id, value) of bound values that still have to be stored, they wait
+    ///until the builder is past the phi nodes of the block
+    pending: Vec<(String, ValueId, BasicValueEnum<'a>)>,
+}
+
+impl<'a> DebugInfo<'a> {
+    pub fn new(
+        module: &Module<'a>,
+        source_map: &SourceMap,
+        is_optimized: bool,
+        var_bindings: HashMap<(String, ValueId), Vec<(String, Span)>>,
+    ) -> DebugInfo<'a> {
+        let file_path = source_map.file_path.clone();
+        let path = Path::new(&file_path);
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or(file_path.clone());
+        let directory = path
+            .parent()
+            .and_then(|p| fs::canonicalize(p).ok())
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or(".".to_string());
+
+        let (dibuilder, compile_unit) = module.create_debug_info_builder(
+            true,
+            //there is no DWARF language code for Toy, C is the closest thing debuggers understand
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            "toy_comp",
+            is_optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let file = dibuilder.create_file(&file_name, &directory);
+
+        return DebugInfo {
+            dibuilder,
+            compile_unit,
+            file,
+            var_bindings,
+            subprogram: None,
+            var_slots: HashMap::new(),
+            pending: vec![],
+        };
+    }
+
+    fn di_type(&self, ty: &Option<TirType>) -> DIType<'a> {
+        let (name, encoding) = match ty {
+            Some(TirType::F64) => ("float", DW_ATE_FLOAT),
+            Some(TirType::I1) => ("bool", DW_ATE_BOOLEAN),
+            Some(TirType::I64) | None => ("int", DW_ATE_SIGNED),
+            Some(TirType::Ptr) => ("str", DW_ATE_ADDRESS),
+            Some(TirType::StructInterface(_)) => ("struct", DW_ATE_ADDRESS),
+            Some(TirType::Void) => ("void", DW_ATE_ADDRESS),
+        };
+        //LLVM hands back the same node for the same (name, size, encoding)
+        return self
+            .dibuilder
+            .create_basic_type(name, 64, encoding, DIFlags::ZERO)
+            .unwrap()
+            .as_type();
+    }
+
+    ///Creates the DISubprogram for a function and points the builder at its declaration
+    pub fn begin_function(
+        &mut self,
+        ctx: &'a Context,
+        builder: &Builder<'a>,
+        source_map: &SourceMap,
+        llvm_func: FunctionValue<'a>,
+        name: &str,
+        params: &[SSAValue],
+        ret_type: &TirType,
+        is_optimized: bool,
+    ) {
+        let line = source_map.func_line(name);
+        let ret = if *ret_type == TirType::Void {
+            None
+        } else {
+            Some(self.di_type(&Some(ret_type.clone())))
+        };
+        let param_types: Vec<DIType> = params.iter().map(|p| self.di_type(&p.ty)).collect();
+        let subroutine_type =
+            self.dibuilder
+                .create_subroutine_type(self.file, ret, &param_types, DIFlags::ZERO);
+        let subprogram = self.dibuilder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name,
+            None,
+            self.file,
+            line,
+            subroutine_type,
+            name != "user_main" && !name.starts_with("std::"),
+            true,
+            line,
+            DIFlags::PUBLIC,
+            is_optimized,
+        );
+        llvm_func.set_subprogram(subprogram);
+        self.subprogram = Some(subprogram);
+        self.var_slots.clear();
+        self.pending.clear();
+        let loc = self.location(ctx, line, 0);
+        builder.set_current_debug_location(loc);
+    }
+
+    fn location(&self, ctx: &'a Context, line: u32, col: u32) -> DILocation<'a> {
+        return self.dibuilder.create_debug_location(
+            ctx,
+            line,
+            col,
+            self.subprogram.unwrap().as_debug_info_scope(),
+            None,
+        );
+    }
+
+    ///Moves the builder's debug location to the statement that produced the instruction,
+    ///instructions without a span keep the previous location
+    pub fn set_location(
+        &self,
+        ctx: &'a Context,
+        builder: &Builder<'a>,
+        source_map: &SourceMap,
+        func_name: &str,
+        id: ValueId,
+    ) {
+        if let Some((line, col)) = source_map.ins_line_col(func_name, id) {
+            builder.set_current_debug_location(self.location(ctx, line, col));
+        }
+    }
+
+    ///Queues a newly defined value if any variable is bound to it
+    pub fn queue_binding(&mut self, func_name: &str, id: ValueId, val: BasicValueEnum<'a>) {
+        if self.var_bindings.contains_key(&(func_name.to_string(), id)) {
+            self.pending.push((func_name.to_string(), id, val));
+        }
+    }
+
+    ///Stores every queued value into the stack slot of the variables bound to it.
+    ///Must only be called once the builder is past the phi nodes of the current block
+    pub fn flush_bindings(
+        &mut self,
+        ctx: &'a Context,
+        builder: &Builder<'a>,
+        source_map: &SourceMap,
+        llvm_func: FunctionValue<'a>,
+    ) {
+        let pending: Vec<_> = self.pending.drain(..).collect();
+        for (func_name, id, val) in pending {
+            let bindings = self.var_bindings[&(func_name.clone(), id)].clone();
+            for (var_name, span) in bindings {
+                let (line, col) = source_map.line_col(&span);
+                self.store_var(ctx, builder, llvm_func, &var_name, (line, col), val, None);
+            }
+        }
+    }
+
+    ///Declares the parameters of the current function, the builder must be at the start of the entry block
+    pub fn bind_params(
+        &mut self,
+        ctx: &'a Context,
+        builder: &Builder<'a>,
+        source_map: &SourceMap,
+        llvm_func: FunctionValue<'a>,
+        func_name: &str,
+        params: &[SSAValue],
+    ) {
+        for (n, p) in params.iter().enumerate() {
+            let Some(bindings) = self
+                .var_bindings
+                .get(&(func_name.to_string(), p.val))
+                .cloned()
+            else {
+                continue;
+            };
+            let val = llvm_func.get_nth_param(n as u32).unwrap();
+            for (var_name, span) in bindings {
+                let (line, col) = source_map.line_col(&span);
+                self.store_var(
+                    ctx,
+                    builder,
+                    llvm_func,
+                    &var_name,
+                    (line, col),
+                    val,
+                    Some(n as u32 + 1),
+                );
+            }
+        }
+    }
+
+    fn store_var(
+        &mut self,
+        ctx: &'a Context,
+        builder: &Builder<'a>,
+        llvm_func: FunctionValue<'a>,
+        var_name: &str,
+        (line, col): (u32, u32),
+        val: BasicValueEnum<'a>,
+        arg_no: Option<u32>,
+    ) {
+        let slot = match self.var_slots.get(var_name) {
+            Some(slot) => *slot,
+            None => {
+                //slots live at the top of the entry block so they dominate every store
+                let entry = llvm_func.get_first_basic_block().unwrap();
+                let alloca_builder = ctx.create_builder();
+                match entry.get_first_instruction() {
+                    Some(first) => alloca_builder.position_before(&first),
+                    None => alloca_builder.position_at_end(entry),
+                }
+                let slot = alloca_builder
+                    .build_alloca(val.get_type(), var_name)
+                    .unwrap();
+                let scope = self.subprogram.unwrap().as_debug_info_scope();
+                let ty = self.di_type(&Some(match val {
+                    BasicValueEnum::FloatValue(_) => TirType::F64,
+                    BasicValueEnum::PointerValue(_) => TirType::Ptr,
+                    _ => TirType::I64,
+                }));
+                let var = match arg_no {
+                    Some(n) => self.dibuilder.create_parameter_variable(
+                        scope,
+                        var_name,
+                        n,
+                        self.file,
+                        line,
+                        ty,
+                        true,
+                        DIFlags::ZERO,
+                    ),
+                    None => self.dibuilder.create_auto_variable(
+                        scope,
+                        var_name,
+                        self.file,
+                        line,
+                        ty,
+                        true,
+                        DIFlags::ZERO,
+                        0,
+                    ),
+                };
+                let loc = self.location(ctx, line, col);
+                let store = alloca_builder
+                    .build_store(slot, val.get_type().const_zero())
+                    .unwrap();
+                self.dibuilder
+                    .insert_declare_before_instruction(slot, Some(var), None, loc, store);
+                self.var_slots.insert(var_name.to_string(), slot);
+                slot
+            }
+        };
+        builder.build_store(slot, val).unwrap();
+    }
+
+    ///Resolves all debug info nodes and tags the module with the debug info version, must run before verification
+    pub fn finalize(&self, ctx: &'a Context, module: &Module<'a>) {
+        self.dibuilder.finalize();
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            ctx.i32_type().const_int(debug_metadata_version() as u64, false),
+        );
+    }
+}
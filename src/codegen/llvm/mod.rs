@@ -1,16 +1,22 @@
 use std::collections::HashMap;
 
+mod abi;
+mod debug_info;
+mod source_map;
+use debug_info::DebugInfo;
+use source_map::SourceMap;
 use inkwell::{
     AddressSpace,
+    attributes::{Attribute, AttributeLoc},
     basic_block::BasicBlock,
     builder::Builder,
     context::Context,
     module::{Linkage, Module},
-    targets::{TargetMachineOptions, TargetTriple},
+    targets::{RelocMode, TargetMachineOptions, TargetTriple},
     types::{BasicMetadataTypeEnum, BasicTypeEnum, FunctionType, StructType},
     values::{
-        BasicMetadataValueEnum, BasicValue, BasicValueEnum, FloatValue, FunctionValue, PhiValue,
-        PointerValue, ValueKind,
+        BasicMetadataValueEnum, BasicValue, BasicValueEnum, FloatValue, FunctionValue, IntValue,
+        PhiValue, PointerValue, ValueKind,
     },
 };
 use inkwell::{FloatPredicate, IntPredicate};
@@ -18,16 +24,17 @@ use inkwell::{FloatPredicate, IntPredicate};
 use crate::{
     codegen::{
         Block, Function, SSAValue, TIR, TirType,
-        tir::ir::{BlockId, BoolInfixOp, NumericInfixOp},
+        tir::ir::{BlockId, BoolInfixOp, NumericInfixOp, ValueId},
     },
+    compile_target::{Arch, CompileTarget},
     driver::Driver,
-    errors::ToyError,
+    errors::{Span, ToyError},
+    token::ExternType,
 };
 use inkwell::{
     OptimizationLevel,
     targets::{FileType, InitializationConfig, Target},
 };
-use std::env;
 use std::path::Path;
 
 pub struct LlvmGenerator<'a> {
@@ -36,11 +43,30 @@ pub struct LlvmGenerator<'a> {
     tir_to_val: HashMap<(String, SSAValue), BasicValueEnum<'a>>,
     func_map: HashMap<String, FunctionType<'a>>,
     block_id_to_block: HashMap<BlockId, BasicBlock<'a>>,
+    ///TIR block -> LLVM block it ends in, differs from block_id_to_block when inline array checks split the block
+    block_exits: HashMap<BlockId, BasicBlock<'a>>,
     curr_func: Option<FunctionValue<'a>>,
     curr_tir_func: Option<Function>,
     phi_fixups: Vec<(PhiValue<'a>, String, BlockId, SSAValue)>,
     ///Maps TirType Interface -> (LLVM Struct Type, INTERFACE name)
     struct_interfaces: HashMap<TirType, (StructType<'a>, String)>,
+    source_map: SourceMap,
+    ///only set when compiling with `-g`
+    debug_info: Option<DebugInfo<'a>>,
+    ///shadow stack frame of the current function, `[prev, func name, file, line]` as i64s
+    frame: Option<PointerValue<'a>>,
+    ///line last written to the frame in the current block
+    frame_line: u32,
+    ///file path global shared by every frame in the module
+    frame_file: Option<BasicValueEnum<'a>>,
+    ///extern name -> declared C (params, return), calls to these are lowered in abi.rs
+    extern_abis: HashMap<String, (Vec<ExternType>, Option<ExternType>)>,
+    ///`--lib` builds, (Toy function, C symbol, C signature) of every wrapper to emit
+    c_exports: Vec<(String, String, ExternType)>,
+    ///false when the module is handed to the JIT instead of being written to an object file
+    emit_object: bool,
+    ///set for a REPL entry, its functions stay visible to the entries the JIT adds after it
+    external_functions: bool,
 }
 impl<'a> LlvmGenerator<'a> {
     pub fn new(ctx: &'a Context, main_module: Module<'a>) -> LlvmGenerator<'a> {
@@ -50,12 +76,93 @@ impl<'a> LlvmGenerator<'a> {
             tir_to_val: HashMap::new(),
             func_map: HashMap::new(),
             block_id_to_block: HashMap::new(),
+            block_exits: HashMap::new(),
             curr_func: None,
             curr_tir_func: None,
             phi_fixups: vec![],
             struct_interfaces: HashMap::new(),
+            source_map: SourceMap::new(HashMap::new(), HashMap::new(), HashMap::new()),
+            debug_info: None,
+            frame: None,
+            frame_line: 0,
+            frame_file: None,
+            extern_abis: HashMap::new(),
+            c_exports: vec![],
+            emit_object: true,
+            external_functions: false,
         };
     }
+    ///Spans and names recorded by the TirBuilder that produced the TIR, used for backtraces and debug info
+    pub fn set_source_info(
+        &mut self,
+        ins_spans: HashMap<(String, ValueId), Span>,
+        func_spans: HashMap<String, Span>,
+        display_names: HashMap<String, String>,
+    ) {
+        self.source_map = SourceMap::new(ins_spans, func_spans, display_names);
+    }
+    ///C signatures of the externs declared in the TIR
+    pub fn set_extern_abis(
+        &mut self,
+        extern_abis: HashMap<String, (Vec<ExternType>, Option<ExternType>)>,
+    ) {
+        self.extern_abis = extern_abis;
+    }
+    ///Exported functions that get a C wrapper, only set on the main module of a `--lib` build
+    pub fn set_c_exports(&mut self, c_exports: Vec<(String, String, ExternType)>) {
+        self.c_exports = c_exports;
+    }
+    pub fn set_emit_object(&mut self, emit_object: bool) {
+        self.emit_object = emit_object;
+    }
+    pub fn set_external_functions(&mut self, external_functions: bool) {
+        self.external_functions = external_functions;
+    }
+    ///The generated module, for the JIT to take once `generate` has run
+    pub fn into_module(self) -> Module<'a> {
+        return self.main_module;
+    }
+    ///Turns on DWARF emission, must be called after `set_source_info`
+    pub fn enable_debug_info(&mut self, var_bindings: HashMap<(String, ValueId), Vec<(String, Span)>>) {
+        self.debug_info = Some(DebugInfo::new(
+            &self.main_module,
+            &self.source_map,
+            LlvmGenerator::opt_level() != OptimizationLevel::None,
+            var_bindings,
+        ));
+    }
+    fn unchecked_bounds() -> bool {
+        let args: Vec<String> = Driver::args();
+        return args.contains(&"--unchecked-bounds".to_string());
+    }
+    ///array accesses go through the runtime when sanitizing so use after free checks see every access
+    fn sanitize_memory() -> bool {
+        let args: Vec<String> = Driver::args();
+        return args.contains(&"--sanitize=memory".to_string());
+    }
+    ///shadow stack frames are built unless `--no-backtrace` turns them off to save their cost on every call
+    fn backtraces() -> bool {
+        let args: Vec<String> = Driver::args();
+        return !args.contains(&"--no-backtrace".to_string());
+    }
+    ///libraries end up in position independent executables and shared objects
+    fn reloc_mode() -> RelocMode {
+        let args: Vec<String> = Driver::args();
+        if args.iter().any(|a| a.starts_with("--lib=")) {
+            RelocMode::PIC
+        } else {
+            RelocMode::Default
+        }
+    }
+    fn opt_level() -> OptimizationLevel {
+        let args: Vec<String> = Driver::args();
+        //opts can conflict with CTLA
+        if args.contains(&"--repl".to_string()) || args.contains(&"--no-op".to_string()) {
+            OptimizationLevel::None
+        } else {
+            OptimizationLevel::Aggressive
+        }
+    }
     fn get_ssa_val(&self, func_name: &str, ssa: SSAValue) -> BasicValueEnum<'a> {
         if let Some(v) = self.tir_to_val.get(&(func_name.to_string(), ssa.clone())) {
             return *v;
@@ -129,6 +236,16 @@ impl<'a> LlvmGenerator<'a> {
                     },
                 ))
             }
+            TIR::CallExternFunction(id, name, params, _, ret_type, _) if self.uses_c_abi(&name) => {
+                let ret = self.build_c_call(builder, &curr_func_name, &name, &params, &ret_type)?;
+                Some((
+                    ret,
+                    SSAValue {
+                        val: id,
+                        ty: Some(ret_type),
+                    },
+                ))
+            }
             //I am going to rush ahead to call_extern without dealing with any other function types for debuggability purposes
             TIR::CallExternFunction(id, name, params, _, ret_type, _) => {
                 let func_body = if let Some(f) = self.main_module.get_function(&name) {
@@ -640,7 +757,16 @@ impl<'a> LlvmGenerator<'a> {
                 let phi = builder.build_phi(ty, "phi")?;
 
                 for (block_id, ssa_val) in block_ids.iter().zip(vals.iter()) {
-                    let block = self.block_id_to_block.get(block_id).unwrap();
+                    //predecessors that are not compiled yet may still be split, wait for their exit block
+                    let Some(block) = self.block_exits.get(block_id) else {
+                        self.phi_fixups.push((
+                            phi,
+                            curr_func_name.clone(),
+                            *block_id,
+                            ssa_val.clone(),
+                        ));
+                        continue;
+                    };
                     if let Some(val) = self
                         .tir_to_val
                         .get(&(curr_func_name.clone(), ssa_val.clone()))
@@ -857,8 +983,154 @@ impl<'a> LlvmGenerator<'a> {
                     },
                 ))
             }
+            TIR::AllocArr(id, len, elem_type_code, degree) => {
+                let i64_type = self.ctx.i64_type();
+                let len = self.get_ssa_val(&curr_func_name, len).into_int_value();
+                let arr = self
+                    .call_runtime(
+                        builder,
+                        "toy_malloc_arr",
+                        &[
+                            len.into(),
+                            i64_type.const_int(elem_type_code as u64, true).into(),
+                            i64_type.const_int(degree as u64, true).into(),
+                        ],
+                    )?
+                    .unwrap();
+                Some((
+                    arr,
+                    SSAValue {
+                        val: id,
+                        ty: Some(TirType::Ptr),
+                    },
+                ))
+            }
+            TIR::ArrLoad(id, arr, idx, elem_type) => {
+                let elem = self.compile_arr_load(builder, &curr_func_name, id, arr, idx)?;
+                let val = if elem_type == TirType::F64 {
+                    builder.build_bit_cast(elem, self.ctx.f64_type(), "i64_to_double_bitcast")?
+                } else {
+                    elem.into()
+                };
+                Some((
+                    val,
+                    SSAValue {
+                        val: id,
+                        ty: Some(elem_type),
+                    },
+                ))
+            }
+            TIR::ArrStore(id, arr, idx, new_val, type_code) => {
+                self.compile_arr_store(builder, &curr_func_name, id, arr, idx, new_val, type_code)?;
+                None
+            }
+            TIR::ArrLen(id, arr) => {
+                let arr = self.get_ssa_val(&curr_func_name, arr);
+                let len = if LlvmGenerator::sanitize_memory() {
+                    let arr = self.value_to_i64(builder, arr)?;
+                    self.call_runtime(builder, "toy_arrlen", &[arr.into()])?
+                        .unwrap()
+                } else {
+                    //len is the first field of ToyArr
+                    let arr_ptr = self.value_to_ptr(builder, arr)?;
+                    builder.build_load(self.ctx.i64_type(), arr_ptr, "arr_len")?
+                };
+                Some((
+                    len,
+                    SSAValue {
+                        val: id,
+                        ty: Some(TirType::I64),
+                    },
+                ))
+            }
+            TIR::ArrPush(_, arr, new_val, type_code) => {
+                let arr = self.get_ssa_val(&curr_func_name, arr);
+                let arr = self.value_to_i64(builder, arr)?;
+                let new_val = self.get_ssa_val(&curr_func_name, new_val);
+                let new_val = self.value_to_i64(builder, new_val)?;
+                let type_code = self.ctx.i64_type().const_int(type_code as u64, true);
+                self.call_runtime(
+                    builder,
+                    "toy_arr_push",
+                    &[arr.into(), new_val.into(), type_code.into()],
+                )?;
+                None
+            }
+            TIR::AllocMap(id, key_type_code, value_type_code) => {
+                let i64_type = self.ctx.i64_type();
+                let map = self
+                    .call_runtime(
+                        builder,
+                        "toy_malloc_map",
+                        &[
+                            i64_type.const_int(key_type_code as u64, true).into(),
+                            i64_type.const_int(value_type_code as u64, true).into(),
+                        ],
+                    )?
+                    .unwrap();
+                Some((
+                    map,
+                    SSAValue {
+                        val: id,
+                        ty: Some(TirType::Ptr),
+                    },
+                ))
+            }
+            TIR::MapLoad(id, map, key, value_type) => {
+                let map = self.get_ssa_val(&curr_func_name, map);
+                let map = self.value_to_i64(builder, map)?;
+                let key = self.get_ssa_val(&curr_func_name, key);
+                let key = self.value_to_i64(builder, key)?;
+                let value = self
+                    .call_runtime(builder, "toy_map_get", &[map.into(), key.into()])?
+                    .unwrap();
+                let value = if value_type == TirType::F64 {
+                    builder.build_bit_cast(value, self.ctx.f64_type(), "i64_to_double_bitcast")?
+                } else {
+                    value
+                };
+                Some((
+                    value,
+                    SSAValue {
+                        val: id,
+                        ty: Some(value_type),
+                    },
+                ))
+            }
+            TIR::MapStore(_, map, key, new_val) => {
+                let map = self.get_ssa_val(&curr_func_name, map);
+                let map = self.value_to_i64(builder, map)?;
+                let key = self.get_ssa_val(&curr_func_name, key);
+                let key = self.value_to_i64(builder, key)?;
+                let new_val = self.get_ssa_val(&curr_func_name, new_val);
+                let new_val = self.value_to_i64(builder, new_val)?;
+                self.call_runtime(
+                    builder,
+                    "toy_map_set",
+                    &[map.into(), key.into(), new_val.into()],
+                )?;
+                None
+            }
+            TIR::FuncAddr(id, name, callback) => {
+                let trampoline = self.callback_trampoline(&name, &callback)?;
+                let addr = builder.build_ptr_to_int(
+                    trampoline.as_global_value().as_pointer_value(),
+                    self.ctx.i64_type(),
+                    "callback_addr",
+                )?;
+                Some((
+                    addr.into(),
+                    SSAValue {
+                        val: id,
+                        ty: Some(TirType::I64),
+                    },
+                ))
+            }
         };
         if let Some((llvm_ir, val)) = res {
+            if let Some(debug_info) = self.debug_info.as_mut() {
+                debug_info.queue_binding(&curr_func_name, val.val, llvm_ir);
+            }
             self.tir_to_val.insert((curr_func_name, val), llvm_ir);
         }
         return Ok(());
@@ -871,9 +1143,29 @@ impl<'a> LlvmGenerator<'a> {
         name: String,
     ) -> Result<(), ToyError> {
         builder.position_at_end(llvm_block);
+        self.frame_line = 0;
         for ins in tir_block.ins {
+            if let Some(debug_info) = self.debug_info.as_mut() {
+                if !matches!(ins, TIR::Phi(..)) {
+                    debug_info.flush_bindings(
+                        self.ctx,
+                        builder,
+                        &self.source_map,
+                        self.curr_func.unwrap(),
+                    );
+                }
+                debug_info.set_location(self.ctx, builder, &self.source_map, &name, ins.get_id());
+            }
+            if !matches!(ins, TIR::Phi(..)) {
+                self.update_frame_line(builder, &name, ins.get_id())?;
+            }
+            if matches!(ins, TIR::Ret(..)) {
+                self.pop_frame(builder)?;
+            }
             self.compile_instruction(ins, builder, name.clone())?;
         }
+        self.block_exits
+            .insert(tir_block.id, builder.get_insert_block().unwrap());
 
         return Ok(());
     }
@@ -912,9 +1204,12 @@ impl<'a> LlvmGenerator<'a> {
             TirType::Void => self.ctx.void_type().fn_type(llvm_params.as_slice(), false),
         };
 
+        // Keep external linkage for user_main and std:: functions
+        let is_external = self.external_functions
+            || &*func.name == "user_main"
+            || func.name.starts_with("std::");
         let llvm_func = if let Some(f) = self.main_module.get_function(&func.name) {
-            // Keep external linkage for user_main and std:: functions
-            if &*func.name != "user_main" && !func.name.starts_with("std::") {
+            if !is_external {
                 f.set_linkage(Linkage::Internal);
             }
             f
@@ -922,16 +1217,34 @@ impl<'a> LlvmGenerator<'a> {
             self.main_module.add_function(
                 &*func.name.clone(),
                 fn_type,
-                Some(
-                    if &*func.name == "user_main" || func.name.starts_with("std::") {
-                        Linkage::External
-                    } else {
-                        Linkage::Internal
-                    },
-                ),
+                Some(if is_external {
+                    Linkage::External
+                } else {
+                    Linkage::Internal
+                }),
             )
         };
         self.curr_func = Some(llvm_func);
+        //a runtime error in JIT code unwinds through it back to toy_jit_run
+        if !self.emit_object {
+            let uwtable = self
+                .ctx
+                .create_enum_attribute(Attribute::get_named_enum_kind_id("uwtable"), 2);
+            llvm_func.add_attribute(AttributeLoc::Function, uwtable);
+        }
+        self.frame = None;
+        if let Some(debug_info) = self.debug_info.as_mut() {
+            debug_info.begin_function(
+                self.ctx,
+                &builder,
+                &self.source_map,
+                llvm_func,
+                &func.name,
+                &func.params,
+                &func.ret_type,
+                LlvmGenerator::opt_level() != OptimizationLevel::None,
+            );
+        }
         //</Boiler plate to setup function>
         for (n, p) in func.params.iter().enumerate() {
             let p_val = llvm_func.get_nth_param(n as u32).unwrap(); //is probably safe, maybe will cause bugs :D
@@ -950,6 +1263,20 @@ impl<'a> LlvmGenerator<'a> {
                 .append_basic_block(llvm_func, &format!("block_{}", b.id));
             self.block_id_to_block.insert(b.id, llvm_block);
         }
+        if let Some(entry) = llvm_func.get_first_basic_block() {
+            builder.position_at_end(entry);
+            self.push_frame(&builder, &func.name)?;
+            if let Some(debug_info) = self.debug_info.as_mut() {
+                debug_info.bind_params(
+                    self.ctx,
+                    &builder,
+                    &self.source_map,
+                    llvm_func,
+                    &func.name,
+                    &func.params,
+                );
+            }
+        }
 
         for b in &func.body {
             let llvm_block = self.block_id_to_block.get(&b.id).unwrap();
@@ -958,13 +1285,301 @@ impl<'a> LlvmGenerator<'a> {
 
         let fixups: Vec<_> = self.phi_fixups.drain(..).collect();
         for (phi, func_name, block_id, ssa_val) in fixups {
-            let block = self.block_id_to_block.get(&block_id).unwrap();
+            let block = self.block_exits.get(&block_id).unwrap();
             let val = self.get_ssa_val(&func_name, ssa_val);
             phi.add_incoming(&[(&val, *block)]);
         }
 
         return Ok(());
     }
+    ///Builds the function's shadow stack frame at the top of the entry block and links it in,
+    ///the runtime walks these frames to print a Toy backtrace when something goes wrong. Skipped
+    ///with `--no-backtrace`
+    fn push_frame(&mut self, builder: &Builder<'a>, func_name: &str) -> Result<(), ToyError> {
+        if !LlvmGenerator::backtraces() {
+            return Ok(());
+        }
+        let i64_type = self.ctx.i64_type();
+        let frame = builder.build_alloca(i64_type.array_type(4), "toy_frame")?;
+        let display_name = builder.build_global_string_ptr(
+            &self.source_map.display_name(func_name),
+            &format!("frame_name_{}", func_name),
+        )?;
+        let file = match self.frame_file {
+            Some(f) => f,
+            None => {
+                let global = builder
+                    .build_global_string_ptr(&self.source_map.file_path, "frame_file")?;
+                let f: BasicValueEnum = global.as_pointer_value().into();
+                self.frame_file = Some(f);
+                f
+            }
+        };
+        let line = self.source_map.func_line(func_name);
+        let fields: [BasicValueEnum; 3] = [
+            display_name.as_pointer_value().into(),
+            file,
+            i64_type.const_int(line as u64, false).into(),
+        ];
+        for (i, val) in fields.into_iter().enumerate() {
+            let field_ptr = self.frame_field(builder, frame, i as u64 + 1)?;
+            builder.build_store(field_ptr, val)?;
+        }
+        let frame_i64 = builder.build_ptr_to_int(frame, i64_type, "frame_ptr_to_i64")?;
+        let push = self.get_runtime_function("toy_frame_push");
+        builder.build_call(push, &[frame_i64.into()], "toy_frame_push")?;
+        self.frame = Some(frame);
+        self.frame_line = line;
+        return Ok(());
+    }
+    fn frame_field(
+        &self,
+        builder: &Builder<'a>,
+        frame: PointerValue<'a>,
+        idx: u64,
+    ) -> Result<PointerValue<'a>, ToyError> {
+        let i64_type = self.ctx.i64_type();
+        let ptr = unsafe {
+            builder.build_gep(
+                i64_type.array_type(4),
+                frame,
+                &[i64_type.const_zero(), i64_type.const_int(idx, false)],
+                "frame_field",
+            )?
+        };
+        return Ok(ptr);
+    }
+    ///Records the line of the statement an instruction came from in the frame, only when it changes
+    fn update_frame_line(
+        &mut self,
+        builder: &Builder<'a>,
+        func_name: &str,
+        id: ValueId,
+    ) -> Result<(), ToyError> {
+        let (Some(frame), Some((line, _))) =
+            (self.frame, self.source_map.ins_line_col(func_name, id))
+        else {
+            return Ok(());
+        };
+        if line == self.frame_line {
+            return Ok(());
+        }
+        let field_ptr = self.frame_field(builder, frame, 3)?;
+        builder.build_store(field_ptr, self.ctx.i64_type().const_int(line as u64, false))?;
+        self.frame_line = line;
+        return Ok(());
+    }
+    fn pop_frame(&mut self, builder: &Builder<'a>) -> Result<(), ToyError> {
+        if self.frame.is_some() {
+            let pop = self.get_runtime_function("toy_frame_pop");
+            builder.build_call(pop, &[], "toy_frame_pop")?;
+        }
+        return Ok(());
+    }
+    ///"file:line:col" of the statement the instruction came from as an i64, 0 when it is unknown.
+    ///The runtime adds it to the error of a bad array index
+    fn access_site(
+        &self,
+        builder: &Builder<'a>,
+        func_name: &str,
+        id: ValueId,
+    ) -> Result<IntValue<'a>, ToyError> {
+        let i64_type = self.ctx.i64_type();
+        let Some((line, col)) = self.source_map.ins_line_col(func_name, id) else {
+            return Ok(i64_type.const_zero());
+        };
+        let site = format!("{}:{}:{}", self.source_map.file_path, line, col);
+        let global = builder.build_global_string_ptr(&site, "arr_site")?;
+        return self.value_to_i64(builder, global.as_pointer_value().into());
+    }
+    ///Loads arr[idx] as an i64. The bounds check is a compare against the len field, only the failing
+    ///path calls into the runtime, which reports the bad index and where it happened, then exits
+    fn compile_arr_load(
+        &mut self,
+        builder: &Builder<'a>,
+        func_name: &str,
+        id: ValueId,
+        arr: SSAValue,
+        idx: SSAValue,
+    ) -> Result<IntValue<'a>, ToyError> {
+        let i64_type = self.ctx.i64_type();
+        let arr = self.get_ssa_val(func_name, arr);
+        let idx = self.get_ssa_val(func_name, idx).into_int_value();
+        if LlvmGenerator::sanitize_memory() {
+            let arr = self.value_to_i64(builder, arr)?;
+            let site = self.access_site(builder, func_name, id)?;
+            let elem = self
+                .call_runtime(
+                    builder,
+                    "toy_read_from_arr",
+                    &[arr.into(), idx.into(), site.into()],
+                )?
+                .unwrap();
+            return Ok(elem.into_int_value());
+        }
+        let arr_ptr = self.value_to_ptr(builder, arr)?;
+        if !LlvmGenerator::unchecked_bounds() {
+            let len = builder
+                .build_load(i64_type, arr_ptr, "arr_len")?
+                .into_int_value();
+            //unsigned, so negative indexes fail the same check
+            let in_bounds = builder.build_int_compare(IntPredicate::ULT, idx, len, "in_bounds")?;
+            let ok_block = self.insert_block_after_current(builder, "arr_load_ok");
+            let oob_block = self.insert_block_after_current(builder, "arr_load_oob");
+            builder.build_conditional_branch(in_bounds, ok_block, oob_block)?;
+            builder.position_at_end(oob_block);
+            let arr = self.value_to_i64(builder, arr_ptr.into())?;
+            let site = self.access_site(builder, func_name, id)?;
+            self.call_runtime(
+                builder,
+                "toy_read_from_arr",
+                &[arr.into(), idx.into(), site.into()],
+            )?;
+            builder.build_unreachable()?;
+            builder.position_at_end(ok_block);
+        }
+        let elem_ptr = self.arr_elem_ptr(builder, arr_ptr, idx)?;
+        let elem = builder.build_load(i64_type, elem_ptr, "elem")?;
+        return Ok(elem.into_int_value());
+    }
+    ///Stores arr[idx] = val inline when idx is in bounds, anything else (appending, bad indexes) goes to toy_write_to_arr
+    fn compile_arr_store(
+        &mut self,
+        builder: &Builder<'a>,
+        func_name: &str,
+        id: ValueId,
+        arr: SSAValue,
+        idx: SSAValue,
+        new_val: SSAValue,
+        type_code: i64,
+    ) -> Result<(), ToyError> {
+        let i64_type = self.ctx.i64_type();
+        let arr = self.get_ssa_val(func_name, arr);
+        let idx = self.get_ssa_val(func_name, idx).into_int_value();
+        let new_val = self.get_ssa_val(func_name, new_val);
+        let new_val = self.value_to_i64(builder, new_val)?;
+        let arr_i64 = self.value_to_i64(builder, arr)?;
+        let site = self.access_site(builder, func_name, id)?;
+        let write_params: [BasicMetadataValueEnum; 5] = [
+            arr_i64.into(),
+            new_val.into(),
+            idx.into(),
+            i64_type.const_int(type_code as u64, true).into(),
+            site.into(),
+        ];
+        //a str[] may own its strings, the runtime copies the string in and frees the one it replaces
+        if LlvmGenerator::sanitize_memory() || type_code == 0 {
+            self.call_runtime(builder, "toy_write_to_arr", &write_params)?;
+            return Ok(());
+        }
+        let arr_ptr = self.value_to_ptr(builder, arr)?;
+        let len = builder
+            .build_load(i64_type, arr_ptr, "arr_len")?
+            .into_int_value();
+        let in_bounds = builder.build_int_compare(IntPredicate::ULT, idx, len, "in_bounds")?;
+        let done_block = self.insert_block_after_current(builder, "arr_store_done");
+        let slow_block = self.insert_block_after_current(builder, "arr_store_slow");
+        let fast_block = self.insert_block_after_current(builder, "arr_store_fast");
+        builder.build_conditional_branch(in_bounds, fast_block, slow_block)?;
+
+        builder.position_at_end(fast_block);
+        let elem_ptr = self.arr_elem_ptr(builder, arr_ptr, idx)?;
+        builder.build_store(elem_ptr, new_val)?;
+        builder.build_unconditional_branch(done_block)?;
+
+        builder.position_at_end(slow_block);
+        self.call_runtime(builder, "toy_write_to_arr", &write_params)?;
+        builder.build_unconditional_branch(done_block)?;
+
+        builder.position_at_end(done_block);
+        return Ok(());
+    }
+    ///Pointer to arr[idx], ToyArr is `{len, cap, data}` so the data pointer is the third i64 sized field
+    fn arr_elem_ptr(
+        &self,
+        builder: &Builder<'a>,
+        arr_ptr: PointerValue<'a>,
+        idx: IntValue<'a>,
+    ) -> Result<PointerValue<'a>, ToyError> {
+        let i64_type = self.ctx.i64_type();
+        let ptr_type = self.ctx.ptr_type(AddressSpace::default());
+        let data_field = unsafe {
+            builder.build_gep(
+                i64_type,
+                arr_ptr,
+                &[i64_type.const_int(2, false)],
+                "arr_data_field",
+            )?
+        };
+        let data = builder
+            .build_load(ptr_type, data_field, "arr_data")?
+            .into_pointer_value();
+        let elem_ptr = unsafe { builder.build_gep(i64_type, data, &[idx], "elem_ptr")? };
+        return Ok(elem_ptr);
+    }
+    ///Appends a block right after the one the builder is in, used when an instruction needs its own control flow
+    fn insert_block_after_current(&self, builder: &Builder<'a>, name: &str) -> BasicBlock<'a> {
+        let curr = builder.get_insert_block().unwrap();
+        return self.ctx.insert_basic_block_after(curr, name);
+    }
+    ///Everything the runtime gets is an i64, floats are passed by their bits
+    fn value_to_i64(
+        &self,
+        builder: &Builder<'a>,
+        val: BasicValueEnum<'a>,
+    ) -> Result<IntValue<'a>, ToyError> {
+        let i64_type = self.ctx.i64_type();
+        return Ok(match val {
+            BasicValueEnum::FloatValue(f) => builder
+                .build_bit_cast(f, i64_type, "double_to_i64_bitcast")?
+                .into_int_value(),
+            BasicValueEnum::PointerValue(p) => builder.build_ptr_to_int(p, i64_type, "ptr_to_i64")?,
+            BasicValueEnum::IntValue(i) if i.get_type().get_bit_width() != 64 => {
+                builder.build_int_z_extend(i, i64_type, "int_to_i64")?
+            }
+            BasicValueEnum::IntValue(i) => i,
+            _ => unreachable!(),
+        });
+    }
+    fn value_to_ptr(
+        &self,
+        builder: &Builder<'a>,
+        val: BasicValueEnum<'a>,
+    ) -> Result<PointerValue<'a>, ToyError> {
+        return Ok(match val {
+            BasicValueEnum::PointerValue(p) => p,
+            BasicValueEnum::IntValue(i) => builder.build_int_to_ptr(
+                i,
+                self.ctx.ptr_type(AddressSpace::default()),
+                "i64_to_ptr",
+            )?,
+            _ => unreachable!(),
+        });
+    }
+    ///Calls a function declared in declare_builtin_functions, returns None for void functions
+    fn call_runtime(
+        &self,
+        builder: &Builder<'a>,
+        name: &str,
+        args: &[BasicMetadataValueEnum<'a>],
+    ) -> Result<Option<BasicValueEnum<'a>>, ToyError> {
+        let func = self.get_runtime_function(name);
+        let call = builder.build_call(func, args, name)?;
+        return Ok(match call.try_as_basic_value() {
+            ValueKind::Basic(v) => Some(v),
+            _ => None,
+        });
+    }
+    fn get_runtime_function(&self, name: &str) -> FunctionValue<'a> {
+        return match self.main_module.get_function(name) {
+            Some(f) => f,
+            None => self.main_module.add_function(
+                name,
+                self.func_map[name],
+                Some(Linkage::External),
+            ),
+        };
+    }
     fn _tir_to_llvm_type(&self, t: TirType) -> BasicTypeEnum<'a> {
         return match t {
             TirType::I64 => self.ctx.i64_type().into(),
@@ -1078,12 +1693,23 @@ impl<'a> LlvmGenerator<'a> {
         );
         self.declare_individual_function(
             "toy_write_to_arr",
-            vec![TirType::I64, TirType::I64, TirType::I64, TirType::I64],
+            vec![
+                TirType::I64,
+                TirType::I64,
+                TirType::I64,
+                TirType::I64,
+                TirType::I64,
+            ],
+            TirType::Void,
+        );
+        self.declare_individual_function(
+            "toy_arr_push",
+            vec![TirType::I64, TirType::I64, TirType::I64],
             TirType::Void,
         );
         self.declare_individual_function(
             "toy_read_from_arr",
-            vec![TirType::I64, TirType::I64],
+            vec![TirType::I64, TirType::I64, TirType::I64],
             TirType::I64,
         );
         self.declare_individual_function("toy_arrlen", vec![TirType::I64], TirType::I64);
@@ -1098,6 +1724,75 @@ impl<'a> LlvmGenerator<'a> {
             TirType::Void,
         );
         self.declare_individual_function("toy_free_arr", vec![TirType::I64], TirType::Void);
+        self.declare_individual_function(
+            "toy_malloc_map",
+            vec![TirType::I64, TirType::I64],
+            TirType::I64,
+        );
+        self.declare_individual_function(
+            "toy_map_get",
+            vec![TirType::I64, TirType::I64],
+            TirType::I64,
+        );
+        self.declare_individual_function(
+            "toy_map_set",
+            vec![TirType::I64, TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function(
+            "toy_map_has",
+            vec![TirType::I64, TirType::I64],
+            TirType::I64,
+        );
+        self.declare_individual_function(
+            "toy_map_remove",
+            vec![TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function("toy_map_len", vec![TirType::I64], TirType::I64);
+        self.declare_individual_function("toy_map_keys", vec![TirType::I64], TirType::I64);
+        self.declare_individual_function("toy_map_values", vec![TirType::I64], TirType::I64);
+        self.declare_individual_function("toy_free_map", vec![TirType::I64], TirType::Void);
+        self.declare_individual_function(
+            "toy_json_encode",
+            vec![TirType::I64, TirType::I64],
+            TirType::I64,
+        );
+        self.declare_individual_function(
+            "toy_sanitize_site",
+            vec![TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function(
+            "toy_test_assert",
+            vec![TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function(
+            "toy_test_assert_eq",
+            vec![TirType::I64, TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function("toy_test_selected", vec![TirType::I64], TirType::I64);
+        self.declare_individual_function("toy_frame_push", vec![TirType::I64], TirType::Void);
+        self.declare_individual_function("toy_frame_pop", vec![], TirType::Void);
+        self.declare_individual_function(
+            "toy_profile_site",
+            vec![TirType::I64, TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function("toy_profile_site_end", vec![], TirType::Void);
+        self.declare_individual_function("toy_sanitize_site_push", vec![], TirType::Void);
+        self.declare_individual_function("toy_sanitize_site_pop", vec![], TirType::Void);
+        //floats are stored as their bits so the value is declared as an int
+        self.declare_individual_function(
+            "toy_repl_store",
+            vec![TirType::I64, TirType::I64],
+            TirType::Void,
+        );
+        self.declare_individual_function("toy_repl_load", vec![TirType::I64], TirType::I64);
+        self.declare_individual_function("toy_repl_load_ptr", vec![TirType::I64], TirType::I64);
+        self.declare_individual_function("toy_repl_load_float", vec![TirType::I64], TirType::F64);
         return Ok(());
     }
     fn generate_internal(&mut self, funcs: Vec<Function>) -> Result<(), ToyError> {
@@ -1109,26 +1804,26 @@ impl<'a> LlvmGenerator<'a> {
     }
     pub fn generate(&mut self, funcs: Vec<Function>, prgm_name: String) -> Result<(), ToyError> {
         self.generate_internal(funcs)?;
+        for (toy_name, symbol, signature) in &self.c_exports {
+            self.export_wrapper(toy_name, symbol, signature)?;
+        }
 
         //llvm shit
-        let args: Vec<String> = env::args().collect();
-        Target::initialize_x86(&InitializationConfig::default());
-        //opts can conflict with CTLA
-        let opt_level =
-            if args.contains(&"--repl".to_string()) || args.contains(&"--no-op".to_string()) {
-                OptimizationLevel::None
-            } else {
-                OptimizationLevel::Aggressive
-            };
-        let triple = TargetTriple::create(if cfg!(target_os = "windows") {
-            "x86_64-pc-windows-gnu"
-        } else {
-            "x86_64-unknown-linux-gnu"
-        });
-        let options = TargetMachineOptions::new()
-            .set_abi("gnu")
+        let compile_target = CompileTarget::current();
+        match compile_target.arch {
+            Arch::X86_64 => Target::initialize_x86(&InitializationConfig::default()),
+            Arch::AArch64 => Target::initialize_aarch64(&InitializationConfig::default()),
+            Arch::Wasm32 => Target::initialize_webassembly(&InitializationConfig::default()),
+        }
+        let opt_level = LlvmGenerator::opt_level();
+        let triple = TargetTriple::create(compile_target.triple);
+        let mut options = TargetMachineOptions::new()
             //.set_cpu("x64")
-            .set_level(opt_level);
+            .set_level(opt_level)
+            .set_reloc_mode(LlvmGenerator::reloc_mode());
+        if compile_target.arch == Arch::X86_64 {
+            options = options.set_abi("gnu");
+        }
         let target = Target::from_triple(&triple).unwrap();
         let target_machine = target
             .create_target_machine_from_options(&triple, options)
@@ -1136,13 +1831,18 @@ impl<'a> LlvmGenerator<'a> {
         self.main_module.set_triple(&triple);
         self.main_module
             .set_data_layout(&target_machine.get_target_data().get_data_layout());
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.finalize(self.ctx, &self.main_module);
+        }
         Driver::verify_module(&self.main_module)?;
-        let obj_file = format!("{}.o", prgm_name);
-        let obj_path: &Path = Path::new(&obj_file);
-        target_machine.write_to_file(&self.main_module, FileType::Object, obj_path)?;
-        let ll_file = format!("{}.ll", prgm_name);
-        self.main_module.print_to_file(Path::new(&ll_file))?;
-        let args: Vec<String> = env::args().collect();
+        if self.emit_object {
+            let obj_file = format!("{}.o", prgm_name);
+            let obj_path: &Path = Path::new(&obj_file);
+            target_machine.write_to_file(&self.main_module, FileType::Object, obj_path)?;
+            let ll_file = format!("{}.ll", prgm_name);
+            self.main_module.print_to_file(Path::new(&ll_file))?;
+        }
+        let args: Vec<String> = Driver::args();
         if args.contains(&"--debug-llvm".to_string()) || args.contains(&"--debug-ALL".to_string()) {
             self.main_module.print_to_file("./debug/LLVM.ll")?;
         }
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::{codegen::tir::ir::ValueId, driver::Driver, errors::Span};
+
+///Maps TIR instructions and functions back to the .toy source they came from
+pub struct SourceMap {
+    pub file_path: String,
+    ///byte offset of the start of every line in the source file, used to turn spans into line/col pairs
+    line_starts: Vec<i64>,
+    ///(func_name, instruction id) -> span, from the TirBuilder
+    ins_spans: HashMap<(String, ValueId), Span>,
+    ///func_name -> span of the declaration, from the TirBuilder
+    func_spans: HashMap<String, Span>,
+    ///func_name -> demangled name, from the TirBuilder
+    display_names: HashMap<String, String>,
+}
+
+impl SourceMap {
+    pub fn new(
+        ins_spans: HashMap<(String, ValueId), Span>,
+        func_spans: HashMap<String, Span>,
+        display_names: HashMap<String, String>,
+    ) -> SourceMap {
+        let file_path = Driver::get_current_file_path().unwrap_or("<unknown>".to_string());
+        let mut line_starts = vec![0];
+        if let Ok(content) = fs::read_to_string(&file_path) {
+            for (idx, b) in content.bytes().enumerate() {
+                if b == b'\n' {
+                    line_starts.push(idx as i64 + 1);
+                }
+            }
+        }
+        return SourceMap {
+            file_path,
+            line_starts,
+            ins_spans,
+            func_spans,
+            display_names,
+        };
+    }
+
+    ///1 indexed (line, col) of the start of the span, (0, 0) if the span is not from this file
+    pub fn line_col(&self, span: &Span) -> (u32, u32) {
+        if span.start_offset_bytes < 0 || span.file_path != self.file_path {
+            return (0, 0);
+        }
+        let line_idx = match self.line_starts.binary_search(&span.start_offset_bytes) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let col = span.start_offset_bytes - self.line_starts[line_idx] + 1;
+        return (line_idx as u32 + 1, col as u32);
+    }
+
+    ///(line, col) of the statement that produced the instruction, None if it is unknown
+    pub fn ins_line_col(&self, func_name: &str, id: ValueId) -> Option<(u32, u32)> {
+        let span = self.ins_spans.get(&(func_name.to_string(), id))?;
+        let (line, col) = self.line_col(span);
+        if line == 0 {
+            return None;
+        }
+        return Some((line, col));
+    }
+
+    ///line the function was declared on, 0 for compiler generated functions
+    pub fn func_line(&self, func_name: &str) -> u32 {
+        return match self.func_spans.get(func_name) {
+            Some(span) => self.line_col(span).0,
+            None => 0,
+        };
+    }
+
+    pub fn display_name(&self, func_name: &str) -> String {
+        return match self.display_names.get(func_name) {
+            Some(name) => name.clone(),
+            None => Driver::demangle_name(func_name, &[]),
+        };
+    }
+}
@@ -1,4 +1,5 @@
 use inkwell::context::Context;
+use std::io::{Read, Write};
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Duration;
@@ -24,6 +25,10 @@ fn capture_program_output(program: String) -> String {
 
 macro_rules! compile_code_aot {
     ($o:ident, $i:expr, $test_name:expr) => {
+        compile_code_aot!($o, $i, $test_name, &[]);
+    };
+    //compiles with the given compiler flags, like `&["--sanitize=memory"]`
+    ($o:ident, $i:expr, $test_name:expr, $args:expr) => {
         let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let output_name = format!("output_{}", $test_name);
         let output_path = project_root.join("temp").join(&output_name);
@@ -37,7 +42,7 @@ macro_rules! compile_code_aot {
         let ctx = Context::create();
         let mut d =
             crate::driver::Driver::new_with_name(source_path, format!("temp/{}", output_name));
-        d.start(&ctx).unwrap();
+        crate::driver::Driver::with_args($args, || d.start(&ctx)).unwrap();
 
         thread::sleep(Duration::from_millis(200));
 
@@ -333,4 +338,953 @@ fn test_llvm_fs(){
     compile_code_aot!(output, r#"import std.fs; let d = fs.read_dir("."); println(d.to_str());"#, "fs_import");
     assert!(!output.contains("error"));//should work??
     assert!(output.contains("Cargo.toml"), "output contained {}", output);
-}
\ No newline at end of file
+}
+#[test]
+fn test_llvm_runtime_error_backtrace() {
+    compile_code_aot!(
+        output,
+        "fn parse(s: str): int {\n    return int(s);\n}\nprintln(parse(\"abc\"));\n",
+        "runtime_error_backtrace"
+    );
+    assert!(output.contains("Toy backtrace"));
+    assert!(output.contains("0: parse(str) at"));
+    assert!(output.contains("output_runtime_error_backtrace.toy:2"));
+    assert!(output.contains("1: main at"));
+    assert!(output.contains("output_runtime_error_backtrace.toy:4"));
+}
+
+#[test]
+fn test_llvm_runtime_error_no_backtrace() {
+    compile_code_aot!(
+        output,
+        "fn parse(s: str): int {\n    return int(s);\n}\nprintln(parse(\"abc\"));\n",
+        "runtime_error_no_backtrace",
+        &["--no-backtrace"]
+    );
+    assert!(output.contains("ERROR"), "output contained {}", output);
+    assert!(!output.contains("Toy backtrace"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_arr_bounds() {
+    compile_code_aot!(
+        output,
+        "let arr: int[] = [1, 2, 3];\narr[3] = 4;\npush(arr, 5);\nprintln(arr[4]);\nprintln(arr[10]);\n",
+        "arr_bounds",
+        &["--no-backtrace"]
+    );
+    assert!(output.contains("5"));
+    //the location comes with the error itself, not from the backtrace
+    assert!(
+        output.contains("Index 10 is out of bounds for an array of length 5 at "),
+        "output contained {}",
+        output
+    );
+    assert!(output.contains("output_arr_bounds.toy:5:"), "output contained {}", output);
+    assert!(!output.contains("Toy backtrace"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_arr_write_past_end() {
+    compile_code_aot!(
+        output,
+        "let arr: int[] = [1, 2, 3];\narr[5] = 4;\n",
+        "arr_write_past_end",
+        &["--no-backtrace"]
+    );
+    assert!(output.contains("Index 5 is out of bounds for an array of length 3"));
+    assert!(
+        output.contains("output_arr_write_past_end.toy:2:"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_std_arr() {
+    compile_code_aot!(
+        output,
+        r#"import std.arr;
+let nums: int[] = [5, 3, 9];
+arr.push(nums, 1);
+arr.insert(nums, 0, 7);
+println(arr.remove(nums, 1));
+arr.sort(nums);
+println(nums);
+println(arr.pop(nums));
+arr.reverse(nums);
+println(arr.slice(nums, 0, 2));
+println(arr.index_of(nums, 3));
+let names: str[] = ["pear", "apple"];
+arr.sort(names);
+println(arr.contains(names, "pear"));
+println(arr.concat(names, ["fig"]));
+"#,
+        "std_arr"
+    );
+    assert!(output.contains("5\n"), "output contained {}", output);
+    assert!(output.contains("[1, 3, 7, 9]"), "output contained {}", output);
+    assert!(output.contains("9\n"), "output contained {}", output);
+    assert!(output.contains("[7, 3]"), "output contained {}", output);
+    assert!(output.contains("true"), "output contained {}", output);
+    assert!(output.contains("[apple, pear, fig]"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_std_arr_str_ownership() {
+    compile_code_aot!(
+        output,
+        r#"import std.arr;
+let names: str[] = ["pear", "apple", "fig"];
+let part = arr.slice(names, 0, 2);
+part[0] = "plum";
+let both = arr.concat(part, names);
+println(arr.pop(part));
+println(arr.remove(both, 0));
+println(arr.pop(names));
+arr.insert(both, 1, "kiwi");
+println(part);
+println(both);
+println(names);
+"#,
+        "std_arr_str_ownership",
+        &["--sanitize=memory"]
+    );
+    assert!(output.contains("apple\nplum\nfig\n"), "output contained {}", output);
+    assert!(output.contains("[plum]\n"), "output contained {}", output);
+    assert!(output.contains("[apple, kiwi, pear, apple, fig]"), "output contained {}", output);
+    assert!(output.contains("[pear, apple]"), "output contained {}", output);
+    assert!(!output.contains("FAIL_TST"), "output contained {}", output);
+    assert!(!output.contains("ERROR"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_sanitize_site_is_restored_after_a_call() {
+    //the leaked string is made on line 6 after helper ran, it must not be blamed on helper's lines
+    compile_code_aot!(
+        output,
+        r#"extern fn toy_strlen(s: released c_char_ptr): c_int64_t;
+fn helper(): int {
+    let t = "a" + "b";
+    return len(t);
+}
+let n = toy_strlen(str(helper()));
+println(n);
+"#,
+        "sanitize_site_after_call",
+        &["--sanitize=memory"]
+    );
+    assert!(output.contains("1\n"), "output contained {}", output);
+    assert!(
+        output.contains("output_sanitize_site_after_call.toy:6 were never freed"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_runtime_str_arrays_are_freed() {
+    compile_code_aot!(
+        output,
+        r#"import std.str;
+import std.json;
+import std.fs;
+fn second(path: str): str {
+    let parts = str.split(path, "/");
+    return parts[2];
+}
+let ages: map<str, int> = {"bob": 31, "amy": 27};
+let names: map<int, str> = {};
+names[1] = "one";
+println(keys(ages));
+println(values(names));
+println(json.keys(json.parse("{\"x\": 1}")));
+println(second("/api/users/42"));
+let dir = fs.read_dir(".");
+println(len(dir.files) > 0);
+"#,
+        "runtime_str_arrays_are_freed",
+        &["--sanitize=memory"]
+    );
+    assert!(output.contains("[bob, amy]\n[one]\n[x]\nusers\ntrue\n"), "output contained {}", output);
+    assert!(!output.contains("FAIL_TST"), "output contained {}", output);
+    assert!(!output.contains("ERROR"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_std_str() {
+    compile_code_aot!(
+        output,
+        r#"import std.str;
+let path = " /api/users/42 ";
+let parts = str.split(str.trim(path), "/");
+println(parts[2]);
+println(str.join(parts, "|"));
+println(str.starts_with(str.trim(path), "/api"));
+println(str.replace("a-b-c", "-", "::"));
+println(str.to_upper(str.substring("hello", 1, 4)));
+println(str.find("hello", "z"));
+println(str.format("{} is {}, {{{0}}}", ["bob", str(7)]));
+"#,
+        "std_str"
+    );
+    assert!(output.contains("users\n"), "output contained {}", output);
+    assert!(output.contains("|api|users|42"), "output contained {}", output);
+    assert!(output.contains("true"), "output contained {}", output);
+    assert!(output.contains("a::b::c"), "output contained {}", output);
+    assert!(output.contains("ELL"), "output contained {}", output);
+    assert!(output.contains("-1"), "output contained {}", output);
+    assert!(output.contains("bob is 7, {bob}"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_map() {
+    compile_code_aot!(
+        output,
+        r#"let ages: map<str, int> = {"bob": 31, "amy": 27};
+ages["cat"] = 4;
+ages["bob"] = 32;
+remove(ages, "amy");
+println(ages["bob"]);
+println(len(ages));
+println(has(ages, "amy"));
+println(keys(ages));
+let names: map<int, str> = {};
+names[7] = "seven";
+println(names[7] + "!");
+println(ages);
+println(ages["zed"]);
+"#,
+        "map"
+    );
+    assert!(output.contains("32\n"), "output contained {}", output);
+    assert!(output.contains("2\n"), "output contained {}", output);
+    assert!(output.contains("false"), "output contained {}", output);
+    assert!(output.contains("[bob, cat]"), "output contained {}", output);
+    assert!(output.contains("seven!"), "output contained {}", output);
+    assert!(output.contains("{bob: 32, cat: 4}"), "output contained {}", output);
+    assert!(
+        output.contains("Key zed is not in the map"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_std_json() {
+    compile_code_aot!(
+        output,
+        r#"import std.json;
+struct Point{
+    x: float,
+    y: float
+}
+struct User{
+    name: str,
+    age: int,
+    admin: bool,
+    home: Point,
+    tags: str[]
+}
+let u = User{name: "amy", age: 31, admin: false, home: Point{x: 1.5, y: 0.0}, tags: ["a", "b"]};
+println(to_json(u));
+let v = json.parse("{\"items\": [1, 2, 3], \"name\": \"box\"}");
+println(json.size(json.get(v, "items")));
+println(json.as_int(json.at(json.get(v, "items"), 2)));
+println(json.as_str(json.get(v, "name")));
+println(json.kind(json.get(v, "missing")));
+let out = json.set(json.object(), "ok", json.from(true));
+out = json.set(out, "list", json.append(json.array(), json.from("x")));
+println(json.stringify(out));
+println(json.is_valid("{"));
+let items = json.get(v, "items");
+json.append(items, json.from(4));
+json.release(items);
+println(json.get(v, "items"));
+let bad = json.parse("{");
+println(json.parsed(bad));
+println(json.parse_error());
+"#,
+        "std_json"
+    );
+    assert!(
+        output.contains(
+            r#"{"admin":false,"age":31,"home":{"x":1.5,"y":0.0},"name":"amy","tags":["a","b"]}"#
+        ),
+        "output contained {}",
+        output
+    );
+    assert!(output.contains("3\n"), "output contained {}", output);
+    assert!(output.contains("box\n"), "output contained {}", output);
+    assert!(output.contains("null\n"), "output contained {}", output);
+    assert!(
+        output.contains(r#"{"list":["x"],"ok":true}"#),
+        "output contained {}",
+        output
+    );
+    assert!(output.contains("false\n"), "output contained {}", output);
+    //items is a handle into v, appending to it changes v
+    assert!(output.contains("[1,2,3,4]\n"), "output contained {}", output);
+    assert!(
+        output.contains("EOF while parsing an object"),
+        "output contained {}",
+        output
+    );
+    assert!(!output.contains("ERROR"), "output contained {}", output);
+}
+
+#[test]
+fn test_llvm_std_net_server() {
+    compile_code_aot!(
+        output,
+        r#"import std.net;
+fn user(handle: int){}
+fn post(handle: int){}
+fn files(handle: int){}
+let server = net.listen(18437);
+let req = net.accept(server, 10);
+println(net.timed_out(req));
+let r = net.router();
+net.on(r, "GET", "/users/:id", user);
+net.on(r, "POST", "/users/:id/posts/:post", post);
+net.on(r, "*", "/static/*", files);
+let m = net.match_path(r, "POST", "/users/7/posts/12");
+println(m.route);
+println(net.param(m, "id"));
+println(net.param(m, "post"));
+println(net.match_path(r, "DELETE", "/static/css/site.css").route);
+println(net.matched(net.match_path(r, "POST", "/users/7")));
+"#,
+        "std_net_server"
+    );
+    assert!(output.contains("true\n"), "output contained {}", output);
+    assert!(
+        output.contains("1\n7\n12\n2\nfalse"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_std_net_client() {
+    //the stand-in server is a toy program too, it runs in the background while the client runs
+    let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let server_path = project_root.join("temp").join("output_std_net_stand_in");
+    let server_source = project_root
+        .join("temp")
+        .join("output_std_net_stand_in.toy");
+    let _ = std::fs::remove_file(&server_path);
+    std::fs::write(
+        &server_source,
+        r#"import std.net;
+fn echo(handle: int){
+    let req = net.request_of(handle);
+    let reply = req.method + " " + req.body + " " + net.header(req, "Authorization");
+    net.respond(req, 200, 1, reply + " " + net.route_param(req, "word"));
+}
+let server = net.listen(18438);
+let r = net.router();
+net.on(r, "*", "/echo/:word", echo);
+let served = 0;
+while served < 2 {
+    let req = net.accept(server, 10000);
+    if net.timed_out(req) {
+        break;
+    }
+    net.set_header(req, "X-Reply", "stand-in");
+    if !net.dispatch(r, req) {
+        net.respond(req, 404, 1, "missing");
+    }
+    served += 1;
+}
+"#,
+    )
+    .unwrap();
+    let ctx = Context::create();
+    let mut d = crate::driver::Driver::new_with_name(
+        server_source,
+        "temp/output_std_net_stand_in".to_string(),
+    );
+    d.start(&ctx).unwrap();
+    let mut server = Command::new(&server_path)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to spawn the stand-in server");
+
+    compile_code_aot!(
+        output,
+        r#"import std.net;
+let headers: map<str, str> = {"Authorization": "Bearer abc"};
+let res = net.request("POST", "http://127.0.0.1:18438/echo/hi", headers, "hello", 5000);
+println(res.status);
+println(res.body);
+println(net.response_header(res, "x-reply"));
+let missing = net.get("http://127.0.0.1:18438/nowhere");
+println(missing.status);
+println(net.ok(missing));
+let refused = net.get("http://127.0.0.1:18439/");
+println(refused.status);
+println(net.failed(refused));
+println(net.get_url("http://127.0.0.1:18439/") == "");
+println(net.socket_error() != "");
+"#,
+        "std_net_client"
+    );
+    server.wait().unwrap();
+    assert!(
+        output.contains("200\nPOST hello Bearer abc hi\nstand-in\n404\nfalse\n0\ntrue\ntrue\ntrue"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_std_net_server_request() {
+    //the server answers one real request, the body is not ascii so its byte length differs
+    //from its length in code points
+    let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let server_path = project_root.join("temp").join("output_std_net_request");
+    let server_source = project_root.join("temp").join("output_std_net_request.toy");
+    let _ = std::fs::remove_file(&server_path);
+    std::fs::write(
+        &server_source,
+        r#"import std.net;
+let server = net.listen(18440);
+let req = net.accept(server, 10000);
+println(req.method + " " + req.path);
+let params = net.query_params(req);
+println(params["name"]);
+println(params["lang"]);
+println(net.headers(req)["X-Token"]);
+println(net.header(req, "x-token"));
+net.set_header(req, "X-Reply", "first");
+net.set_header(req, "X-Reply", "ok");
+net.respond(req, 201, 1, "grüße ✓");
+"#,
+    )
+    .unwrap();
+    let ctx = Context::create();
+    let mut d = crate::driver::Driver::new_with_name(
+        server_source,
+        "temp/output_std_net_request".to_string(),
+    );
+    d.start(&ctx).unwrap();
+    let server = Command::new(&server_path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn the server");
+
+    let mut stream = None;
+    for _ in 0..50 {
+        match std::net::TcpStream::connect("127.0.0.1:18440") {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(_) => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+    let mut stream = stream.expect("The server never started listening");
+    stream
+        .write_all(
+            b"GET /greet?name=J%C3%BCrgen+K&lang=de HTTP/1.1\r\nHost: localhost\r\nX-Token: abc\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let output = server.wait_with_output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+
+    assert!(
+        output.contains("GET /greet\nJürgen K\nde\nabc\nabc\n"),
+        "output contained {}",
+        output
+    );
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(
+        head.starts_with("HTTP/1.1 201"),
+        "response was {}",
+        response
+    );
+    assert!(
+        head.contains("X-Reply: ok\r\n"),
+        "response was {}",
+        response
+    );
+    assert!(!head.contains("first"), "response was {}", response);
+    assert!(
+        head.contains(&format!("Content-Length: {}", "grüße ✓".len())),
+        "response was {}",
+        response
+    );
+    assert_eq!(body, "grüße ✓");
+}
+
+#[test]
+fn test_llvm_std_net_sockets() {
+    compile_code_aot!(
+        output,
+        r#"import std.net;
+let l = net.tcp_listen(0);
+println(net.listening(l));
+let client = net.tcp_connect("127.0.0.1", net.tcp_port(l), 1000);
+let conn = net.tcp_accept(l, 1000);
+println(net.connected(conn));
+net.tcp_write(client, "status\nquit\n");
+print(net.tcp_read_line(conn));
+print(net.tcp_read_line(conn));
+net.tcp_write_bytes(client, [255, 0, 104]);
+println(net.tcp_read(conn, 16) == "");
+println(net.socket_error());
+let got = net.tcp_read_bytes(conn, 16);
+println(len(got));
+println(got[0]);
+net.tcp_close(client);
+println(net.tcp_read_line(conn) == "");
+let none = net.tcp_accept(l, 10);
+println(net.connected(none));
+println(net.socket_error());
+let a = net.udp_bind(0);
+let b = net.udp_bind(0);
+net.udp_send(a, "127.0.0.1", net.udp_port(b), "ping");
+let d = net.udp_recv(b, 1500, 1000);
+println(d.data);
+println(d.from_port == net.udp_port(a));
+println(net.received(net.udp_recv(b, 1500, 10)));
+net.udp_send_bytes(a, "127.0.0.1", net.udp_port(b), [1, 2, 250]);
+let bd = net.udp_recv_bytes(b, 1500, 1000);
+println(len(bd.data));
+println(bd.data[2]);
+"#,
+        "std_net_sockets"
+    );
+    //bytes that are not UTF-8 are left for tcp_read_bytes instead of being replaced
+    assert!(
+        output.contains("true\ntrue\nstatus\nquit\ntrue\nthe data is not valid UTF-8, read it as bytes instead\n3\n255\n"),
+        "output contained {}",
+        output
+    );
+    assert!(
+        output.contains("true\nfalse\ntimed out\nping\ntrue\nfalse\n3\n250"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_std_fs() {
+    compile_code_aot!(
+        output,
+        r#"import std.fs;
+let root = fs.join("temp", "fs_test_dir");
+fs.remove_dir(root, true);
+println(fs.mkdir(fs.join(root, "a/b"), false));
+println(fs.mkdir(fs.join(root, "a/b"), true));
+let file = fs.join(root, "a/notes.txt");
+println(fs.write_file(file, "one\n"));
+println(fs.append(file, "two\n"));
+let lines = fs.read_lines(file);
+println(lines[1]);
+let r = fs.open_reader(file);
+print(fs.read_line(r));
+fs.close_reader(r);
+println(fs.file_size(file));
+println(fs.exists(file) && fs.is_file(file) && !fs.is_dir(file));
+println(fs.copy(file, fs.join(root, "a/b/copy.txt")));
+println(fs.rename(fs.join(root, "a/b/copy.txt"), fs.join(root, "a/moved.md")));
+println(fs.file_count(fs.join(root, "a")));
+println(fs.entry_count(fs.join(root, "a")));
+println(fs.folder_count(fs.join(root, "a")));
+println(fs.remove_dir(root, false));
+println(fs.append_file(fs.join(root, "missing/x.txt"), "x"));
+println(fs.write(fs.join(root, "missing/x.txt"), "x"));
+println(fs.read_file(fs.join(root, "missing.txt")) == "");
+println(fs.last_error() != "");
+println(fs.is_dir(root) && fs.last_error() == "");
+println(fs.basename(file) + " " + fs.extension(file) + " " + fs.dirname(file));
+println(fs.remove_dir(root, true));
+println(fs.exists(root));
+"#,
+        "std_fs"
+    );
+    assert!(
+        output.contains(
+            "false\ntrue\n0\ntrue\ntwo\none\n8\ntrue\ntrue\ntrue\n2\n3\n1\nfalse\n1\nfalse\ntrue\ntrue\ntrue\nnotes.txt txt temp/fs_test_dir/a\ntrue\nfalse"
+        ),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_link_native_library() {
+    //a small C library built for the test, linked through a `link` statement
+    let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let temp = project_root.join("temp");
+    let c_source = temp.join("toy_link_test.c");
+    let object = temp.join("toy_link_test.o");
+    let archive = temp.join("libtoy_link_test.a");
+    std::fs::write(
+        &c_source,
+        r#"#include <stdint.h>
+struct point { int64_t x; int64_t y; };
+int32_t add_small(int32_t a, int8_t b) { return a + b; }
+int64_t manhattan(struct point p) { return p.x + p.y; }
+int64_t apply_twice(int32_t (*f)(int32_t), int32_t v) { return f(f(v)); }
+float halve(float x) { return x / 2; }
+"#,
+    )
+    .unwrap();
+    let _ = std::fs::remove_file(&archive);
+    let cc = Command::new("cc")
+        .args(["-c", "-fPIC", "-o"])
+        .arg(&object)
+        .arg(&c_source)
+        .status()
+        .expect("Failed to run cc");
+    assert!(cc.success());
+    let ar = Command::new("ar")
+        .arg("rcs")
+        .arg(&archive)
+        .arg(&object)
+        .status()
+        .expect("Failed to run ar");
+    assert!(ar.success());
+
+    compile_code_aot!(
+        output,
+        r#"link "temp/libtoy_link_test.a";
+struct Point{x: int, y: int};
+extern fn add_small(a: c_int32_t, b: c_int8_t): c_int32_t;
+extern fn manhattan(p: byval Point): c_int64_t;
+extern fn apply_twice(f: fn(c_int32_t): c_int32_t, v: c_int32_t): c_int64_t;
+extern fn halve(x: c_float): c_float;
+fn inc(x: int): int {
+    return x + 1;
+}
+println(add_small(40, -3));
+println(manhattan(Point{x: 3, y: 4}));
+println(apply_twice(inc, 5));
+println(halve(5.0));
+"#,
+        "link_native_library"
+    );
+    assert!(
+        output.contains("37\n7\n7\n2.5"),
+        "output contained {}",
+        output
+    );
+}
+
+#[test]
+fn test_llvm_c_program_uses_toy_library() {
+    //a C program includes the generated header, links the library and frees what it got back
+    let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let temp = project_root.join("temp");
+    let c_source = temp.join("toy_c_lib_main.c");
+    std::fs::write(
+        &c_source,
+        r#"#include <inttypes.h>
+#include <stdio.h>
+#include TOY_HEADER
+int main(void){
+    toy_init();
+    printf("%" PRId64 "\n", area(3, 4));
+    const char* g = greet("C");
+    printf("%s\n", g);
+    toy_release(g);
+    Point* p = shifted(2.5);
+    printf("%.1f %.1f\n", p->x, p->y);
+    toy_release(p);
+    toy_shutdown();
+    return 0;
+}
+"#,
+    )
+    .unwrap();
+    for (kind, name) in [
+        ("static", "toy_c_lib_static"),
+        ("shared", "toy_c_lib_shared"),
+    ] {
+        let source = temp.join(format!("{}.toy", name));
+        std::fs::write(
+            &source,
+            r#"struct Point{x: float, y: float};
+export fn area(w: int, h: int): int {
+    return w * h;
+}
+export fn greet(name: str): str {
+    return "hello " + name;
+}
+export fn shifted(dx: float): Point {
+    return Point{x: 1.0 + dx, y: 2.0};
+}
+"#,
+        )
+        .unwrap();
+        let ctx = Context::create();
+        let mut d = crate::driver::Driver::new_with_name(source, format!("temp/{}", name));
+        crate::driver::Driver::with_args(&[&format!("--lib={}", kind)], || d.start(&ctx)).unwrap();
+
+        let exe = temp.join(format!("{}_main", name));
+        let _ = std::fs::remove_file(&exe);
+        let mut cc = Command::new("cc");
+        cc.arg(format!("-DTOY_HEADER=\"{}.h\"", name))
+            .arg(format!("-I{}", temp.to_string_lossy()))
+            .arg(&c_source)
+            .arg("-o")
+            .arg(&exe);
+        if kind == "static" {
+            cc.arg(temp.join(format!("lib{}.a", name)));
+        } else {
+            cc.arg(format!("-L{}", temp.to_string_lossy()))
+                .arg(format!("-l{}", name))
+                .arg(format!("-Wl,-rpath,{}", temp.to_string_lossy()));
+        }
+        let status = cc.arg("-lm").status().expect("Failed to run cc");
+        assert!(status.success(), "cc failed for the {} library", kind);
+
+        let output = capture_program_output(exe.to_string_lossy().to_string());
+        assert_eq!(
+            output, "12\nhello C\n3.5 2.0\n",
+            "the {} library printed {}",
+            kind, output
+        );
+    }
+}
+
+///Compiles source with the given flags without running it, gives the path of the program
+fn compile_only(source: &str, test_name: &str, args: &[&str]) -> PathBuf {
+    let temp = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("temp");
+    let output_name = format!("output_{}", test_name);
+    let source_path = temp.join(format!("{}.toy", output_name));
+    std::fs::write(&source_path, source).unwrap();
+    let ctx = Context::create();
+    let mut d = crate::driver::Driver::new_with_name(source_path, format!("temp/{}", output_name));
+    crate::driver::Driver::with_args(args, || d.start(&ctx)).unwrap();
+    let target =
+        crate::driver::Driver::with_args(args, crate::compile_target::CompileTarget::current);
+    return temp.join(format!("{}{}", output_name, target.exe_extension()));
+}
+
+///The cross target tests skip themselves when the runner or the target's runtime is not installed
+fn can_run_target(target: &str, runner: &str) -> bool {
+    let target = crate::compile_target::CompileTarget::parse(target).unwrap();
+    let runner_found = Command::new(runner)
+        .arg("--version")
+        .output()
+        .is_ok_and(|o| o.status.success());
+    if !runner_found || !target.lib_dir().join("libruntime.a").exists() {
+        eprintln!(
+            "skipping, {} or the {} runtime is not installed",
+            runner, target.triple
+        );
+        return false;
+    }
+    return true;
+}
+
+const CROSS_TARGET_PROGRAM: &str = r#"fn twice(s: str): str {
+    return s + s;
+}
+println(6 * 7);
+println(twice("ab"));
+println(2.5 * 3.0);
+"#;
+
+#[test]
+fn test_llvm_target_aarch64_runs_under_qemu() {
+    if !can_run_target("aarch64-linux-gnu", "qemu-aarch64") {
+        return;
+    }
+    let program = compile_only(
+        CROSS_TARGET_PROGRAM,
+        "target_aarch64",
+        &["--target", "aarch64-linux-gnu"],
+    );
+    let output = Command::new("qemu-aarch64")
+        .args(["-L", "/usr/aarch64-linux-gnu"])
+        .arg(&program)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(stdout, "42\nabab\n7.5\n");
+}
+
+#[test]
+fn test_llvm_target_wasm_runs_under_wasmtime() {
+    if !can_run_target("wasm32-wasi", "wasmtime") {
+        return;
+    }
+    let program = compile_only(
+        CROSS_TARGET_PROGRAM,
+        "target_wasm",
+        &["--target=wasm32-wasi"],
+    );
+    assert_eq!(program.extension().unwrap(), "wasm");
+    let output = Command::new("wasmtime")
+        .arg("run")
+        .arg(&program)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(stdout, "42\nabab\n7.5\n");
+}
+
+///Types of the program headers of a 64 bit little endian ELF file
+fn elf_program_header_types(elf: &[u8]) -> Vec<u32> {
+    assert_eq!(&elf[..4], b"\x7fELF", "not an ELF file");
+    assert_eq!(elf[4], 2, "not a 64 bit ELF file");
+    let read = |at: usize, len: usize| {
+        elf[at..at + len]
+            .iter()
+            .rev()
+            .fold(0usize, |n, byte| n << 8 | *byte as usize)
+    };
+    let (phoff, phentsize, phnum) = (read(0x20, 8), read(0x36, 2), read(0x38, 2));
+    return (0..phnum)
+        .map(|i| read(phoff + i * phentsize, 4) as u32)
+        .collect();
+}
+
+#[test]
+fn test_llvm_static_executable_has_no_interpreter() {
+    let musl = crate::compile_target::CompileTarget::parse("x86_64-linux-musl").unwrap();
+    if !cfg!(all(target_os = "linux", target_arch = "x86_64"))
+        || !musl.lib_dir().join("libruntime.a").exists()
+    {
+        eprintln!("skipping, the x86_64-unknown-linux-musl runtime is not installed");
+        return;
+    }
+    let program = compile_only(CROSS_TARGET_PROGRAM, "static", &["--static"]);
+    const PT_INTERP: u32 = 3;
+    let header_types = elf_program_header_types(&std::fs::read(&program).unwrap());
+    assert!(!header_types.is_empty());
+    assert!(
+        !header_types.contains(&PT_INTERP),
+        "{} asks for a dynamic linker",
+        program.display()
+    );
+    let output = Command::new(&program).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\nabab\n7.5\n");
+}
+
+#[test]
+fn test_llvm_heap_profile_counts_runtime_allocations_of_a_call() {
+    let report = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("temp/heap_profile_split.txt");
+    let _ = std::fs::remove_file(&report);
+    let program = compile_only(
+        "import std.str;\nlet parts = str.split(\"a/b/c\", \"/\");\nprintln(len(parts));\n",
+        "heap_profile",
+        &["--profile=heap"],
+    );
+    let output = Command::new(program)
+        .env("TOY_HEAP_PROFILE", &report)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    //the three strings and the array split makes all count towards its call site
+    let report = std::fs::read_to_string(&report).unwrap();
+    assert!(
+        report.contains("(toy_str_split)\n  allocations: 4\n"),
+        "report was {}",
+        report
+    );
+}
+
+#[test]
+fn test_llvm_test_runner_filters_and_reports_failures() {
+    let source_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("temp/output_test_runner.toy");
+    std::fs::write(
+        &source_path,
+        r#"extern fn toy_strlen(s: released c_char_ptr): c_int64_t;
+fn add(a: int, b: int): int {
+    return a + b;
+}
+test fn adds() {
+    assert_eq(add(2, 2), 4);
+}
+test fn adds_wrong() {
+    assert(add(1, 1) == 3);
+}
+test fn leaks() {
+    //the string is released to the runtime, which never frees it
+    let s = str(42);
+    assert(toy_strlen(s) == 2);
+}
+test fn never_runs() {
+    assert(false);
+}
+println("top level statements are not part of the tests");
+"#,
+    )
+    .unwrap();
+    let path = source_path.to_string_lossy().to_string();
+    let mut out: Vec<u8> = vec![];
+    //the filter comes after a flag, every test with an `a` in its name runs
+    let args = [
+        "test",
+        path.as_str(),
+        "--sanitize=memory",
+        "a",
+        "--name",
+        "temp/output_test_runner_tests",
+    ];
+    let passed = crate::driver::Driver::with_args(&args, || {
+        let args = crate::driver::Driver::args();
+        let operands = crate::test_operands(&args);
+        assert_eq!(operands, vec![path.as_str(), "a"]);
+        crate::run_tests(&path, operands.get(1).copied(), &mut out)
+    })
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!passed, "report was {}", out);
+    assert!(out.starts_with("running 3 tests\n"), "report was {}", out);
+    assert!(out.contains("test adds ... ok\n"), "report was {}", out);
+    assert!(
+        out.contains("test adds_wrong ... FAILED\n"),
+        "report was {}",
+        out
+    );
+    assert!(
+        out.contains("test leaks ... FAILED\n"),
+        "report was {}",
+        out
+    );
+    assert!(!out.contains("never_runs"), "report was {}", out);
+    assert!(!out.contains("top level"), "report was {}", out);
+    //the failed assertion points at its line and column in the test file
+    assert!(
+        out.contains(&format!(
+            "---- adds_wrong ----\n{}:9:5: assert(add(1, 1) == 3",
+            path
+        )),
+        "report was {}",
+        out
+    );
+    //leaks passes its assertion, the leak report fails it
+    let leaks = out.split("---- leaks ----").nth(1).unwrap();
+    assert!(leaks.contains("were never freed"), "report was {}", out);
+    //the leak alone is what makes the test binary exit with 1
+    let status = Command::new("./temp/output_test_runner_tests")
+        .arg("leaks")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(1));
+    assert!(
+        out.ends_with("test result: FAILED. 1 passed; 2 failed; 1 filtered out\n"),
+        "report was {}",
+        out
+    );
+}
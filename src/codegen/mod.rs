@@ -2,16 +2,16 @@ pub mod ctla;
 mod llvm;
 mod tir;
 use crate::codegen::llvm::LlvmGenerator;
+use crate::driver::Driver;
 use crate::errors::{Span, ToyErrorType};
 use crate::parser::ast::Ast;
-use crate::token::TypeTok;
+use crate::token::{ExternType, TypeTok};
 use crate::{codegen::ctla::CTLA, errors::ToyError};
 use ctla::cfg::CFGFunction;
 use inkwell::context::Context;
 use inkwell::module::Module;
 use serde_json;
 use std::collections::HashMap;
-use std::env;
 use std::fs;
 use tir::AstToIrConverter;
 pub use tir::ir::{Block, Function, SSAValue, TIR, TirType};
@@ -35,6 +35,24 @@ impl<'a> Generator<'a> {
             .builder
             .register_extern_func(name, ret_type, false, vec![]);
     }
+    ///C wrappers for a `--lib` build, (Toy function, C symbol, C signature)
+    pub fn set_c_exports(&mut self, c_exports: Vec<(String, String, ExternType)>) {
+        self.generator.set_c_exports(c_exports);
+    }
+    ///Keeps the module in memory for the JIT instead of writing `<name>.o`
+    pub fn set_emit_object(&mut self, emit_object: bool) {
+        self.generator.set_emit_object(emit_object);
+    }
+    ///Compiles a REPL entry. user_main loads `globals`, the top level variables of earlier entries,
+    ///and stores every top level variable when it ends, functions stay visible to later entries
+    pub fn set_repl_globals(&mut self, globals: Vec<(String, TypeTok)>) {
+        self.converter.set_repl_globals(globals);
+        self.generator.set_external_functions(true);
+    }
+    ///The LLVM module `compile_to_object` generated
+    pub fn into_module(self) -> Module<'a> {
+        return self.generator.into_module();
+    }
     pub fn set_original_text(&mut self, text: String) {
         self.analyzer.set_original_text(text);
     }
@@ -43,6 +61,15 @@ impl<'a> Generator<'a> {
         self.analyzer.set_external_modules(modules);
     }
 
+    ///Lowers the main program to TIR without analyzing or generating it, the REPL's `:tir`
+    pub fn compile_to_tir(
+        &mut self,
+        ast: Vec<Ast>,
+        name: String,
+    ) -> Result<Vec<Function>, ToyError> {
+        return self.converter.convert(ast, true, &name);
+    }
+
     fn pretty_print_tir(ir: &Vec<Function>) -> Result<String, ToyError> {
         let res = serde_json::to_string(ir);
         match res {
@@ -71,17 +98,42 @@ impl<'a> Generator<'a> {
         name: String,
         is_main: bool,
     ) -> Result<(), ToyError> {
+        let args: Vec<String> = Driver::args();
+        self.converter
+            .set_sanitize(args.contains(&"--sanitize=memory".to_string()));
         let pre_ctla_ir = self.converter.convert(ast, is_main, &name)?;
-        let args: Vec<String> = env::args().collect();
         if args.contains(&"--debug-tir".to_string()) || args.contains(&"--debug-ALL".to_string()) {
             let s = Generator::pretty_print_tir(&pre_ctla_ir)?;
             fs::write("./debug/TIR.json", s).unwrap(); //rly should be an io error -> toy error conversion
         }
-        let ir = self.analyzer.analyze(self.converter.builder.clone())?;
+        let mut ir = self.analyzer.analyze(self.converter.builder.clone())?;
+        if args.contains(&"--profile=heap".to_string()) {
+            let mut builder = self.converter.builder.clone();
+            builder.set_funcs(ir, 0);
+            builder.splice_profile_sites();
+            ir = builder.funcs;
+        }
+        if args.contains(&"--sanitize=memory".to_string()) {
+            let mut builder = self.converter.builder.clone();
+            builder.set_funcs(ir, 0);
+            builder.splice_sanitize_frames();
+            ir = builder.funcs;
+        }
         if args.contains(&"--debug-cfg".to_string()) || args.contains(&"--debug-ALL".to_string()) {
             let s = Generator::pretty_print_cfg(self.analyzer.cfg_functions())?;
             fs::write("./debug/CFG.json", s).unwrap(); //rly should be an io error -> toy error conversion
         }
+        let builder = &self.converter.builder;
+        self.generator.set_source_info(
+            builder.ins_spans.clone(),
+            builder.func_spans.clone(),
+            builder.func_display_names.clone(),
+        );
+        self.generator.set_extern_abis(builder.extern_abis.clone());
+        if args.contains(&"-g".to_string()) {
+            self.generator
+                .enable_debug_info(builder.var_bindings.clone());
+        }
         self.generator.generate(ir, name)?;
         Ok(())
     }
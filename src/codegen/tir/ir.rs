@@ -3,9 +3,9 @@ use itertools::Itertools;
 use std::collections::{BTreeSet, HashMap, HashSet};
 type AllocationId = u64;
 use crate::{
-    errors::{ToyError, ToyErrorType},
+    errors::{Span, ToyError, ToyErrorType},
     parser::ast::InfixOp,
-    token::TypeTok,
+    token::{ExternType, TypeTok},
 };
 use serde::{Deserialize, Serialize};
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
@@ -111,6 +111,24 @@ pub enum TIR {
     Phi(ValueId, Vec<BlockId>, Vec<SSAValue>),
     ///takes a string and puts it into global data
     GlobalString(ValueId, Box<String>),
+    ///allocates a new array, SSAValue is the length, first i64 is the runtime type code of the elements (see TirBuilder::type_code), second is the degree
+    AllocArr(ValueId, SSAValue, i64, i64),
+    ///reads arr[idx], first SSA is the array, second is the index, TirType is the type of the element. Out of bounds reads are a runtime error
+    ArrLoad(ValueId, SSAValue, SSAValue, TirType),
+    ///writes arr[idx] = val, SSAs are array, index and value. Writing at idx == len appends, i64 is the type code handed to the runtime
+    ArrStore(ValueId, SSAValue, SSAValue, SSAValue, i64),
+    ///length of the given array
+    ArrLen(ValueId, SSAValue),
+    ///appends the second SSA to the end of the array, i64 is the type code handed to the runtime
+    ArrPush(ValueId, SSAValue, SSAValue, i64),
+    ///allocates a new empty map, i64s are the runtime type codes of the keys and the values
+    AllocMap(ValueId, i64, i64),
+    ///reads map[key], first SSA is the map, second is the key, TirType is the type of the value. Missing keys are a runtime error
+    MapLoad(ValueId, SSAValue, SSAValue, TirType),
+    ///writes map[key] = val, SSAs are map, key and value. The map copies strings so it never encapsulates the value
+    MapStore(ValueId, SSAValue, SSAValue, SSAValue),
+    ///address of the named function as an i64, called through a C callback of the given c_fn type
+    FuncAddr(ValueId, Box<String>, ExternType),
 }
 
 impl TIR {
@@ -133,6 +151,15 @@ impl TIR {
             TIR::Not(id, _) => *id,
             TIR::Phi(id, _, _) => *id,
             TIR::GlobalString(id, _) => *id,
+            TIR::AllocArr(id, _, _, _) => *id,
+            TIR::ArrLoad(id, _, _, _) => *id,
+            TIR::ArrStore(id, _, _, _, _) => *id,
+            TIR::ArrLen(id, _) => *id,
+            TIR::ArrPush(id, _, _, _) => *id,
+            TIR::AllocMap(id, _, _) => *id,
+            TIR::MapLoad(id, _, _, _) => *id,
+            TIR::MapStore(id, _, _, _) => *id,
+            TIR::FuncAddr(id, _, _) => *id,
         }
     }
 }
@@ -180,6 +207,18 @@ pub struct TirBuilder {
     pub curr_block: Option<usize>, //index into self.curr_func.body,
     ///func_name -> (is_allocator, ret_type, Vec<param_1_not_owned, param_2_not_owned, ... , param_n_not_owned>, is_read_only)
     pub extern_funcs: HashMap<String, (bool, TypeTok, Vec<bool>, bool)>, //external function name to is_allocator, return_type, doesnt_take_ownership, is_read_only
+    ///func_name -> (C param types, C return type) of extern declarations, used to lower calls to the C ABI
+    pub extern_abis: HashMap<String, (Vec<ExternType>, Option<ExternType>)>,
+    ///span of the statement currently being lowered, every instruction created is tagged with it
+    curr_span: Option<Span>,
+    ///(func_name, instruction id) -> span of the statement that created it, used for debug info
+    pub ins_spans: HashMap<(String, ValueId), Span>,
+    ///func_name -> span of the function declaration
+    pub func_spans: HashMap<String, Span>,
+    ///(func_name, ssa value id) -> names of the variables bound to that value, used for debug info
+    pub var_bindings: HashMap<(String, ValueId), Vec<(String, Span)>>,
+    ///func_name -> demangled name shown in runtime backtraces
+    pub func_display_names: HashMap<String, String>,
 }
 impl TirBuilder {
     pub fn new() -> TirBuilder {
@@ -189,11 +228,36 @@ impl TirBuilder {
             curr_func: None,
             curr_block: None,
             extern_funcs: HashMap::new(),
+            extern_abis: HashMap::new(),
+            curr_span: None,
+            ins_spans: HashMap::new(),
+            func_spans: HashMap::new(),
+            var_bindings: HashMap::new(),
+            func_display_names: HashMap::new(),
         };
     }
     fn _next_value_id(&mut self) -> ValueId {
         self.funcs[self.curr_func.unwrap()].ins_counter += 1;
-        return self.funcs[self.curr_func.unwrap()].ins_counter - 1;
+        let id = self.funcs[self.curr_func.unwrap()].ins_counter - 1;
+        if let Some(span) = &self.curr_span {
+            let func_name = *self.funcs[self.curr_func.unwrap()].name.clone();
+            self.ins_spans.insert((func_name, id), span.clone());
+        }
+        return id;
+    }
+    ///Sets the span that every instruction created from now on is tagged with
+    pub fn set_span(&mut self, span: Span) {
+        if span.start_offset_bytes >= 0 {
+            self.curr_span = Some(span);
+        }
+    }
+    ///Records that the variable `name` holds `val` in the current function
+    pub fn bind_var(&mut self, name: String, val: &SSAValue, span: Span) {
+        let func_name = *self.funcs[self.curr_func.unwrap()].name.clone();
+        self.var_bindings
+            .entry((func_name, val.val))
+            .or_default()
+            .push((name, span));
     }
     fn _next_alloc_id(&mut self) -> AllocationId {
         self.funcs[self.curr_func.unwrap()].heap_counter += 1;
@@ -208,6 +272,9 @@ impl TirBuilder {
         // Params are allocated from the caller's (parent function's) counter, so their IDs
         // can be arbitrary. Starting at max_param_id + 1 ensures no instruction shadows a param.
         let ins_counter_start = params.iter().map(|p| p.val + 1).max().unwrap_or(0);
+        if let Some(span) = &self.curr_span {
+            self.func_spans.insert(*name.clone(), span.clone());
+        }
         let func = Function {
             name: name,
             params: params.clone(),
@@ -236,12 +303,24 @@ impl TirBuilder {
                 | TypeTok::IntArr(_)
                 | TypeTok::FloatArr(_)
                 | TypeTok::BoolArr(_)
+                | TypeTok::AnyArr(_)
                 | TypeTok::StructArr(_, _)
+                | TypeTok::Map(_, _)
         );
         self.extern_funcs
             .insert(name, (is_allocator, ret_type, doesnt_take_ownership_list, is_read_only));
     }
 
+    ///Records the C signature of an extern so calls to it follow the C ABI, None as the return keeps the Toy return type
+    pub fn register_extern_abi(
+        &mut self,
+        name: String,
+        params: Vec<ExternType>,
+        ret: Option<ExternType>,
+    ) {
+        self.extern_abis.insert(name, (params, ret));
+    }
+
     /// Updates the parameters of the current function
     pub fn set_func_params(&mut self, params: Vec<SSAValue>) {
         if let Some(func_idx) = self.curr_func {
@@ -983,6 +1062,10 @@ impl TirBuilder {
             val: id,
             ty: Some(TirType::Ptr),
         };
+        return self.copy_str(val);
+    }
+    ///Copies a string to the heap, the copy is an allocation owned by the current function
+    pub fn copy_str(&mut self, val: SSAValue) -> Result<SSAValue, ToyError> {
         let mut val2 = self.call_extern("toy_malloc".to_string(), vec![val])?;
         val2.ty = Some(TirType::Ptr);
         self.funcs[self.curr_func.unwrap()]
@@ -995,14 +1078,171 @@ impl TirBuilder {
             });
         return Ok(val2);
     }
-    ///injects type codes, this should NOT be called for any types
-    pub fn inject_type_param(
+    ///Emits a pointer to a constant global string without copying it to the heap.
+    ///The result must never be freed or handed to toy code
+    pub fn global_string_const(&mut self, name: String) -> Result<SSAValue, ToyError> {
+        let id = self._next_value_id();
+        let ins = TIR::GlobalString(id, Box::new(name));
+        self.funcs[self.curr_func.unwrap()].body[self.curr_block.unwrap()]
+            .ins
+            .push(ins);
+        return Ok(SSAValue {
+            val: id,
+            ty: Some(TirType::I64),
+        });
+    }
+    ///Records that the given values are referenced in the current block, for every heap allocation they belong to
+    fn _track_refs(&mut self, vals: &[SSAValue]) {
+        let curr_func_name = self.funcs[self.curr_func.unwrap()].name.clone();
+        let curr_block = self.get_curr_block_id();
+        let func = &mut self.funcs[self.curr_func.unwrap()];
+        func.heap_allocations.iter_mut().for_each(|alloc| {
+            vals.iter().for_each(|v| {
+                if v.val == alloc.alloc_ins.val || alloc.refs.iter().any(|r| r.2 == v.val) {
+                    alloc.refs.push((curr_func_name.clone(), curr_block, v.val));
+                }
+            })
+        });
+    }
+    fn _push_ins(&mut self, ins: TIR) {
+        self.funcs[self.curr_func.unwrap()].body[self.curr_block.unwrap()]
+            .ins
+            .push(ins);
+    }
+    ///Allocates an array of `len` zeroed elements, the array is a heap allocation owned by the current function
+    pub fn alloc_arr(
         &mut self,
-        t: &TypeTok,
-        inject_dimension: bool,
-        use_element_type: bool,
-        param_values: &mut Vec<SSAValue>,
-    ) -> Result<(), ToyError> {
+        len: SSAValue,
+        elem_type_code: i64,
+        degree: i64,
+    ) -> Result<SSAValue, ToyError> {
+        let id = self._next_value_id();
+        self._push_ins(TIR::AllocArr(id, len, elem_type_code, degree));
+        let val = SSAValue {
+            val: id,
+            ty: Some(TirType::Ptr),
+        };
+        self._push_heap_allocation(val.clone());
+        return Ok(val);
+    }
+    pub fn arr_load(
+        &mut self,
+        arr: SSAValue,
+        idx: SSAValue,
+        elem_type: TirType,
+    ) -> Result<SSAValue, ToyError> {
+        self._track_refs(&[arr.clone(), idx.clone()]);
+        let id = self._next_value_id();
+        self._push_ins(TIR::ArrLoad(id, arr, idx, elem_type.clone()));
+        return Ok(SSAValue {
+            val: id,
+            ty: Some(elem_type),
+        });
+    }
+    pub fn arr_store(
+        &mut self,
+        arr: SSAValue,
+        idx: SSAValue,
+        val: SSAValue,
+        type_code: i64,
+    ) -> Result<SSAValue, ToyError> {
+        self._track_refs(&[arr.clone(), idx.clone(), val.clone()]);
+        let id = self._next_value_id();
+        self._push_ins(TIR::ArrStore(id, arr, idx, val, type_code));
+        return Ok(SSAValue { val: id, ty: None });
+    }
+    pub fn arr_len(&mut self, arr: SSAValue) -> Result<SSAValue, ToyError> {
+        self._track_refs(&[arr.clone()]);
+        let id = self._next_value_id();
+        self._push_ins(TIR::ArrLen(id, arr));
+        return Ok(SSAValue {
+            val: id,
+            ty: Some(TirType::I64),
+        });
+    }
+    pub fn arr_push(
+        &mut self,
+        arr: SSAValue,
+        val: SSAValue,
+        type_code: i64,
+    ) -> Result<SSAValue, ToyError> {
+        self._track_refs(&[arr.clone(), val.clone()]);
+        let id = self._next_value_id();
+        self._push_ins(TIR::ArrPush(id, arr, val, type_code));
+        return Ok(SSAValue { val: id, ty: None });
+    }
+    ///Allocates an empty map, the map is a heap allocation owned by the current function
+    pub fn alloc_map(
+        &mut self,
+        key_type_code: i64,
+        value_type_code: i64,
+    ) -> Result<SSAValue, ToyError> {
+        let id = self._next_value_id();
+        self._push_ins(TIR::AllocMap(id, key_type_code, value_type_code));
+        let val = SSAValue {
+            val: id,
+            ty: Some(TirType::Ptr),
+        };
+        self._push_heap_allocation(val.clone());
+        return Ok(val);
+    }
+    ///Reads map[key], string values come back as a fresh copy that the current function owns
+    pub fn map_load(
+        &mut self,
+        map: SSAValue,
+        key: SSAValue,
+        value_type: TirType,
+    ) -> Result<SSAValue, ToyError> {
+        self._track_refs(&[map.clone(), key.clone()]);
+        let id = self._next_value_id();
+        self._push_ins(TIR::MapLoad(id, map, key, value_type.clone()));
+        let val = SSAValue {
+            val: id,
+            ty: Some(value_type.clone()),
+        };
+        if value_type == TirType::Ptr {
+            self._push_heap_allocation(val.clone());
+        }
+        return Ok(val);
+    }
+    pub fn map_store(
+        &mut self,
+        map: SSAValue,
+        key: SSAValue,
+        val: SSAValue,
+    ) -> Result<SSAValue, ToyError> {
+        self._track_refs(&[map.clone(), key.clone(), val.clone()]);
+        let id = self._next_value_id();
+        self._push_ins(TIR::MapStore(id, map, key, val));
+        return Ok(SSAValue { val: id, ty: None });
+    }
+    ///Takes the address of a Toy function so C can call it back, the value is a plain i64 that is never freed
+    pub fn func_addr(&mut self, name: String, callback: ExternType) -> Result<SSAValue, ToyError> {
+        let id = self._next_value_id();
+        self._push_ins(TIR::FuncAddr(id, Box::new(name), callback));
+        return Ok(SSAValue {
+            val: id,
+            ty: Some(TirType::I64),
+        });
+    }
+    fn _push_heap_allocation(&mut self, val: SSAValue) {
+        let curr_func_name = self.funcs[self.curr_func.unwrap()].name.clone();
+        let curr_block = self.get_curr_block_id();
+        let alloc = HeapAllocation {
+            block: curr_block,
+            allocation_id: self._next_alloc_id(),
+            function: curr_func_name.clone(),
+            refs: vec![(curr_func_name, curr_block, val.val)],
+            alloc_ins: val,
+            aliases: BTreeSet::new(),
+            encapsulators: BTreeSet::new(),
+        };
+        self.funcs[self.curr_func.unwrap()]
+            .heap_allocations
+            .push(alloc);
+    }
+    ///(type code, degree) the runtime uses for the given type, see inject_type_param
+    pub fn type_code(t: &TypeTok, use_element_type: bool) -> (i64, i64) {
         let (n, degree) = match t {
             &TypeTok::Str => (0, 0),
             &TypeTok::Bool => (1, 0),
@@ -1015,12 +1255,24 @@ impl TirBuilder {
             &TypeTok::AnyArr(n) => (if use_element_type && n == 1 { 0 } else { 4 }, n),
             TypeTok::Struct(_) => (8, 0),
             TypeTok::StructArr(_, n) => (if use_element_type && *n == 1 { 8 } else { 8 }, *n),
+            TypeTok::Map(_, _) => (9, 0),
             _ => unreachable!(), // parser validated
         };
+        return (n, degree as i64);
+    }
+    ///injects type codes, this should NOT be called for any types
+    pub fn inject_type_param(
+        &mut self,
+        t: &TypeTok,
+        inject_dimension: bool,
+        use_element_type: bool,
+        param_values: &mut Vec<SSAValue>,
+    ) -> Result<(), ToyError> {
+        let (n, degree) = TirBuilder::type_code(t, use_element_type);
         let v = self.iconst(n, TypeTok::Int)?;
         param_values.push(v);
         if inject_dimension {
-            let d = self.iconst(degree as i64, TypeTok::Int)?;
+            let d = self.iconst(degree, TypeTok::Int)?;
             param_values.push(d);
         }
         return Ok(());
@@ -1039,7 +1291,8 @@ impl TirBuilder {
             | TypeTok::IntArr(_)
             | TypeTok::FloatArr(_)
             | TypeTok::StructArr(_, _)
-            | TypeTok::AnyArr(_) => TirType::Ptr,
+            | TypeTok::AnyArr(_)
+            | TypeTok::Map(_, _) => TirType::Ptr,
             TypeTok::Struct(i) => {
                 let mut types: Vec<TirType> = vec![];
                 for (_, ty) in i.iter() {
@@ -1080,6 +1333,118 @@ impl TirBuilder {
         }
         return seen.into_values().collect();
     }
+    ///Wraps every heap allocation a function owns in toy_profile_site and toy_profile_site_end calls, passing the
+    ///allocation id, the function name and the allocator name. Used by `--profile=heap`, must run after CTLA
+    pub fn splice_profile_sites(&mut self) {
+        for func in self.funcs.iter_mut() {
+            let sites: Vec<(BlockId, ValueId, AllocationId)> = func
+                .heap_allocations
+                .iter()
+                .filter(|a| a.function == func.name)
+                .map(|a| (a.block, a.alloc_ins.val, a.allocation_id))
+                .collect();
+            for (block_id, alloc_val, alloc_id) in sites {
+                let Some(block) = func.body.iter_mut().find(|b| b.id == block_id) else {
+                    continue;
+                };
+                let Some(idx) = block.ins.iter().position(|ins| ins.get_id() == alloc_val) else {
+                    continue;
+                };
+                let allocator = match &block.ins[idx] {
+                    TIR::CallLocalFunction(_, name, _, _, _)
+                    | TIR::CallExternFunction(_, name, _, _, _, _) => *name.clone(),
+                    TIR::AllocArr(..) => "toy_malloc_arr".to_string(),
+                    TIR::AllocMap(..) => "toy_malloc_map".to_string(),
+                    TIR::MapLoad(..) => "toy_map_get".to_string(),
+                    _ => continue,
+                };
+                let site_id = func.ins_counter;
+                let func_name_id = site_id + 1;
+                let allocator_id = site_id + 2;
+                let call_id = site_id + 3;
+                let end_id = site_id + 4;
+                func.ins_counter += 5;
+                let params = [site_id, func_name_id, allocator_id]
+                    .iter()
+                    .map(|id| SSAValue {
+                        val: *id,
+                        ty: Some(TirType::I64),
+                    })
+                    .collect();
+                block.ins.insert(
+                    idx + 1,
+                    TIR::CallExternFunction(
+                        end_id,
+                        Box::new("toy_profile_site_end".to_string()),
+                        vec![],
+                        false,
+                        TirType::Void,
+                        vec![],
+                    ),
+                );
+                block.ins.splice(
+                    idx..idx,
+                    [
+                        TIR::IConst(site_id, alloc_id as i64, TirType::I64),
+                        TIR::GlobalString(func_name_id, func.name.clone()),
+                        TIR::GlobalString(allocator_id, Box::new(allocator)),
+                        TIR::CallExternFunction(
+                            call_id,
+                            Box::new("toy_profile_site".to_string()),
+                            params,
+                            false,
+                            TirType::Void,
+                            vec![true, true, true],
+                        ),
+                    ],
+                );
+            }
+        }
+    }
+    ///Calls toy_sanitize_site_push when every function starts and toy_sanitize_site_pop right before it returns,
+    ///so the sanitizer's site is back at the caller's statement after a call. Used by `--sanitize=memory`,
+    ///must run after CTLA so the frees it adds before a return still count towards the function
+    pub fn splice_sanitize_frames(&mut self) {
+        for func in self.funcs.iter_mut() {
+            for block in func.body.iter_mut() {
+                let rets: Vec<usize> = block
+                    .ins
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, ins)| matches!(ins, TIR::Ret(..)))
+                    .map(|(idx, _)| idx)
+                    .collect();
+                for idx in rets.into_iter().rev() {
+                    block.ins.insert(
+                        idx,
+                        TIR::CallExternFunction(
+                            func.ins_counter,
+                            Box::new("toy_sanitize_site_pop".to_string()),
+                            vec![],
+                            false,
+                            TirType::Void,
+                            vec![],
+                        ),
+                    );
+                    func.ins_counter += 1;
+                }
+            }
+            if let Some(entry) = func.body.first_mut() {
+                entry.ins.insert(
+                    0,
+                    TIR::CallExternFunction(
+                        func.ins_counter,
+                        Box::new("toy_sanitize_site_push".to_string()),
+                        vec![],
+                        false,
+                        TirType::Void,
+                        vec![],
+                    ),
+                );
+                func.ins_counter += 1;
+            }
+        }
+    }
     ///Will add a manually created TIR instruction before the specified instruction
     ///Will NOT move the cursor
     pub fn splice_free_before(
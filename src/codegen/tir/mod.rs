@@ -1,12 +1,12 @@
 #![allow(unused)]
 use crate::codegen::tir::ir::{BlockId, Function, SSAValue, TirBuilder, ValueId};
 use crate::driver::Driver;
-use crate::errors::ToyErrorType;
+use crate::errors::{Span, ToyErrorType};
 use crate::lexer::Lexer;
 use crate::parser::ast::InfixOp;
 use crate::parser::boxer::Boxer;
 use crate::parser::toy_box::TBox;
-use crate::token::TypeTok;
+use crate::token::{ExternType, TypeTok};
 use crate::{
     codegen::tir::ir::{TIR, TirType},
     errors::ToyError,
@@ -59,6 +59,11 @@ pub struct AstToIrConverter {
     interfaces: HashMap<String, (HashMap<String, usize>, TirType)>,
     main_func_name: String,
     loop_stack: Vec<LoopContext>,
+    ///set by `--sanitize=memory`, emits a toy_sanitize_site call before each statement
+    sanitize: bool,
+    ///set for a REPL entry, the top level variables of earlier entries. user_main loads them from the
+    ///runtime and stores every top level variable back before it returns
+    repl_globals: Option<Vec<(String, TypeTok)>>,
 }
 
 #[derive(Debug, Clone)]
@@ -110,8 +115,92 @@ impl AstToIrConverter {
             interfaces: HashMap::new(),
             main_func_name: "user_main".to_string(),
             loop_stack: vec![],
+            sanitize: false,
+            repl_globals: None,
         };
     }
+    pub fn set_sanitize(&mut self, sanitize: bool) {
+        self.sanitize = sanitize;
+    }
+    pub fn set_repl_globals(&mut self, globals: Vec<(String, TypeTok)>) {
+        self.repl_globals = Some(globals);
+    }
+    ///Reads a REPL variable an earlier entry stored, a struct is put back together from its fields
+    fn load_repl_global(&mut self, name: &str, ty: &TypeTok) -> Result<SSAValue, ToyError> {
+        if let TypeTok::Struct(fields) = ty {
+            let mut vals = Vec::with_capacity(fields.len());
+            for (field, field_ty) in fields {
+                vals.push(self.load_repl_global(&format!("{}.{}", name, field), field_ty)?);
+            }
+            let struct_ty = self.builder.type_tok_to_tir_type(ty.clone());
+            return self.builder.create_struct_literal(vals, struct_ty);
+        }
+        let key = self.builder.global_string_const(name.to_string())?;
+        return match ty {
+            TypeTok::Int => self
+                .builder
+                .call_extern("toy_repl_load".to_string(), vec![key]),
+            TypeTok::Float => self
+                .builder
+                .call_extern("toy_repl_load_float".to_string(), vec![key]),
+            TypeTok::Bool => {
+                let val = self
+                    .builder
+                    .call_extern("toy_repl_load".to_string(), vec![key])?;
+                let zero = self.builder.iconst(0, TypeTok::Int)?;
+                self.builder.boolean_infix(val, zero, InfixOp::NotEquals)
+            }
+            _ => self
+                .builder
+                .call_extern("toy_repl_load_ptr".to_string(), vec![key]),
+        };
+    }
+    ///Keeps a top level variable for the next REPL entry. Struct literals live on the stack so each
+    ///field is stored on its own, the struct is passed along too so CTLA sees what it holds escape
+    fn store_repl_global(
+        &mut self,
+        name: &str,
+        val: SSAValue,
+        ty: &TypeTok,
+    ) -> Result<(), ToyError> {
+        if let TypeTok::Struct(fields) = ty {
+            for (idx, (field, field_ty)) in fields.iter().enumerate() {
+                let field_tir_ty = self.builder.type_tok_to_tir_type(*field_ty.clone());
+                let field_val =
+                    self.builder
+                        .read_struct_literal(val.clone(), idx as u64, field_tir_ty)?;
+                self.store_repl_global(&format!("{}.{}", name, field), field_val, field_ty)?;
+            }
+        }
+        let key = self.builder.global_string_const(name.to_string())?;
+        return self
+            .builder
+            .call_extern_void("toy_repl_store".to_string(), vec![key, val]);
+    }
+    ///struct, interface, extern, import and link declarations, the REPL loads its variables after them
+    ///since a struct variable needs its interface
+    fn is_declaration(node: &Ast) -> bool {
+        return matches!(
+            node,
+            Ast::StructInterface(..)
+                | Ast::ExternFuncDec(..)
+                | Ast::ImportStmt(..)
+                | Ast::LinkStmt(..)
+        );
+    }
+    ///Tells the sanitizer runtime which .toy line is executing, so allocations and frees can be reported with a source location
+    fn emit_sanitize_site(&mut self, node: &Ast) -> Result<(), ToyError> {
+        let span = node.span();
+        if span.start_offset_bytes < 0 {
+            return Ok(());
+        }
+        let ((line, _), _) = span.get_line_col();
+        let file = self.builder.global_string_const(span.file_path.clone())?;
+        let line = self.builder.iconst(line as i64, TypeTok::Int)?;
+        self.builder
+            .call_extern_void("toy_sanitize_site".to_string(), vec![file, line])?;
+        return Ok(());
+    }
     fn get_expr_type(&self, node: &Ast, scope: &Rc<RefCell<Scope>>) -> Result<TypeTok, ToyError> {
         match node {
             Ast::IntLit(_, _) => Ok(TypeTok::Int),
@@ -119,6 +208,7 @@ impl AstToIrConverter {
             Ast::StringLit(_, _) => Ok(TypeTok::Str),
             Ast::FloatLit(_, _) => Ok(TypeTok::Float),
             Ast::VarRef(n, _) => scope.as_ref().borrow().get_var_type(n),
+            Ast::FuncRef(_, _, _) => Ok(TypeTok::Int),
             Ast::InfixExpr(l, r, op, _) => match op {
                 InfixOp::Equals
                 | InfixOp::NotEquals
@@ -131,11 +221,30 @@ impl AstToIrConverter {
                 _ => self.get_expr_type(l, scope),
             },
             Ast::EmptyExpr(e, _) => self.get_expr_type(e, scope),
-            Ast::FuncCall(n, _, _) => match n.as_str() {
-                "print" | "println" | "toy_write_to_arr" => Ok(TypeTok::Void),
+            Ast::FuncCall(n, p, _) => match n.as_str() {
+                "print" | "println" | "push" | "remove" | "toy_write_to_arr" | "toy_arr_push" => {
+                    Ok(TypeTok::Void)
+                }
+                "has" => Ok(TypeTok::Bool),
+                "keys" | "values" => {
+                    let TypeTok::Map(key_ty, value_ty) = self.get_expr_type(&p[0], scope)? else {
+                        unreachable!() // parser validated
+                    };
+                    let elem_ty = if n.as_str() == "keys" {
+                        *key_ty
+                    } else {
+                        *value_ty
+                    };
+                    Ok(match elem_ty {
+                        TypeTok::Int => TypeTok::IntArr(1),
+                        TypeTok::Bool => TypeTok::BoolArr(1),
+                        TypeTok::Float => TypeTok::FloatArr(1),
+                        _ => TypeTok::StrArr(1),
+                    })
+                }
                 "len" | "toy_strlen" | "toy_arrlen" | "toy_type_to_int" | "toy_type_to_bool"
                 | "toy_type_to_float" | "toy_malloc_arr" | "toy_input" => Ok(TypeTok::Int),
-                "str" | "toy_type_to_str" => Ok(TypeTok::Str),
+                "str" | "toy_type_to_str" | "to_json" => Ok(TypeTok::Str),
                 "int" => Ok(TypeTok::Int),
                 "float" => Ok(TypeTok::Float),
                 "bool" => Ok(TypeTok::Bool),
@@ -161,9 +270,11 @@ impl AstToIrConverter {
                 }
             },
             Ast::ArrLit(ty, _, _) => Ok(ty.clone()),
+            Ast::MapLit(ty, _, _) => Ok(ty.clone()),
             Ast::IndexAccess(target, _, _) => {
                 let target_ty = self.get_expr_type(target, scope)?;
                 match target_ty {
+                    TypeTok::Map(_, value_ty) => Ok(*value_ty),
                     TypeTok::IntArr(d) => {
                         if d == 1 {
                             Ok(TypeTok::Int)
@@ -222,6 +333,36 @@ impl AstToIrConverter {
         }
     }
 
+    ///Describes how a value of type t is laid out for toy_json_encode, see Layout in the runtime.
+    ///Struct fields are listed in slot order, which is the sorted order of their names
+    fn json_layout(t: &TypeTok) -> String {
+        let nested = |elem: String, dim: &u64| {
+            format!("{}{}{}", "[".repeat(*dim as usize), elem, "]".repeat(*dim as usize))
+        };
+        return match t {
+            TypeTok::Str => "s".to_string(),
+            TypeTok::Float => "f".to_string(),
+            TypeTok::Bool => "b".to_string(),
+            TypeTok::Map(_, _) => "M".to_string(),
+            TypeTok::StrArr(d) => nested("s".to_string(), d),
+            TypeTok::FloatArr(d) => nested("f".to_string(), d),
+            TypeTok::BoolArr(d) => nested("b".to_string(), d),
+            TypeTok::IntArr(d) | TypeTok::AnyArr(d) => nested("i".to_string(), d),
+            TypeTok::Struct(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, ty)| format!("{}:{}", name, AstToIrConverter::json_layout(ty)))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+            TypeTok::StructArr(fields, d) => nested(
+                AstToIrConverter::json_layout(&TypeTok::Struct(fields.clone())),
+                d,
+            ),
+            _ => "i".to_string(),
+        };
+    }
+
     fn compile_expr(
         &mut self,
         node: Ast,
@@ -285,6 +426,7 @@ impl AstToIrConverter {
             }
             Ast::EmptyExpr(c, _) => self.compile_expr(*c, scope),
             Ast::VarRef(n, _) => scope.as_ref().borrow().get_var(&*n),
+            Ast::FuncRef(n, callback, _) => self.builder.func_addr(*n, callback),
             Ast::FuncCall(n, p, _) => {
                 let mut ssa_params: Vec<SSAValue> = Vec::new();
                 for param in p.clone() {
@@ -295,19 +437,22 @@ impl AstToIrConverter {
                 let name: &str = match &*n.as_str() {
                     "print" => "toy_print",
                     "println" => "toy_println",
-                    "len" => {
-                        if p[0].node_type() == "StringLit" || p[0].node_type() == "InfixExpr" {
-                            //probably not ideal
-                            "toy_strlen"
-                        } else {
-                            "toy_arrlen"
-                        }
-                    }
+                    "len" => match self.get_expr_type(&p[0], scope)? {
+                        TypeTok::Str => "toy_strlen",
+                        TypeTok::Map(_, _) => "toy_map_len",
+                        _ => "toy_arrlen",
+                    },
                     "str" => "toy_type_to_str",
                     "int" => "toy_type_to_int",
                     "float" => "toy_type_to_float",
                     "bool" => "toy_type_to_bool",
                     "input" => "toy_input",
+                    "push" => "toy_arr_push",
+                    "has" => "toy_map_has",
+                    "remove" => "toy_map_remove",
+                    "keys" => "toy_map_keys",
+                    "values" => "toy_map_values",
+                    "to_json" => "toy_json_encode",
                     _ => &*n,
                 };
 
@@ -387,7 +532,8 @@ impl AstToIrConverter {
                             | TypeTok::IntArr(_)
                             | TypeTok::BoolArr(_)
                             | TypeTok::FloatArr(_)
-                            | TypeTok::StrArr(_) => {
+                            | TypeTok::StrArr(_)
+                            | TypeTok::Map(_, _) => {
                                 self.builder.inject_type_param(
                                     &ty,
                                     true,
@@ -429,6 +575,31 @@ impl AstToIrConverter {
                     let ty = self.get_expr_type(&p[0], scope)?;
                     self.builder
                         .inject_type_param(&ty, false, false, &mut final_params)?;
+                } else if !is_user_defined && name == "toy_arr_push" {
+                    if p.len() != 2 {
+                        return unreachable!();
+                    }
+                    let arr_ty = self.get_expr_type(&p[0], scope)?;
+                    let (type_code, _) = TirBuilder::type_code(&arr_ty, false);
+                    return self.builder.arr_push(
+                        ssa_params[0].clone(),
+                        ssa_params[1].clone(),
+                        type_code,
+                    );
+                } else if !is_user_defined && name == "toy_arrlen" {
+                    return self.builder.arr_len(ssa_params[0].clone());
+                } else if !is_user_defined && name == "toy_json_encode" {
+                    let ty = self.get_expr_type(&p[0], scope)?;
+                    final_params.push(ssa_params[0].clone());
+                    final_params.push(
+                        self.builder
+                            .global_string_const(AstToIrConverter::json_layout(&ty))?,
+                    );
+                } else if !is_user_defined && name == "toy_map_has" {
+                    //the runtime hands back an i64, compare it to get a real bool
+                    let found = self.builder.call(name.to_string(), ssa_params)?;
+                    let zero = self.builder.iconst(0, TypeTok::Int)?;
+                    return self.builder.boolean_infix(found, zero, InfixOp::NotEquals);
                 } else {
                     final_params = ssa_params;
                 }
@@ -455,26 +626,38 @@ impl AstToIrConverter {
                     TypeTok::StructArr(_, d) => d,
                     _ => panic!("Type {:?} does not have a degree", ty),
                 };
-                let mut params = vec![len];
-                self.builder
-                    .inject_type_param(ty, false, true, &mut params)?;
-                params.push(self.builder.iconst(*degree as i64, TypeTok::Int)?);
-                let arr = self.builder.call("toy_malloc_arr".to_string(), params)?;
+                let (type_code, _) = TirBuilder::type_code(ty, true);
+                let arr = self.builder.alloc_arr(len, type_code, *degree as i64)?;
                 for (i, ssa_val) in ssa_vals.iter().enumerate() {
                     let idx = self.builder.iconst(i as i64, TypeTok::Int)?;
-                    let x: SSAValue = ssa_val.clone();
-                    let mut write_params: Vec<SSAValue> = vec![arr.clone(), x, idx];
-
                     self.builder
-                        .inject_type_param(&ty, false, true, &mut write_params)?;
-                    self.builder
-                        .call("toy_write_to_arr".to_string(), write_params);
+                        .arr_store(arr.clone(), idx, ssa_val.clone(), type_code)?;
                 }
 
                 return Ok(arr);
             }
+            Ast::MapLit(ref ty, ref entries, _) => {
+                let TypeTok::Map(key_ty, value_ty) = ty else {
+                    unreachable!() // parser validated
+                };
+                let (key_type_code, _) = TirBuilder::type_code(key_ty, false);
+                let (value_type_code, _) = TirBuilder::type_code(value_ty, false);
+                let map = self.builder.alloc_map(key_type_code, value_type_code)?;
+                for (key, value) in entries.clone() {
+                    let key = self.compile_expr(key, scope)?;
+                    let value = self.compile_expr(value, scope)?;
+                    self.builder.map_store(map.clone(), key, value)?;
+                }
+                return Ok(map);
+            }
             Ast::IndexAccess(target, index, _) => {
                 let target_ty = self.get_expr_type(&target, scope)?;
+                if let TypeTok::Map(_, value_ty) = &target_ty {
+                    let value_tir_ty = self.builder.type_tok_to_tir_type(*value_ty.clone());
+                    let map = self.compile_expr(*target, scope)?;
+                    let key = self.compile_expr(*index, scope)?;
+                    return self.builder.map_load(map, key, value_tir_ty);
+                }
                 let elem_ty = match target_ty {
                     TypeTok::IntArr(n) => {
                         if n == 1 {
@@ -521,15 +704,16 @@ impl AstToIrConverter {
                     _ => unreachable!(),
                 };
 
+                let elem_is_str = matches!(elem_ty, TypeTok::Str);
                 let target_val = self.compile_expr(*target, scope)?;
                 let idx_val = self.compile_expr(*index, scope)?;
-                let read_params = vec![target_val, idx_val];
-                let mut res = self
-                    .builder
-                    .call("toy_read_from_arr".to_string(), read_params)?;
-
-                res.ty = Some(self.builder.type_tok_to_tir_type(elem_ty));
-                Ok(res)
+                let elem_tir_ty = self.builder.type_tok_to_tir_type(elem_ty);
+                let elem = self.builder.arr_load(target_val, idx_val, elem_tir_ty)?;
+                //like map values, strings read from an array are copies since the array may free its own
+                if elem_is_str {
+                    return self.builder.copy_str(elem);
+                }
+                Ok(elem)
             }
             Ast::StructLit(interface_name, kv, _) => {
                 let mut compiled_map: BTreeMap<String, SSAValue> = BTreeMap::new();
@@ -597,7 +781,9 @@ impl AstToIrConverter {
         ty: TypeTok,
         scope: &Rc<RefCell<Scope>>,
     ) -> Result<SSAValue, ToyError> {
+        let span = ast_val.span();
         let compiled_val = self.compile_expr(ast_val, scope)?;
+        self.builder.bind_var(name.clone(), &compiled_val, span);
         scope
             .as_ref()
             .borrow_mut()
@@ -606,8 +792,8 @@ impl AstToIrConverter {
     }
 
     fn compile_if_stmt(&mut self, node: Ast, scope: &Rc<RefCell<Scope>>) -> Result<(), ToyError> {
-        let (cond, body, alt) = match node {
-            Ast::IfStmt(c, b, a, _) => (*c, b, a),
+        let (cond, body, alt, span) = match node {
+            Ast::IfStmt(c, b, a, span) => (*c, b, a, span),
             _ => unreachable!(),
         };
 
@@ -654,12 +840,15 @@ impl AstToIrConverter {
                             vec![true_end_block, pre_if_block],
                             vec![true_val.0.clone(), pre_val.0.clone()],
                         )?;
+                        let phi_val = SSAValue {
+                            val: phi_id,
+                            ty: pre_val.0.ty.clone(),
+                        };
+                        self.builder
+                            .bind_var(var_name.clone(), &phi_val, span.clone());
                         scope.as_ref().borrow_mut().set_var(
                             var_name.clone(),
-                            SSAValue {
-                                val: phi_id,
-                                ty: pre_val.0.ty.clone(),
-                            },
+                            phi_val,
                             pre_val.1.clone(),
                         );
                     }
@@ -713,12 +902,15 @@ impl AstToIrConverter {
                         vec![true_end_block, false_end_block],
                         vec![true_val.clone(), false_val.clone()],
                     )?;
+                    let phi_val = SSAValue {
+                        val: phi_id,
+                        ty: pre_val.0.ty.clone(),
+                    };
+                    self.builder
+                        .bind_var(var_name.clone(), &phi_val, span.clone());
                     scope.as_ref().borrow_mut().set_var(
                         var_name.clone(),
-                        SSAValue {
-                            val: phi_id,
-                            ty: pre_val.0.ty.clone(),
-                        },
+                        phi_val,
                         pre_val.1.clone(),
                     );
                 }
@@ -732,8 +924,8 @@ impl AstToIrConverter {
         node: Ast,
         scope: &Rc<RefCell<Scope>>,
     ) -> Result<(), ToyError> {
-        let (cond, body) = match node {
-            Ast::WhileStmt(c, b, _) => (*c, b),
+        let (cond, body, span) = match node {
+            Ast::WhileStmt(c, b, span) => (*c, b, span),
             _ => unreachable!(),
         };
 
@@ -754,14 +946,13 @@ impl AstToIrConverter {
 
         for (var_name, pre_val) in &pre_loop_vars {
             if let Some(&phi_id) = phi_id_map.get(var_name) {
-                scope.as_ref().borrow_mut().set_var(
-                    var_name.clone(),
-                    SSAValue {
-                        val: phi_id,
-                        ty: pre_val.0.ty.clone(),
-                    },
-                    pre_val.1.clone(),
-                );
+                let phi_val = SSAValue {
+                    val: phi_id,
+                    ty: pre_val.0.ty.clone(),
+                };
+                self.builder
+                    .bind_var(var_name.clone(), &phi_val, span.clone());
+                scope.as_ref().borrow_mut().set_var(var_name.clone(), phi_val, pre_val.1.clone());
             }
         }
 
@@ -896,17 +1087,20 @@ impl AstToIrConverter {
         node: Ast,
         _scope: &Rc<RefCell<Scope>>,
     ) -> Result<(), ToyError> {
-        let (name, params, ret_type) = match node {
-            Ast::ExternFuncDec(n, p, r, _) => (*n, p, r),
+        let (name, params, ret_type, c_ret_type) = match node {
+            Ast::ExternFuncDec(n, p, r, c, _) => (*n, p, r, c),
             _ => unreachable!(),
         };
-        let doesnt_take_ownership_list: Vec<bool> = params
-            .into_iter()
-            .filter_map(|p| match p {
-                Ast::ExternFuncParam(_, qualified_type, _) => Some(!qualified_type.is_released),
-                _ => None,
-            })
-            .collect();
+        let mut doesnt_take_ownership_list: Vec<bool> = vec![];
+        let mut c_param_types: Vec<ExternType> = vec![];
+        for p in params {
+            if let Ast::ExternFuncParam(_, qualified_type, _) = p {
+                doesnt_take_ownership_list.push(!qualified_type.is_released);
+                c_param_types.push(qualified_type.ty);
+            }
+        }
+        self.builder
+            .register_extern_abi(name.clone(), c_param_types, c_ret_type);
         self.builder
             .register_extern_func(name, ret_type, false, doesnt_take_ownership_list);
         Ok(())
@@ -919,20 +1113,30 @@ impl AstToIrConverter {
         };
         let func_scope = Scope::new_child(scope);
         let mut ssa_params: Vec<SSAValue> = Vec::new();
+        let mut param_bindings: Vec<(String, SSAValue, Span)> = Vec::new();
+        let mut param_types: Vec<TypeTok> = Vec::new();
         for p in params {
-            let (name, param_type) = match p {
-                Ast::FuncParam(n, t, _) => (*n, t),
+            let (name, param_type, span) = match p {
+                Ast::FuncParam(n, t, s) => (*n, t, s),
                 _ => unreachable!(),
             };
             let ssa_v = self.builder.generic_ssa(param_type.clone());
             func_scope
                 .as_ref()
                 .borrow_mut()
-                .set_var(name, ssa_v.clone(), param_type);
+                .set_var(name.clone(), ssa_v.clone(), param_type.clone());
+            param_bindings.push((name, ssa_v.clone(), span));
             ssa_params.push(ssa_v);
+            param_types.push(param_type);
         }
+        self.builder
+            .func_display_names
+            .insert(name.clone(), Driver::demangle_name(&name, &param_types));
         self.builder
             .new_func(Box::new(name), ssa_params, ret_type.clone());
+        for (name, ssa_v, span) in param_bindings {
+            self.builder.bind_var(name, &ssa_v, span);
+        }
         for stmt in body {
             self.compile_stmt(stmt, &func_scope)?;
         }
@@ -945,6 +1149,21 @@ impl AstToIrConverter {
         return Ok(());
     }
     fn compile_stmt(&mut self, node: Ast, scope: &Rc<RefCell<Scope>>) -> Result<(), ToyError> {
+        self.builder.set_span(node.span());
+        if self.sanitize
+            && !matches!(
+                node,
+                Ast::FuncDec(..)
+                    | Ast::ExternFuncDec(..)
+                    | Ast::StructInterface(..)
+                    | Ast::ImportStmt(..)
+                    | Ast::LinkStmt(..)
+                    | Ast::Break(_)
+                    | Ast::Continue(_)
+            )
+        {
+            self.emit_sanitize_site(&node)?;
+        }
         match node {
             Ast::IntLit(_, _)
             | Ast::BoolLit(_, _)
@@ -954,6 +1173,7 @@ impl AstToIrConverter {
             | Ast::VarRef(_, _)
             | Ast::StringLit(_, _)
             | Ast::ArrLit(_, _, _)
+            | Ast::MapLit(_, _, _)
             | Ast::StructLit(_, _, _)
             | Ast::Not(_, _) => {
                 let _ = self.compile_expr(node, scope)?;
@@ -964,10 +1184,18 @@ impl AstToIrConverter {
             Ast::Assignment(lhs, rhs, _) => {
                 let val = self.compile_expr(*rhs, scope)?;
                 match *lhs {
-                    Ast::VarRef(name, _) => {
+                    Ast::VarRef(name, span) => {
+                        self.builder.bind_var(*name.clone(), &val, span);
                         let ty = scope.as_ref().borrow().get_var_type(&name)?;
                         scope.as_ref().borrow_mut().set_var(*name, val, ty);
                     }
+                    Ast::IndexAccess(target, index, _)
+                        if matches!(self.get_expr_type(&target, scope)?, TypeTok::Map(_, _)) =>
+                    {
+                        let map = self.compile_expr(*target, scope)?;
+                        let key = self.compile_expr(*index, scope)?;
+                        self.builder.map_store(map, key, val)?;
+                    }
                     Ast::IndexAccess(target, index, _) => {
                         let arr = self.compile_expr(*target, scope)?;
                         let idx = self.compile_expr(*index, scope)?;
@@ -979,11 +1207,7 @@ impl AstToIrConverter {
                             Some(TirType::F64) => 3, // Float element
                             _ => 2,                  // Default to Int
                         };
-                        let type_param = self.builder.iconst(type_val, TypeTok::Int)?;
-
-                        let write_params = vec![arr, val, idx, type_param];
-                        self.builder
-                            .call("toy_write_to_arr".to_string(), write_params)?;
+                        self.builder.arr_store(arr, idx, val, type_val)?;
                     }
                     Ast::MemberAccess(target, field_name, _) => {
                         let struct_val = self.compile_expr(*target, scope)?;
@@ -1026,7 +1250,7 @@ impl AstToIrConverter {
             Ast::IfStmt(_, _, _, _) => self.compile_if_stmt(node, scope)?,
             Ast::WhileStmt(_, _, _) => self.compile_while_stmt(node, scope)?,
             Ast::FuncDec(_, _, _, _, _) => self.compile_func_dec(node, scope)?,
-            Ast::ExternFuncDec(_, _, _, _) => self.compile_extern_func_dec(node, scope)?,
+            Ast::ExternFuncDec(_, _, _, _, _) => self.compile_extern_func_dec(node, scope)?,
             Ast::Return(v, _) => {
                 let ast_val = *v;
                 let compiled_val = self.compile_expr(ast_val, scope)?;
@@ -1057,7 +1281,7 @@ impl AstToIrConverter {
                             let prefix = name.replace(".", "::");
                             for b in boxes {
                                 match b {
-                                    TBox::ExternFuncDec(name_tok, params, ret_type, _) => {
+                                    TBox::ExternFuncDec(name_tok, params, ret_type, _, _) => {
                                         if let Some(n) = name_tok.get_var_name() {
                                             let full_name = format!("{}::{}", prefix, n);
                                             let doesnt_take_ownership_list: Vec<bool> = params
@@ -1171,6 +1395,8 @@ impl AstToIrConverter {
                     })?;
                 self.builder.jump_block_un_cond(merge_id)?;
             }
+            //the driver hands these to the linker
+            Ast::LinkStmt(_, _) => {}
 
             _ => todo!("Chase you have not implemented {} yet", node),
         };
@@ -1245,14 +1471,21 @@ impl AstToIrConverter {
             "toy_write_to_arr".to_string(),
             false,
             TypeTok::Void,
-            vec![true, true, true, true],
+            vec![true, true, true, true, true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_arr_push".to_string(),
+            false,
+            TypeTok::Void,
+            vec![true, true, true],
             true,
         );
         self.builder.register_extern(
             "toy_read_from_arr".to_string(),
             false,
             TypeTok::Int,
-            vec![true, true],
+            vec![true, true, true],
             true,
         );
         self.builder
@@ -1268,6 +1501,55 @@ impl AstToIrConverter {
             vec![false],
             true,
         );
+        self.builder.register_extern(
+            "toy_map_has".to_string(),
+            false,
+            TypeTok::Int,
+            vec![true, true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_map_remove".to_string(),
+            false,
+            TypeTok::Void,
+            vec![true, true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_map_len".to_string(),
+            false,
+            TypeTok::Int,
+            vec![true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_map_keys".to_string(),
+            true,
+            TypeTok::AnyArr(1),
+            vec![true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_map_values".to_string(),
+            true,
+            TypeTok::AnyArr(1),
+            vec![true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_free_map".to_string(),
+            false,
+            TypeTok::Void,
+            vec![false],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_json_encode".to_string(),
+            true,
+            TypeTok::Str,
+            vec![true, true],
+            true,
+        );
         self.builder.register_extern(
             "toy_malloc_struct".to_string(),
             true,
@@ -1275,6 +1557,65 @@ impl AstToIrConverter {
             vec![true, true],
             true,
         );
+        self.builder.register_extern(
+            "toy_sanitize_site".to_string(),
+            false,
+            TypeTok::Void,
+            vec![true, true],
+            true,
+        );
+        //assert and assert_eq, runtimeRS/src/testing.rs
+        self.builder.register_extern(
+            "toy_test_assert".to_string(),
+            false,
+            TypeTok::Void,
+            vec![true, true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_test_assert_eq".to_string(),
+            false,
+            TypeTok::Void,
+            vec![true, true, true],
+            true,
+        );
+        //the `toy test` harness asks it which tests to run
+        self.builder.register_extern(
+            "toy_test_selected".to_string(),
+            false,
+            TypeTok::Int,
+            vec![true],
+            true,
+        );
+        //the REPL's variables, runtimeRS/src/stub.rs. The stored value is handed to the runtime
+        self.builder.register_extern(
+            "toy_repl_store".to_string(),
+            false,
+            TypeTok::Void,
+            vec![true, false],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_repl_load".to_string(),
+            false,
+            TypeTok::Int,
+            vec![true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_repl_load_float".to_string(),
+            false,
+            TypeTok::Float,
+            vec![true],
+            true,
+        );
+        self.builder.register_extern(
+            "toy_repl_load_ptr".to_string(),
+            false,
+            TypeTok::Any,
+            vec![true],
+            true,
+        );
     }
     ///ast to convert, is_main_module, and module name
     pub fn convert(
@@ -1297,9 +1638,28 @@ impl AstToIrConverter {
         self.builder
             .new_func(Box::new(self.main_func_name.clone()), vec![], TypeTok::Int);
         let user_main_scope = Scope::new_child(&self.global_scope);
+        let mut repl_globals = if is_main {
+            self.repl_globals.clone()
+        } else {
+            None
+        };
         for node in ast {
+            if !AstToIrConverter::is_declaration(&node)
+                && let Some(globals) = repl_globals.take()
+            {
+                for (name, ty) in globals {
+                    let val = self.load_repl_global(&name, &ty)?;
+                    user_main_scope.borrow_mut().set_var(name, val, ty);
+                }
+            }
             self.compile_stmt(node, &user_main_scope)?;
         }
+        if is_main && self.repl_globals.is_some() {
+            let top_level = user_main_scope.borrow().vars.clone();
+            for (name, (val, ty)) in top_level {
+                self.store_repl_global(&name, val, &ty)?;
+            }
+        }
         //seems bad
         let to_res = self.builder.iconst(0, TypeTok::Int)?;
         self.builder.ret(to_res);
@@ -1200,7 +1200,7 @@ fn test_tirgen_arr_lit_read_and_write() {
             name: Box::new("user_main".to_string()),
             params: vec![],
             ret_type: TirType::I64,
-            ins_counter: 26,
+            ins_counter: 20,
             body: vec![Block {
                 id: 0,
                 ins: vec![
@@ -1208,175 +1208,116 @@ fn test_tirgen_arr_lit_read_and_write() {
                     TIR::IConst(1, 2, TirType::I64),
                     TIR::IConst(2, 3, TirType::I64),
                     TIR::IConst(3, 3, TirType::I64),
-                    TIR::IConst(4, 2, TirType::I64),
-                    TIR::IConst(5, 1, TirType::I64),
-                    TIR::CallExternFunction(
-                        6,
-                        Box::new("toy_malloc_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 3,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 4,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 5,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        true,
-                        TirType::Ptr,
-                        vec![true],
+                    TIR::AllocArr(
+                        4,
+                        SSAValue {
+                            val: 3,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
+                        1,
                     ),
-                    TIR::IConst(7, 0, TirType::I64),
-                    TIR::IConst(8, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        9,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 0,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 7,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 8,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
+                    TIR::IConst(5, 0, TirType::I64),
+                    TIR::ArrStore(
+                        6,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 5,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 0,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
                     ),
-                    TIR::IConst(10, 1, TirType::I64),
-                    TIR::IConst(11, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        12,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 1,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 10,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 11,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
+                    TIR::IConst(7, 1, TirType::I64),
+                    TIR::ArrStore(
+                        8,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 7,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 1,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
                     ),
-                    TIR::IConst(13, 2, TirType::I64),
-                    TIR::IConst(14, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        15,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 2,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 13,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 14,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
+                    TIR::IConst(9, 2, TirType::I64),
+                    TIR::ArrStore(
+                        10,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 9,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 2,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
                     ),
-                    TIR::IConst(16, 9, TirType::I64),
-                    TIR::IConst(17, 2, TirType::I64),
-                    TIR::IConst(18, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        19,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 16,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 17,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 18,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
+                    TIR::IConst(11, 9, TirType::I64),
+                    TIR::IConst(12, 2, TirType::I64),
+                    TIR::ArrStore(
+                        13,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 12,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 11,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
                     ),
-                    TIR::IConst(20, 1, TirType::I64),
-                    TIR::CallExternFunction(
-                        21,
-                        Box::new("toy_read_from_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 20,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
+                    TIR::IConst(14, 1, TirType::I64),
+                    TIR::ArrLoad(
+                        15,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 14,
+                            ty: Some(TirType::I64),
+                        },
                         TirType::I64,
-                        vec![true],
                     ),
-                    TIR::IConst(22, 3, TirType::I64),
+                    TIR::IConst(16, 3, TirType::I64),
                     TIR::NumericInfix(
-                        23,
+                        17,
                         SSAValue {
-                            val: 21,
+                            val: 15,
                             ty: Some(TirType::I64),
                         },
                         SSAValue {
-                            val: 22,
+                            val: 16,
                             ty: Some(TirType::I64),
                         },
                         NumericInfixOp::Plus,
                     ),
                     // Implicit return 0
-                    TIR::IConst(24, 0, TirType::I64),
+                    TIR::IConst(18, 0, TirType::I64),
                     TIR::Ret(
-                        25,
+                        19,
                         SSAValue {
-                            val: 24,
+                            val: 18,
                             ty: Some(TirType::I64),
                         },
                     ),
@@ -1904,7 +1845,7 @@ fn test_tirgen_print_arr_lit() {
             name: Box::new("user_main".to_string()),
             params: vec![],
             ret_type: TirType::I64,
-            ins_counter: 21,
+            ins_counter: 16,
             body: vec![Block {
                 id: 0,
                 ins: vec![
@@ -1912,126 +1853,82 @@ fn test_tirgen_print_arr_lit() {
                     TIR::IConst(1, 2, TirType::I64),
                     TIR::IConst(2, 3, TirType::I64),
                     TIR::IConst(3, 3, TirType::I64),
-                    TIR::IConst(4, 2, TirType::I64),
-                    TIR::IConst(5, 1, TirType::I64),
-                    TIR::CallExternFunction(
+                    TIR::AllocArr(
+                        4,
+                        SSAValue {
+                            val: 3,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
+                        1,
+                    ),
+                    TIR::IConst(5, 0, TirType::I64),
+                    TIR::ArrStore(
                         6,
-                        Box::new("toy_malloc_arr".to_string()),
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 5,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 0,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
+                    ),
+                    TIR::IConst(7, 1, TirType::I64),
+                    TIR::ArrStore(
+                        8,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 7,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 1,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
+                    ),
+                    TIR::IConst(9, 2, TirType::I64),
+                    TIR::ArrStore(
+                        10,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 9,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 2,
+                            ty: Some(TirType::I64),
+                        },
+                        2,
+                    ),
+                    TIR::IConst(11, 6, TirType::I64),
+                    TIR::IConst(12, 1, TirType::I64),
+                    TIR::CallExternFunction(
+                        13,
+                        Box::new("toy_println".to_string()),
                         vec![
-                            SSAValue {
-                                val: 3,
-                                ty: Some(TirType::I64),
-                            },
                             SSAValue {
                                 val: 4,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 5,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        true,
-                        TirType::Ptr,
-                        vec![true],
-                    ),
-                    TIR::IConst(7, 0, TirType::I64),
-                    TIR::IConst(8, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        9,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 0,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 7,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 8,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
-                    ),
-                    TIR::IConst(10, 1, TirType::I64),
-                    TIR::IConst(11, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        12,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
                                 ty: Some(TirType::Ptr),
                             },
-                            SSAValue {
-                                val: 1,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 10,
-                                ty: Some(TirType::I64),
-                            },
                             SSAValue {
                                 val: 11,
                                 ty: Some(TirType::I64),
                             },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
-                    ),
-                    TIR::IConst(13, 2, TirType::I64),
-                    TIR::IConst(14, 2, TirType::I64),
-                    TIR::CallExternFunction(
-                        15,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 2,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 13,
-                                ty: Some(TirType::I64),
-                            },
                             SSAValue {
-                                val: 14,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
-                    ),
-                    TIR::IConst(16, 6, TirType::I64),
-                    TIR::IConst(17, 1, TirType::I64),
-                    TIR::CallExternFunction(
-                        18,
-                        Box::new("toy_println".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 16,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 17,
+                                val: 12,
                                 ty: Some(TirType::I64),
                             },
                         ],
@@ -2039,11 +1936,11 @@ fn test_tirgen_print_arr_lit() {
                         TirType::Void,
                         vec![true],
                     ),
-                    TIR::IConst(19, 0, TirType::I64),
+                    TIR::IConst(14, 0, TirType::I64),
                     TIR::Ret(
-                        20,
+                        15,
                         SSAValue {
-                            val: 19,
+                            val: 14,
                             ty: Some(TirType::I64),
                         },
                     ),
@@ -2526,181 +2423,133 @@ println(points);
                                 vec![true],
                             ),
                             TIR::IConst(19, 3, TirType::I64),
-                            TIR::IConst(20, 8, TirType::I64),
-                            TIR::IConst(21, 1, TirType::I64),
-                            TIR::CallExternFunction(
-                                22,
-                                Box::new("toy_malloc_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 19,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 20,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 21,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                true,
-                                TirType::Ptr,
-                                vec![true],
+                            TIR::AllocArr(
+                                20,
+                                SSAValue {
+                                    val: 19,
+                                    ty: Some(TirType::I64),
+                                },
+                                8,
+                                1,
                             ),
-                            TIR::IConst(23, 0, TirType::I64),
-                            TIR::IConst(24, 8, TirType::I64),
-                            TIR::CallExternFunction(
-                                25,
-                                Box::new("toy_write_to_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 22,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 8,
-                                        ty: Some(TirType::StructInterface(vec![
-                                            TirType::F64,
-                                            TirType::F64,
-                                        ])),
-                                    },
-                                    SSAValue {
-                                        val: 23,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 24,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::Void,
-                                vec![true],
+                            TIR::IConst(21, 0, TirType::I64),
+                            TIR::ArrStore(
+                                22,
+                                SSAValue {
+                                    val: 20,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 21,
+                                    ty: Some(TirType::I64),
+                                },
+                                SSAValue {
+                                    val: 8,
+                                    ty: Some(TirType::StructInterface(vec![
+                                        TirType::F64,
+                                        TirType::F64,
+                                    ])),
+                                },
+                                8,
                             ),
-                            TIR::IConst(26, 1, TirType::I64),
-                            TIR::IConst(27, 8, TirType::I64),
-                            TIR::CallExternFunction(
-                                28,
-                                Box::new("toy_write_to_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 22,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 13,
-                                        ty: Some(TirType::StructInterface(vec![
-                                            TirType::F64,
-                                            TirType::F64,
-                                        ])),
-                                    },
-                                    SSAValue {
-                                        val: 26,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 27,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::Void,
-                                vec![true],
+                            TIR::IConst(23, 1, TirType::I64),
+                            TIR::ArrStore(
+                                24,
+                                SSAValue {
+                                    val: 20,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 23,
+                                    ty: Some(TirType::I64),
+                                },
+                                SSAValue {
+                                    val: 13,
+                                    ty: Some(TirType::StructInterface(vec![
+                                        TirType::F64,
+                                        TirType::F64,
+                                    ])),
+                                },
+                                8,
                             ),
-                            TIR::IConst(29, 2, TirType::I64),
-                            TIR::IConst(30, 8, TirType::I64),
-                            TIR::CallExternFunction(
-                                31,
-                                Box::new("toy_write_to_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 22,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 18,
-                                        ty: Some(TirType::StructInterface(vec![
-                                            TirType::F64,
-                                            TirType::F64,
-                                        ])),
-                                    },
-                                    SSAValue {
-                                        val: 29,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 30,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::Void,
-                                vec![true],
+                            TIR::IConst(25, 2, TirType::I64),
+                            TIR::ArrStore(
+                                26,
+                                SSAValue {
+                                    val: 20,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 25,
+                                    ty: Some(TirType::I64),
+                                },
+                                SSAValue {
+                                    val: 18,
+                                    ty: Some(TirType::StructInterface(vec![
+                                        TirType::F64,
+                                        TirType::F64,
+                                    ])),
+                                },
+                                8,
                             ),
-                            TIR::IConst(32, 0, TirType::I64),
-                            TIR::JumpBlockUnCond(33, 2),
+                            TIR::IConst(27, 0, TirType::I64),
+                            TIR::JumpBlockUnCond(28, 2),
                         ],
                     },
                     Block {
                         id: 2,
                         ins: vec![
                             TIR::Phi(
-                                34,
+                                29,
                                 vec![0, 3],
                                 vec![
                                     SSAValue {
-                                        val: 22,
+                                        val: 20,
                                         ty: Some(TirType::Ptr),
                                     },
                                     SSAValue {
-                                        val: 34,
+                                        val: 29,
                                         ty: Some(TirType::Ptr),
                                     },
                                 ],
                             ),
                             TIR::Phi(
-                                35,
+                                30,
                                 vec![0, 3],
                                 vec![
                                     SSAValue {
-                                        val: 32,
+                                        val: 27,
                                         ty: Some(TirType::I64),
                                     },
                                     SSAValue {
-                                        val: 47,
+                                        val: 42,
                                         ty: Some(TirType::I64),
                                     },
                                 ],
                             ),
-                            TIR::CallExternFunction(
-                                36,
-                                Box::new("toy_arrlen".to_string()),
-                                vec![SSAValue {
-                                    val: 34,
+                            TIR::ArrLen(
+                                31,
+                                SSAValue {
+                                    val: 29,
                                     ty: Some(TirType::Ptr),
-                                }],
-                                false,
-                                TirType::I64,
-                                vec![true],
+                                },
                             ),
                             TIR::BoolInfix(
-                                37,
+                                32,
                                 SSAValue {
-                                    val: 35,
+                                    val: 30,
                                     ty: Some(TirType::I64),
                                 },
                                 SSAValue {
-                                    val: 36,
+                                    val: 31,
                                     ty: Some(TirType::I64),
                                 },
                                 BoolInfixOp::LessThan,
                             ),
                             TIR::JumpCond(
-                                38,
+                                33,
                                 SSAValue {
-                                    val: 37,
+                                    val: 32,
                                     ty: Some(TirType::I1),
                                 },
                                 3,
@@ -2711,104 +2560,99 @@ println(points);
                     Block {
                         id: 3,
                         ins: vec![
-                            TIR::CallExternFunction(
-                                39,
-                                Box::new("toy_read_from_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 34,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 35,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::I64,
-                                vec![true],
+                            TIR::ArrLoad(
+                                34,
+                                SSAValue {
+                                    val: 29,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 30,
+                                    ty: Some(TirType::I64),
+                                },
+                                TirType::StructInterface(vec![TirType::F64, TirType::F64]),
                             ),
-                            TIR::FConst(40, 5.0, TirType::F64),
-                            TIR::IConst(41, 0, TirType::I64),
-                            TIR::FConst(42, 2.0, TirType::F64),
+                            TIR::FConst(35, 5.0, TirType::F64),
+                            TIR::IConst(36, 0, TirType::I64),
+                            TIR::FConst(37, 2.0, TirType::F64),
                             TIR::ItoF(
-                                43,
+                                38,
                                 SSAValue {
-                                    val: 41,
+                                    val: 36,
                                     ty: Some(TirType::I64),
                                 },
                                 TirType::F64,
                             ),
                             TIR::NumericInfix(
-                                44,
+                                39,
                                 SSAValue {
-                                    val: 43,
+                                    val: 38,
                                     ty: Some(TirType::F64),
                                 },
                                 SSAValue {
-                                    val: 42,
+                                    val: 37,
                                     ty: Some(TirType::F64),
                                 },
                                 NumericInfixOp::Minus,
                             ),
                             TIR::CallLocalFunction(
-                                45,
+                                40,
                                 Box::new("Point:::move_struct_float_float".to_string()),
                                 vec![
                                     SSAValue {
-                                        val: 39,
+                                        val: 34,
                                         ty: Some(TirType::StructInterface(vec![
                                             TirType::F64,
                                             TirType::F64,
                                         ])),
                                     },
                                     SSAValue {
-                                        val: 40,
+                                        val: 35,
                                         ty: Some(TirType::F64),
                                     },
                                     SSAValue {
-                                        val: 44,
+                                        val: 39,
                                         ty: Some(TirType::F64),
                                     },
                                 ],
                                 false,
                                 TirType::Void,
                             ),
-                            TIR::IConst(46, 1, TirType::I64),
+                            TIR::IConst(41, 1, TirType::I64),
                             TIR::NumericInfix(
-                                47,
+                                42,
                                 SSAValue {
-                                    val: 35,
+                                    val: 30,
                                     ty: Some(TirType::I64),
                                 },
                                 SSAValue {
-                                    val: 46,
+                                    val: 41,
                                     ty: Some(TirType::I64),
                                 },
                                 NumericInfixOp::Plus,
                             ),
-                            TIR::JumpBlockUnCond(48, 2),
+                            TIR::JumpBlockUnCond(43, 2),
                         ],
                     },
                     Block {
                         id: 4,
                         ins: vec![
-                            TIR::IConst(49, 4, TirType::I64),
-                            TIR::IConst(50, 1, TirType::I64),
+                            TIR::IConst(44, 4, TirType::I64),
+                            TIR::IConst(45, 1, TirType::I64),
                             TIR::CallExternFunction(
-                                51,
+                                46,
                                 Box::new("toy_println".to_string()),
                                 vec![
                                     SSAValue {
-                                        val: 34,
+                                        val: 29,
                                         ty: Some(TirType::Ptr),
                                     },
                                     SSAValue {
-                                        val: 49,
+                                        val: 44,
                                         ty: Some(TirType::I64),
                                     },
                                     SSAValue {
-                                        val: 50,
+                                        val: 45,
                                         ty: Some(TirType::I64),
                                     },
                                 ],
@@ -2816,18 +2660,18 @@ println(points);
                                 TirType::Void,
                                 vec![true],
                             ),
-                            TIR::IConst(52, 0, TirType::I64),
+                            TIR::IConst(47, 0, TirType::I64),
                             TIR::Ret(
-                                53,
+                                48,
                                 SSAValue {
-                                    val: 52,
+                                    val: 47,
                                     ty: Some(TirType::I64),
                                 },
                             ),
                         ],
                     },
                 ],
-                ins_counter: 54,
+                ins_counter: 49,
                 ret_type: TirType::I64,
                 heap_allocations: vec![],
                 heap_counter: 4,
@@ -3162,66 +3006,52 @@ fn test_tirgen_weird_loop_bug() {
                         id: 1,
                         ins: vec![
                             TIR::IConst(2, 0, TirType::I64),
-                            TIR::IConst(3, 0, TirType::I64),
-                            TIR::IConst(4, 1, TirType::I64),
-                            TIR::CallExternFunction(
-                                5,
-                                Box::new("toy_malloc_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 2,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 3,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 4,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                true,
-                                TirType::Ptr,
-                                vec![true],
+                            TIR::AllocArr(
+                                3,
+                                SSAValue {
+                                    val: 2,
+                                    ty: Some(TirType::I64),
+                                },
+                                0,
+                                1,
                             ),
-                            TIR::IConst(6, 0, TirType::I64),
-                            TIR::JumpBlockUnCond(7, 2),
+                            TIR::IConst(4, 0, TirType::I64),
+                            TIR::JumpBlockUnCond(5, 2),
                         ],
                     },
                     Block {
                         id: 2,
                         ins: vec![
                             TIR::Phi(
-                                8,
+                                6,
                                 vec![1, 3],
                                 vec![
                                     SSAValue {
-                                        val: 5,
+                                        val: 3,
                                         ty: Some(TirType::Ptr),
                                     },
                                     SSAValue {
-                                        val: 8,
+                                        val: 6,
                                         ty: Some(TirType::Ptr),
                                     },
                                 ],
                             ),
                             TIR::Phi(
-                                9,
+                                7,
                                 vec![1, 3],
                                 vec![
                                     SSAValue {
-                                        val: 6,
+                                        val: 4,
                                         ty: Some(TirType::I64),
                                     },
                                     SSAValue {
-                                        val: 19,
+                                        val: 16,
                                         ty: Some(TirType::I64),
                                     },
                                 ],
                             ),
                             TIR::Phi(
-                                10,
+                                8,
                                 vec![1, 3],
                                 vec![
                                     SSAValue {
@@ -3229,13 +3059,13 @@ fn test_tirgen_weird_loop_bug() {
                                         ty: Some(TirType::Ptr),
                                     },
                                     SSAValue {
-                                        val: 10,
+                                        val: 8,
                                         ty: Some(TirType::Ptr),
                                     },
                                 ],
                             ),
                             TIR::Phi(
-                                11,
+                                9,
                                 vec![1, 3],
                                 vec![
                                     SSAValue {
@@ -3243,147 +3073,118 @@ fn test_tirgen_weird_loop_bug() {
                                         ty: Some(TirType::Ptr),
                                     },
                                     SSAValue {
-                                        val: 11,
+                                        val: 9,
                                         ty: Some(TirType::Ptr),
                                     },
                                 ],
                             ),
-                            TIR::CallExternFunction(
-                                12,
-                                Box::new("toy_arrlen".to_string()),
-                                vec![SSAValue {
-                                    val: 11,
+                            TIR::ArrLen(
+                                10,
+                                SSAValue {
+                                    val: 9,
                                     ty: Some(TirType::Ptr),
-                                }],
-                                false,
-                                TirType::I64,
-                                vec![true],
+                                },
                             ),
                             TIR::BoolInfix(
+                                11,
+                                SSAValue {
+                                    val: 7,
+                                    ty: Some(TirType::I64),
+                                },
+                                SSAValue {
+                                    val: 10,
+                                    ty: Some(TirType::I64),
+                                },
+                                BoolInfixOp::LessThan,
+                            ),
+                            TIR::JumpCond(
+                                12,
+                                SSAValue {
+                                    val: 11,
+                                    ty: Some(TirType::I1),
+                                },
+                                3,
+                                4,
+                            ),
+                        ],
+                    },
+                    Block {
+                        id: 3,
+                        ins: vec![
+                            TIR::ArrLoad(
                                 13,
                                 SSAValue {
                                     val: 9,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 7,
                                     ty: Some(TirType::I64),
                                 },
+                                TirType::Ptr,
+                            ),
+                            TIR::ArrStore(
+                                14,
                                 SSAValue {
-                                    val: 12,
+                                    val: 6,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 7,
                                     ty: Some(TirType::I64),
                                 },
-                                BoolInfixOp::LessThan,
-                            ),
-                            TIR::JumpCond(
-                                14,
                                 SSAValue {
                                     val: 13,
-                                    ty: Some(TirType::I1),
+                                    ty: Some(TirType::Ptr),
                                 },
-                                3,
-                                4,
-                            ),
-                        ],
-                    },
-                    Block {
-                        id: 3,
-                        ins: vec![
-                            TIR::CallExternFunction(
-                                15,
-                                Box::new("toy_read_from_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 11,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 9,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::I64,
-                                vec![true],
-                            ),
-                            TIR::IConst(16, 0, TirType::I64),
-                            TIR::CallExternFunction(
-                                17,
-                                Box::new("toy_write_to_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 8,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 15,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 9,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 16,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::Void,
-                                vec![true],
+                                0,
                             ),
-                            TIR::IConst(18, 1, TirType::I64),
+                            TIR::IConst(15, 1, TirType::I64),
                             TIR::NumericInfix(
-                                19,
+                                16,
                                 SSAValue {
-                                    val: 9,
+                                    val: 7,
                                     ty: Some(TirType::I64),
                                 },
                                 SSAValue {
-                                    val: 18,
+                                    val: 15,
                                     ty: Some(TirType::I64),
                                 },
                                 NumericInfixOp::Plus,
                             ),
-                            TIR::JumpBlockUnCond(20, 2),
+                            TIR::JumpBlockUnCond(17, 2),
                         ],
                     },
                     Block {
                         id: 4,
                         ins: vec![
-                            TIR::IConst(21, 0, TirType::I64),
-                            TIR::CallExternFunction(
-                                22,
-                                Box::new("toy_write_to_arr".to_string()),
-                                vec![
-                                    SSAValue {
-                                        val: 8,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 10,
-                                        ty: Some(TirType::Ptr),
-                                    },
-                                    SSAValue {
-                                        val: 9,
-                                        ty: Some(TirType::I64),
-                                    },
-                                    SSAValue {
-                                        val: 21,
-                                        ty: Some(TirType::I64),
-                                    },
-                                ],
-                                false,
-                                TirType::Void,
-                                vec![true],
+                            TIR::ArrStore(
+                                18,
+                                SSAValue {
+                                    val: 6,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                SSAValue {
+                                    val: 7,
+                                    ty: Some(TirType::I64),
+                                },
+                                SSAValue {
+                                    val: 8,
+                                    ty: Some(TirType::Ptr),
+                                },
+                                0,
                             ),
                             TIR::Ret(
-                                23,
+                                19,
                                 SSAValue {
-                                    val: 8,
+                                    val: 6,
                                     ty: Some(TirType::Ptr),
                                 },
                             ),
                         ],
                     },
                 ],
-                ins_counter: 24,
+                ins_counter: 20,
                 heap_allocations: vec![],
                 heap_counter: 0,
             },
@@ -3976,110 +3777,72 @@ fn test_tirgen_ins_elimination_bug() {
                         vec![true],
                     ),
                     TIR::IConst(4, 2, TirType::I64),
-                    TIR::IConst(5, 0, TirType::I64),
-                    TIR::IConst(6, 1, TirType::I64),
-                    TIR::CallExternFunction(
+                    TIR::AllocArr(
+                        5,
+                        SSAValue {
+                            val: 4,
+                            ty: Some(TirType::I64),
+                        },
+                        0,
+                        1,
+                    ),
+                    TIR::IConst(6, 0, TirType::I64),
+                    TIR::ArrStore(
                         7,
-                        Box::new("toy_malloc_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 4,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 5,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 6,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        true,
-                        TirType::Ptr,
-                        vec![true],
+                        SSAValue {
+                            val: 5,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 6,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 1,
+                            ty: Some(TirType::Ptr),
+                        },
+                        0,
                     ),
-                    TIR::IConst(8, 0, TirType::I64),
-                    TIR::IConst(9, 0, TirType::I64),
-                    TIR::CallExternFunction(
+                    TIR::IConst(8, 1, TirType::I64),
+                    TIR::ArrStore(
+                        9,
+                        SSAValue {
+                            val: 5,
+                            ty: Some(TirType::Ptr),
+                        },
+                        SSAValue {
+                            val: 8,
+                            ty: Some(TirType::I64),
+                        },
+                        SSAValue {
+                            val: 3,
+                            ty: Some(TirType::Ptr),
+                        },
+                        0,
+                    ),
+                    TIR::ArrLen(
                         10,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 7,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 1,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 8,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 9,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
+                        SSAValue {
+                            val: 5,
+                            ty: Some(TirType::Ptr),
+                        },
                     ),
-                    TIR::IConst(11, 1, TirType::I64),
+                    TIR::IConst(11, 2, TirType::I64),
                     TIR::IConst(12, 0, TirType::I64),
                     TIR::CallExternFunction(
                         13,
-                        Box::new("toy_write_to_arr".to_string()),
-                        vec![
-                            SSAValue {
-                                val: 7,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 3,
-                                ty: Some(TirType::Ptr),
-                            },
-                            SSAValue {
-                                val: 11,
-                                ty: Some(TirType::I64),
-                            },
-                            SSAValue {
-                                val: 12,
-                                ty: Some(TirType::I64),
-                            },
-                        ],
-                        false,
-                        TirType::Void,
-                        vec![true],
-                    ),
-                    TIR::CallExternFunction(
-                        14,
-                        Box::new("toy_arrlen".to_string()),
-                        vec![SSAValue {
-                            val: 7,
-                            ty: Some(TirType::Ptr),
-                        }],
-                        false,
-                        TirType::I64,
-                        vec![true],
-                    ),
-                    TIR::IConst(15, 2, TirType::I64),
-                    TIR::IConst(16, 0, TirType::I64),
-                    TIR::CallExternFunction(
-                        17,
                         Box::new("toy_println".to_string()),
                         vec![
                             SSAValue {
-                                val: 14,
+                                val: 10,
                                 ty: Some(TirType::I64),
                             },
                             SSAValue {
-                                val: 15,
+                                val: 11,
                                 ty: Some(TirType::I64),
                             },
                             SSAValue {
-                                val: 16,
+                                val: 12,
                                 ty: Some(TirType::I64),
                             },
                         ],
@@ -4087,11 +3850,11 @@ fn test_tirgen_ins_elimination_bug() {
                         TirType::Void,
                         vec![true],
                     ),
-                    TIR::IConst(18, 0, TirType::I64),
+                    TIR::IConst(14, 0, TirType::I64),
                     TIR::Ret(
-                        19,
+                        15,
                         SSAValue {
-                            val: 18,
+                            val: 14,
                             ty: Some(TirType::I64),
                         },
                     ),
@@ -4101,3 +3864,112 @@ fn test_tirgen_ins_elimination_bug() {
         }],
     )
 }
+
+#[test]
+fn test_tir_sanitize_emits_site_markers() {
+    let (ast, _driver) = parse_test_code("let x = \"hi\"; println(x);");
+    let mut t = AstToIrConverter::new();
+    t.set_sanitize(true);
+    let funcs = t.convert(ast, true, "test").unwrap();
+    let site_calls = funcs[0]
+        .body
+        .iter()
+        .flat_map(|b| b.ins.iter())
+        .filter(|ins| {
+            matches!(ins, TIR::CallExternFunction(_, name, _, _, _, _) if **name == *"toy_sanitize_site")
+        })
+        .count();
+    assert_eq!(site_calls, 2);
+}
+
+#[test]
+fn test_tir_sanitize_frames_wrap_functions() {
+    setup_tir!(o, "fn f(): int { return 1; } let x = f();");
+    let mut builder = AstToIrConverter::new().builder;
+    builder.set_funcs(o, 0);
+    builder.splice_sanitize_frames();
+    let is_call = |ins: &TIR, name: &str| {
+        matches!(ins, TIR::CallExternFunction(_, f, _, false, _, _) if **f == *name)
+    };
+    for func in &builder.funcs {
+        assert!(
+            is_call(&func.body[0].ins[0], "toy_sanitize_site_push"),
+            "{} does not start by pushing a site",
+            func.name
+        );
+        let ins: Vec<&TIR> = func.body.iter().flat_map(|b| b.ins.iter()).collect();
+        for (i, ret) in ins.iter().enumerate() {
+            if let TIR::Ret(..) = ret {
+                assert!(
+                    is_call(ins[i - 1], "toy_sanitize_site_pop"),
+                    "a return of {} does not pop its site",
+                    func.name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_tir_profile_sites_wrap_allocations() {
+    setup_tir!(o, "let x = \"a\" + \"b\";");
+    let mut builder = AstToIrConverter::new().builder;
+    builder.set_funcs(o, 0);
+    builder.splice_profile_sites();
+    let ins: Vec<&TIR> = builder.funcs[0].body.iter().flat_map(|b| b.ins.iter()).collect();
+    for (i, alloc) in ins.iter().enumerate() {
+        if let TIR::CallExternFunction(_, name, _, true, _, _) = alloc {
+            assert!(
+                matches!(ins[i - 1], TIR::CallExternFunction(_, site_fn, _, false, _, _) if **site_fn == *"toy_profile_site"),
+                "{} is not preceded by a profile site",
+                name
+            );
+            assert!(
+                matches!(ins[i + 1], TIR::CallExternFunction(_, end_fn, _, false, _, _) if **end_fn == *"toy_profile_site_end"),
+                "{} is not followed by the end of its profile site",
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_tir_records_spans_and_var_bindings() {
+    let (ast, _driver) = parse_test_code("fn add(a: int): int { return a + 1; }\nlet x = add(2);");
+    let mut t = AstToIrConverter::new();
+    let funcs = t.convert(ast, true, "test").unwrap();
+    for func in funcs.iter().filter(|f| *f.name == "add_int") {
+        for ins in func.body.iter().flat_map(|b| b.ins.iter()) {
+            assert!(
+                t.builder
+                    .ins_spans
+                    .contains_key(&(*func.name.clone(), ins.get_id())),
+                "{:?} in {} has no span",
+                ins,
+                func.name
+            );
+        }
+    }
+    assert!(t.builder.func_spans.contains_key("add_int"));
+    let mut bound: Vec<String> = t
+        .builder
+        .var_bindings
+        .values()
+        .flatten()
+        .map(|(name, _)| name.clone())
+        .collect();
+    bound.sort();
+    assert_eq!(bound, vec!["a".to_string(), "x".to_string()]);
+}
+
+#[test]
+fn test_tir_records_demangled_func_names() {
+    let (ast, _driver) = parse_test_code("fn add(a: int, b: float): float { return b; }\nadd(1, 2.0);");
+    let mut t = AstToIrConverter::new();
+    t.convert(ast, true, "test").unwrap();
+    assert_eq!(
+        t.builder.func_display_names.get("add_int_float"),
+        Some(&"add(int, float)".to_string())
+    );
+    assert_eq!(Driver::demangle_name("user_main", &[]), "main");
+}
@@ -0,0 +1,163 @@
+//! `--target` selection. A target decides the triple LLVM compiles for, the calling convention
+//! extern calls follow, the `lib/<triple>/` directory its runtime lives in and how the executable
+//! is linked.
+use crate::driver::Driver;
+use crate::errors::{Span, ToyError, ToyErrorType};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arch {
+    X86_64,
+    AArch64,
+    Wasm32,
+}
+
+///How the objects and the runtime become an executable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkerFlavor {
+    ///ld.lld against the target's glibc, loaded by its dynamic linker
+    ElfDynamic,
+    ///ld.lld against musl's libc.a, no interpreter or shared libraries
+    ElfStatic,
+    ///ld.lld in MinGW mode
+    MinGw,
+    ///lld's wasm-ld flavor against wasi-libc, the output is a WASI command module
+    Wasm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompileTarget {
+    ///rust and LLVM triple, also the name of the target's directory in `lib/`
+    pub triple: &'static str,
+    ///shorter names `--target` accepts
+    pub aliases: &'static [&'static str],
+    pub arch: Arch,
+    pub linker: LinkerFlavor,
+}
+
+pub const TARGETS: [CompileTarget; 5] = [
+    CompileTarget {
+        triple: "x86_64-unknown-linux-gnu",
+        aliases: &["x86_64-linux-gnu"],
+        arch: Arch::X86_64,
+        linker: LinkerFlavor::ElfDynamic,
+    },
+    CompileTarget {
+        triple: "x86_64-unknown-linux-musl",
+        aliases: &["x86_64-linux-musl"],
+        arch: Arch::X86_64,
+        linker: LinkerFlavor::ElfStatic,
+    },
+    CompileTarget {
+        triple: "aarch64-unknown-linux-gnu",
+        aliases: &["aarch64-linux-gnu"],
+        arch: Arch::AArch64,
+        linker: LinkerFlavor::ElfDynamic,
+    },
+    CompileTarget {
+        triple: "x86_64-pc-windows-gnu",
+        aliases: &["x86_64-windows-gnu"],
+        arch: Arch::X86_64,
+        linker: LinkerFlavor::MinGw,
+    },
+    CompileTarget {
+        triple: "wasm32-wasip1",
+        aliases: &["wasm32-wasi"],
+        arch: Arch::Wasm32,
+        linker: LinkerFlavor::Wasm,
+    },
+];
+
+impl CompileTarget {
+    ///The target the compiler itself was built for
+    pub fn host() -> CompileTarget {
+        let host = env!("TARGET").replace("\"", "");
+        return CompileTarget::parse(&host).unwrap_or(TARGETS[0]);
+    }
+    ///Looks a target up by its triple or one of its aliases
+    pub fn parse(name: &str) -> Option<CompileTarget> {
+        return TARGETS
+            .iter()
+            .find(|t| t.triple == name || t.aliases.contains(&name))
+            .copied();
+    }
+    ///The target picked by `--target <triple>` or `--target=<triple>`, `--static` is short for
+    ///`--target x86_64-linux-musl`. Defaults to the host
+    pub fn from_args() -> Result<CompileTarget, ToyError> {
+        let args: Vec<String> = Driver::args();
+        let value = args.iter().enumerate().find_map(|(i, a)| {
+            if a == "--target" {
+                Some(args.get(i + 1).cloned().unwrap_or_default())
+            } else {
+                a.strip_prefix("--target=").map(|v| v.to_string())
+            }
+        });
+        let static_link = args.contains(&"--static".to_string());
+        let Some(value) = value else {
+            return Ok(if static_link {
+                TARGETS[1]
+            } else {
+                CompileTarget::host()
+            });
+        };
+        let Some(target) = CompileTarget::parse(&value) else {
+            let known: Vec<&str> = TARGETS.iter().map(|t| t.triple).collect();
+            return Err(ToyError::new(
+                ToyErrorType::InvalidFlag(format!(
+                    "--target={}, expected one of {}",
+                    value,
+                    known.join(", ")
+                )),
+                Span::null_span(),
+            ));
+        };
+        if static_link && target.linker != LinkerFlavor::ElfStatic {
+            return Err(ToyError::new(
+                ToyErrorType::ConflictingFlags,
+                Span::null_span_with_msg(&format!(
+                    "--static links against musl and cannot be used with --target={}",
+                    value
+                )),
+            ));
+        }
+        return Ok(target);
+    }
+    ///`from_args` for code running after the driver validated the flags
+    pub fn current() -> CompileTarget {
+        return CompileTarget::from_args().unwrap_or_else(|_| CompileTarget::host());
+    }
+    ///`lib/<triple>/`, holds the runtime archive and the C runtime files the target links
+    pub fn lib_dir(&self) -> PathBuf {
+        return PathBuf::from("lib").join(self.triple);
+    }
+    pub fn exe_extension(&self) -> &'static str {
+        return match self.linker {
+            LinkerFlavor::MinGw => ".exe",
+            LinkerFlavor::Wasm => ".wasm",
+            _ => "",
+        };
+    }
+    ///ld.lld `-m` emulation for ELF targets
+    pub fn elf_emulation(&self) -> &'static str {
+        return match self.arch {
+            Arch::X86_64 => "elf_x86_64",
+            Arch::AArch64 => "aarch64linux",
+            Arch::Wasm32 => unreachable!("wasm is not linked as ELF"),
+        };
+    }
+    ///The program interpreter of dynamically linked ELF executables
+    pub fn dynamic_linker(&self) -> &'static str {
+        return match self.arch {
+            Arch::X86_64 => "/lib64/ld-linux-x86-64.so.2",
+            Arch::AArch64 => "/lib/ld-linux-aarch64.so.1",
+            Arch::Wasm32 => unreachable!("wasm is not linked as ELF"),
+        };
+    }
+    ///WASI has no sockets, so std.net is left out of its runtime
+    pub fn has_sockets(&self) -> bool {
+        return self.arch != Arch::Wasm32;
+    }
+}
+
+#[cfg(test)]
+mod tests;
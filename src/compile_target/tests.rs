@@ -0,0 +1,45 @@
+use super::*;
+
+#[test]
+fn test_target_parse_triples_and_aliases() {
+    let aarch64 = CompileTarget::parse("aarch64-linux-gnu").unwrap();
+    assert_eq!(aarch64.triple, "aarch64-unknown-linux-gnu");
+    assert_eq!(
+        CompileTarget::parse("aarch64-unknown-linux-gnu"),
+        Some(aarch64)
+    );
+    assert_eq!(aarch64.elf_emulation(), "aarch64linux");
+    assert_eq!(aarch64.dynamic_linker(), "/lib/ld-linux-aarch64.so.1");
+    assert_eq!(
+        aarch64.lib_dir(),
+        PathBuf::from("lib/aarch64-unknown-linux-gnu")
+    );
+
+    let musl = CompileTarget::parse("x86_64-linux-musl").unwrap();
+    assert_eq!(musl.linker, LinkerFlavor::ElfStatic);
+    assert_eq!(musl.exe_extension(), "");
+    assert_eq!(
+        CompileTarget::parse("x86_64-windows-gnu")
+            .unwrap()
+            .exe_extension(),
+        ".exe"
+    );
+    assert_eq!(CompileTarget::parse("riscv64-linux-gnu"), None);
+}
+
+#[test]
+fn test_target_wasm() {
+    let wasm = CompileTarget::parse("wasm32-wasi").unwrap();
+    assert_eq!(wasm.triple, "wasm32-wasip1");
+    assert_eq!(wasm.linker, LinkerFlavor::Wasm);
+    assert_eq!(wasm.exe_extension(), ".wasm");
+    assert!(!wasm.has_sockets());
+    assert!(CompileTarget::host().has_sockets());
+}
+
+#[test]
+fn test_target_defaults_to_host() {
+    //the test binary is run without --target
+    assert_eq!(CompileTarget::current(), CompileTarget::host());
+    assert_eq!(CompileTarget::host().triple, env!("TARGET"));
+}
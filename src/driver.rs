@@ -13,30 +13,151 @@ thread_local! {
 thread_local! {
     static BUILD_DIR: RefCell<String> = RefCell::new("build".to_string());
 }
+#[cfg(test)]
+thread_local! {
+    ///flags a test compiles with in place of the command line, see `Driver::with_args`
+    static TEST_ARGS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
 use inkwell::{context::Context, module::Module};
 
 use crate::{
-    codegen::{Generator, ctla::CTLASchema},
+    c_library::{self, LibraryKind},
+    codegen::{Function, Generator, ctla::CTLASchema},
+    compile_target::{CompileTarget, LinkerFlavor},
     errors::{Span, ToyError, ToyErrorType},
+    jit,
     lexer::Lexer,
-    parser::{ast::Ast, ast_gen::AstGenerator, boxer::Boxer, toy_box::TBox},
+    parser::{
+        ast::{Ast, InfixOp},
+        ast_gen::AstGenerator,
+        boxer::Boxer,
+        toy_box::TBox,
+    },
+    runtime_manifest,
     token::{ExternType, TypeTok},
 };
 
-pub static FILE_EXTENSION_EXE: &str = if cfg!(target_os = "windows") {
-    ".exe"
-} else {
-    ""
-};
-
-pub struct Linker {}
+pub struct Linker {
+    ///`--link-lib` values and `link "foo";` statements, names become `-lfoo`, paths to .a, .so and .o files are passed as they are
+    libs: Vec<String>,
+    ///`--link-search` directories
+    search_dirs: Vec<String>,
+    ///`--link-arg` values, handed to ld.lld untouched
+    extra_args: Vec<String>,
+}
 
 impl Linker {
     pub fn new() -> Linker {
-        Linker {}
+        Linker {
+            libs: Linker::flag_values("--link-lib"),
+            search_dirs: Linker::flag_values("--link-search"),
+            extra_args: Linker::flag_values("--link-arg"),
+        }
+    }
+
+    ///Every value given to a repeatable flag, as `--flag value` or `--flag=value`
+    fn flag_values(flag: &str) -> Vec<String> {
+        let args: Vec<String> = Driver::args();
+        let prefix = format!("{}=", flag);
+        let mut values = vec![];
+        let mut i = 1;
+        while i < args.len() {
+            if args[i] == flag && i + 1 < args.len() {
+                values.push(args[i + 1].clone());
+                i += 2;
+                continue;
+            }
+            if let Some(v) = args[i].strip_prefix(&prefix) {
+                values.push(v.to_string());
+            }
+            i += 1;
+        }
+        values
+    }
+
+    ///Adds a library named by a `link` statement, a library named twice is linked once
+    pub fn add_lib(&mut self, lib: String) {
+        if !self.libs.contains(&lib) {
+            self.libs.push(lib);
+        }
+    }
+
+    ///`-L` for every search dir followed by the user libraries and raw args, goes after the program objects
+    fn user_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = self
+            .search_dirs
+            .iter()
+            .map(|d| format!("-L{}", d))
+            .collect();
+        for lib in &self.libs {
+            let is_file = [".a", ".so", ".o", ".obj", ".lib", ".dll"]
+                .iter()
+                .any(|ext| lib.ends_with(ext))
+                || lib.contains(".so.");
+            if is_file {
+                args.push(lib.clone());
+            } else {
+                args.push(format!("-l{}", lib));
+            }
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+
+    ///Loads the user libraries into the compiler process for the JIT, which can only use shared libraries
+    pub fn load_for_jit(&self) -> Result<(), ToyError> {
+        if !self.extra_args.is_empty() {
+            return Err(ToyError::new(
+                ToyErrorType::JitFailure(
+                    "--link-arg needs a linked build, compile the program instead".to_string(),
+                ),
+                Span::null_span(),
+            ));
+        }
+        for lib in &self.libs {
+            //already part of the compiler process
+            if ["c", "m", "pthread", "dl"].contains(&lib.as_str()) {
+                continue;
+            }
+            let is_shared =
+                [".so", ".dll"].iter().any(|ext| lib.ends_with(ext)) || lib.contains(".so.");
+            let is_static = [".a", ".o", ".obj", ".lib"]
+                .iter()
+                .any(|ext| lib.ends_with(ext));
+            if is_static {
+                return Err(ToyError::new(
+                    ToyErrorType::JitFailure(format!(
+                        "{} is a static library or object file, compile the program to link it",
+                        lib
+                    )),
+                    Span::null_span(),
+                ));
+            }
+            let path = if is_shared {
+                PathBuf::from(lib)
+            } else {
+                let file = format!(
+                    "{}{}{}",
+                    env::consts::DLL_PREFIX,
+                    lib,
+                    env::consts::DLL_SUFFIX
+                );
+                self.search_dirs
+                    .iter()
+                    .map(PathBuf::from)
+                    .chain([CompileTarget::host().lib_dir()])
+                    .map(|dir| dir.join(&file))
+                    .find(|p| p.exists())
+                    //left to the system loader's search path
+                    .unwrap_or(PathBuf::from(file))
+            };
+            jit::load_library(&path)?;
+        }
+        Ok(())
     }
 
-    fn collect_static_archives(dir: &Path) -> Vec<PathBuf> {
+    ///`runtime_flavor` swaps libruntime.a for the instrumented runtime in `<dir>/<flavor>`
+    fn collect_static_archives(dir: &Path, runtime_flavor: Option<&str>) -> Vec<PathBuf> {
         let mut libs = Vec::new();
 
         let rd = match fs::read_dir(dir) {
@@ -49,7 +170,12 @@ impl Linker {
             if p.is_file() {
                 if let Some(ext) = p.extension() {
                     if ext == "a" {
-                        libs.push(p);
+                        match runtime_flavor {
+                            Some(flavor) if p.file_name().unwrap() == "libruntime.a" => {
+                                libs.push(dir.join(flavor).join("libruntime.a"));
+                            }
+                            _ => libs.push(p),
+                        }
                     }
                 }
             }
@@ -79,10 +205,19 @@ impl Linker {
         files: Vec<String>,
         output: String,
         save_temps: bool,
+        runtime_flavor: Option<&str>,
+        target: CompileTarget,
     ) -> Result<(), ToyError> {
-        let target = env!("TARGET").replace("\"", "");
-        let lib_str = format!("lib/{}/", target);
-        let lib_path = Path::new(&lib_str);
+        Linker::check_runtime(target)?;
+        match target.linker {
+            LinkerFlavor::ElfStatic => return self.link_static(files, output, save_temps, target),
+            LinkerFlavor::Wasm => return self.link_wasm(files, output, save_temps, target),
+            _ => {}
+        }
+        let lib_buf = target.lib_dir();
+        let lib_path = lib_buf.as_path();
+        //ld.lld ships with the host's lib dir
+        let host_lib = CompileTarget::host().lib_dir();
 
         let crt2_path = lib_path.join("crt2.o");
         let crtbegin_path = lib_path.join("crtbegin.o");
@@ -92,10 +227,10 @@ impl Linker {
         let libc_path = lib_path.join("libc.so.6");
         let libm_path = lib_path.join("libm.so.6");
 
-        let output_name = format!("{}{}", output, FILE_EXTENSION_EXE);
+        let output_name = format!("{}{}", output, target.exe_extension());
 
         //this seems like a bad idea...
-        let rust_self_contained_lib = if env::consts::OS == "windows" {
+        let rust_self_contained_lib = if target.linker == LinkerFlavor::MinGw {
             Command::new("rustc")
                 .args(["--print", "sysroot"])
                 .output()
@@ -113,7 +248,7 @@ impl Linker {
                     let p = Path::new(&sysroot)
                         .join("lib")
                         .join("rustlib")
-                        .join(&target)
+                        .join(target.triple)
                         .join("lib")
                         .join("self-contained");
 
@@ -123,7 +258,7 @@ impl Linker {
             None
         };
 
-        let args: Vec<String> = if env::consts::OS == "windows" {
+        let args: Vec<String> = if target.linker == LinkerFlavor::MinGw {
             let mut args: Vec<String> = vec![
                 "-m".into(),
                 "i386pep".into(),
@@ -139,7 +274,12 @@ impl Linker {
             for file in &files {
                 args.push(file.clone());
             }
+            args.extend(self.user_args());
 
+            if let Some(flavor) = runtime_flavor {
+                args.push("-L".into());
+                args.push(lib_path.join(flavor).to_string_lossy().into_owned());
+            }
             args.push("-L".into());
             args.push(lib_path.to_string_lossy().into_owned());
 
@@ -151,7 +291,7 @@ impl Linker {
             args.push("--start-group".into());
 
             // all static archives
-            for lib in Self::collect_static_archives(lib_path) {
+            for lib in Self::collect_static_archives(lib_path, runtime_flavor) {
                 let stem = lib
                     .file_stem()
                     .map(|s| s.to_string_lossy().into_owned())
@@ -183,7 +323,7 @@ impl Linker {
         } else {
             let mut args: Vec<String> = vec![
                 "-m".into(),
-                "elf_x86_64".into(),
+                target.elf_emulation().into(),
                 crt1_path.to_string_lossy().into_owned(),
                 crti_path.to_string_lossy().into_owned(),
             ];
@@ -191,23 +331,21 @@ impl Linker {
             for file in &files {
                 args.push(file.clone());
             }
+            //user libraries come before the runtime so their own libc and libm references still resolve
+            args.push(format!("-L{}", lib_path.to_string_lossy()));
+            args.extend(self.user_args());
             args.push("--start-group".to_string());
-            for lib in Self::collect_static_archives(lib_path) {
+            for lib in Self::collect_static_archives(lib_path, runtime_flavor) {
                 args.push(lib.to_string_lossy().into_owned());
             }
             args.push("--end-group".to_string());
-            args.push(
-                lib_path
-                    .join("cacert.o".to_string())
-                    .to_string_lossy()
-                    .into(),
-            );
+            args.extend(Linker::cacert(lib_path));
             args.extend_from_slice(&[
                 crtn_path.to_string_lossy().into_owned(),
                 libc_path.to_string_lossy().into_owned(),
                 libm_path.to_string_lossy().into_owned(),
                 "-dynamic-linker".into(),
-                "/lib64/ld-linux-x86-64.so.2".into(),
+                target.dynamic_linker().into(),
                 "-o".into(),
                 output_name.clone(),
             ]);
@@ -215,7 +353,190 @@ impl Linker {
             args
         };
 
-        let rstatus = Command::new(lib_path.join("ld.lld")).args(&args).status();
+        Linker::run_lld(&host_lib, &args)?;
+        Linker::remove_temps(&files, save_temps);
+
+        Ok(())
+    }
+
+    ///The embedded CA object, when the target's lib dir has one
+    fn cacert(lib_path: &Path) -> Option<String> {
+        let cacert = lib_path.join("cacert.o");
+        return cacert
+            .exists()
+            .then(|| cacert.to_string_lossy().into_owned());
+    }
+
+    ///Errors unless the runtime was built for `target`, cross targets are only built when their rust target is installed
+    fn check_runtime(target: CompileTarget) -> Result<(), ToyError> {
+        if target.lib_dir().join("libruntime.a").exists() {
+            return Ok(());
+        }
+        return Err(ToyError::new(
+            ToyErrorType::InternalLinkerFailure,
+            Span::null_span_with_msg(&format!(
+                "there is no runtime for {} in {}, run `rustup target add {}` and rebuild the compiler",
+                target.triple,
+                target.lib_dir().to_string_lossy(),
+                target.triple
+            )),
+        ));
+    }
+
+    ///Links a fully static executable against musl, the runtime built for it lives in `lib/<triple>/`
+    ///together with musl's libc.a and C runtime objects
+    fn link_static(
+        &mut self,
+        files: Vec<String>,
+        output: String,
+        save_temps: bool,
+        target: CompileTarget,
+    ) -> Result<(), ToyError> {
+        let lib_buf = target.lib_dir();
+        let lib_path = lib_buf.as_path();
+
+        let mut args: Vec<String> = vec![
+            "-m".into(),
+            target.elf_emulation().into(),
+            "-static".into(),
+            lib_path.join("crt1.o").to_string_lossy().into_owned(),
+            lib_path.join("crti.o").to_string_lossy().into_owned(),
+        ];
+        args.extend(files.iter().cloned());
+        args.push(format!("-L{}", lib_path.to_string_lossy()));
+        args.extend(self.user_args());
+        //libc.a and libunwind.a sit next to the runtime, so the group resolves everything
+        args.push("--start-group".into());
+        for lib in Self::collect_static_archives(lib_path, None) {
+            args.push(lib.to_string_lossy().into_owned());
+        }
+        args.push("--end-group".into());
+        args.extend(Linker::cacert(lib_path));
+        args.push(lib_path.join("crtn.o").to_string_lossy().into_owned());
+        args.push("-o".into());
+        args.push(output);
+
+        Linker::run_lld(&CompileTarget::host().lib_dir(), &args)?;
+        Linker::remove_temps(&files, save_temps);
+        Ok(())
+    }
+
+    ///Links a WASI command module with lld's wasm-ld flavor, `lib/<triple>/` holds the wasm runtime,
+    ///wasi-libc and its `_start` object
+    fn link_wasm(
+        &mut self,
+        files: Vec<String>,
+        output: String,
+        save_temps: bool,
+        target: CompileTarget,
+    ) -> Result<(), ToyError> {
+        let lib_buf = target.lib_dir();
+        let lib_path = lib_buf.as_path();
+
+        let mut args: Vec<String> = vec![
+            "-flavor".into(),
+            "wasm".into(),
+            //the default 64KiB stack is too small for the runtime
+            "-z".into(),
+            "stack-size=1048576".into(),
+            lib_path.join("crt1-command.o").to_string_lossy().into_owned(),
+        ];
+        args.extend(files.iter().cloned());
+        args.push(format!("-L{}", lib_path.to_string_lossy()));
+        args.extend(self.user_args());
+        args.push("--start-group".into());
+        for lib in Self::collect_static_archives(lib_path, None) {
+            args.push(lib.to_string_lossy().into_owned());
+        }
+        args.push("--end-group".into());
+        args.push("-o".into());
+        args.push(format!("{}{}", output, target.exe_extension()));
+
+        Linker::run_lld(&CompileTarget::host().lib_dir(), &args)?;
+        Linker::remove_temps(&files, save_temps);
+        Ok(())
+    }
+
+    ///Links the objects, the runtime and the user libraries into `lib<name>.a` or `lib<name>.so` next to
+    ///`output`, `symbols` are the C entry points the static archive indexes. Returns the library path
+    pub fn link_library(
+        &mut self,
+        files: Vec<String>,
+        output: String,
+        kind: LibraryKind,
+        symbols: &[String],
+        save_temps: bool,
+        target: CompileTarget,
+    ) -> Result<String, ToyError> {
+        let lib_buf = target.lib_dir();
+        let lib_path = lib_buf.as_path();
+        let output_path = Path::new(&output);
+        let stem = output_path
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or(output.clone());
+        let lib_file = |ext: &str| {
+            output_path
+                .with_file_name(format!("lib{}.{}", stem, ext))
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        //a static library is one relocatable object holding the program and the parts of the runtime it uses
+        let linked = match kind {
+            LibraryKind::Static => lib_file("o"),
+            LibraryKind::Shared => lib_file("so"),
+        };
+        let mut args: Vec<String> = vec!["-m".into(), target.elf_emulation().into()];
+        match kind {
+            LibraryKind::Static => args.push("-r".into()),
+            LibraryKind::Shared => {
+                args.push("-shared".into());
+                args.push("-soname".into());
+                args.push(format!("lib{}.so", stem));
+            }
+        }
+        args.extend(files.iter().cloned());
+        args.push(format!("-L{}", lib_path.to_string_lossy()));
+        args.extend(self.user_args());
+        args.push("--start-group".into());
+        for lib in Self::collect_static_archives(lib_path, Some("library")) {
+            args.push(lib.to_string_lossy().into_owned());
+        }
+        args.push("--end-group".into());
+        args.extend(Linker::cacert(lib_path));
+        if kind == LibraryKind::Shared {
+            args.push(lib_path.join("libc.so.6").to_string_lossy().into_owned());
+            args.push(lib_path.join("libm.so.6").to_string_lossy().into_owned());
+        }
+        args.push("-o".into());
+        args.push(linked.clone());
+        Linker::run_lld(&CompileTarget::host().lib_dir(), &args)?;
+        Linker::remove_temps(&files, save_temps);
+
+        if kind == LibraryKind::Shared {
+            return Ok(linked);
+        }
+        let archive_path = lib_file("a");
+        let write_archive = || -> std::io::Result<()> {
+            let object = fs::read(&linked)?;
+            fs::write(
+                &archive_path,
+                c_library::archive("toy_lib.o", &object, symbols),
+            )
+        };
+        write_archive().map_err(|e| {
+            ToyError::new(
+                ToyErrorType::InternalLinkerFailure,
+                Span::null_span_with_msg(&format!("could not write {}: {}", archive_path, e)),
+            )
+        })?;
+        Linker::remove_temps(&[linked], save_temps);
+        Ok(archive_path)
+    }
+
+    fn run_lld(lib_path: &Path, args: &[String]) -> Result<(), ToyError> {
+        let rstatus = Command::new(lib_path.join("ld.lld")).args(args).status();
 
         let status = match rstatus {
             Ok(f) => f,
@@ -233,16 +554,17 @@ impl Linker {
                 Span::null_span_with_msg(&format!("{:?}", args)),
             ));
         }
+        Ok(())
+    }
 
+    fn remove_temps(files: &[String], save_temps: bool) {
         if save_temps || std::env::var("TOY_DEBUG").unwrap_or("FALSE".to_string()) == "TRUE" {
-            return Ok(());
+            return;
         }
 
-        for file in &files {
+        for file in files {
             let _ = fs::remove_file(file);
         }
-
-        Ok(())
     }
 }
 #[derive(Debug)]
@@ -277,6 +599,71 @@ impl ProjectExportTable {
         };
     }
 }
+///What the entries of a REPL session that ran so far leave for the next one
+#[derive(Clone, Default)]
+pub struct ReplScope {
+    ///functions they defined, (name, params, return type), the JIT still has them
+    pub funcs: Vec<(String, Vec<TypeTok>, TypeTok)>,
+    ///top level variables, the runtime keeps their values
+    pub globals: Vec<(String, TypeTok)>,
+    ///struct, interface, extern, import and link declarations. They generate no code so they are
+    ///put in front of every entry
+    pub decls: Vec<TBox>,
+}
+impl ReplScope {
+    fn is_declaration(b: &TBox) -> bool {
+        return matches!(
+            b,
+            TBox::StructInterface(..)
+                | TBox::Interface(..)
+                | TBox::ExternFuncDec(..)
+                | TBox::ImportStmt(..)
+                | TBox::LinkStmt(..)
+        );
+    }
+    ///The scope after an entry with the declarations `decls` and the AST `ast`, a function an earlier
+    ///entry defined can not be defined again since the JIT would keep calling the first one
+    fn after(&self, decls: Vec<TBox>, ast: &[Ast]) -> Result<ReplScope, ToyError> {
+        let mut next = self.clone();
+        for node in ast {
+            match node {
+                Ast::FuncDec(name, params, ret, _, span) => {
+                    if self.funcs.iter().any(|(f, _, _)| f == &**name) {
+                        return Err(ToyError::new(ToyErrorType::FunctionRedefined, span.clone()));
+                    }
+                    let params = params
+                        .iter()
+                        .filter_map(|p| match p {
+                            Ast::FuncParam(_, ty, _) => Some(ty.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    next.funcs.push((*name.clone(), params, ret.clone()));
+                }
+                Ast::VarDec(name, ty, _, _) => {
+                    next.globals.retain(|(g, _)| g != &**name);
+                    next.globals.push((*name.clone(), ty.clone()));
+                }
+                _ => {}
+            }
+        }
+        next.decls.extend(decls);
+        return Ok(next);
+    }
+}
+///What `Driver::compile` produced, object files for the linker or in memory modules for the JIT
+struct Compiled<'ctx> {
+    object_files: Vec<String>,
+    ///main module first, empty unless compiled for the JIT
+    modules: Vec<Module<'ctx>>,
+    ///file stem of the main program
+    module_name: String,
+    library: Option<LibraryKind>,
+    lib_structs: Vec<(String, BTreeMap<String, Box<TypeTok>>)>,
+    lib_exports: Vec<c_library::CExport>,
+    target: CompileTarget,
+}
+
 pub struct Driver {
     pub table: ProjectExportTable,
     ///project name, for now just the name of the main file, defaults to "program"
@@ -290,6 +677,16 @@ pub struct Driver {
     pub file_path_to_text: HashMap<String, String>,
     pub file_path_to_ctla: HashMap<String, CTLASchema>,
     pub mangled_lookup: HashMap<String, String>,
+    ///libraries named by `link` statements in the program and its imports
+    pub link_libs: Vec<String>,
+    ///`test fn`s of the main program in source order, filled in once it is parsed
+    pub tests: Vec<String>,
+    ///C types of the params of the functions, of every module, that take callbacks
+    callback_params: HashMap<String, Vec<ExternType>>,
+    ///set when the main program is an entry of the REPL, what the entries before it left
+    pub repl: Option<ReplScope>,
+    ///the scope once the REPL entry has run, filled in by the front end
+    repl_next: Option<ReplScope>,
 }
 
 impl Driver {
@@ -306,6 +703,24 @@ impl Driver {
     pub fn set_build_dir(new_dir: String) {
         BUILD_DIR.with(|b| *b.borrow_mut() = new_dir);
     }
+    ///The command line, every flag is read through here
+    pub fn args() -> Vec<String> {
+        #[cfg(test)]
+        if let Some(args) = TEST_ARGS.with(|a| a.borrow().clone()) {
+            return args;
+        }
+        return env::args().collect();
+    }
+    ///Runs `f` as if the compiler was started with `args`, which follow the program name
+    #[cfg(test)]
+    pub fn with_args<T>(args: &[&str], f: impl FnOnce() -> T) -> T {
+        let mut full = vec!["toy_comp".to_string()];
+        full.extend(args.iter().map(|a| a.to_string()));
+        TEST_ARGS.with(|a| *a.borrow_mut() = Some(full));
+        let res = f();
+        TEST_ARGS.with(|a| *a.borrow_mut() = None);
+        return res;
+    }
     pub fn mangle_name(module_prefix: Option<&str>, name: &str, params: &[TypeTok]) -> String {
         let prefixed_name = if let Some(prefix) = module_prefix {
             if prefix.is_empty() {
@@ -332,6 +747,80 @@ impl Driver {
         }
         return final_mangled_name;
     }
+    ///Turns a name produced by `mangle_name` back into something readable, `std::math::pow_int_int` with
+    ///params `[Int, Int]` becomes `std::math::pow(int, int)` and struct methods use `Struct.method`
+    pub fn demangle_name(mangled: &str, params: &[TypeTok]) -> String {
+        if mangled == "user_main" {
+            return "main".to_string();
+        }
+        let name = Driver::base_name(mangled, params);
+        let type_names: Vec<String> = params.iter().map(|t| t.type_str().to_lowercase()).collect();
+        return format!("{}({})", name.replace(":::", "."), type_names.join(", "));
+    }
+    ///The name a function was declared with, `pow_int_int` with params `[Int, Int]` is `pow`
+    pub fn base_name<'n>(mangled: &'n str, params: &[TypeTok]) -> &'n str {
+        let mut name = mangled;
+        for t in params.iter().rev() {
+            if let Some(stripped) = name.strip_suffix(&format!("_{}", t.type_str().to_lowercase()))
+            {
+                name = stripped;
+            }
+        }
+        return name;
+    }
+    ///Picks the runtime build to link against from `--sanitize=memory` and `--profile=heap`
+    fn runtime_flavor() -> Result<Option<&'static str>, ToyError> {
+        let args: Vec<String> = Driver::args();
+        //a library has no main, it links the runtime build without one
+        if c_library::library_kind()?.is_some() {
+            if args.contains(&"--sanitize=memory".to_string())
+                || args.contains(&"--profile=heap".to_string())
+            {
+                return Err(ToyError::new(
+                    ToyErrorType::ConflictingFlags,
+                    Span::null_span_with_msg(
+                        "--lib cannot be combined with --sanitize=memory or --profile=heap",
+                    ),
+                ));
+            }
+            return Ok(Some("library"));
+        }
+        //tests link the instrumented runtime so leaks and use-after-free fail them, unless they profile
+        //or build for another target, only the host has instrumented runtimes
+        let profile = args.contains(&"--profile=heap".to_string());
+        let cross = CompileTarget::current() != CompileTarget::host();
+        let sanitize =
+            args.contains(&"--sanitize=memory".to_string()) || (cfg!(test) && !profile && !cross);
+        return match (sanitize, profile) {
+            (true, true) => Err(ToyError::new(
+                ToyErrorType::ConflictingFlags,
+                Span::null_span_with_msg("--sanitize=memory and --profile=heap cannot be used together"),
+            )),
+            (true, false) => Ok(Some("sanitize")),
+            (false, true) => Ok(Some("profile")),
+            (false, false) => Ok(None),
+        };
+    }
+    ///The `--target` to build for, the instrumented and library runtimes are only built for the host
+    fn compile_target() -> Result<CompileTarget, ToyError> {
+        let args: Vec<String> = Driver::args();
+        let target = CompileTarget::from_args()?;
+        if target != CompileTarget::host()
+            && (c_library::library_kind()?.is_some()
+                || args.contains(&"--sanitize=memory".to_string())
+                || args.contains(&"--profile=heap".to_string()))
+        {
+            return Err(ToyError::new(
+                ToyErrorType::ConflictingFlags,
+                Span::null_span_with_msg(&format!(
+                    "--lib, --sanitize=memory and --profile=heap are only supported for {}, not {}",
+                    CompileTarget::host().triple,
+                    target.triple
+                )),
+            ));
+        }
+        return Ok(target);
+    }
     pub fn new(prgm_path: PathBuf) -> Driver {
         return Driver {
             table: ProjectExportTable::new(),
@@ -342,6 +831,11 @@ impl Driver {
             file_path_to_text: HashMap::new(),
             file_path_to_ctla: HashMap::new(),
             mangled_lookup: HashMap::new(),
+            link_libs: vec![],
+            tests: vec![],
+            callback_params: HashMap::new(),
+            repl: None,
+            repl_next: None,
         };
     }
     #[allow(unused)]
@@ -355,12 +849,36 @@ impl Driver {
             file_path_to_text: HashMap::new(),
             file_path_to_ctla: HashMap::new(),
             mangled_lookup: HashMap::new(),
+            link_libs: vec![],
+            tests: vec![],
+            callback_params: HashMap::new(),
+            repl: None,
+            repl_next: None,
         };
     }
     fn name_to_path(&self, path: String) -> String {
         let segments: Vec<&str> = path.split(".").collect();
         return segments.join("/") + ".toy";
     }
+    ///Checks the extern declarations of runtime functions against what the runtime actually exports.
+    ///std modules only declare runtime functions, so there an extern the runtime lacks is an error too
+    fn check_runtime_externs(boxes: &[TBox], is_std: bool) -> Result<(), ToyError> {
+        for tbox in boxes {
+            if let TBox::ExternFuncDec(name, _, return_type, c_return_type, span) = tbox {
+                let name = name.get_var_name().unwrap();
+                if is_std || runtime_manifest::runtime_exports().contains_key(name.as_str()) {
+                    runtime_manifest::validate_extern(
+                        &name,
+                        &tbox.get_extern_param_types(),
+                        return_type,
+                        c_return_type.as_ref(),
+                        span.clone(),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
     pub fn extern_type_to_type_tok(ety: ExternType) -> TypeTok{
         return match ety{
             ExternType::c_int64_t(0) => TypeTok::Int,
@@ -373,6 +891,28 @@ impl Driver {
             ExternType::c_double(n) => TypeTok::FloatArr(n),
             ExternType::c_char(n) => TypeTok::StrArr(n - 1),
             ExternType::c_void(n) => TypeTok::AnyArr(n - 1),
+            ExternType::c_int8_t(0)
+            | ExternType::c_int16_t(0)
+            | ExternType::c_int32_t(0)
+            | ExternType::c_uint8_t(0)
+            | ExternType::c_uint16_t(0)
+            | ExternType::c_uint32_t(0)
+            | ExternType::c_uint64_t(0) => TypeTok::Int,
+            ExternType::c_float(0) => TypeTok::Float,
+            ExternType::c_bool(0) => TypeTok::Bool,
+            //raw pointers to sized values are opaque to Toy
+            ExternType::c_int8_t(_)
+            | ExternType::c_int16_t(_)
+            | ExternType::c_int32_t(_)
+            | ExternType::c_uint8_t(_)
+            | ExternType::c_uint16_t(_)
+            | ExternType::c_uint32_t(_)
+            | ExternType::c_uint64_t(_)
+            | ExternType::c_float(_)
+            | ExternType::c_bool(_) => TypeTok::Any,
+            ExternType::c_struct(ty, _) => ty,
+            //Toy functions are passed by name, C function pointers are plain ints
+            ExternType::c_fn(_, _) => TypeTok::Int,
         }
     }
 
@@ -422,6 +962,9 @@ impl Driver {
         for (k, v) in new_lookups {
             self.mangled_lookup.insert(k, v);
         }
+        for (name, types) in &self.callback_params {
+            ast_gen.register_callback_params(name.clone(), types.clone());
+        }
     }
     pub fn verify_module(module: &Module) -> Result<(), ToyError> {
         if let Err(e) = module.verify() {
@@ -456,12 +999,22 @@ impl Driver {
         for t_box in boxes {
             match t_box {
                 TBox::ImportStmt(import_name, import_span) => {
+                    if import_name == "std.net" && !CompileTarget::current().has_sockets() {
+                        return Err(ToyError::new(
+                            ToyErrorType::UnsupportedPlatform(format!(
+                                "std.net needs sockets, which {} does not have",
+                                CompileTarget::current().triple
+                            )),
+                            import_span,
+                        ));
+                    }
                     let path = self.name_to_path(import_name.clone());
                     import_list.push((path.clone(), import_span));
                     self.table
                         .alias_to_path
                         .insert(path, import_name.split(".").last().unwrap().to_string());
                 }
+                TBox::LinkStmt(lib, _) => self.link_libs.push(lib),
                 _ => continue,
             }
         }
@@ -511,7 +1064,11 @@ impl Driver {
                 .to_string();
             let prefix = module_name.replace(".", "::");
             let mut b = Boxer::with_module_prefix(prefix.clone());
-            let import_boxes = b.box_toks(import_toks)?;
+            let mut import_boxes = b.box_toks(import_toks)?;
+            Driver::check_runtime_externs(&import_boxes, module_name.starts_with("std."))?;
+            self.callback_params.extend(b.callbacks().clone());
+            //only the tests of the main program run
+            Driver::drop_tests(&mut import_boxes, b.tests());
 
             let mut specific_module_exports: Vec<ModuleExport> = vec![];
             for import_box in import_boxes.clone() {
@@ -526,7 +1083,7 @@ impl Driver {
                         let export = ModuleExport::new(*name.get_var_name().unwrap(), ty);
                         specific_module_exports.push(export);
                     }
-                    TBox::ExternFuncDec(name, params, return_type, _) => {
+                    TBox::ExternFuncDec(name, params, return_type, _, _) => {
                         let mut param_types = Vec::new();
                         for p in params {
                             if let TBox::ExternFuncParam(_, qualified_type, _) = p {
@@ -571,41 +1128,138 @@ impl Driver {
     ///Will automatically compile and build the program
     ///Linking in all necessary modules
     pub fn start(&mut self, ctx: &Context) -> Result<(), ToyError> {
-        let args: Vec<String> = env::args().collect();
-        let idx = args.iter().position(|r| r == "--build");
-        if idx.is_some() {
-            Driver::set_build_dir(args[idx.unwrap() + 1].clone());
+        let Compiled {
+            object_files,
+            module_name,
+            library,
+            lib_structs,
+            lib_exports,
+            target,
+            ..
+        } = self.compile(ctx, false)?;
+
+        //Link
+        let args = Driver::args();
+        let save_temps = args.contains(&"--save-temps".to_string());
+        let mut linker = Linker::new();
+        for lib in &self.link_libs {
+            linker.add_lib(lib.clone());
         }
-        Driver::set_current_file_path(&self.main_program_path.to_string_lossy());
-        let main_program = fs::read_to_string(&self.main_program_path).map_err(|_| {
+        let Some(kind) = library else {
+            linker.link(
+                object_files,
+                self.name.clone(),
+                save_temps,
+                Driver::runtime_flavor()?,
+                target,
+            )?;
+            return Ok(());
+        };
+
+        let output = Path::new(&self.name);
+        let lib_name = output
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or(module_name);
+        let header_path = output.with_file_name(format!("{}.h", lib_name));
+        fs::write(
+            &header_path,
+            c_library::header(&lib_name, &lib_structs, &lib_exports),
+        )
+        .map_err(|e| {
             ToyError::new(
-                ToyErrorType::MissingFile,
+                ToyErrorType::InternalLinkerFailure,
                 Span::null_span_with_msg(&format!(
-                    "Could not find file: {}",
-                    self.main_program_path.to_string_lossy()
+                    "could not write {}: {}",
+                    header_path.to_string_lossy(),
+                    e
                 )),
             )
         })?;
-        self.file_path_to_text.insert(
-            self.main_program_path.to_string_lossy().to_string(),
-            main_program.clone(),
+        let mut symbols: Vec<String> = lib_exports.iter().map(|e| e.symbol.clone()).collect();
+        symbols.extend(
+            c_library::RUNTIME_ENTRY_POINTS
+                .iter()
+                .map(|s| s.to_string()),
         );
+        linker.link_library(
+            object_files,
+            self.name.clone(),
+            kind,
+            &symbols,
+            save_temps,
+            target,
+        )?;
 
-        //Lex and box main program
-        let mut l = Lexer::new();
-        let main_prgm_toks = l.lex(main_program)?;
-
-        //I am aware this defeats the purpose of the parser meta module
-        let mut b = Boxer::new();
-        let main_prgm_boxes = b.box_toks(main_prgm_toks)?;
-
-        self.find_and_parse_dependencies(main_prgm_boxes.clone())?;
+        Ok(())
+    }
+    ///The JIT runs on the host with the plain runtime
+    fn check_jit_flags() -> Result<(), ToyError> {
+        let args: Vec<String> = Driver::args();
+        //not runtime_flavor, it picks the debug heap for every cargo test
+        if c_library::library_kind()?.is_some()
+            || args.contains(&"--sanitize=memory".to_string())
+            || args.contains(&"--profile=heap".to_string())
+            || CompileTarget::from_args()? != CompileTarget::host()
+        {
+            return Err(ToyError::new(
+                ToyErrorType::ConflictingFlags,
+                Span::null_span_with_msg(
+                    "the JIT runs on the host with the plain runtime, --lib, --target, --sanitize=memory and --profile=heap need a compiled build",
+                ),
+            ));
+        }
+        return Ok(());
+    }
+    ///Loads the libraries named by `link` and `--link-lib` for the JIT
+    fn load_link_libs(&self) -> Result<(), ToyError> {
+        let mut linker = Linker::new();
+        for lib in &self.link_libs {
+            linker.add_lib(lib.clone());
+        }
+        return linker.load_for_jit();
+    }
+    ///Compiles the program in memory and runs it in the compiler process, returns what `user_main` returned
+    pub fn run_jit(&mut self, ctx: &Context) -> Result<i64, ToyError> {
+        Driver::check_jit_flags()?;
+        let compiled = self.compile(ctx, true)?;
+        self.load_link_libs()?;
+        return jit::run(&compiled.modules);
+    }
+    ///Compiles the main program as an entry of the REPL on top of `repl` and runs it in `engine`.
+    ///Afterwards `repl` is what the next entry starts from. An entry that stopped on a runtime error
+    ///still leaves its functions and declarations, the engine has them, but not its variables since
+    ///they were never stored
+    pub fn run_repl_entry(
+        &mut self,
+        ctx: &'static Context,
+        engine: &mut jit::Engine,
+    ) -> Result<i64, ToyError> {
+        Driver::check_jit_flags()?;
+        let compiled = self.compile(ctx, true)?;
+        self.load_link_libs()?;
+        let res = engine.run(&compiled.modules);
+        if let Some(mut next) = self.repl_next.take() {
+            if res.is_err() {
+                next.globals = self.repl.take().unwrap_or_default().globals;
+            }
+            self.repl = Some(next);
+        }
+        return res;
+    }
+    ///Lexes, parses and generates the program and every module it imports. Each module is written to
+    ///`<name>.o`, or kept in memory when `jit` is set
+    fn compile<'ctx>(&mut self, ctx: &'ctx Context, jit: bool) -> Result<Compiled<'ctx>, ToyError> {
+        let (main_prgm_boxes, main_ast) = self.front_end()?;
 
-        let mut ast_gen = AstGenerator::new();
-        self.feed_to_ast_gen(&mut ast_gen);
-        let main_ast = ast_gen.generate(main_prgm_boxes)?;
+        let library = c_library::library_kind()?;
+        //fails early when --lib or --target is combined with an instrumented runtime
+        Driver::runtime_flavor()?;
+        let target = Driver::compile_target()?;
+        let (lib_structs, lib_exports) = Driver::library_interface(&main_prgm_boxes);
 
         let mut object_files = Vec::new();
+        let mut modules = Vec::new();
 
         //Compile Dependencies
         for (path, ast) in &self.file_path_to_ast {
@@ -631,11 +1285,118 @@ impl Driver {
                 }
             }
             generator.set_external_modules(external_modules);
+            generator.set_emit_object(!jit);
 
             generator.compile_to_object(ast.clone(), module_name.clone(), false)?;
-            object_files.push(format!("{}.o", module_name));
+            if jit {
+                modules.push(generator.into_module());
+            } else {
+                object_files.push(format!("{}.o", module_name));
+            }
+        }
+
+        let (mut generator, module_name) = self.main_generator(ctx);
+
+        if library.is_some() {
+            generator.set_c_exports(
+                lib_exports
+                    .iter()
+                    .map(|e| (e.toy_name.clone(), e.symbol.clone(), e.c_signature()))
+                    .collect(),
+            );
+        }
+
+        Driver::set_current_file_path(&self.main_program_path.to_string_lossy());
+        generator.set_emit_object(!jit);
+        generator.compile_to_object(main_ast, self.name.clone(), true)?;
+        if jit {
+            //the JIT creates its engine from the main module
+            modules.insert(0, generator.into_module());
+        } else {
+            object_files.push(format!("{}.o", self.name));
+        }
+
+        Ok(Compiled {
+            object_files,
+            modules,
+            module_name,
+            library,
+            lib_structs,
+            lib_exports,
+            target,
+        })
+    }
+    ///Reads, lexes and boxes the main program, parses its imports and builds its AST
+    fn front_end(&mut self) -> Result<(Vec<TBox>, Vec<Ast>), ToyError> {
+        let args: Vec<String> = Driver::args();
+        let idx = args.iter().position(|r| r == "--build");
+        if idx.is_some() {
+            Driver::set_build_dir(args[idx.unwrap() + 1].clone());
         }
+        Driver::set_current_file_path(&self.main_program_path.to_string_lossy());
+        let main_program = fs::read_to_string(&self.main_program_path).map_err(|_| {
+            ToyError::new(
+                ToyErrorType::MissingFile,
+                Span::null_span_with_msg(&format!(
+                    "Could not find file: {}",
+                    self.main_program_path.to_string_lossy()
+                )),
+            )
+        })?;
+        self.file_path_to_text.insert(
+            self.main_program_path.to_string_lossy().to_string(),
+            main_program.clone(),
+        );
 
+        //Lex and box main program
+        let mut l = Lexer::new();
+        let main_prgm_toks = l.lex(main_program)?;
+
+        //I am aware this defeats the purpose of the parser meta module
+        let mut b = Boxer::new();
+        let mut main_prgm_boxes = b.box_toks(main_prgm_toks)?;
+        Driver::check_runtime_externs(&main_prgm_boxes, false)?;
+        self.tests = b.tests().clone();
+        self.callback_params.extend(b.callbacks().clone());
+        let test_build = Driver::test_build();
+        if !test_build {
+            Driver::drop_tests(&mut main_prgm_boxes, &self.tests);
+        }
+        let repl = self.repl.clone();
+        let mut repl_decls = vec![];
+        if let Some(scope) = &repl {
+            repl_decls = main_prgm_boxes
+                .iter()
+                .filter(|b| ReplScope::is_declaration(b))
+                .cloned()
+                .collect();
+            main_prgm_boxes.splice(0..0, scope.decls.iter().cloned());
+        }
+
+        self.find_and_parse_dependencies(main_prgm_boxes.clone())?;
+
+        let mut ast_gen = AstGenerator::new();
+        self.feed_to_ast_gen(&mut ast_gen);
+        if let Some(scope) = &repl {
+            for (name, params, ret) in &scope.funcs {
+                ast_gen.register_function(name.clone(), params.clone(), ret.clone());
+            }
+            for (name, ty) in &scope.globals {
+                ast_gen.declare_var(name.clone(), ty.clone());
+            }
+        }
+        let mut main_ast = ast_gen.generate(main_prgm_boxes.clone())?;
+        if test_build {
+            main_ast = Driver::test_harness(main_ast, &self.tests);
+        }
+        if let Some(scope) = &repl {
+            self.repl_next = Some(scope.after(repl_decls, &main_ast)?);
+        }
+        return Ok((main_prgm_boxes, main_ast));
+    }
+    ///Generator for the main module that knows the summaries and functions of every imported module,
+    ///returns it with the file stem of the main program
+    fn main_generator<'ctx>(&self, ctx: &'ctx Context) -> (Generator<'ctx>, String) {
         let main_module = ctx.create_module("program");
         let mut generator = Generator::new(ctx, main_module);
         if let Some(text) = self
@@ -682,18 +1443,146 @@ impl Driver {
                 }
             }
         }
+        //functions of earlier REPL entries are in the JIT already
+        if let Some(scope) = &self.repl {
+            for (name, _, ret) in &scope.funcs {
+                generator.register_imported_func(name.clone(), ret.clone());
+            }
+            generator.set_repl_globals(scope.globals.clone());
+        }
 
+        return (generator, module_name);
+    }
+    ///`toy test` builds the program's tests instead of the program. Under cargo test argv[1] is a test
+    ///filter, so only the args given to `with_args` count there
+    fn test_build() -> bool {
+        #[cfg(test)]
+        if TEST_ARGS.with(|a| a.borrow().is_none()) {
+            return false;
+        }
+        let args: Vec<String> = Driver::args();
+        return args.get(1).is_some_and(|a| a == "test");
+    }
+    ///Removes the `test fn`s named in `tests`, a build that is not `toy test` never compiles them
+    fn drop_tests(boxes: &mut Vec<TBox>, tests: &[String]) {
+        boxes.retain(|b| match b {
+            TBox::FuncDec(name, ..) => !tests.contains(&name.tok.to_string()),
+            _ => true,
+        });
+    }
+    ///The program `toy test` builds. The top level statements of the program are dropped and
+    ///`user_main` runs each test the binary was asked to run, `toy_test_selected` reads argv[1]
+    fn test_harness(ast: Vec<Ast>, tests: &[String]) -> Vec<Ast> {
+        let mut harness: Vec<Ast> = ast
+            .into_iter()
+            .filter(|node| {
+                matches!(
+                    node,
+                    Ast::FuncDec(..)
+                        | Ast::ExternFuncDec(..)
+                        | Ast::StructInterface(..)
+                        | Ast::ImportStmt(..)
+                        | Ast::LinkStmt(..)
+                )
+            })
+            .collect();
+        for test in tests {
+            let span = harness
+                .iter()
+                .find_map(|node| match node {
+                    Ast::FuncDec(name, _, _, _, span) if **name == *test => Some(span.clone()),
+                    _ => None,
+                })
+                .unwrap_or_else(Span::null_span);
+            let selected = Ast::FuncCall(
+                Box::new("toy_test_selected".to_string()),
+                vec![Ast::StringLit(Box::new(test.clone()), span.clone())],
+                span.clone(),
+            );
+            harness.push(Ast::IfStmt(
+                Box::new(Ast::InfixExpr(
+                    Box::new(selected),
+                    Box::new(Ast::IntLit(1, span.clone())),
+                    InfixOp::Equals,
+                    span.clone(),
+                )),
+                vec![Ast::FuncCall(Box::new(test.clone()), vec![], span.clone())],
+                None,
+                span,
+            ));
+        }
+        return harness;
+    }
+    ///Builds the tests of the program into an executable named after the driver and returns its path.
+    ///The runner executes it, so it has to be built for the host
+    pub fn build_tests(&mut self, ctx: &Context) -> Result<PathBuf, ToyError> {
+        let target = CompileTarget::from_args()?;
+        if c_library::library_kind()?.is_some() || target != CompileTarget::host() {
+            return Err(ToyError::new(
+                ToyErrorType::ConflictingFlags,
+                Span::null_span_with_msg(
+                    "toy test runs the tests on the host, --lib and --target cannot be used",
+                ),
+            ));
+        }
+        self.start(ctx)?;
+        let exe = format!("{}{}", self.name, target.exe_extension());
+        return Ok(Path::new(".").join(exe));
+    }
+    ///AST of the main program, used by the REPL's `:ast` and `:type`
+    pub fn main_ast(&mut self) -> Result<Vec<Ast>, ToyError> {
+        return Ok(self.front_end()?.1);
+    }
+    ///TIR of the main program before CTLA runs, used by the REPL's `:tir`
+    pub fn main_tir(&mut self, ctx: &Context) -> Result<Vec<Function>, ToyError> {
+        let (_, main_ast) = self.front_end()?;
+        let (mut generator, _) = self.main_generator(ctx);
         Driver::set_current_file_path(&self.main_program_path.to_string_lossy());
-        generator.compile_to_object(main_ast, self.name.clone(), true)?;
-        object_files.push(format!("{}.o", self.name));
-
-        //Link
-        let args = env::args().collect::<Vec<String>>();
-        let save_temps = args.contains(&"--save-temps".to_string());
-        let mut linker = Linker::new();
-        linker.link(object_files, self.name.clone(), save_temps)?;
-
-        Ok(())
+        return generator.compile_to_tir(main_ast, self.name.clone());
+    }
+    ///The struct interfaces and `export fn`s of the main program a `--lib` build hands to C
+    fn library_interface(
+        boxes: &[TBox],
+    ) -> (
+        Vec<(String, BTreeMap<String, Box<TypeTok>>)>,
+        Vec<c_library::CExport>,
+    ) {
+        let mut structs = vec![];
+        let mut funcs = vec![];
+        for b in boxes {
+            match b {
+                TBox::StructInterface(name, types, _) => {
+                    let fields = types
+                        .iter()
+                        .map(|(k, v)| (k.clone(), Box::new(v.clone())))
+                        .collect();
+                    structs.push((*name.clone(), fields));
+                }
+                TBox::FuncDec(name, params, ret, _, _, true) => {
+                    let Some(mangled) = name.tok.get_var_name() else {
+                        continue;
+                    };
+                    //methods are reached through their struct, only free functions are exported
+                    if mangled.contains(":::") {
+                        continue;
+                    }
+                    let mut named_params = vec![];
+                    for p in params {
+                        if let TBox::FuncParam(param, ty, _) = p {
+                            let param_name =
+                                param.tok.get_var_name().map(|n| *n).unwrap_or_default();
+                            named_params.push((param_name, ty.clone()));
+                        }
+                    }
+                    let param_types: Vec<TypeTok> =
+                        named_params.iter().map(|(_, t)| t.clone()).collect();
+                    let base = Driver::base_name(&mangled, &param_types).to_string();
+                    funcs.push((*mangled.clone(), base, named_params, ret.clone()));
+                }
+                _ => {}
+            }
+        }
+        return (structs, c_library::c_exports(funcs));
     }
     #[allow(unused)]
     pub fn compile_to_ast_from_str(
@@ -705,6 +1594,7 @@ impl Driver {
         let toks = l.lex(code)?;
         let mut b = Boxer::new();
         let boxes = b.box_toks(toks)?;
+        self.callback_params.extend(b.callbacks().clone());
 
         self.find_and_parse_dependencies(boxes.clone())?;
         self.feed_to_ast_gen(ast_gen);
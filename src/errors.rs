@@ -27,11 +27,14 @@ impl fmt::Display for Span {
 
         match fs::read_to_string(&self.file_path) {
             Ok(content) => {
-                let bytes = content.as_bytes();
-                let start = self.start_offset_bytes as usize;
-                let end = self.end_offset_bytes as usize;
+                let mut start = self.start_offset_bytes as usize;
+                //spans of the last token end at the end of the file
+                let end = std::cmp::min(
+                    self.end_offset_bytes as usize,
+                    content.len().saturating_sub(1),
+                );
 
-                if start >= bytes.len() || end >= bytes.len() || start > end {
+                if start >= content.len() || start > end {
                     return write!(
                         f,
                         "<invalid span for {} {}..=>{}>",
@@ -39,12 +42,15 @@ impl fmt::Display for Span {
                     );
                 }
 
-                if let Some(slice) = content.get(start..=end) {
-                    write!(f, "{}", slice)
-                } else {
-                    let slice = &bytes[start..=end];
-                    write!(f, "{}", String::from_utf8_lossy(slice))
+                //offsets are in bytes, widen them to whole chars so multibyte chars are never cut in half
+                while !content.is_char_boundary(start) {
+                    start -= 1;
                 }
+                let mut end_exclusive = end + 1;
+                while !content.is_char_boundary(end_exclusive) {
+                    end_exclusive += 1;
+                }
+                write!(f, "{}", &content[start..end_exclusive])
             }
             Err(_) => write!(f, "<could not read file: {}>", self.file_path),
         }
@@ -66,7 +72,7 @@ impl Span {
     pub fn null_span_with_msg(msg: &str) -> Span {
         return Span::new(msg, -1, -1);
     }
-    //(line, col), (line, col)
+    //(line, col), (line, col), col counts chars not bytes
     pub fn get_line_col(&self) -> ((u64, u64), (u64, u64)) {
         let content = match fs::read_to_string(self.file_path.clone()) {
             Ok(c) => c,
@@ -79,20 +85,20 @@ impl Span {
         let mut end_line = 1u64;
         let mut end_col = 1u64;
 
-        for (byte_idx, ch) in content.bytes().enumerate() {
-            let idx = byte_idx as i64;
+        for (byte_idx, ch) in content.char_indices() {
+            let char_bytes = byte_idx as i64..(byte_idx + ch.len_utf8()) as i64;
 
-            if idx == self.start_offset_bytes {
+            if char_bytes.contains(&self.start_offset_bytes) {
                 start_line = line;
                 start_col = col;
             }
 
-            if idx == self.end_offset_bytes {
+            if char_bytes.contains(&self.end_offset_bytes) {
                 end_line = line;
                 end_col = col;
             }
 
-            if ch == b'\n' {
+            if ch == '\n' {
                 line += 1;
                 col = 1;
             } else {
@@ -129,6 +135,7 @@ pub enum ToyErrorType {
     ArrayElementsMustMatchArrayType,
     ExpressionNotBoolean,
     ArrayTypeInvalid,
+    MapTypeInvalid,
     KeyNotOnStruct,
     TypeMismatch,
     TypeHintNeeded,
@@ -141,6 +148,7 @@ pub enum ToyErrorType {
     UnsupportedOS,
     UndefinedVariable,
     UnknownCharacter(char),
+    InvalidEscapeSequence,
     MalformedStructInterface,
     MalformedType,
     MalformedFuncCall,
@@ -149,9 +157,16 @@ pub enum ToyErrorType {
     LlvmError(String),
     UndefinedSSAValue,
     MalformedImportStatement,
+    MalformedLinkStatement,
     MissingFile,
     IncorrectNumberOfArguments,
     SerializationError,
+    ConflictingFlags,
+    ExternSignatureMismatch(String),
+    InvalidFlag(String),
+    UnsupportedPlatform(String),
+    JitFailure(String),
+    FunctionRedefined,
 }
 
 #[derive(Debug, Error)]
@@ -240,6 +255,7 @@ impl fmt::Display for ToyErrorType {
             Self::UnknownSymbol(token) => write!(f, "Unknown Symbol: {:?}", token),
             Self::UndefinedVariable => write!(f, "Undefined Variable"),
             Self::UnknownCharacter(token) => write!(f, "Unknown Character: {:?}", token),
+            Self::InvalidEscapeSequence => write!(f, "Invalid Escape Sequence"),
             Self::MalformedStructInterface => write!(f, "Malformed Struct"),
             Self::MalformedType => write!(f, "Malformed Type"),
             Self::MalformedFuncCall => write!(f, "Malformed FuncCall"),
@@ -254,6 +270,7 @@ impl fmt::Display for ToyErrorType {
             Self::ExpressionNotNumeric => write!(f, "Expression Not Numeric"),
             Self::ExpressionNotBoolean => write!(f, "Expression Not Boolean"),
             Self::ArrayTypeInvalid => write!(f, "Array Type Invalid"),
+            Self::MapTypeInvalid => write!(f, "Map Type Invalid"),
             Self::KeyNotOnStruct => write!(f, "Key Not On Struct"),
             Self::TypeMismatch => write!(f, "Type Mismatch"),
             Self::InvalidOperationOnGivenType => write!(f, "Invalid Operation On Given Type"),
@@ -261,6 +278,13 @@ impl fmt::Display for ToyErrorType {
             Self::VariableNotAStruct => write!(f, "VariableNotAStruct"),
             Self::IncorrectNumberOfArguments => write!(f, "Incorrect Number Of Arguments"),
             Self::MalformedImportStatement => write!(f, "Malformed Import Statement"),
+            Self::MalformedLinkStatement => write!(f, "Malformed Link Statement"),
+            Self::ConflictingFlags => write!(f, "Conflicting Flags"),
+            Self::ExternSignatureMismatch(s) => write!(f, "Extern Signature Mismatch ({})", s),
+            Self::InvalidFlag(s) => write!(f, "Invalid Flag ({})", s),
+            Self::UnsupportedPlatform(s) => write!(f, "Unsupported Platform ({})", s),
+            Self::JitFailure(s) => write!(f, "JIT Failure ({})", s),
+            Self::FunctionRedefined => write!(f, "Function Redefined"),
             _ => todo!("chase implement error type {:?}", self),
         }
     }
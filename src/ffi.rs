@@ -69,10 +69,10 @@ unsafe extern "C" {
     pub fn toy_malloc_arr(len: i64, type_: i64, degree: i64) -> ToyPtr;
 }
 unsafe extern "C" {
-    pub fn toy_write_to_arr(arr_in_ptr: ToyPtr, value: i64, idx: i64, type_: i64);
+    pub fn toy_write_to_arr(arr_in_ptr: ToyPtr, value: i64, idx: i64, type_: i64, site: ToyPtr);
 }
 unsafe extern "C" {
-    pub fn toy_read_from_arr(arr_in_ptr: ToyPtr, idx: i64) -> i64;
+    pub fn toy_read_from_arr(arr_in_ptr: ToyPtr, idx: i64, site: ToyPtr) -> i64;
 }
 unsafe extern "C" {
     pub fn toy_arrlen(arr_in_ptr: ToyPtr) -> i64;
@@ -0,0 +1,168 @@
+use crate::compile_target::CompileTarget;
+use crate::errors::{Span, ToyError, ToyErrorType};
+use inkwell::OptimizationLevel;
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use inkwell::support;
+use inkwell::targets::{InitializationConfig, Target};
+use std::collections::HashSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+///toy_jit_run of the library runtime, calls `user_main` and catches a runtime error
+type JitRun = unsafe extern "C" fn(user_main: usize, res: *mut i64) -> i64;
+
+///Set once the runtime is loaded into the compiler, holds why when it could not be
+static RUNTIME: OnceLock<Result<(), String>> = OnceLock::new();
+
+fn jit_error(msg: &str) -> ToyError {
+    return ToyError::new(ToyErrorType::JitFailure(msg.to_string()), Span::null_span());
+}
+
+///The entry point free runtime built as a shared library, `lib/<host>/library/libruntime.so` on Linux
+fn runtime_path() -> PathBuf {
+    return CompileTarget::host()
+        .lib_dir()
+        .join("library")
+        .join(format!(
+            "{}runtime{}",
+            env::consts::DLL_PREFIX,
+            env::consts::DLL_SUFFIX
+        ));
+}
+
+///Calls a `extern "C" fn()` the runtime exports, toy_init or toy_shutdown
+fn call_runtime(symbol: &str) -> Result<(), String> {
+    let Some(address) = support::search_for_address_of_symbol(symbol) else {
+        return Err(format!("the runtime does not export {}", symbol));
+    };
+    let hook: extern "C" fn() = unsafe { std::mem::transmute(address) };
+    hook();
+    return Ok(());
+}
+
+///Loads the runtime into the compiler process and runs toy_init, done once no matter how many programs run
+fn load_runtime() -> Result<(), ToyError> {
+    let loaded = RUNTIME.get_or_init(|| {
+        //libc and libm come from the compiler itself
+        support::load_visible_symbols();
+        let path = runtime_path();
+        if support::load_library_permanently(&path).is_err() {
+            return Err(format!("could not load {}", path.to_string_lossy()));
+        }
+        return call_runtime("toy_init");
+    });
+    return loaded.clone().map_err(|e| jit_error(&e));
+}
+
+///Makes a shared library named by `link` or `--link-lib` visible to JIT code
+pub fn load_library(path: &Path) -> Result<(), ToyError> {
+    return support::load_library_permanently(path)
+        .map_err(|_| jit_error(&format!("could not load {}", path.to_string_lossy())));
+}
+
+///Calls the `user_main` at `address` through toy_jit_run, which catches a runtime error
+fn call_user_main(address: usize) -> Result<i64, ToyError> {
+    let Some(jit_run) = support::search_for_address_of_symbol("toy_jit_run") else {
+        return Err(jit_error("the runtime does not export toy_jit_run"));
+    };
+    let jit_run: JitRun = unsafe { std::mem::transmute(jit_run) };
+    let mut res = 0;
+    let failed = unsafe { jit_run(address, &mut res) };
+    if failed != 0 {
+        //the runtime already printed the error and the Toy backtrace
+        return Err(jit_error("the program stopped on a runtime error"));
+    }
+    return Ok(res);
+}
+
+///Runs `user_main` of a program compiled to `modules`, the first of them is the main module
+pub fn run(modules: &[Module]) -> Result<i64, ToyError> {
+    load_runtime()?;
+    Target::initialize_native(&InitializationConfig::default()).map_err(|e| jit_error(&e))?;
+    let Some((main, deps)) = modules.split_first() else {
+        return Err(jit_error("nothing was compiled"));
+    };
+    let engine = main
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|e| jit_error(&e.to_string()))?;
+    for module in deps {
+        engine
+            .add_module(module)
+            .map_err(|_| jit_error("could not add a module to the execution engine"))?;
+    }
+    let user_main = engine
+        .get_function_address("user_main")
+        .map_err(|e| jit_error(&e.to_string()))?;
+    engine.run_static_constructors();
+    let res = call_user_main(user_main);
+    engine.run_static_destructors();
+    return res;
+}
+
+///An execution engine that outlives the programs it runs. The REPL adds every entry to it as a
+///module of its own, so the functions of earlier entries are there to call
+pub struct Engine {
+    engine: ExecutionEngine<'static>,
+    ///names of the imported modules already added, an entry compiles its imports again
+    loaded: HashSet<String>,
+    entries: usize,
+}
+
+impl Engine {
+    pub fn new(ctx: &'static Context) -> Result<Engine, ToyError> {
+        load_runtime()?;
+        Target::initialize_native(&InitializationConfig::default()).map_err(|e| jit_error(&e))?;
+        let engine = ctx
+            .create_module("repl")
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .map_err(|e| jit_error(&e.to_string()))?;
+        return Ok(Engine {
+            engine,
+            loaded: HashSet::new(),
+            entries: 0,
+        });
+    }
+    ///Adds an entry compiled to `modules`, main module first, and runs it. Its `user_main` is
+    ///renamed so it does not clash with the ones of earlier entries
+    pub fn run(&mut self, modules: &[Module<'static>]) -> Result<i64, ToyError> {
+        let Some((main, deps)) = modules.split_first() else {
+            return Err(jit_error("nothing was compiled"));
+        };
+        for module in deps {
+            let name = module.get_name().to_string_lossy().into_owned();
+            if !self.loaded.insert(name) {
+                continue;
+            }
+            self.engine
+                .add_module(module)
+                .map_err(|_| jit_error("could not add a module to the execution engine"))?;
+        }
+        self.entries += 1;
+        let entry = format!("repl_entry_{}", self.entries);
+        let Some(user_main) = main.get_function("user_main") else {
+            return Err(jit_error("the entry has no user_main"));
+        };
+        user_main.as_global_value().set_name(&entry);
+        self.engine
+            .add_module(main)
+            .map_err(|_| jit_error("could not add a module to the execution engine"))?;
+        let address = self
+            .engine
+            .get_function_address(&entry)
+            .map_err(|e| jit_error(&e.to_string()))?;
+        return call_user_main(address);
+    }
+}
+
+///Writes the leak and heap reports and frees argv, called once when the REPL or `toy run` exits
+pub fn shutdown() {
+    if let Some(Ok(())) = RUNTIME.get() {
+        let _ = call_runtime("toy_shutdown");
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,33 @@
+use crate::driver::Driver;
+use inkwell::context::Context;
+use std::path::PathBuf;
+
+fn jit_code(code: &str, test_name: &str) -> Result<i64, crate::errors::ToyError> {
+    let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let source_path = project_root
+        .join("temp")
+        .join(format!("jit_{}.toy", test_name));
+    std::fs::create_dir_all(project_root.join("temp")).unwrap();
+    std::fs::write(&source_path, code).unwrap();
+    let ctx = Context::create();
+    let mut driver = Driver::new(source_path);
+    return driver.run_jit(&ctx);
+}
+
+#[test]
+fn test_jit_runs_program() {
+    let res = jit_code(
+        "fn sq(n: int): int{ return n * n; } let x = sq(4); println(x);",
+        "runs_program",
+    );
+    assert_eq!(res.unwrap(), 0);
+}
+
+#[test]
+fn test_jit_runtime_error_returns_error() {
+    //out of bounds read panics in the runtime, the compiler process has to survive it
+    let res = jit_code("let arr = [1, 2]; println(arr[5]);", "runtime_error");
+    assert!(res.is_err());
+    let res = jit_code("let arr = [1, 2]; println(arr[1]);", "after_runtime_error");
+    assert_eq!(res.unwrap(), 0);
+}
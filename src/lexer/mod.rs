@@ -46,6 +46,14 @@ impl Lexer {
         debug!(targets: ["lexer", "lexer_verbose"], offset, self.cursor, &self.source_chars);
         return self.source_chars[self.cursor + offset];
     }
+    ///the first char at or after offset that is not whitespace
+    fn peek_past_whitespace(&self, offset: usize) -> char {
+        let mut offset = offset;
+        while self.peek(offset).is_whitespace() {
+            offset += 1;
+        }
+        return self.peek(offset);
+    }
     /// Push a token, recording `start_cursor` (char index) as its span start.
     fn push_tok(&mut self, tok: Token, start_cursor: usize) {
         let start_byte = self
@@ -82,8 +90,10 @@ impl Lexer {
         if prev_char.is_alphanumeric() || prev_char == '_' {
             return false;
         }
-        // lparen is not alphanumeric but otherwise `int` would match `print()`
-        if (next_char.is_alphanumeric() || next_char == '_') || next_char == '(' {
+        // lparen is not alphanumeric but otherwise `int` would match `print()`,
+        // a dot means it is a module alias like `str.split()`
+        if (next_char.is_alphanumeric() || next_char == '_') || next_char == '(' || next_char == '.'
+        {
             return false;
         }
 
@@ -149,6 +159,75 @@ impl Lexer {
         return Ok(true);
     }
     
+    ///Lexes `map<K, V>` into a single type token like lex_arr_def does for `int[]`,
+    ///anything that does not look like a map type (`map < 5`) is left alone
+    fn lex_map_def(&mut self) -> Result<bool, ToyError> {
+        let start_cursor = self.cursor;
+        for (i, c) in "map".char_indices() {
+            if self.peek(i) != c {
+                return Ok(false);
+            }
+        }
+        let prev_char = if self.cursor == 0 {
+            '\0'
+        } else {
+            self.source_chars[self.cursor - 1]
+        };
+        if prev_char.is_alphanumeric() || prev_char == '_' {
+            return Ok(false);
+        }
+
+        let mut scan = self.cursor + 3;
+        let mut words: Vec<String> = Vec::new();
+        for expected in ['<', ',', '>'] {
+            while scan < self.source_chars.len() && self.source_chars[scan] == ' ' {
+                scan += 1;
+            }
+            if scan >= self.source_chars.len() || self.source_chars[scan] != expected {
+                return Ok(false);
+            }
+            scan += 1;
+            if expected == '>' {
+                break;
+            }
+            while scan < self.source_chars.len() && self.source_chars[scan] == ' ' {
+                scan += 1;
+            }
+            let mut word = String::new();
+            while scan < self.source_chars.len()
+                && (self.source_chars[scan].is_alphanumeric() || self.source_chars[scan] == '_')
+            {
+                word.push(self.source_chars[scan]);
+                scan += 1;
+            }
+            words.push(word);
+        }
+
+        let to_type = |word: &str| match word {
+            "str" => Some(TypeTok::Str),
+            "int" => Some(TypeTok::Int),
+            "bool" => Some(TypeTok::Bool),
+            "float" => Some(TypeTok::Float),
+            _ => None,
+        };
+        let (Some(key_type), Some(value_type)) = (to_type(&words[0]), to_type(&words[1])) else {
+            return Ok(false);
+        };
+        if key_type != TypeTok::Str && key_type != TypeTok::Int {
+            return Err(ToyError::new(
+                ToyErrorType::MapTypeInvalid,
+                self.get_error_span(),
+            ));
+        }
+
+        self.push_tok(
+            Token::Type(TypeTok::Map(Box::new(key_type), Box::new(value_type))),
+            start_cursor,
+        );
+        self.cursor = scan;
+        return Ok(true);
+    }
+
     fn lex_extern_type(&mut self) -> Result<bool, ToyError> {
         let start_cursor = self.cursor;
 
@@ -198,6 +277,39 @@ impl Lexer {
         return Ok(true);
     }
 
+    /// Lex the `{XXXX}` part of a `\u{XXXX}` escape, the cursor starts on the `u` and ends after the `}`
+    fn lex_unicode_escape(&mut self) -> Result<char, ToyError> {
+        let escape_span = self.get_error_span();
+        self.eat();
+        if self.peek(0) != '{' {
+            return Err(ToyError::new(
+                ToyErrorType::InvalidEscapeSequence,
+                escape_span,
+            ));
+        }
+        self.eat();
+        let mut hex = String::new();
+        while self.cursor < self.source_chars.len() && self.source_chars[self.cursor] != '}' {
+            hex.push(self.source_chars[self.cursor]);
+            self.eat();
+        }
+        if self.cursor >= self.source_chars.len() {
+            return Err(ToyError::new(
+                ToyErrorType::InvalidEscapeSequence,
+                escape_span,
+            ));
+        }
+        self.eat();
+        return u32::from_str_radix(&hex, 16)
+            .ok()
+            .filter(|_| !hex.is_empty() && hex.len() <= 6)
+            .and_then(char::from_u32)
+            .ok_or(ToyError::new(
+                ToyErrorType::InvalidEscapeSequence,
+                escape_span,
+            ));
+    }
+
     pub fn lex(&mut self, input: String) -> Result<Vec<SpannedToken>, ToyError> {
         self.char_byte_offsets = input.char_indices().map(|(byte_pos, _)| byte_pos).collect();
         self.source_chars = input.chars().collect();
@@ -223,6 +335,11 @@ impl Lexer {
                             '\\' => self.string_buffer.push('\\'),
                             '"' => self.string_buffer.push('"'),
                             '0' => self.string_buffer.push('\0'),
+                            'u' => {
+                                let escaped = self.lex_unicode_escape()?;
+                                self.string_buffer.push(escaped);
+                                continue;
+                            }
                             _ => {
                                 self.string_buffer.push('\\');
                                 self.string_buffer.push(next_c);
@@ -243,6 +360,9 @@ impl Lexer {
                 self.eat();
                 continue;
             }
+            if self.lex_map_def()? {
+                continue;
+            }
             //lex arrs before regular type to avoid int[] becoming (int) empty array
             if self.lex_arr_def("int", TypeTok::Int)? {
                 continue;
@@ -322,6 +442,12 @@ impl Lexer {
             if self.lex_keyword("import", Token::Import) {
                 continue;
             }
+            //link is only a keyword in front of a string literal, so it still works as a name
+            if self.peek_past_whitespace("link".len()) == '"'
+                && self.lex_keyword("link", Token::Link)
+            {
+                continue;
+            }
             if self.lex_keyword("export", Token::Export) {
                 continue;
             }
@@ -644,7 +770,8 @@ impl Lexer {
                 self.eat();
                 continue;
             }
-            if c.is_ascii() {
+            //identifiers may use any unicode letter or digit
+            if c.is_ascii() || c.is_alphanumeric() {
                 self.flush_num();
                 if self.string_buffer.is_empty() {
                     self.buffer_start_cursor = self.cursor;
@@ -665,7 +792,7 @@ impl Lexer {
         self.flush_num();
         // build SpannedTokens: each token spans [its_start, next_token_start)
         let file_path = Driver::get_current_file_path().unwrap_or_else(|| "<unknown>".to_string());
-        let source_end_byte = self.char_byte_offsets.last().copied().unwrap_or(0) as u64;
+        let source_end_byte = input.len() as u64;
         let starts = self.token_start_bytes.clone();
         let spanned: Vec<SpannedToken> = self
             .pending_tokens
@@ -434,6 +434,56 @@ fn test_lexer_string_lit() {
     )
 }
 
+#[test]
+fn test_lexer_unicode_ident_and_escape() {
+    let mut l = Lexer::new();
+    let out = l.lex(r#"let größe = "é\u{1F600}";"#.to_string());
+    compare_tokens(
+        "test_lexer_unicode_ident_and_escape",
+        out.unwrap(),
+        vec![
+            Token::Let,
+            Token::VarName(Box::new("größe".to_string())),
+            Token::Assign,
+            Token::StringLit(Box::new("é😀".to_string())),
+            Token::Semicolon,
+        ],
+    )
+}
+
+#[test]
+fn test_lexer_invalid_unicode_escape() {
+    let mut l = Lexer::new();
+    assert!(l.lex(r#"let x = "\u{110000}";"#.to_string()).is_err());
+    let mut l = Lexer::new();
+    assert!(l.lex(r#"let x = "\u1F600";"#.to_string()).is_err());
+}
+
+#[test]
+fn test_lexer_map_type() {
+    let mut l = Lexer::new();
+    let out = l.lex(r#"let m: map<str, int> = {"a": 1};"#.to_string());
+    compare_tokens(
+        "test_lexer_map_type",
+        out.unwrap(),
+        vec![
+            Token::Let,
+            Token::VarName(Box::new("m".to_string())),
+            Token::Colon,
+            Token::Type(TypeTok::Map(Box::new(TypeTok::Str), Box::new(TypeTok::Int))),
+            Token::Assign,
+            Token::LBrace,
+            Token::StringLit(Box::new("a".to_string())),
+            Token::Colon,
+            Token::IntLit(1),
+            Token::RBrace,
+            Token::Semicolon,
+        ],
+    );
+    let mut l = Lexer::new();
+    assert!(l.lex("let m: map<float, int> = {};".to_string()).is_err());
+}
+
 #[test]
 fn test_call_builtin() {
     let mut l = Lexer::new();
@@ -1527,6 +1577,37 @@ fn test_lexer_import() {
     )
 }
 
+#[test]
+fn test_lexer_keyword_module_alias() {
+    let mut l = Lexer::new();
+    let toks = l
+        .lex("import std.str; let x: str = str.trim(\" a \");".to_string())
+        .unwrap();
+    compare_tokens(
+        "test_lexer_keyword_module_alias",
+        toks,
+        vec![
+            Token::Import,
+            Token::VarName(Box::new("std".to_string())),
+            Token::Dot,
+            Token::VarRef(Box::new("str".to_string())),
+            Token::Semicolon,
+            Token::Let,
+            Token::VarName(Box::new("x".to_string())),
+            Token::Colon,
+            Token::Type(TypeTok::Str),
+            Token::Assign,
+            Token::VarRef(Box::new("str".to_string())),
+            Token::Dot,
+            Token::VarRef(Box::new("trim".to_string())),
+            Token::LParen,
+            Token::StringLit(Box::new(" a ".to_string())),
+            Token::RParen,
+            Token::Semicolon,
+        ],
+    )
+}
+
 #[test]
 fn test_lexer_extern_c_type(){
     let mut l = Lexer::new();
@@ -1592,4 +1673,82 @@ fn test_lexer_extern_c_type_pointer_depth() {
             Token::Semicolon,
         ],
     )
-}
\ No newline at end of file
+}
+#[test]
+fn test_lexer_extern_sized_c_types() {
+    let mut l = Lexer::new();
+    let toks = l
+        .lex("extern fn foo(a: c_int8_t, b: c_uint16_t_ptr, c: c_int32_t, d: c_float, e: c_bool): c_uint64_t;".to_string())
+        .unwrap();
+
+    let param = |name: &str, ty: ExternType| -> Vec<Token> {
+        vec![
+            Token::VarRef(Box::new(name.to_string())),
+            Token::Colon,
+            Token::ExternType(QualifiedExternType { ty, is_released: true }),
+        ]
+    };
+    let mut expected = vec![
+        Token::Extern,
+        Token::Func,
+        Token::VarName(Box::new("foo".to_string())),
+        Token::LParen,
+    ];
+    expected.extend(param("a", ExternType::c_int8_t(0)));
+    expected.push(Token::Comma);
+    expected.extend(param("b", ExternType::c_uint16_t(1)));
+    expected.push(Token::Comma);
+    expected.extend(param("c", ExternType::c_int32_t(0)));
+    expected.push(Token::Comma);
+    expected.extend(param("d", ExternType::c_float(0)));
+    expected.push(Token::Comma);
+    expected.extend(param("e", ExternType::c_bool(0)));
+    expected.extend(vec![
+        Token::RParen,
+        Token::Colon,
+        Token::ExternType(QualifiedExternType { ty: ExternType::c_uint64_t(0), is_released: true }),
+        Token::Semicolon,
+    ]);
+    compare_tokens("test_lexer_extern_sized_c_types", toks, expected)
+}
+
+#[test]
+fn test_lexer_link_stmt() {
+    let mut l = Lexer::new();
+    let toks = l.lex("link \"m\"; let linked = 1;".to_string()).unwrap();
+    compare_tokens(
+        "test_lexer_link_stmt",
+        toks,
+        vec![
+            Token::Link,
+            Token::StringLit(Box::new("m".to_string())),
+            Token::Semicolon,
+            Token::Let,
+            Token::VarName(Box::new("linked".to_string())),
+            Token::Assign,
+            Token::IntLit(1),
+            Token::Semicolon,
+        ],
+    )
+}
+
+#[test]
+fn test_lexer_link_is_still_a_name() {
+    let mut l = Lexer::new();
+    let toks = l.lex("let link = 1; link += link;".to_string()).unwrap();
+    compare_tokens(
+        "test_lexer_link_is_still_a_name",
+        toks,
+        vec![
+            Token::Let,
+            Token::VarName(Box::new("link".to_string())),
+            Token::Assign,
+            Token::IntLit(1),
+            Token::Semicolon,
+            Token::VarRef(Box::new("link".to_string())),
+            Token::CompoundPlus,
+            Token::VarRef(Box::new("link".to_string())),
+            Token::Semicolon,
+        ],
+    )
+}
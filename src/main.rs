@@ -3,10 +3,9 @@
 
 use crate::driver::Driver;
 use std::env;
-use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::process;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
 mod lexer;
 pub mod parser;
 mod token;
@@ -15,71 +14,115 @@ mod macros;
 pub mod codegen;
 pub(crate) mod driver;
 mod errors;
+mod c_library;
 mod ffi;
+mod runtime_manifest;
+mod compile_target;
+mod jit;
+mod repl;
 use inkwell::context::Context;
-fn run_repl() {
-    loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read line");
+///`toy run file.toy` compiles the file in memory and runs it without linking, returns its exit code
+fn jit_run(file_path: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let ctx: Context = Context::create();
+    let mut driver = Driver::new(PathBuf::from(file_path));
+    let res = driver.run_jit(&ctx)?;
+    jit::shutdown();
 
-        let input = input.trim();
-        if input.to_lowercase() == "exit" || input.to_lowercase() == "quit" {
-            println!("Exiting");
-            return;
-        }
+    Ok(res)
+}
+///`toy test file.toy [filter]` builds the tests of the file into `<stem>_tests`, or the `--name` given,
+///and runs every test whose name contains `filter` in a process of its own. With `--sanitize=memory` a
+///test that leaks fails too. The report goes to out, returns whether every test passed
+fn run_tests(
+    file_path: &str,
+    filter: Option<&str>,
+    out: &mut impl Write,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let ctx: Context = Context::create();
+    let stem = Path::new(file_path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("program");
+    let name = name_arg(&Driver::args()).unwrap_or_else(|| format!("{}_tests", stem));
+    let mut driver = Driver::new_with_name(PathBuf::from(file_path), name);
+    let binary = driver.build_tests(&ctx)?;
 
-        if let Err(e) = compile_and_run(input.to_string()) {
-            eprintln!("{}", e);
+    let selected: Vec<&String> = driver
+        .tests
+        .iter()
+        .filter(|t| filter.is_none_or(|f| t.contains(f)))
+        .collect();
+    writeln!(out, "running {} tests", selected.len())?;
+    let mut failures = vec![];
+    for test in &selected {
+        let output = Command::new(&binary).arg(test.as_str()).output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        //a failed assertion exits with 1, and with --sanitize=memory so does a test that leaks
+        if output.status.success() {
+            writeln!(out, "test {} ... ok", test)?;
+        } else {
+            writeln!(out, "test {} ... FAILED", test)?;
+            failures.push((test, stdout, stderr));
         }
     }
-}
-fn compile_and_run(source: String) -> Result<(), Box<dyn std::error::Error>> {
-    let repl_path = PathBuf::from("./temp/repl.toy");
-    fs::create_dir_all("temp")?;
-    fs::write(&repl_path, source)?;
-
-    let ctx: Context = Context::create();
-    let mut driver = driver::Driver::new(repl_path);
-    driver.start(&ctx)?;
-    let exe_path = format!("./Program{}", driver::FILE_EXTENSION_EXE);
-    
-    process::Command::new(exe_path)
-        .stdin(process::Stdio::inherit())
-        .stdout(process::Stdio::inherit())
-        .stderr(process::Stdio::inherit())
-        .spawn()?
-        .wait()?;
 
-    Ok(())
+    for (test, stdout, stderr) in &failures {
+        writeln!(out, "\n---- {} ----", test)?;
+        write!(out, "{}", stdout)?;
+        write!(out, "{}", stderr)?;
+    }
+    writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed; {} filtered out",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        selected.len() - failures.len(),
+        failures.len(),
+        driver.tests.len() - selected.len()
+    )?;
+    Ok(failures.is_empty())
 }
 fn compile_and_print(file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let ctx: Context = Context::create();
     let args: Vec<String> = env::args().collect();
-    let name = if args
-        .iter()
-        .position(|a| a == &"--name".to_string())
-        .is_some()
-    {
-        args[args
-            .iter()
-            .position(|a| a == &"--name".to_string())
-            .unwrap()
-            + 1]
-        .clone()
-    } else {
-        "program".to_string()
-    };
+    let name = name_arg(&args).unwrap_or("program".to_string());
     let mut driver = Driver::new_with_name(PathBuf::from(file_path), name);
     driver.start(&ctx)?;
 
     Ok(())
 }
 
+///The value of `--name`, the name of the program being built
+fn name_arg(args: &[String]) -> Option<String> {
+    let idx = args.iter().position(|a| a == "--name")?;
+    args.get(idx + 1).cloned()
+}
+
+///Flags whose value is the argument after them
+const VALUE_FLAGS: [&str; 6] = [
+    "--name",
+    "--build",
+    "--target",
+    "--link-lib",
+    "--link-search",
+    "--link-arg",
+];
+
+///The file and filter of `toy test <filename> [filter]`, the arguments after `test` that are neither
+///flags nor the values of flags, so flags can come before, between or after them
+fn test_operands(args: &[String]) -> Vec<&str> {
+    let mut operands = vec![];
+    let mut rest = args.iter().skip(2);
+    while let Some(arg) = rest.next() {
+        if VALUE_FLAGS.contains(&arg.as_str()) {
+            rest.next();
+        } else if !arg.starts_with('-') {
+            operands.push(arg.as_str());
+        }
+    }
+    operands
+}
+
 fn compile_file(filename: &str) -> Result<(), Box<dyn std::error::Error>> {
     compile_and_print(filename)
 }
@@ -88,15 +131,47 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.contains(&"--repl".to_string()) {
-        run_repl();
+        repl::run();
         return;
     }
 
     if args.len() < 2 {
         eprintln!("Usage: {} <filename> [--repl]", args[0]);
+        eprintln!("       {} run <filename>", args[0]);
+        eprintln!("       {} test <filename> [filter]", args[0]);
         process::exit(1);
     }
 
+    if args[1] == "run" {
+        let Some(filename) = args.get(2) else {
+            eprintln!("Usage: {} run <filename>", args[0]);
+            process::exit(1);
+        };
+        match jit_run(filename) {
+            Ok(code) => process::exit(code as i32),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if args[1] == "test" {
+        let operands = test_operands(&args);
+        let Some(filename) = operands.first() else {
+            eprintln!("Usage: {} test <filename> [filter]", args[0]);
+            process::exit(1);
+        };
+        match run_tests(filename, operands.get(1).copied(), &mut std::io::stdout()) {
+            Ok(true) => process::exit(0),
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     let filename = &args[1];
     if let Err(e) = compile_file(filename) {
         eprintln!("Error: {}", e);
@@ -1,6 +1,6 @@
 use std::fmt::{self};
 
-use crate::{errors::Span, token::{QualifiedExternType, TypeTok}};
+use crate::{errors::Span, token::{ExternType, QualifiedExternType, TypeTok}};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -27,13 +27,15 @@ pub enum Ast {
     ///Name, Params, ReturnType, Body, raw text
     FuncDec(Box<String>, Vec<Ast>, TypeTok, Vec<Ast>, Span),
 
-    ///Name, Params, ReturnType, raw text
+    ///Name, Params, ReturnType, C return type if it was declared with one, raw text
     ///Params will ALWAYS be ExternFuncParam
-    ExternFuncDec(Box<String>, Vec<Ast>, TypeTok, Span),
+    ExternFuncDec(Box<String>, Vec<Ast>, TypeTok, Option<ExternType>, Span),
     ///name type, span
     ExternFuncParam(String, QualifiedExternType, Span),
     ///Name, params as exprs, raw text
     FuncCall(Box<String>, Vec<Ast>, Span),
+    ///Toy function passed to a callback param of an extern, name, the c_fn type of the param, raw text
+    FuncRef(Box<String>, ExternType, Span),
 
     ///Val, raw text
     Return(Box<Ast>, Span),
@@ -50,6 +52,8 @@ pub enum Ast {
 
     ///Type, elements, raw text
     ArrLit(TypeTok, Vec<Ast>, Span),
+    ///Map type, (key, value) pairs in source order, raw text
+    MapLit(TypeTok, Vec<(Ast, Ast)>, Span),
 
     ///Name, types, raw text
     StructInterface(Box<String>, Box<BTreeMap<String, TypeTok>>, Span),
@@ -67,6 +71,8 @@ pub enum Ast {
     Not(Box<Ast>, Span),
     ///Path to the module being imported from, source code
     ImportStmt(String, Span),
+    ///Library handed to the linker, source code
+    LinkStmt(String, Span),
 }
 impl Ast {
     pub fn node_type(&self) -> String {
@@ -80,8 +86,9 @@ impl Ast {
             Ast::EmptyExpr(_, _) => "EmptyExpr".to_string(),
             Ast::FuncParam(_, _, _) => "FuncParam".to_string(),
             Ast::FuncDec(_, _, _, _, _) => "FuncDec".to_string(),
-            Ast::ExternFuncDec(_, _, _, _) => "ExternFuncDec".to_string(),
+            Ast::ExternFuncDec(_, _, _, _, _) => "ExternFuncDec".to_string(),
             Ast::FuncCall(_, _, _) => "FuncCall".to_string(),
+            Ast::FuncRef(_, _, _) => "FuncRef".to_string(),
             Ast::Return(_, _) => "Return".to_string(),
             Ast::StringLit(_, _) => "StringLit".to_string(),
             Ast::WhileStmt(_, _, _) => "WhileStmt".to_string(),
@@ -89,6 +96,7 @@ impl Ast {
             Ast::Break(_) => "Break".to_string(),
             Ast::FloatLit(_, _) => "FloatLit".to_string(),
             Ast::ArrLit(_, _, _) => "ArrLit".to_string(),
+            Ast::MapLit(_, _, _) => "MapLit".to_string(),
             Ast::StructInterface(_, _, _) => "StructInterface".to_string(),
             Ast::StructLit(_, _, _) => "StructLit".to_string(),
             Ast::IndexAccess(_, _, _) => "IndexAccess".to_string(),
@@ -96,6 +104,7 @@ impl Ast {
             Ast::Assignment(_, _, _) => "Assignment".to_string(),
             Ast::Not(_, _) => "Not".to_string(),
             Ast::ImportStmt(_, _) => "ImportStmt".to_string(),
+            Ast::LinkStmt(_, _) => "LinkStmt".to_string(),
             Ast::ExternFuncParam(_, _, _) => "ExternFuncParam".to_string()
         };
     }
@@ -111,8 +120,9 @@ impl Ast {
             Ast::EmptyExpr(_, s) => s.clone(),
             Ast::FuncParam(_, _, s) => s.clone(),
             Ast::FuncDec(_, _, _, _, s) => s.clone(),
-            Ast::ExternFuncDec(_, _, _, s) => s.clone(),
+            Ast::ExternFuncDec(_, _, _, _, s) => s.clone(),
             Ast::FuncCall(_, _, s) => s.clone(),
+            Ast::FuncRef(_, _, s) => s.clone(),
             Ast::Return(_, s) => s.clone(),
             Ast::StringLit(_, s) => s.clone(),
             Ast::WhileStmt(_, _, s) => s.clone(),
@@ -120,6 +130,7 @@ impl Ast {
             Ast::Continue(s) => s.clone(),
             Ast::FloatLit(_, s) => s.clone(),
             Ast::ArrLit(_, _, s) => s.clone(),
+            Ast::MapLit(_, _, s) => s.clone(),
             Ast::StructInterface(_, _, s) => s.clone(),
             Ast::StructLit(_, _, s) => s.clone(),
             Ast::IndexAccess(_, _, s) => s.clone(),
@@ -127,6 +138,7 @@ impl Ast {
             Ast::Assignment(_, _, s) => s.clone(),
             Ast::Not(_, s) => s.clone(),
             Ast::ImportStmt(_, s) => s.clone(),
+            Ast::LinkStmt(_, s) => s.clone(),
             Ast::ExternFuncParam(_, _, s) => s.clone()
         }
     }
@@ -178,14 +190,16 @@ impl fmt::Display for Ast {
                     "FuncDec Name({}), Params({:?}), ReturnType({:?}), Body({:?}), Literal({})",
                     *name, params, return_type, body, s
                 ),
-                Ast::ExternFuncDec(name, params, return_type, s) => format!(
-                    "ExternFuncDec Name({}), Params({:?}), ReturnType({:?}), Literal({})",
-                    *name, params, return_type, s
+                Ast::ExternFuncDec(name, params, return_type, c_return_type, s) => format!(
+                    "ExternFuncDec Name({}), Params({:?}), ReturnType({:?}), CReturnType({:?}), Literal({})",
+                    *name, params, return_type, c_return_type, s
                 ),
                 Ast::FuncCall(name, params, s) => format!(
                     "FuncCall, Name({}), Params({:?}), Literal({})",
                     *name, params, s
                 ),
+                Ast::FuncRef(name, ty, s) =>
+                    format!("FuncRef Name({}), Type({}), Literal({})", *name, ty.to_str(), s),
                 Ast::Return(val, s) => format!("Return Val({}), Literal({})", *val, s),
                 Ast::StringLit(st, s) => format!("StringLit Val({}), Literal({})", *st, s),
                 Ast::WhileStmt(cond, body, s) => format!(
@@ -197,6 +211,8 @@ impl fmt::Display for Ast {
                 Ast::FloatLit(fl, _) => format!("FloatLit({})", *fl),
                 Ast::ArrLit(t, v, s) =>
                     format!("ArrLit Type({:?}), Val({:?}), Literal({})", t, v, s),
+                Ast::MapLit(t, kv, s) =>
+                    format!("MapLit Type({:?}), Entries({:?}), Literal({})", t, kv, s),
                 Ast::StructInterface(n, kv, s) => format!(
                     "StructInterface Name({}), Types({:?}), Literal({})",
                     *n, *kv, s
@@ -211,6 +227,7 @@ impl fmt::Display for Ast {
                 Ast::Assignment(l, r, s) =>
                     format!("Assignment LHS({}), RHS({}), Literal({})", *l, *r, s),
                 Ast::ImportStmt(path, s) => format!("ImportStmt Path({}), Literal({})", path, s),
+                Ast::LinkStmt(lib, s) => format!("LinkStmt Lib({}), Literal({})", lib, s),
                 Ast::ExternFuncParam(n, t, s) => format!("ExternFuncParam Name({}), Type({:?}), Literal({})", n, t, s)
             }
         )
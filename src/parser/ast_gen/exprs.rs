@@ -316,6 +316,64 @@ impl AstGenerator {
             arr_type,
         ));
     }
+    ///Parses `{key: value, ...}`, every key and every value must have the same type,
+    ///`{}` has no types until parse_var_dec gives it the declared ones
+    pub fn parse_map_lit(&self, toks: &Vec<SpannedToken>) -> Result<(Ast, TypeTok), ToyError> {
+        let cumulative_span = AstGenerator::total_span(toks.clone());
+        let inner = &toks[1..toks.len() - 1];
+        let mut unprocessed_entries: Vec<&[SpannedToken]> = Vec::new();
+        let mut start = 0usize;
+        let mut depth = 0i32;
+        for (i, t) in inner.iter().enumerate() {
+            match t.tok.tok_type().as_str() {
+                "LBrace" | "LBrack" | "LParen" => depth += 1,
+                "RBrace" | "RBrack" | "RParen" => depth -= 1,
+                "Comma" => {
+                    if depth == 0 {
+                        unprocessed_entries.push(&inner[start..i]);
+                        start = i + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if start < inner.len() {
+            unprocessed_entries.push(&inner[start..inner.len()]);
+        }
+
+        let mut entries: Vec<(Ast, Ast)> = Vec::new();
+        let mut map_type: Option<(TypeTok, TypeTok)> = None;
+        for entry in unprocessed_entries {
+            let Some(colon_idx) = entry.iter().position(|t| t.tok == Token::Colon) else {
+                return Err(ToyError::new(
+                    ToyErrorType::ExpectedExpression,
+                    cumulative_span,
+                ));
+            };
+            let (key, key_type) = self.parse_expr(&entry[..colon_idx].to_vec())?;
+            let (value, value_type) = self.parse_expr(&entry[colon_idx + 1..].to_vec())?;
+            if !matches!(key_type, TypeTok::Str | TypeTok::Int)
+                || !matches!(
+                    value_type,
+                    TypeTok::Str | TypeTok::Int | TypeTok::Bool | TypeTok::Float
+                )
+            {
+                return Err(ToyError::new(ToyErrorType::MapTypeInvalid, cumulative_span));
+            }
+            if map_type
+                .as_ref()
+                .is_some_and(|t| *t != (key_type.clone(), value_type.clone()))
+            {
+                return Err(ToyError::new(ToyErrorType::TypeMismatch, cumulative_span));
+            }
+            map_type = Some((key_type, value_type));
+            entries.push((key, value));
+        }
+
+        let (key_type, value_type) = map_type.unwrap_or((TypeTok::Any, TypeTok::Any));
+        let ty = TypeTok::Map(Box::new(key_type), Box::new(value_type));
+        return Ok((Ast::MapLit(ty.clone(), entries, cumulative_span), ty));
+    }
     pub fn parse_struct_def(
         &self,
         toks: &Vec<SpannedToken>,
@@ -536,6 +594,13 @@ impl AstGenerator {
             return self.parse_arr_lit(toks);
         }
 
+        //Map literals, a brace can only start an expression if it is a map
+        if toks.first().unwrap().tok.tok_type() == "LBrace"
+            && toks.last().unwrap().tok.tok_type() == "RBrace"
+        {
+            return self.parse_map_lit(toks);
+        }
+
         //Struct literal
         if toks.first().unwrap().tok.tok_type() == "VarRef" && toks[1].tok.tok_type() == "LBrace" {
             let name = match toks[0].clone().tok {
@@ -798,6 +863,16 @@ impl AstGenerator {
                 let index_toks = &toks[best_idx + 1..toks.len() - 1];
 
                 let (left_ast, left_type) = self.parse_expr(&left.to_vec())?;
+                if let TypeTok::Map(key_type, value_type) = left_type {
+                    let (key_ast, key_type_found) = self.parse_expr(&index_toks.to_vec())?;
+                    if key_type_found != *key_type {
+                        return Err(ToyError::new(ToyErrorType::TypeMismatch, cumulative_span));
+                    }
+                    return Ok((
+                        Ast::IndexAccess(Box::new(left_ast), Box::new(key_ast), cumulative_span),
+                        *value_type,
+                    ));
+                }
                 let (index_ast, index_type) = self.parse_num_expr(&index_toks.to_vec())?;
 
                 if index_type != TypeTok::Int {
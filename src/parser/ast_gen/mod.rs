@@ -10,7 +10,7 @@ use crate::parser::toy_box::TBox;
 use crate::token::{ExternType, QualifiedExternType, TypeTok};
 use crate::token::{SpannedToken, Token};
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::{env, fs};
+use std::fs;
 
 mod exprs;
 pub struct AstGenerator {
@@ -27,6 +27,8 @@ pub struct AstGenerator {
     ///module name -> path so std.math maps to /std/math.toy (posix)
     imports: HashMap<String, String>,
     extern_funcs: HashSet<String>,
+    ///extern func name -> the C types of its params, used to find callback params
+    extern_param_types: HashMap<String, Vec<ExternType>>,
     module_prefix: Option<String>,
 }
 
@@ -92,6 +94,17 @@ impl AstGenerator {
         fptm.insert("int".to_string(), [TypeTok::Any].to_vec());
         fptm.insert("float".to_string(), [TypeTok::Any].to_vec());
         fptm.insert("input".to_string(), [TypeTok::Str].to_vec());
+        fptm.insert("push".to_string(), [TypeTok::Any, TypeTok::Any].to_vec());
+        fptm.insert("has".to_string(), [TypeTok::Any, TypeTok::Any].to_vec());
+        fptm.insert("remove".to_string(), [TypeTok::Any, TypeTok::Any].to_vec());
+        fptm.insert("keys".to_string(), [TypeTok::Any].to_vec());
+        fptm.insert("values".to_string(), [TypeTok::Any].to_vec());
+        fptm.insert("to_json".to_string(), [TypeTok::Any].to_vec());
+        fptm.insert("assert".to_string(), [TypeTok::Bool].to_vec());
+        fptm.insert(
+            "assert_eq".to_string(),
+            [TypeTok::Any, TypeTok::Any].to_vec(),
+        );
 
         let mut frtm: HashMap<String, TypeTok> = HashMap::new();
         frtm.insert("print".to_string(), TypeTok::Void);
@@ -102,6 +115,15 @@ impl AstGenerator {
         frtm.insert("int".to_string(), TypeTok::Int);
         frtm.insert("float".to_string(), TypeTok::Float);
         frtm.insert("input".to_string(), TypeTok::Str);
+        frtm.insert("push".to_string(), TypeTok::Void);
+        frtm.insert("has".to_string(), TypeTok::Bool);
+        frtm.insert("remove".to_string(), TypeTok::Void);
+        //keys and values depend on the map, see map_builtin_type
+        frtm.insert("keys".to_string(), TypeTok::AnyArr(1));
+        frtm.insert("values".to_string(), TypeTok::AnyArr(1));
+        frtm.insert("to_json".to_string(), TypeTok::Str);
+        frtm.insert("assert".to_string(), TypeTok::Void);
+        frtm.insert("assert_eq".to_string(), TypeTok::Void);
 
         return AstGenerator {
             boxes: b_vec,
@@ -114,6 +136,7 @@ impl AstGenerator {
             struct_type_to_name: HashMap::new(),
             imports: HashMap::new(),
             extern_funcs: HashSet::new(),
+            extern_param_types: HashMap::new(),
             module_prefix: None,
         };
     }
@@ -130,10 +153,20 @@ impl AstGenerator {
         self.func_return_type_map.insert(name, ret);
     }
 
+    ///C types of the params of a Toy function that takes callbacks, name is not mangled with its params
+    pub fn register_callback_params(&mut self, name: String, types: Vec<ExternType>) {
+        self.extern_param_types.insert(name, types);
+    }
+
     pub fn register_struct(&mut self, name: String, fields: BTreeMap<String, Box<TypeTok>>) {
         self.struct_type_to_name.insert(fields, name);
     }
 
+    ///A variable declared before the boxes being generated, the REPL's variables from earlier entries
+    pub fn declare_var(&mut self, name: String, ty: TypeTok) {
+        self.insert_var_type(name, ty);
+    }
+
     fn push_scope(&mut self) {
         self.var_type_scopes.push(HashMap::new());
     }
@@ -323,7 +356,19 @@ impl AstGenerator {
         }
 
         let mut processed_params: Vec<(Ast, TypeTok)> = Vec::new();
-        for p in unprocessed_params {
+        let extern_params = self.callback_params_of(&name);
+        for (i, p) in unprocessed_params.into_iter().enumerate() {
+            //a bare function name passed to a callback param is a reference to that function
+            if let Some(callback @ ExternType::c_fn(_, _)) = extern_params.and_then(|e| e.get(i)) {
+                if let [tok] = p.as_slice() {
+                    if let Token::VarRef(func_name) = &tok.tok {
+                        if self.lookup_var_type(func_name).is_none() {
+                            processed_params.push(self.parse_func_ref(tok, callback)?);
+                            continue;
+                        }
+                    }
+                }
+            }
             processed_params.push(self.parse_expr(&p)?);
         }
 
@@ -423,6 +468,20 @@ impl AstGenerator {
                 Some(a.clone())
             })
             .collect();
+        if resolved_name == "assert" || resolved_name == "assert_eq" {
+            return AstGenerator::lower_assert(processed_params, cumulative_span);
+        }
+        if resolved_name == "push" {
+            AstGenerator::check_push(&processed_params, &cumulative_span)?;
+        }
+        if let Some(ret_type) =
+            AstGenerator::map_builtin_type(&resolved_name, &processed_params, &cumulative_span)?
+        {
+            return Ok((
+                Ast::FuncCall(Box::new(resolved_name.clone()), vals, cumulative_span),
+                ret_type,
+            ));
+        }
         return Ok((
             Ast::FuncCall(Box::new(resolved_name.clone()), vals, cumulative_span),
             self.func_return_type_map
@@ -432,6 +491,165 @@ impl AstGenerator {
         ));
     }
 
+    ///C types of the params of an extern or of a Toy function that takes callbacks, which is also
+    ///found by its name in the current module or through the alias of the module it comes from
+    fn callback_params_of(&self, name: &str) -> Option<&Vec<ExternType>> {
+        if let Some(types) = self.extern_param_types.get(name) {
+            return Some(types);
+        }
+        if let Some(prefix) = &self.module_prefix {
+            let prefixed = Driver::mangle_name(Some(prefix), name, &[]);
+            if let Some(types) = self.extern_param_types.get(&prefixed) {
+                return Some(types);
+            }
+        }
+        let (alias, func) = name.split_once('.')?;
+        let real_path = self.imports.get(alias)?;
+        let alias_prefix = real_path
+            .replace("/", ".")
+            .replace(".toy", "")
+            .replace(".", "::");
+        return self
+            .extern_param_types
+            .get(&Driver::mangle_name(Some(&alias_prefix), func, &[]));
+    }
+
+    ///Resolves a Toy function passed as a callback, its signature must be the Toy version of the callback type
+    fn parse_func_ref(
+        &self,
+        tok: &SpannedToken,
+        callback: &ExternType,
+    ) -> Result<(Ast, TypeTok), ToyError> {
+        let ExternType::c_fn(c_params, c_ret) = callback else {
+            unreachable!()
+        };
+        let name = *tok.get_var_name().unwrap();
+        let params: Vec<TypeTok> = c_params
+            .iter()
+            .map(|p| Driver::extern_type_to_type_tok(p.clone()))
+            .collect();
+        let ret = match **c_ret {
+            ExternType::c_void(0) => TypeTok::Void,
+            _ => Driver::extern_type_to_type_tok(*c_ret.clone()),
+        };
+        let mut candidates = vec![Driver::mangle_name(None, &name, &params), name.clone()];
+        if let Some(prefix) = &self.module_prefix {
+            candidates.push(Driver::mangle_name(Some(prefix), &name, &params));
+            candidates.push(Driver::mangle_name(Some(prefix), &name, &[]));
+        }
+        let resolved = candidates
+            .into_iter()
+            .find(|c| self.func_param_type_map.contains_key(c) && !self.extern_funcs.contains(c))
+            .ok_or_else(|| ToyError::new(ToyErrorType::UndefinedFunction, tok.span.clone()))?;
+        if self.func_param_type_map[&resolved] != params
+            || self.func_return_type_map.get(&resolved) != Some(&ret)
+        {
+            return Err(ToyError::new(ToyErrorType::TypeMismatch, tok.span.clone()));
+        }
+        return Ok((
+            Ast::FuncRef(Box::new(resolved), callback.clone(), tok.span.clone()),
+            TypeTok::Int,
+        ));
+    }
+
+    ///`assert(cond)` and `assert_eq(left, right)` become runtime calls that end the program with the
+    ///location and text of the assertion when it fails. assert_eq compares both sides the way str prints them
+    fn lower_assert(params: Vec<(Ast, TypeTok)>, span: Span) -> Result<(Ast, TypeTok), ToyError> {
+        let ((line, col), _) = span.get_line_col();
+        let msg = Ast::StringLit(
+            Box::new(format!(
+                "{}:{}:{}: {} failed",
+                span.file_path, line, col, span
+            )),
+            span.clone(),
+        );
+        let call = match params.as_slice() {
+            [(cond, _)] => Ast::FuncCall(
+                Box::new("toy_test_assert".to_string()),
+                vec![cond.clone(), msg],
+                span.clone(),
+            ),
+            [(left, left_ty), (right, right_ty)] => {
+                if left_ty != right_ty {
+                    return Err(ToyError::new(ToyErrorType::TypeMismatch, span));
+                }
+                let as_str = |val: &Ast| {
+                    Ast::FuncCall(Box::new("str".to_string()), vec![val.clone()], span.clone())
+                };
+                Ast::FuncCall(
+                    Box::new("toy_test_assert_eq".to_string()),
+                    vec![as_str(left), as_str(right), msg],
+                    span.clone(),
+                )
+            }
+            _ => unreachable!(), // arity checked by parse_func_call
+        };
+        return Ok((call, TypeTok::Void));
+    }
+
+    ///What indexing an array of type `arr` gives, None when it is not an array
+    fn arr_elem_type(arr: TypeTok) -> Option<TypeTok> {
+        let elem = match arr {
+            TypeTok::IntArr(1) => TypeTok::Int,
+            TypeTok::IntArr(n) => TypeTok::IntArr(n - 1),
+            TypeTok::StrArr(1) => TypeTok::Str,
+            TypeTok::StrArr(n) => TypeTok::StrArr(n - 1),
+            TypeTok::BoolArr(1) => TypeTok::Bool,
+            TypeTok::BoolArr(n) => TypeTok::BoolArr(n - 1),
+            TypeTok::FloatArr(1) => TypeTok::Float,
+            TypeTok::FloatArr(n) => TypeTok::FloatArr(n - 1),
+            TypeTok::AnyArr(1) => TypeTok::Any,
+            TypeTok::AnyArr(n) => TypeTok::AnyArr(n - 1),
+            TypeTok::StructArr(kv, 1) => TypeTok::Struct(kv),
+            TypeTok::StructArr(kv, n) => TypeTok::StructArr(kv, n - 1),
+            _ => return None,
+        };
+        return Some(elem);
+    }
+
+    ///push takes an array and a value of its element type, arrays of any take anything
+    fn check_push(params: &[(Ast, TypeTok)], span: &Span) -> Result<(), ToyError> {
+        let Some(elem_type) = AstGenerator::arr_elem_type(params[0].1.clone()) else {
+            return Err(ToyError::new(ToyErrorType::TypeMismatch, span.clone()));
+        };
+        if elem_type != TypeTok::Any && params[1].1 != elem_type {
+            return Err(ToyError::new(ToyErrorType::TypeMismatch, span.clone()));
+        }
+        return Ok(());
+    }
+
+    ///Type checks has, remove, keys and values, which only take maps.
+    ///Returns None for every other function
+    fn map_builtin_type(
+        name: &str,
+        params: &[(Ast, TypeTok)],
+        span: &Span,
+    ) -> Result<Option<TypeTok>, ToyError> {
+        if !matches!(name, "has" | "remove" | "keys" | "values") {
+            return Ok(None);
+        }
+        let TypeTok::Map(key_type, value_type) = &params[0].1 else {
+            return Err(ToyError::new(ToyErrorType::TypeMismatch, span.clone()));
+        };
+        if params.len() == 2 && params[1].1 != **key_type {
+            return Err(ToyError::new(ToyErrorType::TypeMismatch, span.clone()));
+        }
+        let arr_of = |t: &TypeTok| match t {
+            TypeTok::Int => TypeTok::IntArr(1),
+            TypeTok::Bool => TypeTok::BoolArr(1),
+            TypeTok::Str => TypeTok::StrArr(1),
+            TypeTok::Float => TypeTok::FloatArr(1),
+            _ => TypeTok::AnyArr(1),
+        };
+        let ret_type = match name {
+            "has" => TypeTok::Bool,
+            "remove" => TypeTok::Void,
+            "keys" => arr_of(key_type.as_ref()),
+            _ => arr_of(value_type.as_ref()),
+        };
+        return Ok(Some(ret_type));
+    }
+
     pub fn eat(&mut self) {
         self.bp += 1;
     }
@@ -455,9 +673,18 @@ impl AstGenerator {
             (Ast::ArrLit(TypeTok::Any, elems, raw), _) if elems.is_empty() => {
                 Ast::ArrLit(ret_var_type.clone(), elems.clone(), raw.clone())
             }
+            (Ast::MapLit(_, entries, raw), TypeTok::Map(_, _)) if entries.is_empty() => {
+                Ast::MapLit(ret_var_type.clone(), entries.clone(), raw.clone())
+            }
             _ => val_ast,
         };
         let cumulative_span = AstGenerator::total_span(val.clone());
+        if let TypeTok::Map(key_type, _) = &ret_var_type {
+            //`let m = {};` has nothing to infer the types from
+            if **key_type == TypeTok::Any {
+                return Err(ToyError::new(ToyErrorType::MapTypeInvalid, cumulative_span));
+            }
+        }
         let node = Ast::VarDec(
             Box::new(name_str.clone()),
             ret_var_type.clone(),
@@ -542,8 +769,8 @@ impl AstGenerator {
     }
 
     fn parse_extern_func_dec(&mut self, stmt: TBox, should_eat: bool) -> Result<Ast, ToyError> {
-        let (name_tok, params, return_type, raw_text) = match stmt {
-            TBox::ExternFuncDec(n, p, r, rt) => (n, p, r, rt),
+        let (name_tok, params, return_type, c_return_type, raw_text) = match stmt {
+            TBox::ExternFuncDec(n, p, r, c, rt) => (n, p, r, c, rt),
             _ => unreachable!(),
         };
         let name = match name_tok.tok {
@@ -569,12 +796,7 @@ impl AstGenerator {
                         _ => unreachable!(),
                     };
                     let q = QualifiedExternType {
-                        ty: match type_tok {
-                            TypeTok::Int => ExternType::c_int64_t(0),
-                            TypeTok::Float => ExternType::c_double(0),
-                            TypeTok::Str => ExternType::c_char(1),
-                            _ => ExternType::c_void(1),
-                        },
+                        ty: ExternType::from_type_tok(&type_tok),
                         is_released: true,
                     };
                     (n, q, rt)
@@ -590,10 +812,12 @@ impl AstGenerator {
             param_types.push(param_type.clone());
         }
         let mut ty_params: Vec<TypeTok> = vec![];
-        for p in param_types{
-            ty_params.push(Driver::extern_type_to_type_tok(p.ty));
+        for p in &param_types{
+            ty_params.push(Driver::extern_type_to_type_tok(p.ty.clone()));
         }
         self.extern_funcs.insert(name.clone());
+        self.extern_param_types
+            .insert(name.clone(), param_types.into_iter().map(|p| p.ty).collect());
         self.func_param_type_map.insert(name.clone(), ty_params);
         self.func_return_type_map
             .insert(name.clone(), return_type.clone());
@@ -606,6 +830,7 @@ impl AstGenerator {
             Box::new(name),
             ast_params,
             return_type,
+            c_return_type,
             raw_text,
         ));
     }
@@ -686,15 +911,21 @@ impl AstGenerator {
                 self.parse_var_dec(&name, &v_val, var_type.clone())?
             }
             TBox::Assign(lhs, rhs, raw_text) => {
-                let (lhs_node, _) = self.parse_expr(&lhs)?;
+                let (lhs_node, lhs_type) = self.parse_expr(&lhs)?;
                 let (rhs_node, _) = self.parse_expr(&rhs)?;
+                let rhs_node = match (rhs_node, &lhs_type) {
+                    (Ast::MapLit(_, entries, raw), TypeTok::Map(_, _)) if entries.is_empty() => {
+                        Ast::MapLit(lhs_type.clone(), entries, raw)
+                    }
+                    (node, _) => node,
+                };
                 Ast::Assignment(Box::new(lhs_node), Box::new(rhs_node), raw_text)
             }
             TBox::IfStmt(_, _, _, _, _) => {
                 return self.parse_if_stmt(val, should_eat);
             }
             TBox::FuncDec(_, _, _, _, _, _) => return self.parse_func_dec(val, should_eat),
-            TBox::ExternFuncDec(_, _, _, _) => return self.parse_extern_func_dec(val, should_eat),
+            TBox::ExternFuncDec(_, _, _, _, _) => return self.parse_extern_func_dec(val, should_eat),
             TBox::Return(val, raw_text) => {
                 let expr = match *val {
                     TBox::Expr(ref v, _) => v,
@@ -784,6 +1015,7 @@ impl AstGenerator {
                 }
                 Ast::ImportStmt(name, raw_text)
             }
+            TBox::LinkStmt(lib, raw_text) => Ast::LinkStmt(lib, raw_text),
 
             _ => todo!("Unimplemented statement {}", val),
         };
@@ -813,7 +1045,7 @@ impl AstGenerator {
             let stmt = self.parse_stmt(val, true)?;
             self.nodes.push(stmt)
         }
-        let args: Vec<String> = env::args().collect();
+        let args: Vec<String> = Driver::args();
         if args.contains(&"--debug-ast".to_string()) || args.contains(&"--debug-ALL".to_string()) {
             let s = AstGenerator::pretty_print_ast(&self.nodes)?;
             fs::write("./debug/AST.json", s).unwrap(); //temp, bad
@@ -51,8 +51,8 @@ fn eq_ast_ignoring_src(x: &Ast, y: &Ast) -> bool {
                 && compare_ast_vecs(xb.clone(), yb.clone())
         }
 
-        (Ast::ExternFuncDec(xn, xp, xr, _), Ast::ExternFuncDec(yn, yp, yr, _)) => {
-            xn == yn && xr == yr && compare_ast_vecs(xp.clone(), yp.clone())
+        (Ast::ExternFuncDec(xn, xp, xr, xc, _), Ast::ExternFuncDec(yn, yp, yr, yc, _)) => {
+            xn == yn && xr == yr && xc == yc && compare_ast_vecs(xp.clone(), yp.clone())
         }
 
         (Ast::FuncCall(xn, xp, _), Ast::FuncCall(yn, yp, _)) => {
@@ -71,6 +71,14 @@ fn eq_ast_ignoring_src(x: &Ast, y: &Ast) -> bool {
             xt == yt && compare_ast_vecs(xv.clone(), yv.clone())
         }
 
+        (Ast::MapLit(xt, xkv, _), Ast::MapLit(yt, ykv, _)) => {
+            xt == yt
+                && xkv.len() == ykv.len()
+                && xkv.iter().zip(ykv.iter()).all(|((xk, xv), (yk, yv))| {
+                    eq_ast_ignoring_src(xk, yk) && eq_ast_ignoring_src(xv, yv)
+                })
+        }
+
         (Ast::IndexAccess(xt, xi, _), Ast::IndexAccess(yt, yi, _)) => {
             eq_ast_ignoring_src(xt, yt) && eq_ast_ignoring_src(xi, yi)
         }
@@ -1971,6 +1979,7 @@ fn test_ast_gen_extern_func_dec() {
                 Span::null_span()
             )],
             TypeTok::Int,
+            None,
             Span::null_span()
         )]
     ));
@@ -2241,3 +2250,123 @@ fn test_ast_gen_imported_struct_method_call() {
         "Expected imported struct method call to be resolved and present in AST"
     );
 }
+
+#[test]
+fn test_ast_gen_map() {
+    setup_ast!(
+        r#"let m: map<str, int> = {"a": 1, "b": 2}; m["c"] = 3; println(has(m, "a"));"#,
+        ast
+    );
+    let map_ty = TypeTok::Map(Box::new(TypeTok::Str), Box::new(TypeTok::Int));
+    assert!(compare_ast_vecs(
+        ast,
+        vec![
+            Ast::VarDec(
+                Box::new("m".to_string()),
+                map_ty.clone(),
+                Box::new(Ast::MapLit(
+                    map_ty,
+                    vec![
+                        (
+                            Ast::StringLit(Box::new("a".to_string()), Span::null_span()),
+                            Ast::IntLit(1, Span::null_span())
+                        ),
+                        (
+                            Ast::StringLit(Box::new("b".to_string()), Span::null_span()),
+                            Ast::IntLit(2, Span::null_span())
+                        ),
+                    ],
+                    Span::null_span()
+                )),
+                Span::null_span()
+            ),
+            Ast::Assignment(
+                Box::new(Ast::IndexAccess(
+                    Box::new(Ast::VarRef(Box::new("m".to_string()), Span::null_span())),
+                    Box::new(Ast::StringLit(Box::new("c".to_string()), Span::null_span())),
+                    Span::null_span()
+                )),
+                Box::new(Ast::IntLit(3, Span::null_span())),
+                Span::null_span()
+            ),
+            Ast::FuncCall(
+                Box::new("println".to_string()),
+                vec![Ast::FuncCall(
+                    Box::new("has".to_string()),
+                    vec![
+                        Ast::VarRef(Box::new("m".to_string()), Span::null_span()),
+                        Ast::StringLit(Box::new("a".to_string()), Span::null_span())
+                    ],
+                    Span::null_span()
+                )],
+                Span::null_span()
+            )
+        ]
+    ));
+}
+
+#[test]
+fn test_ast_gen_assert() {
+    setup_ast!("let x = 2; assert(x > 1); assert_eq(x, 2);", ast);
+    let Ast::FuncCall(name, args, _) = &ast[1] else {
+        panic!("assert should lower to a runtime call, got {}", ast[1]);
+    };
+    assert_eq!(**name, "toy_test_assert");
+    assert!(matches!(
+        args.as_slice(),
+        [Ast::InfixExpr(_, _, InfixOp::GreaterThan, _), Ast::StringLit(_, _)]
+    ));
+
+    let Ast::FuncCall(name, args, _) = &ast[2] else {
+        panic!("assert_eq should lower to a runtime call, got {}", ast[2]);
+    };
+    assert_eq!(**name, "toy_test_assert_eq");
+    assert!(compare_ast_vecs(
+        args[..2].to_vec(),
+        vec![
+            Ast::FuncCall(
+                Box::new("str".to_string()),
+                vec![Ast::VarRef(Box::new("x".to_string()), Span::null_span())],
+                Span::null_span()
+            ),
+            Ast::FuncCall(
+                Box::new("str".to_string()),
+                vec![Ast::IntLit(2, Span::null_span())],
+                Span::null_span()
+            ),
+        ]
+    ));
+    assert!(matches!(args[2], Ast::StringLit(_, _)));
+}
+
+#[test]
+fn test_ast_gen_assert_eq_type_mismatch() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let mut a = AstGenerator::new();
+    let toks = l.lex("assert_eq(1, \"1\");".to_string()).unwrap();
+    let boxes = b.box_toks(toks).unwrap();
+    assert!(a.generate(boxes).is_err());
+}
+
+#[test]
+fn test_ast_gen_push_checks_element_type() {
+    setup_ast!(
+        "let a = [1, 2]; push(a, 3); let g = [[1], [2]]; push(g, [3]); let s: str[] = []; push(s, \"x\");",
+        ast
+    );
+    assert_eq!(ast.len(), 6);
+
+    for src in [
+        "let int_arr = [1, 2]; push(int_arr, \"x\");",
+        "let g = [[1], [2]]; push(g, 3);",
+        "let n = 1; push(n, 1);",
+    ] {
+        let mut l = Lexer::new();
+        let mut b = Boxer::new();
+        let mut a = AstGenerator::new();
+        let toks = l.lex(src.to_string()).unwrap();
+        let boxes = b.box_toks(toks).unwrap();
+        assert!(a.generate(boxes).is_err(), "{} should not type check", src);
+    }
+}
@@ -1,7 +1,8 @@
 use crate::errors::{Span, ToyError, ToyErrorType};
 use crate::parser::toy_box::TBox;
-use crate::token::{QualifiedExternType, SpannedToken, Token, TypeTok};
-use std::collections::BTreeMap;
+use crate::driver::Driver;
+use crate::token::{ExternType, QualifiedExternType, SpannedToken, Token, TypeTok};
+use std::collections::{BTreeMap, HashMap};
 pub struct Boxer {
     toks: Vec<SpannedToken>,
     tp: usize, // token pointer
@@ -9,6 +10,10 @@ pub struct Boxer {
     /// Optional module prefix for name mangling (e.g., "std::math")
     module_prefix: Option<String>,
     current_struct: Option<(String, TypeTok)>,
+    ///mangled names of the `test fn`s boxed so far, in source order
+    tests: Vec<String>,
+    ///C types of the params of functions that take callbacks, by function name before the params are mangled in
+    callbacks: HashMap<String, Vec<ExternType>>,
 }
 
 impl Boxer {
@@ -19,6 +24,8 @@ impl Boxer {
             interfaces: BTreeMap::new(),
             module_prefix: None,
             current_struct: None,
+            tests: Vec::new(),
+            callbacks: HashMap::new(),
         }
     }
 
@@ -31,8 +38,18 @@ impl Boxer {
             interfaces: BTreeMap::new(),
             module_prefix: Some(prefix),
             current_struct: None,
+            tests: Vec::new(),
+            callbacks: HashMap::new(),
         }
     }
+    ///Mangled names of the `test fn`s boxed so far, in source order
+    pub fn tests(&self) -> &Vec<String> {
+        return &self.tests;
+    }
+    ///C types of the params of every function boxed so far that takes a callback
+    pub fn callbacks(&self) -> &HashMap<String, Vec<ExternType>> {
+        return &self.callbacks;
+    }
     pub fn total_span(toks: Vec<SpannedToken>) -> Span {
         if toks.is_empty() {
             return Span::null_span();
@@ -342,7 +359,10 @@ impl Boxer {
                 i = for_end;
                 continue;
             }
-            if (ty == "Func" || ty == "Extern" || ty == "Export")
+            //test is not a keyword, it only marks a function when fn follows it
+            let is_test = matches!(&t.tok, Token::VarRef(n) if **n == "test")
+                && input.get(i + 1).is_some_and(|n| n.tok.tok_type() == "Func");
+            if (ty == "Func" || ty == "Extern" || ty == "Export" || is_test)
                 && brace_depth == 0
                 && paren_depth == 0
             {
@@ -353,7 +373,7 @@ impl Boxer {
 
                 // Check if this is an export declaration
                 let is_export = ty == "Export";
-                let actual_start = if is_export { i + 1 } else { i };
+                let actual_start = if is_export || is_test { i + 1 } else { i };
 
                 // If export, verify the next token is Func or Extern
                 if is_export {
@@ -425,7 +445,11 @@ impl Boxer {
                 }
 
                 let func_slice = input[actual_start..func_end].to_vec();
-                boxes.push(self.box_fn_stmt(func_slice, is_export)?);
+                let func = self.box_fn_stmt(func_slice, is_export)?;
+                if is_test {
+                    self.register_test(&func)?;
+                }
+                boxes.push(func);
                 i = func_end;
                 continue;
             }
@@ -441,6 +465,21 @@ impl Boxer {
         return Ok(boxes);
     }
 
+    ///A test takes nothing and returns nothing, `toy test` calls it by name
+    fn register_test(&mut self, func: &TBox) -> Result<(), ToyError> {
+        let TBox::FuncDec(name, params, return_type, _, span, _) = func else {
+            unreachable!()
+        };
+        if self.current_struct.is_some() || !params.is_empty() || *return_type != TypeTok::Void {
+            return Err(ToyError::new(
+                ToyErrorType::MalformedFunctionDeclaration,
+                span.clone(),
+            ));
+        }
+        self.tests.push(name.tok.to_string());
+        return Ok(());
+    }
+
     fn box_params(&mut self, input: Vec<SpannedToken>) -> Result<Vec<TBox>, ToyError> {
         let cumulative_span = if input.is_empty() {
             Span::null_span_with_msg(&"empty input to box_params")
@@ -453,17 +492,117 @@ impl Boxer {
             return Ok(vec![]);
         }
         //Split by comma
-        let triplets: Vec<&[SpannedToken]> =
-            input.as_slice().split(|t| t.tok == Token::Comma).collect();
+        let triplets: Vec<&[SpannedToken]> = Boxer::split_extern_params(&input);
         let mut func_params: Vec<TBox> = Vec::new();
         for triple in triplets {
-            let (param_type, _) = self.parse_type(&triple[2..])?;
+            if triple.len() < 3 {
+                return Err(ToyError::new(
+                    ToyErrorType::MalformedFunctionDeclaration,
+                    cumulative_span.clone(),
+                ));
+            }
+            //a callback is the address of a Toy function, the function itself only sees an int
+            let param_type = if triple[2].tok == Token::Func {
+                self.parse_callback_type(&triple[2..])?;
+                TypeTok::Int
+            } else {
+                self.parse_type(&triple[2..])?.0
+            };
             let param = TBox::FuncParam(triple[0].clone(), param_type, cumulative_span.clone());
             func_params.push(param);
         }
         return Ok(func_params);
     }
 
+    ///The C types of a function's params when one of them is a callback, so a function name passed
+    ///to it is turned into a reference the way it is for an extern
+    fn callback_param_types(
+        &self,
+        input: &[SpannedToken],
+    ) -> Result<Option<Vec<ExternType>>, ToyError> {
+        if !input.iter().any(|t| t.tok == Token::Func) {
+            return Ok(None);
+        }
+        let mut types = vec![];
+        for triple in Boxer::split_extern_params(input) {
+            if triple.len() >= 3 && triple[2].tok == Token::Func {
+                types.push(self.parse_callback_type(&triple[2..])?);
+            } else {
+                types.push(ExternType::from_type_tok(&self.parse_type(&triple[2..])?.0));
+            }
+        }
+        return Ok(Some(types));
+    }
+
+    ///Splits on the commas that are not inside parens, callback types have their own param lists
+    fn split_extern_params(input: &[SpannedToken]) -> Vec<&[SpannedToken]> {
+        let mut params = vec![];
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, t) in input.iter().enumerate() {
+            match t.tok {
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                Token::Comma if depth == 0 => {
+                    params.push(&input[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        params.push(&input[start..]);
+        return params;
+    }
+    ///A C type in an extern declaration, either a c_ type or a Toy type passed the way ExternType::from_type_tok describes
+    fn parse_extern_type(&self, input: &[SpannedToken]) -> Result<ExternType, ToyError> {
+        if input.len() == 1 {
+            if let Token::ExternType(t) = &input[0].tok {
+                return Ok(t.ty.clone());
+            }
+        }
+        let (ty, consumed) = self.parse_type(input)?;
+        if consumed != input.len() {
+            return Err(ToyError::new(
+                ToyErrorType::MalformedType,
+                Boxer::total_span(input.to_vec()),
+            ));
+        }
+        return Ok(ExternType::from_type_tok(&ty));
+    }
+    ///`fn(c_int32_t, c_double): c_int32_t`, the return type can be left off for callbacks that return nothing
+    fn parse_callback_type(&self, input: &[SpannedToken]) -> Result<ExternType, ToyError> {
+        let cumulative_span = Boxer::total_span(input.to_vec());
+        let malformed = || ToyError::new(ToyErrorType::MalformedType, cumulative_span.clone());
+        if input.len() < 3 || input[1].tok != Token::LParen {
+            return Err(malformed());
+        }
+        let close = input.iter().position(|t| t.tok == Token::RParen).ok_or_else(malformed)?;
+        let mut params = vec![];
+        if close > 2 {
+            for param in Boxer::split_extern_params(&input[2..close]) {
+                if param.is_empty() {
+                    return Err(malformed());
+                }
+                params.push(self.parse_extern_type(param)?);
+            }
+        }
+        let ret = if close + 1 == input.len() {
+            ExternType::c_void(0)
+        } else if input[close + 1].tok == Token::Colon && close + 2 < input.len() {
+            self.parse_extern_type(&input[close + 2..])?
+        } else {
+            return Err(malformed());
+        };
+        //callbacks are plain Toy functions, they can not take structs by value or other callbacks
+        let is_passable = |t: &ExternType| {
+            !matches!(t, ExternType::c_struct(_, 0) | ExternType::c_fn(_, _))
+        };
+        if !params.iter().all(is_passable) || !is_passable(&ret) {
+            return Err(malformed());
+        }
+        return Ok(ExternType::c_fn(params, Box::new(ret)));
+    }
+
     fn box_extern_params(&mut self, input: Vec<SpannedToken>) -> Result<Vec<TBox>, ToyError> {
         let cumulative_span = if input.is_empty() {
             Span::null_span_with_msg(&"empty input to box_extern_params")
@@ -475,8 +614,7 @@ impl Boxer {
             return Ok(vec![]);
         }
 
-        let triplets: Vec<&[SpannedToken]> =
-            input.as_slice().split(|t| t.tok == Token::Comma).collect();
+        let triplets: Vec<&[SpannedToken]> = Boxer::split_extern_params(&input);
         let mut extern_params: Vec<TBox> = Vec::new();
 
         for triple in triplets {
@@ -487,6 +625,8 @@ impl Boxer {
                 ));
             }
 
+            let is_byval = triple.len() > 3
+                && triple[2].get_var_name().is_some_and(|n| n.as_str() == "byval");
             let param = match &triple[2].tok {
                 Token::ExternType(t) => {
                     let extern_type = QualifiedExternType {
@@ -495,6 +635,27 @@ impl Boxer {
                     };
                     TBox::ExternFuncParam(triple[0].clone(), extern_type, cumulative_span.clone())
                 }
+                Token::Func => {
+                    let extern_type = QualifiedExternType {
+                        ty: self.parse_callback_type(&triple[2..])?,
+                        is_released: true,
+                    };
+                    TBox::ExternFuncParam(triple[0].clone(), extern_type, cumulative_span.clone())
+                }
+                _ if is_byval => {
+                    let (param_type, _) = self.parse_type(&triple[3..])?;
+                    if !matches!(param_type, TypeTok::Struct(_)) {
+                        return Err(ToyError::new(
+                            ToyErrorType::MalformedType,
+                            cumulative_span.clone(),
+                        ));
+                    }
+                    let extern_type = QualifiedExternType {
+                        ty: ExternType::c_struct(param_type, 0),
+                        is_released: true,
+                    };
+                    TBox::ExternFuncParam(triple[0].clone(), extern_type, cumulative_span.clone())
+                }
                 _ => {
                     let (param_type, _) = self.parse_type(&triple[2..])?;
                     TBox::FuncParam(triple[0].clone(), param_type, cumulative_span.clone())
@@ -534,16 +695,23 @@ impl Boxer {
 
         let mut unboxed_params: Vec<SpannedToken> = Vec::new();
         let mut return_type_begin: usize = 0;
+        let mut depth = 0;
         for i in 4..input.len() {
-            if input[i].tok.tok_type() == "RParen" {
-                return_type_begin = i;
-                break;
+            match input[i].tok {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 0 => {
+                    return_type_begin = i;
+                    break;
+                }
+                Token::RParen => depth -= 1,
+                _ => {}
             }
             unboxed_params.push(input[i].clone());
         }
         let boxed_params: Vec<TBox> = self.box_extern_params(unboxed_params)?;
 
         // Check return type
+        let mut c_return_type = None;
         let return_type = if input[return_type_begin + 1].tok.tok_type() == "Semicolon" {
             TypeTok::Void
         } else {
@@ -553,7 +721,15 @@ impl Boxer {
                     cumulative_span.clone(),
                 ));
             }
-            let (t, consumed) = self.parse_type(&input[return_type_begin + 2..])?;
+            //C typed returns are converted to the matching Toy type after the call
+            let (t, consumed) = match &input[return_type_begin + 2].tok {
+                Token::ExternType(q) if q.ty == ExternType::c_void(0) => (TypeTok::Void, 1),
+                Token::ExternType(q) => {
+                    c_return_type = Some(q.ty.clone());
+                    (Driver::extern_type_to_type_tok(q.ty.clone()), 1)
+                }
+                _ => self.parse_type(&input[return_type_begin + 2..])?,
+            };
 
             if input[return_type_begin + 2 + consumed].tok.tok_type() != "Semicolon" {
                 return Err(ToyError::new(
@@ -568,6 +744,7 @@ impl Boxer {
             func_name,
             boxed_params,
             return_type,
+            c_return_type,
             cumulative_span,
         ));
     }
@@ -596,13 +773,24 @@ impl Boxer {
         }
         let mut unboxed_params: Vec<SpannedToken> = Vec::new();
         let mut return_type_begin: usize = 0;
+        let mut depth = 0;
         for i in 3..input.len() {
-            if input[i].tok.tok_type() == "RParen" {
-                return_type_begin = i;
-                break;
+            match input[i].tok {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 0 => {
+                    return_type_begin = i;
+                    break;
+                }
+                Token::RParen => depth -= 1,
+                _ => {}
             }
             unboxed_params.push(input[i].clone());
         }
+        if self.current_struct.is_none() {
+            if let Some(types) = self.callback_param_types(&unboxed_params)? {
+                self.callbacks.insert(func_name.tok.to_string(), types);
+            }
+        }
         let mut boxed_params: Vec<TBox> = self.box_params(unboxed_params)?;
 
         let mut func_name = func_name;
@@ -814,11 +1002,34 @@ impl Boxer {
         }
         Ok(TBox::ImportStmt(module_name, cumulative_span))
     }
+    ///`link "m";` or `link "vendor/libfoo.a";`
+    fn box_link_stmt(&self, toks: &Vec<SpannedToken>) -> Result<TBox, ToyError> {
+        let cumulative_span = Boxer::total_span(toks.to_vec());
+        let lib = match toks.get(1).map(|t| &t.tok) {
+            Some(Token::StringLit(lib)) if !lib.is_empty() => *lib.clone(),
+            _ => {
+                return Err(ToyError::new(
+                    ToyErrorType::MalformedLinkStatement,
+                    cumulative_span.clone(),
+                ));
+            }
+        };
+        if toks[2..].iter().any(|t| t.tok != Token::Semicolon) {
+            return Err(ToyError::new(
+                ToyErrorType::MalformedLinkStatement,
+                cumulative_span.clone(),
+            ));
+        }
+        Ok(TBox::LinkStmt(lib, cumulative_span))
+    }
     fn box_statement(&mut self, toks: Vec<SpannedToken>) -> Result<TBox, ToyError> {
         let first = toks[0].tok.tok_type();
         if first == "Import" {
             return self.box_import_stmt(&toks);
         }
+        if first == "Link" {
+            return self.box_link_stmt(&toks);
+        }
         if first == "Let" {
             return self.box_var_dec(&toks);
         }
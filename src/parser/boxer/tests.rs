@@ -71,10 +71,11 @@ fn eq_tbox_ignoring_src(x: &TBox, y: &TBox) -> bool {
         (TBox::StructInterface(xn, xkv, _), TBox::StructInterface(yn, ykv, _)) => {
             xn == yn && xkv == ykv
         }
-        (TBox::ExternFuncDec(xn, xp, xr, _), TBox::ExternFuncDec(yn, yp, yr, _)) => {
-            xn.tok == yn.tok && xr == yr && compare_tbox_vecs(xp.clone(), yp.clone())
+        (TBox::ExternFuncDec(xn, xp, xr, xc, _), TBox::ExternFuncDec(yn, yp, yr, yc, _)) => {
+            xn.tok == yn.tok && xr == yr && xc == yc && compare_tbox_vecs(xp.clone(), yp.clone())
         }
         (TBox::ImportStmt(xn, _), TBox::ImportStmt(yn, _)) => xn == yn,
+        (TBox::LinkStmt(xl, _), TBox::LinkStmt(yl, _)) => xl == yl,
         (TBox::Interface(xt, _), TBox::Interface(yt, _)) => xt == yt,
         _ => false,
     }
@@ -1230,6 +1231,7 @@ fn test_boxer_extern_function_declaration() {
                 Span::null_span()
             )],
             TypeTok::Int,
+            None,
             Span::null_span()
         )]
     ))
@@ -1253,11 +1255,154 @@ fn test_boxer_extern_function_declaration_void() {
                 Span::null_span()
             )],
             TypeTok::Void,
+            None,
+            Span::null_span()
+        )]
+    ))
+}
+
+#[test]
+fn test_boxer_extern_function_declaration_c_return() {
+    let input = String::from("extern fn abs(x: c_int32_t): c_int32_t;");
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l.lex(input).unwrap();
+    let boxes = b.box_toks(toks);
+
+    assert!(compare_tbox_vecs(
+        boxes.unwrap(),
+        vec![TBox::ExternFuncDec(
+            SpannedToken::new_null(Token::VarName(Box::new("abs".to_string()))),
+            vec![TBox::ExternFuncParam(
+                SpannedToken::new_null(Token::VarRef(Box::new("x".to_string()))),
+                QualifiedExternType{ty: ExternType::c_int32_t(0), is_released: true},
+                Span::null_span()
+            )],
+            TypeTok::Int,
+            Some(ExternType::c_int32_t(0)),
             Span::null_span()
         )]
     ))
 }
 
+#[test]
+fn test_boxer_extern_function_declaration_callback() {
+    let input = String::from(
+        "extern fn qsort(base: c_void_ptr, n: c_uint64_t, size: c_uint64_t, cmp: fn(c_void_ptr, c_void_ptr): c_int32_t);",
+    );
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l.lex(input).unwrap();
+    let boxes = b.box_toks(toks).unwrap();
+
+    let TBox::ExternFuncDec(_, params, ret, c_ret, _) = &boxes[0] else {
+        panic!("expected an extern declaration, got {:?}", boxes);
+    };
+    assert_eq!(params.len(), 4);
+    assert_eq!(*ret, TypeTok::Void);
+    assert_eq!(*c_ret, None);
+    assert!(eq_tbox_ignoring_src(
+        &params[3],
+        &TBox::ExternFuncParam(
+            SpannedToken::new_null(Token::VarRef(Box::new("cmp".to_string()))),
+            QualifiedExternType {
+                ty: ExternType::c_fn(
+                    vec![ExternType::c_void(1), ExternType::c_void(1)],
+                    Box::new(ExternType::c_int32_t(0))
+                ),
+                is_released: true
+            },
+            Span::null_span()
+        )
+    ));
+}
+
+#[test]
+fn test_boxer_extern_function_declaration_byval_struct() {
+    let input =
+        String::from("struct Point{x: int, y: int}; extern fn norm(p: byval Point): c_double;");
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l.lex(input).unwrap();
+    let boxes = b.box_toks(toks).unwrap();
+
+    let point = TypeTok::Struct(BTreeMap::from([
+        ("x".to_string(), Box::new(TypeTok::Int)),
+        ("y".to_string(), Box::new(TypeTok::Int)),
+    ]));
+    assert!(eq_tbox_ignoring_src(
+        &boxes[1],
+        &TBox::ExternFuncDec(
+            SpannedToken::new_null(Token::VarName(Box::new("norm".to_string()))),
+            vec![TBox::ExternFuncParam(
+                SpannedToken::new_null(Token::VarRef(Box::new("p".to_string()))),
+                QualifiedExternType{ty: ExternType::c_struct(point, 0), is_released: true},
+                Span::null_span()
+            )],
+            TypeTok::Float,
+            Some(ExternType::c_double(0)),
+            Span::null_span()
+        )
+    ));
+}
+
+#[test]
+fn test_boxer_extern_callback_rejects_byval_struct() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l
+        .lex("struct Point{x: int}; extern fn each(f: fn(byval Point));".to_string())
+        .unwrap();
+    assert!(b.box_toks(toks).is_err());
+}
+
+#[test]
+fn test_boxer_function_callback_param() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::with_module_prefix("std::net".to_string());
+    let toks = l
+        .lex("fn on(pattern: str, handler: fn(c_int64_t)){ }".to_string())
+        .unwrap();
+    let boxes = b.box_toks(toks).unwrap();
+
+    let TBox::FuncDec(name, params, _, _, _, _) = &boxes[0] else {
+        panic!("expected a function, got {:?}", boxes);
+    };
+    //inside the function the callback is the address of the function passed to it
+    assert_eq!(name.tok.to_string(), "std::net::on_str_int");
+    assert_eq!(params.len(), 2);
+    assert_eq!(
+        b.callbacks().get("std::net::on"),
+        Some(&vec![
+            ExternType::c_char(1),
+            ExternType::c_fn(vec![ExternType::c_int64_t(0)], Box::new(ExternType::c_void(0)))
+        ])
+    );
+}
+
+#[test]
+fn test_boxer_link_stmt() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l.lex("link \"m\"; link \"vendor/libfoo.a\";".to_string());
+    let boxes = b.box_toks(toks.unwrap());
+    assert!(compare_tbox_vecs(
+        boxes.unwrap(),
+        vec![
+            TBox::LinkStmt("m".to_string(), Span::null_span()),
+            TBox::LinkStmt("vendor/libfoo.a".to_string(), Span::null_span()),
+        ]
+    ));
+}
+
+#[test]
+fn test_boxer_link_stmt_needs_a_string() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l.lex("link m;".to_string()).unwrap();
+    assert!(b.box_toks(toks).is_err());
+}
+
 #[test]
 fn test_boxer_import_stmt() {
     let mut l = Lexer::new();
@@ -1572,3 +1717,55 @@ fn test_boxer_export_function() {
         )]
     ));
 }
+
+#[test]
+fn test_boxer_test_fn() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let input = "test fn adds() {assert(true);} fn helper() {}".to_string();
+    let toks = l.lex(input).unwrap();
+    let boxes = b.box_toks(toks).unwrap();
+
+    assert_eq!(b.tests(), &vec!["adds".to_string()]);
+    assert!(compare_tbox_vecs(
+        boxes,
+        vec![
+            TBox::FuncDec(
+                SpannedToken::new_null(Token::VarName(Box::new("adds".to_string()))),
+                vec![],
+                TypeTok::Void,
+                vec![TBox::Expr(
+                    vec![
+                        SpannedToken::new_null(Token::VarRef(Box::new("assert".to_string()))),
+                        SpannedToken::new_null(Token::LParen),
+                        SpannedToken::new_null(Token::BoolLit(true)),
+                        SpannedToken::new_null(Token::RParen),
+                    ],
+                    Span::null_span()
+                )],
+                Span::null_span(),
+                false
+            ),
+            TBox::FuncDec(
+                SpannedToken::new_null(Token::VarName(Box::new("helper".to_string()))),
+                vec![],
+                TypeTok::Void,
+                vec![],
+                Span::null_span(),
+                false
+            ),
+        ]
+    ));
+}
+
+#[test]
+fn test_boxer_test_fn_takes_nothing() {
+    let mut l = Lexer::new();
+    let mut b = Boxer::new();
+    let toks = l.lex("test fn adds(a: int) {}".to_string()).unwrap();
+    assert!(b.box_toks(toks).is_err());
+
+    let mut b = Boxer::new();
+    let toks = l.lex("test fn adds(): int {return 1;}".to_string()).unwrap();
+    assert!(b.box_toks(toks).is_err());
+}
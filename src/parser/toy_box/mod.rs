@@ -3,7 +3,7 @@ use std::fmt;
 
 use crate::{
     errors::Span,
-    token::{QualifiedExternType, SpannedToken, TypeTok},
+    token::{ExternType, QualifiedExternType, SpannedToken, TypeTok},
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -35,13 +35,15 @@ pub enum TBox {
     ///Name, types, Source code
     StructInterface(Box<String>, Box<BTreeMap<String, TypeTok>>, Span),
     ///used for extern function declarations, those functions are called like any other
-    ///Name, Params, Return Type, source code
+    ///Name, Params, Return Type, C return type if it was declared with one, source code
     ///For the Vec<TBox> it will ALWAYS be Vec<TBOX::ExternFuncDecParam
-    ExternFuncDec(SpannedToken, Vec<TBox>, TypeTok, Span),
+    ExternFuncDec(SpannedToken, Vec<TBox>, TypeTok, Option<ExternType>, Span),
     ///name, type, span
     ExternFuncParam(SpannedToken, QualifiedExternType, Span),
     ///name of the module being imported, source_code
     ImportStmt(String, Span),
+    ///library name or path to a .a, .so or .o, source_code
+    LinkStmt(String, Span),
     ///Interfaces just contain the TypeTok of the interface, then the source code
     Interface(TypeTok, Span),
 }
@@ -63,6 +65,24 @@ impl TBox {
         }
         return vec![];
     }
+    ///will return the c types of the params of an extern func dec, toy typed params are mapped the same way ast_gen maps them
+    pub fn get_extern_param_types(&self) -> Vec<ExternType> {
+        match self {
+            TBox::ExternFuncDec(_, p, _, _, _) => {
+                let mut v: Vec<ExternType> = vec![];
+                for param in p {
+                    match param {
+                        TBox::ExternFuncParam(_, q, _) => v.push(q.ty.clone()),
+                        TBox::FuncParam(_, t, _) => v.push(ExternType::from_type_tok(t)),
+                        _ => unreachable!(),
+                    }
+                }
+                return v;
+            }
+            _ => {}
+        }
+        return vec![];
+    }
 }
 
 impl fmt::Display for TBox {
@@ -102,12 +122,13 @@ impl fmt::Display for TBox {
                     "TBox_Struct_Interface Name({}), KV({:?}), Literal({})",
                     *n, *kv, s
                 ),
-                TBox::ExternFuncDec(name, params, return_type, s) => format!(
-                    "TBox_Extern_Func_Dec Name({}), Params({:?}), ReturnType({:?}), Literal({})",
-                    name, params, return_type, s
+                TBox::ExternFuncDec(name, params, return_type, c_return_type, s) => format!(
+                    "TBox_Extern_Func_Dec Name({}), Params({:?}), ReturnType({:?}), CReturnType({:?}), Literal({})",
+                    name, params, return_type, c_return_type, s
                 ),
                 TBox::ImportStmt(name, s) =>
                     format!("TBox_Import_Stmt Name({}), Literal({})", name, s),
+                TBox::LinkStmt(lib, s) => format!("TBox_Link_Stmt Lib({}), Literal({})", lib, s),
                 TBox::Interface(ty, s) => format!("TBox_Interface Type({:#?}), Literal({})", ty, s),
                 TBox::ExternFuncParam(n, t, s) => format!("TBox_ExternFuncParam Name({}), Type({:?}), Literal({})", n, t, s)
             }
@@ -129,8 +150,9 @@ impl TBox {
             TBox::Break(s) => s.clone(),
             TBox::Continue(s) => s.clone(),
             TBox::StructInterface(_, _, s) => s.clone(),
-            TBox::ExternFuncDec(_, _, _, s) => s.clone(),
+            TBox::ExternFuncDec(_, _, _, _, s) => s.clone(),
             TBox::ImportStmt(_, s) => s.clone(),
+            TBox::LinkStmt(_, s) => s.clone(),
             TBox::Interface(_, s) => s.clone(),
             TBox::ExternFuncParam(_, _, s) => s.clone()
         };
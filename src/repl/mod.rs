@@ -0,0 +1,257 @@
+//! The `--repl` session. Every entry is compiled to a module of its own and added to one JIT engine,
+//! which keeps the functions of earlier entries to call. Top level variables are kept by the
+//! runtime, an entry loads the ones earlier entries stored and stores its own before it returns.
+//! Struct, extern, import and link declarations generate no code so they are put in front of every
+//! entry. Nothing runs twice, reading input or writing files happens once.
+use crate::codegen::Function;
+use crate::driver::{Driver, ReplScope};
+use crate::jit;
+use crate::parser::ast::Ast;
+use crate::token::TypeTok;
+use inkwell::context::Context;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+///every input read, kept across sessions as a json array of strings
+const HISTORY_PATH: &str = "./temp/repl_history.json";
+///variable `:type` binds its expression to
+const TYPE_PROBE: &str = "repl_type_probe";
+///sessions started by this process, each writes its entries to files of its own
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Session {
+    id: usize,
+    ///the engine lives as long as the session, so its context does too
+    ctx: &'static Context,
+    engine: jit::Engine,
+    ///what the entries that compiled so far left for the next one
+    scope: ReplScope,
+    ///entries compiled so far, names the file the next one is written to
+    entries: usize,
+    history: Vec<String>,
+}
+
+impl Session {
+    pub fn new() -> Result<Session, Box<dyn Error>> {
+        let history = fs::read_to_string(HISTORY_PATH)
+            .ok()
+            .and_then(|h| serde_json::from_str(&h).ok())
+            .unwrap_or_default();
+        let ctx: &'static Context = Box::leak(Box::new(Context::create()));
+        return Ok(Session {
+            id: SESSIONS.fetch_add(1, Ordering::SeqCst),
+            ctx,
+            engine: jit::Engine::new(ctx)?,
+            scope: ReplScope::default(),
+            entries: 0,
+            history,
+        });
+    }
+    ///A driver for `source` on top of the session. Entries keep their file, spans of the functions
+    ///they define point into it
+    fn driver(&self, source: &str, file: &str) -> Result<Driver, Box<dyn Error>> {
+        fs::create_dir_all("temp")?;
+        let path = PathBuf::from(format!("./temp/repl_{}_{}.toy", self.id, file));
+        fs::write(&path, format!("{}\n", source))?;
+        let mut driver = Driver::new(path);
+        driver.repl = Some(self.scope.clone());
+        return Ok(driver);
+    }
+    ///Compiles `input` and runs it once in the session's engine
+    pub fn eval(&mut self, input: &str) -> Result<(), Box<dyn Error>> {
+        self.entries += 1;
+        let mut driver = self.driver(input, &self.entries.to_string())?;
+        let res = driver.run_repl_entry(self.ctx, &mut self.engine);
+        if let Some(scope) = driver.repl.take() {
+            self.scope = scope;
+        }
+        res?;
+        Ok(())
+    }
+    ///`:type expr`, the type of `expr` written the way Toy code would
+    pub fn type_of(&self, expr: &str) -> Result<String, Box<dyn Error>> {
+        if expr.is_empty() {
+            return Err("usage: :type <expr>".into());
+        }
+        let probe = format!("let {} = {};", TYPE_PROBE, expr.trim_end_matches(';'));
+        let ast = self.driver(&probe, "probe")?.main_ast()?;
+        let ty = ast.iter().rev().find_map(|node| match node {
+            Ast::VarDec(name, ty, _, _) if **name == TYPE_PROBE => Some(ty),
+            _ => None,
+        });
+        return match ty {
+            Some(ty) => Ok(type_name(ty)),
+            None => Err(format!("{} is not an expression", expr).into()),
+        };
+    }
+    ///`:ast code`, the nodes `code` compiles to, the declarations the session carries without code
+    pub fn ast(&self, code: &str) -> Result<Vec<Ast>, Box<dyn Error>> {
+        let session = self.driver("", "probe")?.main_ast()?;
+        if code.is_empty() {
+            return Ok(session);
+        }
+        let with_code = self.driver(code, "probe")?.main_ast()?;
+        return Ok(with_code[session.len().min(with_code.len())..].to_vec());
+    }
+    ///`:tir code`, the TIR `code` compiles to as the next entry, before CTLA runs
+    pub fn tir(&self, code: &str) -> Result<Vec<Function>, Box<dyn Error>> {
+        let ctx = Context::create();
+        let mut driver = self.driver(code, "probe")?;
+        return Ok(driver.main_tir(&ctx)?);
+    }
+    fn record(&mut self, input: &str) {
+        self.history.push(input.to_string());
+        if let Ok(history) = serde_json::to_string(&self.history) {
+            let _ = fs::create_dir_all("temp").and_then(|_| fs::write(HISTORY_PATH, history));
+        }
+    }
+    ///Handles a meta command, anything that does not start with `:` is Toy code
+    pub fn command(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let Some(command) = text.strip_prefix(':') else {
+            return self.eval(text);
+        };
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .map(|(name, arg)| (name, arg.trim()))
+            .unwrap_or((command, ""));
+        match name {
+            "type" => println!("{}", self.type_of(arg)?),
+            "ast" => {
+                for node in self.ast(arg)? {
+                    println!("{}", node);
+                }
+            }
+            "tir" => {
+                for func in self.tir(arg)? {
+                    println!("{:#?}", func);
+                }
+            }
+            "history" => {
+                for (i, input) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, input.replace('\n', "\n      "));
+                }
+            }
+            "reset" => {
+                //a new engine, so functions can be defined again
+                self.engine = jit::Engine::new(self.ctx)?;
+                self.scope = ReplScope::default();
+                println!("Session cleared");
+            }
+            _ => {
+                return Err(format!(
+                    "unknown command :{}, expected :type, :ast, :tir, :history or :reset",
+                    name
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+///How many `{`, `(` and `[` are still open, ignoring string literals and `//` comments. The REPL
+///keeps reading lines while this is above 0
+pub fn open_delimiters(text: &str) -> i64 {
+    let mut depth = 0;
+    for line in text.lines() {
+        let mut in_str = false;
+        let mut escaped = false;
+        let mut prev = ' ';
+        for c in line.chars() {
+            if in_str {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    in_str = false;
+                }
+                continue;
+            }
+            match c {
+                '"' => in_str = true,
+                '/' if prev == '/' => break,
+                '{' | '(' | '[' => depth += 1,
+                '}' | ')' | ']' => depth -= 1,
+                _ => {}
+            }
+            prev = c;
+        }
+    }
+    return depth;
+}
+
+///`ty` as it is written in Toy code, struct types have no name so they are written as their fields
+pub fn type_name(ty: &TypeTok) -> String {
+    fn fields<'t>(fields: impl Iterator<Item = (&'t String, &'t TypeTok)>) -> String {
+        let fields: Vec<String> = fields
+            .map(|(name, ty)| format!("{}: {}", name, type_name(ty)))
+            .collect();
+        return format!("{{{}}}", fields.join(", "));
+    }
+    let arr = |elem: String, degree: u64| format!("{}{}", elem, "[]".repeat(degree as usize));
+    return match ty {
+        TypeTok::Int => "int".to_string(),
+        TypeTok::Bool => "bool".to_string(),
+        TypeTok::Void => "void".to_string(),
+        TypeTok::Str => "str".to_string(),
+        TypeTok::Any => "any".to_string(),
+        TypeTok::Float => "float".to_string(),
+        TypeTok::IntArr(d) => arr("int".to_string(), *d),
+        TypeTok::BoolArr(d) => arr("bool".to_string(), *d),
+        TypeTok::StrArr(d) => arr("str".to_string(), *d),
+        TypeTok::AnyArr(d) => arr("any".to_string(), *d),
+        TypeTok::FloatArr(d) => arr("float".to_string(), *d),
+        TypeTok::Struct(f) => fields(f.iter().map(|(k, v)| (k, &**v))),
+        TypeTok::StructArr(f, d) => arr(fields(f.iter().map(|(k, v)| (k, &**v))), *d),
+        TypeTok::Interface(f, _) => fields(f.iter()),
+        TypeTok::InterfaceArr(f, _, d) => arr(fields(f.iter()), *d),
+        TypeTok::Map(k, v) => format!("map<{}, {}>", type_name(k), type_name(v)),
+    };
+}
+
+pub fn run() {
+    let mut session = match Session::new() {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        let read = io::stdin()
+            .read_line(&mut line)
+            .expect("Failed to read line");
+        input.push_str(&line);
+        //keep reading until every brace is closed
+        if read != 0 && open_delimiters(&input) > 0 {
+            continue;
+        }
+        let text = std::mem::take(&mut input);
+        let text = text.trim();
+        if read == 0 || text.to_lowercase() == "exit" || text.to_lowercase() == "quit" {
+            println!("Exiting");
+            jit::shutdown();
+            return;
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        session.record(text);
+        if let Err(e) = session.command(text) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
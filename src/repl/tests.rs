@@ -0,0 +1,139 @@
+use super::*;
+use std::collections::BTreeMap;
+
+#[test]
+fn test_repl_open_delimiters() {
+    assert_eq!(open_delimiters("let x = 5;"), 0);
+    assert_eq!(open_delimiters("fn add(a: int, b: int): int{"), 1);
+    assert_eq!(
+        open_delimiters("fn add(a: int, b: int): int{\n    return a + b;\n}"),
+        0
+    );
+    assert_eq!(open_delimiters("while x < 3 {\n    if x == 1 {"), 2);
+    assert_eq!(open_delimiters("let arr = [1, 2,"), 1);
+}
+
+#[test]
+fn test_repl_open_delimiters_skips_strings_and_comments() {
+    assert_eq!(open_delimiters("println(\"{ not a brace\");"), 0);
+    assert_eq!(open_delimiters("println(\"a \\\" quote {\");"), 0);
+    assert_eq!(open_delimiters("let x = 1; //if x {"), 0);
+    assert_eq!(open_delimiters("if x == 1 { //}"), 1);
+}
+
+///A file under temp/ the entries of a test write to, the runtime keeps variables by name for the
+///whole process so each test uses names of its own
+fn out_file(test_name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("temp")
+        .join(format!("repl_{}.txt", test_name));
+    let _ = fs::remove_file(&path);
+    return path.to_string_lossy().into_owned();
+}
+
+#[test]
+fn test_repl_session_keeps_state() {
+    let out = out_file("keeps_state");
+    let mut session = Session::new().unwrap();
+    session.eval("import std.fs;").unwrap();
+    session.eval("let state_total = 2;").unwrap();
+    session
+        .eval("fn state_triple(n: int): int{ return n * 3; }")
+        .unwrap();
+    session.eval("struct StatePoint{x: int, y: float}").unwrap();
+    session
+        .eval("let state_p = StatePoint{x: 1, y: 2.5}; let state_on = true;")
+        .unwrap();
+    session
+        .eval("state_total = state_triple(state_total) + state_p.x;")
+        .unwrap();
+    session
+        .eval(&format!(
+            "if state_on {{ fs.write_file(\"{}\", str(state_total) + \" \" + str(state_p.y)); }}",
+            out
+        ))
+        .unwrap();
+    assert_eq!(fs::read_to_string(&out).unwrap(), "7 2.5");
+    assert_eq!(session.type_of("state_p").unwrap(), "{x: int, y: float}");
+}
+
+#[test]
+fn test_repl_side_effects_run_once() {
+    let out = out_file("runs_once");
+    let mut session = Session::new().unwrap();
+    session.eval("import std.fs;").unwrap();
+    session
+        .eval(&format!("fs.append_file(\"{}\", \"a\\n\");", out))
+        .unwrap();
+    session
+        .eval(&format!("fs.append_file(\"{}\", \"b\\n\");", out))
+        .unwrap();
+    session.eval("let once_x = 1;").unwrap();
+    assert_eq!(fs::read_to_string(&out).unwrap(), "a\nb\n");
+}
+
+#[test]
+fn test_repl_runtime_error_keeps_session() {
+    let out = out_file("runtime_error");
+    let mut session = Session::new().unwrap();
+    session.eval("import std.fs;").unwrap();
+    session.eval("let err_keep = 4;").unwrap();
+    assert!(
+        session
+            .eval(
+                "fn err_inc(n: int): int{ return n + 1; } let err_arr = [1]; println(err_arr[9]);"
+            )
+            .is_err()
+    );
+    //its variables were never stored, its function is in the engine
+    assert!(session.eval("println(err_arr);").is_err());
+    session
+        .eval(&format!(
+            "fs.write_file(\"{}\", str(err_inc(err_keep)));",
+            out
+        ))
+        .unwrap();
+    assert_eq!(fs::read_to_string(&out).unwrap(), "5");
+}
+
+#[test]
+fn test_repl_rejects_redefined_function() {
+    let mut session = Session::new().unwrap();
+    session
+        .eval("fn redef_sq(n: int): int{ return n * n; }")
+        .unwrap();
+    assert!(
+        session
+            .eval("fn redef_sq(n: int): int{ return n + n; }")
+            .is_err()
+    );
+    session.command(":reset").unwrap();
+    session
+        .eval("fn redef_sq(n: int): int{ return n + n; }")
+        .unwrap();
+}
+
+#[test]
+fn test_repl_type_name() {
+    assert_eq!(type_name(&TypeTok::Int), "int");
+    assert_eq!(type_name(&TypeTok::StrArr(2)), "str[][]");
+    assert_eq!(
+        type_name(&TypeTok::Map(
+            Box::new(TypeTok::Str),
+            Box::new(TypeTok::Float)
+        )),
+        "map<str, float>"
+    );
+    let point = BTreeMap::from([
+        ("x".to_string(), Box::new(TypeTok::Float)),
+        ("y".to_string(), Box::new(TypeTok::Float)),
+    ]);
+    assert_eq!(
+        type_name(&TypeTok::Struct(point.clone())),
+        "{x: float, y: float}"
+    );
+    assert_eq!(
+        type_name(&TypeTok::StructArr(point, 1)),
+        "{x: float, y: float}[]"
+    );
+}
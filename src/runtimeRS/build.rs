@@ -15,14 +15,27 @@ fn main() {
     );
     let target = env::var("TARGET").expect("TARGET must be set");
 
-    let source = root
-        .join("src")
-        .join("runtimeRS")
-        .join("target")
+    //instrumented and library flavors are built into their own target dir and live in lib/<target>/<flavor>
+    let flavor = if env::var("CARGO_FEATURE_SANITIZE").is_ok() {
+        Some("sanitize")
+    } else if env::var("CARGO_FEATURE_PROFILE").is_ok() {
+        Some("profile")
+    } else if env::var("CARGO_FEATURE_LIBRARY").is_ok() {
+        Some("library")
+    } else {
+        None
+    };
+    let mut target_dir = root.join("src").join("runtimeRS").join("target");
+    let mut out_dir = root.join("lib").join(&target);
+    if let Some(flavor) = flavor {
+        target_dir = target_dir.join(flavor);
+        out_dir = out_dir.join(flavor);
+    }
+
+    let source = target_dir
         .join(&target)
         .join("release")
         .join("libruntimers.a");
-    let out_dir = root.join("lib").join(&target);
     let dest = out_dir.join("libruntime.a");
 
     if !source.exists() {
@@ -37,4 +50,28 @@ fn main() {
             e
         )
     });
+
+    //built alongside the archive when the compiler asks for a cdylib, the JIT loads it
+    if flavor == Some("library") {
+        let shared_source = target_dir.join(&target).join("release").join(format!(
+            "{}runtimers{}",
+            env::consts::DLL_PREFIX,
+            env::consts::DLL_SUFFIX
+        ));
+        let shared_dest = out_dir.join(format!(
+            "{}runtime{}",
+            env::consts::DLL_PREFIX,
+            env::consts::DLL_SUFFIX
+        ));
+        if shared_source.exists() {
+            fs::copy(&shared_source, &shared_dest).unwrap_or_else(|e| {
+                panic!(
+                    "Failed copying shared runtime from {} to {}: {}",
+                    shared_source.display(),
+                    shared_dest.display(),
+                    e
+                )
+            });
+        }
+    }
 }
@@ -0,0 +1,113 @@
+use std::cell::Cell;
+use std::ffi::{CStr, c_char};
+use std::io::Write;
+
+///Shadow stack frame, lives on the stack of the Toy function it describes.
+///The compiler fills in everything but `prev` and keeps `line` up to date as the function runs
+#[repr(C)]
+pub struct ToyFrame {
+    prev: *const ToyFrame,
+    ///demangled function name, as a C string
+    func: *const c_char,
+    ///path of the .toy file the function is in, as a C string
+    file: *const c_char,
+    line: i64,
+}
+
+thread_local! {
+    static TOP_FRAME: Cell<*const ToyFrame> = const { Cell::new(std::ptr::null()) };
+    ///set while toy_jit_run is on the stack to catch a panic
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+///Called at the start of every Toy function with a pointer to its frame
+#[unsafe(no_mangle)]
+pub fn toy_frame_push(frame: i64) {
+    let frame = frame as *mut ToyFrame;
+    TOP_FRAME.with(|top| {
+        unsafe { (*frame).prev = top.get() };
+        top.set(frame);
+    });
+}
+
+///Called right before every Toy function returns
+#[unsafe(no_mangle)]
+pub fn toy_frame_pop() {
+    TOP_FRAME.with(|top| {
+        let frame = top.get();
+        if !frame.is_null() {
+            top.set(unsafe { (*frame).prev });
+        }
+    });
+}
+
+fn c_str(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return "<unknown>".to_string();
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
+///Prints the Toy call stack of the current thread to stderr, innermost call first
+pub fn _print_toy_backtrace() {
+    let mut frame = TOP_FRAME.with(|top| top.get());
+    if frame.is_null() {
+        return;
+    }
+    eprintln!("Toy backtrace (most recent call first):");
+    let mut depth = 0;
+    while !frame.is_null() {
+        let f = unsafe { &*frame };
+        eprintln!("  {}: {} at {}:{}", depth, c_str(f.func), c_str(f.file), f.line);
+        frame = f.prev;
+        depth += 1;
+    }
+}
+
+///Runs `user_main` of a program the JIT compiled and catches the panic of a runtime error, so the
+///REPL and `toy run` get an error back instead of the process ending. The catch has to happen
+///here, the compiler's std cannot catch a panic raised by the runtime's. Returns 1 on a runtime
+///error, otherwise 0 with what `user_main` returned written to `res`
+#[cfg(feature = "library")]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_jit_run(user_main: extern "C-unwind" fn() -> i64, res: *mut i64) -> i64 {
+    let top = TOP_FRAME.with(|top| top.get());
+    let outer = CATCHING.with(|c| c.replace(true));
+    let caught = std::panic::catch_unwind(|| user_main());
+    CATCHING.with(|c| c.set(outer));
+    return match caught {
+        Ok(v) => {
+            unsafe { *res = v };
+            0
+        }
+        Err(_) => {
+            //the frames the panic unwound through never popped themselves
+            TOP_FRAME.with(|t| t.set(top));
+            1
+        }
+    };
+}
+
+///Replaces the default panic message, which points into the runtime's Rust source,
+///with the panic message followed by the Toy backtrace. The process exits right away, compiled Toy
+///code has no unwind tables, unless the panic happened under toy_jit_run, which JIT code unwinds to
+pub fn _install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "[ERROR] Runtime error".to_string()
+        };
+        if !msg.is_empty() {
+            eprintln!("{}", msg);
+        }
+        _print_toy_backtrace();
+        let _ = std::io::stdout().flush();
+        if !CATCHING.with(|c| c.get()) {
+            std::process::exit(101);
+        }
+    }));
+}
@@ -1,33 +1,131 @@
-use crate::ToyPtr;
+use crate::{ToyPtr, ToyValue};
 use crate::ctla::{_check_pointer, toy_free};
+#[cfg(feature = "sanitize")]
 use crate::stub::DEBUG_HEAP;
 use std::ffi::{CStr, CString};
 use std::io;
 use std::io::Write;
 use std::os::raw::c_void;
 use crate::values::ToyType;
-//datatype is 0 for string, 1 for bool, 2 for int, 3 for float, 4 for str[], 5 for bool[], 6 for int[], 7 for float[], 8 for struct[]
+//datatype is 0 for string, 1 for bool, 2 for int, 3 for float, 4 for str[], 5 for bool[], 6 for int[], 7 for float[], 8 for struct[], 9 for map
 //if datatype is 0 (input is string) then input is a pointer
 //Input could be an int, if sizeof(type) > wordSize
 #[repr(C)]
-//it is ub to reference a ToyArr from C.
+///Compiled code reads len and indexes data directly, only growing the array goes through the runtime.
+///The first three fields must stay `{len, cap, data}`, the rest is private to the runtime
 pub struct ToyArr {
+    len: i64,
+    cap: i64,
+    ///cap i64s from libc::malloc, the first len are initialized
+    data: *mut i64,
     ty: ToyType,
     degree: i64,
+    ///set when the array owns its elements, freeing it frees them too. A str[] that owns its strings
+    ///copies every string written to it
     pub should_free_subelements: bool,
-    arr: Vec<i64>,
+}
+impl ToyArr {
+    fn as_slice(&self) -> &[i64] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.data, self.len as usize) }
+    }
+    fn as_mut_slice(&mut self) -> &mut [i64] {
+        if self.len == 0 {
+            return &mut [];
+        }
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.len as usize) }
+    }
+    fn push(&mut self, value: i64) {
+        if self.len == self.cap {
+            let new_cap = std::cmp::max(4, self.cap * 2);
+            let data = unsafe {
+                libc::realloc(
+                    self.data as *mut c_void,
+                    new_cap as usize * std::mem::size_of::<i64>(),
+                )
+            };
+            if data.is_null() {
+                panic!("[ERROR] Meta malloc failed");
+            }
+            self.data = data as *mut i64;
+            self.cap = new_cap;
+        }
+        unsafe { *self.data.add(self.len as usize) = value };
+        self.len += 1;
+    }
+    fn insert(&mut self, idx: usize, value: i64) {
+        self.push(value);
+        self.as_mut_slice()[idx..].rotate_right(1);
+    }
+    fn remove(&mut self, idx: usize) -> i64 {
+        let value = self.as_slice()[idx];
+        self.as_mut_slice()[idx..].rotate_left(1);
+        self.len -= 1;
+        value
+    }
+    fn holds_strs(&self) -> bool {
+        return self.ty == ToyType::StrArr && self.degree == 1;
+    }
+    fn owns_strs(&self) -> bool {
+        return self.should_free_subelements && self.holds_strs();
+    }
+    ///Gives the array its own copy of every string it holds, so it can be freed on its own
+    fn copy_strs(&mut self) {
+        if !self.holds_strs() {
+            return;
+        }
+        for elem in self.as_mut_slice() {
+            *elem = toy_malloc(*elem);
+        }
+        self.should_free_subelements = true;
+    }
+    ///strings are compared by content, floats by value, everything else by the raw element
+    fn elem_ordering(&self, a: i64, b: i64) -> std::cmp::Ordering {
+        if self.degree != 1 {
+            return a.cmp(&b);
+        }
+        match self.ty {
+            ToyType::StrArr => {
+                let a = unsafe { CStr::from_ptr(a as *const i8) };
+                let b = unsafe { CStr::from_ptr(b as *const i8) };
+                a.cmp(b)
+            }
+            ToyType::FloatArr => f64::from_bits(a as u64).total_cmp(&f64::from_bits(b as u64)),
+            _ => a.cmp(&b),
+        }
+    }
+    ///frees the elements buffer, the array is left empty
+    fn release(&mut self) {
+        unsafe { libc::free(self.data as *mut c_void) };
+        self.data = std::ptr::null_mut();
+        self.len = 0;
+        self.cap = 0;
+    }
+}
+#[macro_export]
+#[cfg(feature = "sanitize")]
+macro_rules! meta_malloc {
+    ($size:expr) => {{ $crate::ctla::_toy_malloc_debug($size) }};
 }
 #[macro_export]
+#[cfg(feature = "profile")]
 macro_rules! meta_malloc {
     ($size:expr) => {{
-        match ::std::env::var("TOY_DEBUG").as_deref() {
-            Ok("TRUE") => $crate::ctla::_toy_malloc_debug($size),
-            _ => unsafe { ::libc::malloc($size) },
-        }
+        let size = $size;
+        let buff = unsafe { ::libc::malloc(size) };
+        $crate::profile::_toy_profile_alloc(buff as i64, size as i64);
+        buff
     }};
 }
+#[macro_export]
+#[cfg(not(any(feature = "sanitize", feature = "profile")))]
+macro_rules! meta_malloc {
+    ($size:expr) => {{ unsafe { ::libc::malloc($size) } }};
+}
 #[unsafe(no_mangle)]
-pub fn _toy_format(input: ToyPtr, datatype: ToyType, degree: i64) -> *mut i8 {
+pub fn _toy_format(input: ToyValue, datatype: ToyType, degree: i64) -> *mut i8 {
     match datatype {
         ToyType::Str => {
             if input == 0 {
@@ -52,13 +150,17 @@ pub fn _toy_format(input: ToyPtr, datatype: ToyType, degree: i64) -> *mut i8 {
             let f = f64::from_bits(input as u64);
             return CString::new(format!("{f}")).unwrap().into_raw();
         }
+        ToyType::Map => {
+            let formatted = crate::map::_toy_format_map(input);
+            CString::new(formatted).unwrap().into_raw()
+        }
         _ => {
             let array = unsafe { &*(input as *const ToyArr) };
             let elem_type = array.ty.to_elem_type(); // convert array type to element type
 
-            let mut element_strs: Vec<String> = Vec::with_capacity(array.arr.len());
+            let mut element_strs: Vec<String> = Vec::with_capacity(array.as_slice().len());
 
-            for &val in &array.arr {
+            for &val in array.as_slice() {
                 let raw = _toy_format(val, elem_type.clone(), degree - 1);
                 let s = unsafe { CString::from_raw(raw) }
                     .to_string_lossy()
@@ -68,8 +170,8 @@ pub fn _toy_format(input: ToyPtr, datatype: ToyType, degree: i64) -> *mut i8 {
 
             let mut buff = String::with_capacity(
                 2 + element_strs.iter().map(|s| s.len()).sum::<usize>()
-                    + if array.arr.len() > 1 {
-                        (array.arr.len() - 1) * 2
+                    + if array.as_slice().len() > 1 {
+                        (array.as_slice().len() - 1) * 2
                     } else {
                         0
                     },
@@ -89,7 +191,7 @@ pub fn _toy_format(input: ToyPtr, datatype: ToyType, degree: i64) -> *mut i8 {
     }
 }
 #[unsafe(no_mangle)]
-pub fn toy_print(input: ToyPtr, datatype: i64, degree: i64) {
+pub fn toy_print(input: ToyValue, datatype: i64, degree: i64) {
     let raw = _toy_format(input, ToyType::try_from(datatype).unwrap(), degree);
     let s = unsafe { CStr::from_ptr(raw).to_str().unwrap() };
     print!("{}", s);
@@ -100,7 +202,7 @@ pub fn toy_print(input: ToyPtr, datatype: i64, degree: i64) {
 }
 
 #[unsafe(no_mangle)]
-pub fn toy_println(input: ToyPtr, datatype: i64, degree: i64) {
+pub fn toy_println(input: ToyValue, datatype: i64, degree: i64) {
     let raw = _toy_format(input, ToyType::try_from(datatype).unwrap(), degree);
     let s = unsafe { CStr::from_ptr(raw).to_str().unwrap() };
     println!("{}", s);
@@ -154,7 +256,9 @@ pub fn toy_strequal(sp1: ToyPtr, sp2: ToyPtr) -> i64 {
 #[unsafe(no_mangle)]
 pub fn toy_strlen(sp1: ToyPtr) -> i64 {
     _check_pointer(sp1 as *mut c_void);
-    return unsafe { libc::strlen(sp1 as *mut i8) } as i64;
+    //code points, not bytes
+    let s = unsafe { CStr::from_ptr(sp1 as *const i8) };
+    return String::from_utf8_lossy(s.to_bytes()).chars().count() as i64;
 }
 
 #[unsafe(no_mangle)]
@@ -275,9 +379,12 @@ pub fn toy_malloc_struct(size: i64, toy_struct: ToyPtr) -> ToyPtr {
 #[unsafe(no_mangle)]
 pub fn toy_malloc_arr(len: i64, ty: i64, degree: i64) -> ToyPtr {
     let toy_ty = ToyType::try_from(ty).unwrap();
-    let capacity = (len as f64 * 1.4) as usize;
-    let mut arr = vec![0i64; len as usize];
-    arr.reserve(capacity - len as usize);
+    let capacity = std::cmp::max((len as f64 * 1.4) as usize, len as usize);
+    //calloc so the first len elements start zeroed, never ask for 0 bytes so data is never null
+    let data = unsafe { libc::calloc(std::cmp::max(capacity, 1), std::mem::size_of::<i64>()) };
+    if data.is_null() {
+        panic!("[ERROR] Meta malloc failed");
+    }
 
     let arr_type = match toy_ty {
         ToyType::Str => ToyType::StrArr,
@@ -288,23 +395,62 @@ pub fn toy_malloc_arr(len: i64, ty: i64, degree: i64) -> ToyPtr {
     };
 
     let toy_arr = Box::new(ToyArr {
+        len,
+        cap: capacity as i64,
+        data: data as *mut i64,
         ty: arr_type,
         degree,
         should_free_subelements: false,
-        arr,
     });
 
-    return Box::into_raw(toy_arr) as ToyPtr;
+    let out = Box::into_raw(toy_arr) as ToyPtr;
+    #[cfg(feature = "profile")]
+    crate::profile::_toy_profile_alloc(
+        out,
+        (std::mem::size_of::<ToyArr>() + capacity * std::mem::size_of::<i64>()) as i64,
+    );
+    return out;
+}
+///New str[] holding strs, which must be fresh allocations. The array owns them, they are freed with it
+pub fn _toy_owned_str_arr(strs: &[ToyPtr]) -> ToyPtr {
+    let arr_ptr = toy_malloc_arr(strs.len() as i64, ToyType::Str as i64, 1);
+    let arr = unsafe { &mut *(arr_ptr as *mut ToyArr) };
+    arr.as_mut_slice().copy_from_slice(strs);
+    arr.should_free_subelements = true;
+    return arr_ptr;
+}
+///New int[] holding values
+pub fn _toy_int_arr(values: &[i64]) -> ToyPtr {
+    let arr_ptr = toy_malloc_arr(values.len() as i64, ToyType::Int as i64, 1);
+    let arr = unsafe { &mut *(arr_ptr as *mut ToyArr) };
+    arr.as_mut_slice().copy_from_slice(values);
+    return arr_ptr;
+}
+///The elements of an array, copied out
+pub fn _toy_arr_values(arr_ptr: ToyPtr) -> Vec<i64> {
+    _check_pointer(arr_ptr as *mut c_void);
+    let arr = unsafe { &*(arr_ptr as *const ToyArr) };
+    return arr.as_slice().to_vec();
+}
+
+///" at file:line:col" of the array access the compiler passed as site, "" when it passed 0
+fn _at_site(site: ToyPtr) -> String {
+    if site == 0 {
+        return String::new();
+    }
+    let site = unsafe { CStr::from_ptr(site as *const i8) };
+    return format!(" at {}", site.to_string_lossy());
 }
 
 #[unsafe(no_mangle)]
-///ty refers to the type of the array, so 4 for str[] not the type of the elements
-pub fn toy_write_to_arr(arr_in_ptr: ToyPtr, value: i64, idx: i64, ty: i64) {
+///ty refers to the type of the array, so 4 for str[] not the type of the elements. site is the
+///"file:line:col" of the write reported when idx is out of bounds, 0 when there is none
+pub fn toy_write_to_arr(arr_in_ptr: ToyPtr, value: ToyValue, idx: i64, ty: i64, site: ToyPtr) {
     _check_pointer(arr_in_ptr as *mut c_void);
     let arr_ptr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
     let toy_ty = ToyType::try_from(arr_ptr.ty.clone()).unwrap();
     if idx < 0 {
-        panic!("[ERROR] Index {idx} is not above zero");
+        panic!("[ERROR] Index {idx} is not above zero{}", _at_site(site));
     }
     if arr_ptr.ty != toy_ty && !(arr_ptr.ty == toy_ty.to_arr_type() || arr_ptr.ty == ToyType::Struct) {
         panic!(
@@ -315,22 +461,53 @@ pub fn toy_write_to_arr(arr_in_ptr: ToyPtr, value: i64, idx: i64, ty: i64) {
     if toy_ty == ToyType::Str {
         _check_pointer(value as *mut c_void);
     }
-    if idx as usize >= arr_ptr.arr.len() {
-        arr_ptr.arr.resize(idx as usize + 1, 0);
+    let len = arr_ptr.len as usize;
+    if idx as usize > len {
+        panic!(
+            "[ERROR] Index {idx} is out of bounds for an array of length {len}{}, writing past the end may only add one element",
+            _at_site(site)
+        );
     }
-    arr_ptr.arr[idx as usize] = value;
+    let value = if arr_ptr.owns_strs() {
+        if (idx as usize) < len {
+            toy_free(arr_ptr.as_slice()[idx as usize] as *mut c_void);
+        }
+        toy_malloc(value)
+    } else {
+        value
+    };
+    if idx as usize == len {
+        arr_ptr.push(value);
+        return;
+    }
+    arr_ptr.as_mut_slice()[idx as usize] = value;
+}
+///Appends value to the end of the array, ty is the type of the array like in toy_write_to_arr
+#[unsafe(no_mangle)]
+pub fn toy_arr_push(arr_in_ptr: ToyPtr, value: ToyValue, ty: i64) {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let len = unsafe { &*(arr_in_ptr as *const ToyArr) }.len;
+    toy_write_to_arr(arr_in_ptr, value, len, ty, 0);
 }
+///site is the "file:line:col" of the read reported when idx is out of bounds, 0 when there is none
 #[unsafe(no_mangle)]
-pub fn toy_read_from_arr(arr_in_ptr: ToyPtr, idx: i64) -> i64 {
+pub fn toy_read_from_arr(arr_in_ptr: ToyPtr, idx: i64, site: ToyPtr) -> i64 {
     _check_pointer(arr_in_ptr as *mut c_void);
     let arr_ptr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
-    return arr_ptr.arr[idx as usize];
+    let len = arr_ptr.len;
+    if idx < 0 || idx >= len {
+        panic!(
+            "[ERROR] Index {idx} is out of bounds for an array of length {len}{}",
+            _at_site(site)
+        );
+    }
+    return arr_ptr.as_slice()[idx as usize];
 }
 #[unsafe(no_mangle)]
 pub fn toy_arrlen(arr_in_ptr: ToyPtr) -> i64 {
     _check_pointer(arr_in_ptr as *mut c_void);
     let arr_ptr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
-    return arr_ptr.arr.len() as i64;
+    return arr_ptr.len;
 }
 #[unsafe(no_mangle)]
 pub fn toy_free_arr(arr_ptr_int: ToyPtr) {
@@ -338,41 +515,37 @@ pub fn toy_free_arr(arr_ptr_int: ToyPtr) {
     let arr = unsafe { &mut *(arr_ptr_int as *mut ToyArr) };
 
     if arr.should_free_subelements {
-        for &val in &arr.arr {
-            let elem_type = arr.ty.clone();
-            if elem_type.is_arr_type() {
+        for &val in arr.as_slice() {
+            //ty is the type of the array, its elements are arrays themselves when degree is above 1
+            if arr.degree > 1 {
                 toy_free_arr(val);
-            } else if elem_type == ToyType::Str || elem_type == ToyType::Struct {
+            } else if arr.ty == ToyType::StrArr || arr.ty == ToyType::Struct {
                 toy_free(val as *mut c_void);
             }
         }
     }
+    #[cfg(feature = "profile")]
+    crate::profile::_toy_profile_free(arr_ptr_int);
     //this is a bodge
-    if let Some(&v) = DEBUG_HEAP
-        .get()
-        .unwrap()
-        .lock()
-        .unwrap()
-        .map
-        .get(&arr_ptr_int)
+    #[cfg(feature = "sanitize")]
     {
-        if v != -1 {
-            DEBUG_HEAP
-                .get()
-                .unwrap()
-                .lock()
-                .unwrap()
-                .total_live_allocations -= 1;
+        let mut heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
+        if let Some(&v) = heap.map.get(&arr_ptr_int) {
+            if v != -1 {
+                heap.total_live_allocations -= 1;
+            }
         }
+        heap.map.insert(arr_ptr_int, -1);
+        let site = heap.current_site();
+        heap.free_sites.insert(arr_ptr_int, site);
+        //keep the header quarantined so the address is never reused, see toy_free
+        arr.release();
     }
-    DEBUG_HEAP
-        .get()
-        .unwrap()
-        .lock()
-        .unwrap()
-        .map
-        .insert(arr_ptr_int, -1);
-    unsafe { drop(Box::from_raw(arr_ptr_int as *mut ToyArr)) };
+    #[cfg(not(feature = "sanitize"))]
+    unsafe {
+        arr.release();
+        drop(Box::from_raw(arr_ptr_int as *mut ToyArr))
+    };
 }
 #[unsafe(no_mangle)]
 pub fn toy_arr_concat(arr1: ToyPtr, arr2: ToyPtr) -> ToyPtr {
@@ -381,15 +554,121 @@ pub fn toy_arr_concat(arr1: ToyPtr, arr2: ToyPtr) -> ToyPtr {
     let a1 = unsafe { &*(arr1 as *const ToyArr) };
     let a2 = unsafe { &*(arr2 as *const ToyArr) };
 
-    let total_len = a1.arr.len() + a2.arr.len();
-    let res_ptr = toy_malloc_arr(total_len as i64, a1.ty.clone() as i64, a1.degree);
+    let total_len = a1.len + a2.len;
+    let res_ptr = toy_malloc_arr(total_len, a1.ty.clone() as i64, a1.degree);
     let res = unsafe { &mut *(res_ptr as *mut ToyArr) };
 
-    res.arr.extend_from_slice(&a1.arr);
-    res.arr.extend_from_slice(&a2.arr);
+    let (front, back) = res.as_mut_slice().split_at_mut(a1.len as usize);
+    front.copy_from_slice(a1.as_slice());
+    back.copy_from_slice(a2.as_slice());
+    res.copy_strs();
 
     return res_ptr;
 }
+///Inserts value before idx, shifting the rest of the array up by one. idx may be len to append
+#[unsafe(no_mangle)]
+pub fn toy_arr_insert(arr_in_ptr: ToyPtr, idx: i64, value: ToyValue) {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
+    let len = arr.len;
+    if idx < 0 || idx > len {
+        panic!(
+            "[ERROR] Index {idx} is out of bounds for an array of length {len}, inserting may only happen up to the end"
+        );
+    }
+    let value = if arr.owns_strs() {
+        toy_malloc(value)
+    } else {
+        value
+    };
+    arr.insert(idx as usize, value);
+}
+#[unsafe(no_mangle)]
+pub fn toy_arr_insert_float(arr_in_ptr: ToyPtr, idx: i64, value: f64) {
+    toy_arr_insert(arr_in_ptr, idx, value.to_bits() as i64);
+}
+///Removes the element at idx, shifting the rest of the array down by one, returns the removed element
+#[unsafe(no_mangle)]
+pub fn toy_arr_remove(arr_in_ptr: ToyPtr, idx: i64) -> i64 {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
+    let len = arr.len;
+    if idx < 0 || idx >= len {
+        panic!("[ERROR] Index {idx} is out of bounds for an array of length {len}");
+    }
+    arr.remove(idx as usize)
+}
+///toy_arr_remove for a str[], the caller owns the string it gets back
+#[unsafe(no_mangle)]
+pub fn toy_arr_remove_str(arr_in_ptr: ToyPtr, idx: i64) -> ToyPtr {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let owns_strs = unsafe { &*(arr_in_ptr as *const ToyArr) }.owns_strs();
+    let value = toy_arr_remove(arr_in_ptr, idx);
+    //strings of an array that does not own them are freed where they were made
+    if owns_strs {
+        return value;
+    }
+    return toy_malloc(value);
+}
+///New array holding arr[start..end], strings are copied so either array can be freed on its own
+#[unsafe(no_mangle)]
+pub fn toy_arr_slice(arr_in_ptr: ToyPtr, start: i64, end: i64) -> ToyPtr {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &*(arr_in_ptr as *const ToyArr) };
+    let len = arr.len;
+    if start < 0 || end < start || end > len {
+        panic!("[ERROR] Slice {start}..{end} is out of bounds for an array of length {len}");
+    }
+    let res_ptr = toy_malloc_arr(end - start, arr.ty.clone() as i64, arr.degree);
+    let res = unsafe { &mut *(res_ptr as *mut ToyArr) };
+    res.as_mut_slice()
+        .copy_from_slice(&arr.as_slice()[start as usize..end as usize]);
+    res.copy_strs();
+    res_ptr
+}
+///Index of the first element equal to value, -1 if there is none
+#[unsafe(no_mangle)]
+pub fn toy_arr_index_of(arr_in_ptr: ToyPtr, value: ToyValue) -> i64 {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &*(arr_in_ptr as *const ToyArr) };
+    match arr
+        .as_slice()
+        .iter()
+        .position(|&elem| arr.elem_ordering(elem, value) == std::cmp::Ordering::Equal)
+    {
+        Some(idx) => idx as i64,
+        None => -1,
+    }
+}
+#[unsafe(no_mangle)]
+pub fn toy_arr_index_of_float(arr_in_ptr: ToyPtr, value: f64) -> i64 {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &*(arr_in_ptr as *const ToyArr) };
+    //NaN is never equal to anything, total_cmp would find it
+    match arr
+        .as_slice()
+        .iter()
+        .position(|&elem| f64::from_bits(elem as u64) == value)
+    {
+        Some(idx) => idx as i64,
+        None => -1,
+    }
+}
+#[unsafe(no_mangle)]
+pub fn toy_arr_reverse(arr_in_ptr: ToyPtr) {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
+    arr.as_mut_slice().reverse();
+}
+///Sorts in place, ascending. Strings sort by their bytes
+#[unsafe(no_mangle)]
+pub fn toy_arr_sort(arr_in_ptr: ToyPtr) {
+    _check_pointer(arr_in_ptr as *mut c_void);
+    let arr = unsafe { &mut *(arr_in_ptr as *mut ToyArr) };
+    let mut elems = arr.as_slice().to_vec();
+    elems.sort_by(|&a, &b| arr.elem_ordering(a, b));
+    arr.as_mut_slice().copy_from_slice(&elems);
+}
 
 #[unsafe(no_mangle)]
 pub fn toy_input(i_prompt: ToyPtr) -> ToyPtr {
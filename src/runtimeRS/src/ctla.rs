@@ -1,69 +1,181 @@
+#[cfg(feature = "sanitize")]
 use crate::stub::DEBUG_HEAP;
-use std::env;
+#[cfg(feature = "sanitize")]
+use std::collections::HashMap;
+#[cfg(feature = "sanitize")]
+use std::ffi::CStr;
+#[cfg(feature = "sanitize")]
 use std::io;
+#[cfg(feature = "sanitize")]
 use std::io::Write;
-use std::{collections::HashMap, os::raw::c_void, usize};
+use std::os::raw::c_void;
+
+///(pointer to the file path global string, line). A null file pointer means the site is unknown
+#[cfg(feature = "sanitize")]
+pub type AllocationSite = (i64, i64);
+
+#[cfg(feature = "sanitize")]
 #[derive(Debug, Clone)]
 pub struct DebugHeap {
     ///ptr -> size
     pub map: HashMap<i64, i64>,
+    ///ptr -> the site the pointer was allocated at
+    pub alloc_sites: HashMap<i64, AllocationSite>,
+    ///ptr -> the site the pointer was freed at
+    pub free_sites: HashMap<i64, AllocationSite>,
+    ///the site of the statement each function call in progress is at, innermost last. Calls push
+    ///their own entry and pop it when they return, so the caller's site is back in place after a call
+    pub site_stack: Vec<AllocationSite>,
     pub total_live_allocations: i64,
     pub total_allocations: i64,
 }
+#[cfg(feature = "sanitize")]
 impl DebugHeap {
     pub fn new() -> DebugHeap {
         return DebugHeap {
             map: HashMap::new(),
+            alloc_sites: HashMap::new(),
+            free_sites: HashMap::new(),
+            site_stack: Vec::new(),
             total_live_allocations: 0,
             total_allocations: 0,
         };
     }
+    ///The site of the statement running in the innermost call
+    pub fn current_site(&self) -> AllocationSite {
+        return self.site_stack.last().copied().unwrap_or((0, 0));
+    }
+}
+
+#[cfg(feature = "sanitize")]
+pub fn format_site(site: AllocationSite) -> String {
+    let (file, line) = site;
+    if file == 0 {
+        return "<unknown site>".to_string();
+    }
+    let path = unsafe { CStr::from_ptr(file as *const i8) };
+    return format!("{}:{}", path.to_string_lossy(), line);
+}
+
+///Called by code compiled with `--sanitize=memory` before every statement,
+///so that allocations can be traced back to a line in the .toy source
+#[unsafe(no_mangle)]
+pub fn toy_sanitize_site(file: i64, line: i64) {
+    #[cfg(feature = "sanitize")]
+    {
+        let mut heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
+        match heap.site_stack.last_mut() {
+            Some(site) => *site = (file, line),
+            None => heap.site_stack.push((file, line)),
+        }
+    }
+    #[cfg(not(feature = "sanitize"))]
+    {
+        let _ = (file, line);
+    }
+}
+
+///Called by code compiled with `--sanitize=memory` when a function starts, the statements of the
+///function set the new site
+#[unsafe(no_mangle)]
+pub fn toy_sanitize_site_push() {
+    #[cfg(feature = "sanitize")]
+    DEBUG_HEAP
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .site_stack
+        .push((0, 0));
 }
 
+///Called right before a function returns, puts the site of the call back
+#[unsafe(no_mangle)]
+pub fn toy_sanitize_site_pop() {
+    #[cfg(feature = "sanitize")]
+    DEBUG_HEAP.get().unwrap().lock().unwrap().site_stack.pop();
+}
+
+#[cfg(feature = "sanitize")]
 pub fn _toy_malloc_debug(size: usize) -> *mut c_void {
     let buff = unsafe { libc::malloc(size) };
     let mut heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
+    let site = heap.current_site();
     heap.map.insert(buff as i64, size as i64);
+    heap.alloc_sites.insert(buff as i64, site);
+    heap.free_sites.remove(&(buff as i64));
     heap.total_live_allocations += 1;
     heap.total_allocations += 1;
     return buff;
 }
+
+///Marks a pointer as freed in the debug heap, reporting a double free if it already was.
+///Returns false if the pointer is not tracked by the debug heap
+#[cfg(feature = "sanitize")]
+pub fn _toy_mark_freed(buff: *mut c_void) -> bool {
+    let mut heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
+    let Some(&value) = heap.map.get(&(buff as i64)) else {
+        return false;
+    };
+    if value == -1 {
+        let alloc_site = heap.alloc_sites.get(&(buff as i64)).copied().unwrap_or((0, 0));
+        let free_site = heap.free_sites.get(&(buff as i64)).copied().unwrap_or((0, 0));
+        eprintln!(
+            "[ERROR] Double free detected! Pointer {:p} allocated at {} was already freed at {} (freed again at {})",
+            buff,
+            format_site(alloc_site),
+            format_site(free_site),
+            format_site(heap.current_site())
+        );
+        println!("\nFAIL_TEST");
+        io::stdout().flush().ok();
+        io::stderr().flush().ok();
+        panic!();
+    }
+    heap.total_live_allocations -= 1;
+    heap.map.insert(buff as i64, -1);
+    let site = heap.current_site();
+    heap.free_sites.insert(buff as i64, site);
+    return true;
+}
+
 #[unsafe(no_mangle)]
 pub fn toy_free(buff: *mut c_void) {
     if buff.is_null() {
         eprintln!("[ERROR] Tried to free a null buffer");
+        crate::backtrace::_print_toy_backtrace();
         unsafe { libc::abort() };
     }
-    let val = env::var("TOY_DEBUG");
-    if let Ok(v) = val {
-        if v == "TRUE" {
-            let mut heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
-            if let Some(&value) = heap.map.get(&(buff as i64)) {
-                if value >= 0 {
-                    heap.total_live_allocations -= 1;
-                }
-            }
-            heap.map.insert(buff as i64, -1);
+    #[cfg(feature = "sanitize")]
+    {
+        //tracked buffers are quarantined rather than released, so their address can never be
+        //handed out again and a later use-after-free or double free is always caught
+        if _toy_mark_freed(buff) {
+            return;
         }
     }
+    #[cfg(feature = "profile")]
+    crate::profile::_toy_profile_free(buff as i64);
     unsafe { libc::free(buff) };
 }
+
+#[cfg(feature = "sanitize")]
 #[unsafe(no_mangle)]
 pub fn _print_debug_heap() {
-    println!("{:#?}", DEBUG_HEAP.get().unwrap().lock().unwrap().map);
-    println!(
-        "Total Allocations: {}",
-        DEBUG_HEAP.get().unwrap().lock().unwrap().total_allocations
-    );
-    println!(
-        "Total Live Allocations: {}",
-        DEBUG_HEAP
-            .get()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .total_live_allocations
-    );
+    let heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
+    let mut leaks: Vec<(&i64, &i64)> = heap.map.iter().filter(|(_, size)| **size >= 0).collect();
+    leaks.sort();
+    for (ptr, size) in leaks {
+        let site = heap.alloc_sites.get(ptr).copied().unwrap_or((0, 0));
+        eprintln!(
+            "[ERROR] Leak detected! {} bytes at {:#x} allocated at {} were never freed",
+            size,
+            ptr,
+            format_site(site)
+        );
+    }
+    println!("Total Allocations: {}", heap.total_allocations);
+    println!("Total Live Allocations: {}", heap.total_live_allocations);
 }
 
 #[unsafe(no_mangle)]
@@ -74,31 +186,30 @@ pub fn _check_pointer(buff: *mut c_void) {
         panic!();
     }
 
-    let v = env::var("TOY_DEBUG");
-    if v.is_err() || v.unwrap() != "TRUE" {
-        return;
-    }
-    if let Some(value) = DEBUG_HEAP
-        .get()
-        .unwrap()
-        .lock()
-        .unwrap()
-        .map
-        .get(&(buff as i64))
+    #[cfg(feature = "sanitize")]
     {
-        if *value == -1 {
-            eprintln!(
-                "[ERROR] Use-after-free detected! Pointer {:p} was already freed",
-                buff
-            );
-            println!("\nFAIL_TEST");
-            io::stdout().flush().ok();
-            io::stderr().flush().ok();
-            panic!();
+        let heap = DEBUG_HEAP.get().unwrap().lock().unwrap();
+        if let Some(value) = heap.map.get(&(buff as i64)) {
+            if *value == -1 {
+                let alloc_site = heap.alloc_sites.get(&(buff as i64)).copied().unwrap_or((0, 0));
+                let free_site = heap.free_sites.get(&(buff as i64)).copied().unwrap_or((0, 0));
+                eprintln!(
+                    "[ERROR] Use-after-free detected! Pointer {:p} allocated at {} was already freed at {} (used at {})",
+                    buff,
+                    format_site(alloc_site),
+                    format_site(free_site),
+                    format_site(heap.current_site())
+                );
+                println!("\nFAIL_TEST");
+                io::stdout().flush().ok();
+                io::stderr().flush().ok();
+                panic!();
+            }
         }
     }
 }
 
+#[cfg(feature = "sanitize")]
 #[unsafe(no_mangle)]
 pub fn should_fail() -> i64 {
     let res = DEBUG_HEAP
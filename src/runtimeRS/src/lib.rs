@@ -2,12 +2,22 @@
 //  variables that are heap allocated and external to rust must use toy_malloc*
 //  variables that are internal to the rust code may use the borrow checker
 
+#[cfg(all(feature = "sanitize", feature = "profile"))]
+compile_error!("the sanitize and profile runtimes are separate flavors, enable only one");
+
+mod backtrace;
 mod builtins;
 mod ctla;
+mod map;
+mod profile;
 mod stub;
+mod testing;
 mod toy_std;
 mod values;
 pub type ToyPtr = i64;
+///A value of any Toy type, such as an array element or what print gets, ints, bools and pointers
+///all fit in 64 bits
+pub type ToyValue = i64;
 use std::alloc::{GlobalAlloc, Layout};
 //makes sure that rust allocations can be freed from C
 struct LibcAllocator;
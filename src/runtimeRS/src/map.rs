@@ -0,0 +1,220 @@
+use crate::ToyPtr;
+use crate::builtins;
+use crate::ctla::{_check_pointer, toy_free};
+use crate::values::ToyType;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_void;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Int(i64),
+    Str(CString),
+}
+impl MapKey {
+    fn to_display(&self) -> String {
+        match self {
+            MapKey::Int(i) => i.to_string(),
+            MapKey::Str(s) => s.to_string_lossy().into_owned(),
+        }
+    }
+}
+///The map owns everything inside it, strings are copied in on writes and copied out on reads,
+///so CTLA can free whatever it handed over and the map frees its own copies when it dies
+pub struct ToyMap {
+    key_ty: ToyType,
+    value_ty: ToyType,
+    ///insertion ordered, keys() and values() follow this order
+    entries: Vec<(MapKey, i64)>,
+    index: HashMap<MapKey, usize>,
+}
+impl ToyMap {
+    fn key(&self, key: i64) -> MapKey {
+        match self.key_ty {
+            ToyType::Str => {
+                _check_pointer(key as *mut c_void);
+                MapKey::Str(unsafe { CStr::from_ptr(key as *const i8) }.to_owned())
+            }
+            _ => MapKey::Int(key),
+        }
+    }
+    ///string values are stored as CStrings owned by the map
+    fn own_value(&self, value: i64) -> i64 {
+        if self.value_ty != ToyType::Str {
+            return value;
+        }
+        _check_pointer(value as *mut c_void);
+        unsafe { CStr::from_ptr(value as *const i8) }
+            .to_owned()
+            .into_raw() as i64
+    }
+    fn release_value(&self, value: i64) {
+        if self.value_ty == ToyType::Str {
+            unsafe { drop(CString::from_raw(value as *mut i8)) };
+        }
+    }
+    ///values handed to compiled code are fresh copies for strings, the caller owns them
+    fn lend_value(&self, value: i64) -> i64 {
+        if self.value_ty == ToyType::Str {
+            return builtins::toy_malloc(value);
+        }
+        value
+    }
+    fn remove(&mut self, key: &MapKey) -> bool {
+        let Some(idx) = self.index.remove(key) else {
+            return false;
+        };
+        let (_, value) = self.entries.remove(idx);
+        self.release_value(value);
+        for pos in self.index.values_mut() {
+            if *pos > idx {
+                *pos -= 1;
+            }
+        }
+        true
+    }
+}
+
+fn get_map<'a>(map_ptr: ToyPtr) -> &'a mut ToyMap {
+    _check_pointer(map_ptr as *mut c_void);
+    unsafe { &mut *(map_ptr as *mut ToyMap) }
+}
+
+///key_ty and value_ty are the type codes of the keys and values, see ToyType
+#[unsafe(no_mangle)]
+pub fn toy_malloc_map(key_ty: i64, value_ty: i64) -> ToyPtr {
+    let map = ToyMap {
+        key_ty: ToyType::try_from(key_ty).unwrap(),
+        value_ty: ToyType::try_from(value_ty).unwrap(),
+        entries: Vec::new(),
+        index: HashMap::new(),
+    };
+    let out = crate::meta_malloc!(std::mem::size_of::<ToyMap>()) as *mut ToyMap;
+    if out.is_null() {
+        panic!("[ERROR] Meta malloc failed");
+    }
+    unsafe { std::ptr::write(out, map) };
+    out as ToyPtr
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_map_get(map_ptr: ToyPtr, key: i64) -> i64 {
+    let map = get_map(map_ptr);
+    let key = map.key(key);
+    match map.index.get(&key) {
+        Some(&idx) => map.lend_value(map.entries[idx].1),
+        None => panic!("[ERROR] Key {} is not in the map", key.to_display()),
+    }
+}
+
+///Inserts or overwrites the value stored under key
+#[unsafe(no_mangle)]
+pub fn toy_map_set(map_ptr: ToyPtr, key: i64, value: i64) {
+    let map = get_map(map_ptr);
+    let key = map.key(key);
+    let value = map.own_value(value);
+    match map.index.get(&key) {
+        Some(&idx) => {
+            let old = std::mem::replace(&mut map.entries[idx].1, value);
+            map.release_value(old);
+        }
+        None => {
+            map.index.insert(key.clone(), map.entries.len());
+            map.entries.push((key, value));
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_map_has(map_ptr: ToyPtr, key: i64) -> i64 {
+    let map = get_map(map_ptr);
+    let key = map.key(key);
+    map.index.contains_key(&key) as i64
+}
+
+///Removing a key that is not in the map does nothing
+#[unsafe(no_mangle)]
+pub fn toy_map_remove(map_ptr: ToyPtr, key: i64) {
+    let map = get_map(map_ptr);
+    let key = map.key(key);
+    map.remove(&key);
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_map_len(map_ptr: ToyPtr) -> i64 {
+    get_map(map_ptr).entries.len() as i64
+}
+
+///Returns a new array of the keys in insertion order
+#[unsafe(no_mangle)]
+pub fn toy_map_keys(map_ptr: ToyPtr) -> ToyPtr {
+    let map = get_map(map_ptr);
+    let arr = builtins::toy_malloc_arr(0, map.key_ty.clone() as i64, 1);
+    for (key, _) in &map.entries {
+        let elem = match key {
+            MapKey::Int(i) => *i,
+            MapKey::Str(s) => builtins::toy_malloc(s.as_ptr() as i64),
+        };
+        builtins::toy_arr_push(arr, elem, map.key_ty.to_arr_type() as i64);
+    }
+    //the keys are copies, so a str[] of them owns its strings
+    unsafe {
+        (*(arr as *mut builtins::ToyArr)).should_free_subelements = map.key_ty == ToyType::Str
+    };
+    arr
+}
+
+///Returns a new array of the values in insertion order
+#[unsafe(no_mangle)]
+pub fn toy_map_values(map_ptr: ToyPtr) -> ToyPtr {
+    let map = get_map(map_ptr);
+    let arr = builtins::toy_malloc_arr(0, map.value_ty.clone() as i64, 1);
+    for (_, value) in &map.entries {
+        builtins::toy_arr_push(
+            arr,
+            map.lend_value(*value),
+            map.value_ty.to_arr_type() as i64,
+        );
+    }
+    //lent strings are copies, structs still belong to the map
+    unsafe {
+        (*(arr as *mut builtins::ToyArr)).should_free_subelements = map.value_ty == ToyType::Str
+    };
+    arr
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_free_map(map_ptr: ToyPtr) {
+    let map = get_map(map_ptr);
+    for (_, value) in &map.entries {
+        map.release_value(*value);
+    }
+    unsafe { std::ptr::drop_in_place(map as *mut ToyMap) };
+    toy_free(map_ptr as *mut c_void);
+}
+
+///`{key: value, ...}` in insertion order, used by print and println
+pub fn _toy_format_map(map_ptr: ToyPtr) -> String {
+    let map = get_map(map_ptr);
+    let entries: Vec<String> = map
+        .entries
+        .iter()
+        .map(|(key, value)| {
+            let raw = builtins::_toy_format(*value, map.value_ty.clone(), 0);
+            let value = unsafe { CString::from_raw(raw) };
+            format!("{}: {}", key.to_display(), value.to_string_lossy())
+        })
+        .collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+///(value type, (key, raw value) in insertion order), int keys are turned into text. Used by std.json
+pub fn _toy_map_entries(map_ptr: ToyPtr) -> (ToyType, Vec<(String, i64)>) {
+    let map = get_map(map_ptr);
+    let entries = map
+        .entries
+        .iter()
+        .map(|(key, value)| (key.to_display(), *value))
+        .collect();
+    (map.value_ty.clone(), entries)
+}
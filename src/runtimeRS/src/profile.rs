@@ -0,0 +1,176 @@
+#[cfg(feature = "profile")]
+use std::collections::HashMap;
+#[cfg(feature = "profile")]
+use std::ffi::CStr;
+#[cfg(feature = "profile")]
+use std::fmt::Write;
+#[cfg(feature = "profile")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "profile")]
+use std::time::{Duration, Instant};
+
+///upper bounds of the lifetime histogram buckets, anything longer lands in the last bucket
+#[cfg(feature = "profile")]
+const LIFETIME_BUCKETS: [(Duration, &str); 7] = [
+    (Duration::from_micros(1), "<1us"),
+    (Duration::from_micros(10), "<10us"),
+    (Duration::from_micros(100), "<100us"),
+    (Duration::from_millis(1), "<1ms"),
+    (Duration::from_millis(10), "<10ms"),
+    (Duration::from_millis(100), "<100ms"),
+    (Duration::from_secs(1), "<1s"),
+];
+
+///(toy function the allocation happens in, allocation id from the TIR HeapAllocation)
+#[cfg(feature = "profile")]
+type SiteKey = (String, i64);
+
+#[cfg(feature = "profile")]
+#[derive(Debug, Clone, Default)]
+pub struct SiteStats {
+    ///runtime or toy function that performed the allocation
+    pub allocator: String,
+    pub count: i64,
+    pub bytes: i64,
+    pub live_bytes: i64,
+    pub peak_live_bytes: i64,
+    pub freed: i64,
+    ///one slot per LIFETIME_BUCKETS entry plus one for everything longer
+    pub lifetimes: [i64; 8],
+}
+
+#[cfg(feature = "profile")]
+#[derive(Debug)]
+pub struct HeapProfile {
+    ///sites of the allocating calls in progress, innermost last. Everything allocated while a call
+    ///runs counts towards its site, including the allocations the runtime makes for it
+    pub site_stack: Vec<(SiteKey, String)>,
+    pub sites: HashMap<SiteKey, SiteStats>,
+    ///ptr -> (site, size, time of allocation)
+    pub live: HashMap<i64, (SiteKey, i64, Instant)>,
+    pub live_bytes: i64,
+    pub peak_live_bytes: i64,
+}
+
+#[cfg(feature = "profile")]
+pub static HEAP_PROFILE: OnceLock<Mutex<HeapProfile>> = OnceLock::new();
+
+#[cfg(feature = "profile")]
+fn heap_profile() -> &'static Mutex<HeapProfile> {
+    return HEAP_PROFILE.get_or_init(|| {
+        Mutex::new(HeapProfile {
+            site_stack: Vec::new(),
+            sites: HashMap::new(),
+            live: HashMap::new(),
+            live_bytes: 0,
+            peak_live_bytes: 0,
+        })
+    });
+}
+
+///Called by code compiled with `--profile=heap` right before every heap allocation, toy_profile_site_end
+///is called right after it. `site` is the allocation id of the TIR HeapAllocation, `func` and
+///`allocator` are C strings
+#[unsafe(no_mangle)]
+pub fn toy_profile_site(site: i64, func: i64, allocator: i64) {
+    #[cfg(feature = "profile")]
+    {
+        let func = unsafe { CStr::from_ptr(func as *const i8) }
+            .to_string_lossy()
+            .into_owned();
+        let allocator = unsafe { CStr::from_ptr(allocator as *const i8) }
+            .to_string_lossy()
+            .into_owned();
+        heap_profile()
+            .lock()
+            .unwrap()
+            .site_stack
+            .push(((func, site), allocator));
+    }
+    #[cfg(not(feature = "profile"))]
+    {
+        let _ = (site, func, allocator);
+    }
+}
+
+///Ends the site the matching toy_profile_site call started
+#[unsafe(no_mangle)]
+pub fn toy_profile_site_end() {
+    #[cfg(feature = "profile")]
+    heap_profile().lock().unwrap().site_stack.pop();
+}
+
+#[cfg(feature = "profile")]
+pub fn _toy_profile_alloc(ptr: i64, size: i64) {
+    let mut profile = heap_profile().lock().unwrap();
+    //allocations the compiler did not tag (e.g. ones made inside the runtime) share one site
+    let (key, allocator) = profile
+        .site_stack
+        .last()
+        .cloned()
+        .unwrap_or((("<untracked>".to_string(), -1), "<runtime>".to_string()));
+    profile.live_bytes += size;
+    profile.peak_live_bytes = profile.peak_live_bytes.max(profile.live_bytes);
+    let stats = profile.sites.entry(key.clone()).or_default();
+    stats.allocator = allocator;
+    stats.count += 1;
+    stats.bytes += size;
+    stats.live_bytes += size;
+    stats.peak_live_bytes = stats.peak_live_bytes.max(stats.live_bytes);
+    profile.live.insert(ptr, (key, size, Instant::now()));
+}
+
+#[cfg(feature = "profile")]
+pub fn _toy_profile_free(ptr: i64) {
+    let mut profile = heap_profile().lock().unwrap();
+    let Some((key, size, allocated_at)) = profile.live.remove(&ptr) else {
+        return;
+    };
+    let lifetime = allocated_at.elapsed();
+    profile.live_bytes -= size;
+    let stats = profile.sites.get_mut(&key).unwrap();
+    stats.live_bytes -= size;
+    stats.freed += 1;
+    let bucket = LIFETIME_BUCKETS
+        .iter()
+        .position(|(bound, _)| lifetime < *bound)
+        .unwrap_or(LIFETIME_BUCKETS.len());
+    stats.lifetimes[bucket] += 1;
+}
+
+///Writes the report to `heap_profile.txt`, or to the path in `TOY_HEAP_PROFILE` if it is set
+#[cfg(feature = "profile")]
+pub fn _write_heap_profile() {
+    let profile = heap_profile().lock().unwrap();
+    let mut sites: Vec<(&SiteKey, &SiteStats)> = profile.sites.iter().collect();
+    sites.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+
+    let total_count: i64 = sites.iter().map(|(_, s)| s.count).sum();
+    let total_bytes: i64 = sites.iter().map(|(_, s)| s.bytes).sum();
+    let mut out = String::new();
+    let _ = writeln!(out, "Toy heap profile");
+    let _ = writeln!(
+        out,
+        "allocations: {}, bytes: {}, peak live bytes: {}\n",
+        total_count, total_bytes, profile.peak_live_bytes
+    );
+    for ((func, site), stats) in sites {
+        let _ = writeln!(out, "site {}#{} ({})", func, site, stats.allocator);
+        let _ = writeln!(out, "  allocations: {}", stats.count);
+        let _ = writeln!(out, "  bytes: {}", stats.bytes);
+        let _ = writeln!(out, "  peak live bytes: {}", stats.peak_live_bytes);
+        let _ = writeln!(out, "  never freed: {}", stats.count - stats.freed);
+        let mut histogram: Vec<String> = LIFETIME_BUCKETS
+            .iter()
+            .enumerate()
+            .map(|(i, (_, label))| format!("{}: {}", label, stats.lifetimes[i]))
+            .collect();
+        histogram.push(format!(">=1s: {}", stats.lifetimes[LIFETIME_BUCKETS.len()]));
+        let _ = writeln!(out, "  lifetimes: {}\n", histogram.join(" | "));
+    }
+
+    let path = std::env::var("TOY_HEAP_PROFILE").unwrap_or("heap_profile.txt".to_string());
+    if let Err(e) = std::fs::write(&path, out) {
+        eprintln!("[ERROR] Could not write heap profile to {}: {}", path, e);
+    }
+}
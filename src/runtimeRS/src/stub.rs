@@ -1,10 +1,20 @@
+#[cfg(feature = "sanitize")]
 use crate::ctla::{_print_debug_heap, DebugHeap};
+#[cfg(not(any(feature = "library", target_os = "wasi")))]
 use ctor::ctor;
-use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "library")]
+use std::collections::BTreeMap;
+#[cfg(any(feature = "sanitize", feature = "library"))]
+use std::sync::Mutex;
+use std::sync::Once;
+#[cfg(feature = "sanitize")]
+use std::sync::OnceLock;
+#[cfg(not(feature = "library"))]
 unsafe extern "C" {
     fn user_main() -> i64;
 }
 
+#[cfg(feature = "sanitize")]
 #[unsafe(no_mangle)]
 pub static DEBUG_HEAP: OnceLock<Mutex<DebugHeap>> = OnceLock::new();
 
@@ -14,44 +24,70 @@ pub static mut GLOBAL_ARGC: i64 = 0;
 #[unsafe(no_mangle)]
 pub static mut GLOBAL_ARGV: *mut *mut libc::c_char = std::ptr::null_mut();
 
-#[ctor]
-fn init() {
-    DEBUG_HEAP.set(Mutex::new(DebugHeap::new())).unwrap();
+static INIT: Once = Once::new();
 
-    *DEBUG_HEAP.get().unwrap().lock().unwrap() = DebugHeap::new();
+///The REPL's top level variables by name, an entry stores them when it ends and the next one loads
+///them. Struct fields are kept one by one as `name.field`
+#[cfg(feature = "library")]
+static REPL_GLOBALS: Mutex<BTreeMap<String, i64>> = Mutex::new(BTreeMap::new());
 
-    unsafe { std::env::set_var("TOY_DEBUG", "TRUE") };
+///Sets up the panic hook, argv and the debug heap. Executables run it from a constructor, a C program
+///using a Toy library calls it before any Toy function. Calling it again does nothing
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_init() {
+    INIT.call_once(|| {
+        crate::backtrace::_install_panic_hook();
 
-    let args: Vec<std::ffi::CString> = std::env::args()
-        .map(|a| std::ffi::CString::new(a).unwrap())
-        .collect();
+        #[cfg(feature = "sanitize")]
+        DEBUG_HEAP.set(Mutex::new(DebugHeap::new())).unwrap();
 
-    unsafe { GLOBAL_ARGC = args.len() as i64 };
-    unsafe {
-        GLOBAL_ARGV = libc::malloc(std::mem::size_of::<*mut libc::c_char>() * args.len())
-            as *mut *mut libc::c_char
-    };
+        let args: Vec<std::ffi::CString> = std::env::args()
+            .map(|a| std::ffi::CString::new(a).unwrap())
+            .collect();
 
-    for (i, arg) in args.iter().enumerate() {
-        let bytes = arg.as_bytes_with_nul();
-        let ptr = unsafe { libc::malloc(bytes.len()) as *mut libc::c_char };
+        unsafe { GLOBAL_ARGC = args.len() as i64 };
         unsafe {
-            std::ptr::copy_nonoverlapping(bytes.as_ptr() as *const libc::c_char, ptr, bytes.len())
+            GLOBAL_ARGV = libc::malloc(std::mem::size_of::<*mut libc::c_char>() * args.len())
+                as *mut *mut libc::c_char
         };
-        unsafe { *GLOBAL_ARGV.add(i) = ptr };
-    }
+
+        for (i, arg) in args.iter().enumerate() {
+            let bytes = arg.as_bytes_with_nul();
+            let ptr = unsafe { libc::malloc(bytes.len()) as *mut libc::c_char };
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    bytes.as_ptr() as *const libc::c_char,
+                    ptr,
+                    bytes.len(),
+                )
+            };
+            unsafe { *GLOBAL_ARGV.add(i) = ptr };
+        }
+    });
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn main() -> i32 {
-    let res = unsafe { user_main() };
+#[cfg(not(any(feature = "library", target_os = "wasi")))]
+#[ctor]
+fn init() {
+    toy_init();
+}
 
+///Frees argv and writes the leak and heap reports, the counterpart of toy_init
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_shutdown() {
     unsafe {
+        if GLOBAL_ARGV.is_null() {
+            return;
+        }
         for i in 0..GLOBAL_ARGC {
             libc::free(*GLOBAL_ARGV.add(i as usize) as *mut libc::c_void);
         }
         libc::free(GLOBAL_ARGV as *mut libc::c_void);
         GLOBAL_ARGV = std::ptr::null_mut();
+        GLOBAL_ARGC = 0;
+    }
+    #[cfg(feature = "sanitize")]
+    {
         let live_allocs = DEBUG_HEAP
             .get()
             .unwrap()
@@ -63,6 +99,83 @@ pub extern "C" fn main() -> i32 {
             println!("\nFAIL_TST");
         }
     }
+    #[cfg(feature = "profile")]
+    crate::profile::_write_heap_profile();
+    //a test that leaks fails, its exit status is all `toy test` looks at
+    #[cfg(feature = "sanitize")]
+    if crate::testing::_running_tests() && crate::ctla::should_fail() != 0 {
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        std::process::exit(1);
+    }
+}
+
+///Frees a str or struct a library function returned to C, the strs a struct holds are freed on
+///their own first. Null does nothing
+#[cfg(feature = "library")]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_release(ptr: i64) {
+    if ptr != 0 {
+        crate::ctla::toy_free(ptr as *mut libc::c_void);
+    }
+}
 
+#[cfg(feature = "library")]
+fn _repl_global_name(name: i64) -> String {
+    let name = unsafe { std::ffi::CStr::from_ptr(name as *const libc::c_char) };
+    return name.to_string_lossy().into_owned();
+}
+
+///Keeps a REPL variable for the next entry, floats are passed as their bits. The JIT only loads the
+///library runtime so only it has these
+#[cfg(feature = "library")]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_repl_store(name: i64, val: i64) {
+    REPL_GLOBALS
+        .lock()
+        .unwrap()
+        .insert(_repl_global_name(name), val);
+}
+
+///The value an earlier entry stored, 0 when none did
+#[cfg(feature = "library")]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_repl_load(name: i64) -> i64 {
+    return REPL_GLOBALS
+        .lock()
+        .unwrap()
+        .get(&_repl_global_name(name))
+        .copied()
+        .unwrap_or(0);
+}
+
+///toy_repl_load for str, arrays and maps, the compiler types its result as a pointer
+#[cfg(feature = "library")]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_repl_load_ptr(name: i64) -> i64 {
+    return toy_repl_load(name);
+}
+
+#[cfg(feature = "library")]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_repl_load_float(name: i64) -> f64 {
+    return f64::from_bits(toy_repl_load(name) as u64);
+}
+
+//a library has no entry point, the C program that links it has its own main
+#[cfg(not(any(feature = "library", target_os = "wasi")))]
+#[unsafe(no_mangle)]
+pub extern "C" fn main() -> i32 {
+    let res = unsafe { user_main() };
+    toy_shutdown();
+    return res as i32;
+}
+
+//wasi-libc's _start reads the args and calls this, there are no constructors to run toy_init
+#[cfg(all(not(feature = "library"), target_os = "wasi"))]
+#[unsafe(no_mangle)]
+pub extern "C" fn __main_argc_argv(_argc: i32, _argv: *mut *mut libc::c_char) -> i32 {
+    toy_init();
+    let res = unsafe { user_main() };
+    toy_shutdown();
     return res as i32;
 }
@@ -0,0 +1,61 @@
+//`test fn`, assert and assert_eq. `toy test` runs every test in its own process, so a failed
+//assertion ends the process and the exit status tells the runner the test failed
+use crate::ToyPtr;
+use crate::ctla::_check_pointer;
+use std::ffi::CStr;
+use std::io::Write;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+///Set once the test harness asks which tests to run, only test binaries call toy_test_selected
+static RUNNING_TESTS: AtomicBool = AtomicBool::new(false);
+
+///Whether this process is a test binary built by `toy test`
+pub fn _running_tests() -> bool {
+    return RUNNING_TESTS.load(Ordering::Relaxed);
+}
+
+fn _read_str(sp: ToyPtr) -> String {
+    _check_pointer(sp as *mut c_void);
+    let s = unsafe { CStr::from_ptr(sp as *const i8) };
+    return String::from_utf8_lossy(s.to_bytes()).into_owned();
+}
+
+///Prints where the assertion failed and exits with 1, the leak report is skipped on purpose
+fn _fail(msg: String) -> ! {
+    let _ = std::io::stdout().flush();
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
+#[unsafe(no_mangle)]
+///msg is "file:line:col: assert(...) failed"
+pub fn toy_test_assert(cond: i64, msg: ToyPtr) {
+    if cond == 0 {
+        _fail(_read_str(msg));
+    }
+}
+
+#[unsafe(no_mangle)]
+///left and right are both sides passed through str
+pub fn toy_test_assert_eq(left: ToyPtr, right: ToyPtr, msg: ToyPtr) {
+    let (left, right) = (_read_str(left), _read_str(right));
+    if left != right {
+        _fail(format!(
+            "{}\n  left: {}\n right: {}",
+            _read_str(msg),
+            left,
+            right
+        ));
+    }
+}
+
+#[unsafe(no_mangle)]
+///1 if the test binary was asked to run the test called name, running it with no argument runs every test
+pub fn toy_test_selected(name: ToyPtr) -> i64 {
+    RUNNING_TESTS.store(true, Ordering::Relaxed);
+    return match std::env::args().nth(1) {
+        Some(selected) => (selected == _read_str(name)) as i64,
+        None => 1,
+    };
+}
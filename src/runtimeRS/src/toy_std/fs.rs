@@ -1,64 +1,265 @@
 use crate::ToyPtr;
 use crate::builtins;
+use crate::values::ToyType;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
 use std::os::raw::c_char;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+
+//failures never panic, they return "", -1 or 0 and leave a message for toy_fs_last_error, which
+//every fs call resets
+thread_local! {
+    static LAST_FS_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+///Records the outcome of an fs call, giving fallback on failure
+fn fs_result<T>(path: &str, result: io::Result<T>, fallback: T) -> T {
+    let (value, error) = match result {
+        Ok(v) => (v, String::new()),
+        Err(e) => (fallback, format!("{path}: {e}")),
+    };
+    LAST_FS_ERROR.with(|last| *last.borrow_mut() = error);
+    value
+}
+
+fn read_str(toy_str: ToyPtr) -> String {
+    unsafe { CStr::from_ptr(toy_str as *const c_char) }
+        .to_string_lossy()
+        .into_owned()
+}
+fn new_toy_str(text: &str) -> ToyPtr {
+    let c_string = CString::new(text.replace('\0', "")).unwrap();
+    builtins::toy_malloc(c_string.as_ptr() as i64)
+}
+fn new_toy_str_arr(items: &[String]) -> ToyPtr {
+    let strs: Vec<ToyPtr> = items.iter().map(|item| new_toy_str(item)).collect();
+    builtins::_toy_owned_str_arr(&strs)
+}
+
+///The message of the last failed fs call on this thread, "" when it succeeded
 #[unsafe(no_mangle)]
-pub fn toy_fs_read_file(toy_path: ToyPtr) -> ToyPtr {
-    let ptr_path = toy_path as *mut c_char;
-    let path: &str = unsafe { CStr::from_ptr(ptr_path as *const i8).to_str().unwrap() };
-    let contents = fs::read_to_string(path).unwrap(); //ffi error handling sucks
+pub fn toy_fs_last_error() -> ToyPtr {
+    LAST_FS_ERROR.with(|last| new_toy_str(&last.borrow()))
+}
 
-    let c_string = CString::new(contents).unwrap();
-    let ptr: *const i8 = c_string.as_ptr();
-    let toy_ptr = builtins::toy_malloc(ptr as i64);
-    return toy_ptr as ToyPtr;
+#[unsafe(no_mangle)]
+pub fn toy_fs_read_file(toy_path: ToyPtr) -> ToyPtr {
+    let path = read_str(toy_path);
+    new_toy_str(&fs_result(&path, fs::read_to_string(&path), String::new()))
 }
 
+///0 on success, 1 on failure
 #[unsafe(no_mangle)]
 pub fn toy_fs_write_file(path: ToyPtr, content: ToyPtr) -> i64 {
-    let path = unsafe { CStr::from_ptr(path as *const i8).to_str().unwrap() };
-    let contents = unsafe { CStr::from_ptr(content as *mut i8).to_str().unwrap() };
-    return match fs::write(path, contents) {
-        Ok(_) => 0,
-        Err(_) => 1,
-    };
+    let path = read_str(path);
+    fs_result(&path, fs::write(&path, read_str(content)).map(|_| 0), 1)
 }
+
+///Creates the file when it does not exist, 0 on success, 1 on failure
 #[unsafe(no_mangle)]
 pub fn toy_fs_append_file(path: ToyPtr, content: ToyPtr) -> i64 {
-    let path = unsafe { CStr::from_ptr(path as *const i8).to_str().unwrap() };
-    let contents = unsafe { CStr::from_ptr(content as *mut i8).to_str().unwrap() };
-    let to_write = format!(
-        "{}{}",
-        contents,
-        fs::read_to_string(path).unwrap().to_string()
-    );
-    return match fs::write(path, to_write) {
-        Ok(_) => 0,
-        Err(_) => 1,
-    };
+    let path = read_str(path);
+    let appended = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(read_str(content).as_bytes()));
+    fs_result(&path, appended.map(|_| 0), 1)
 }
 
+///-1 on failure
 #[unsafe(no_mangle)]
 pub fn toy_fs_file_size(toy_path: ToyPtr) -> i64 {
-    let path = unsafe { CStr::from_ptr(toy_path as *const i8).to_str().unwrap() };
-    let meta = fs::metadata(path).unwrap();
-    return meta.len() as i64;
+    let path = read_str(toy_path);
+    fs_result(&path, fs::metadata(&path).map(|m| m.len() as i64), -1)
 }
 
+///Last modification as seconds since the unix epoch, -1 on failure
 #[unsafe(no_mangle)]
-pub fn toy_fs_get_file_count_in_dir(toy_path: ToyPtr) -> i64 {
-    let path = unsafe { CStr::from_ptr(toy_path as *const i8).to_str().unwrap() };
-    let count = fs::read_dir(path).unwrap().count();
-    return count as i64;
+pub fn toy_fs_modified(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let modified = fs::metadata(&path).and_then(|m| m.modified()).map(|t| {
+        match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    });
+    fs_result(&path, modified, -1)
 }
 
 #[unsafe(no_mangle)]
-pub fn toy_fs_read_dir(toy_path: ToyPtr) -> ToyPtr {
-    let path = unsafe { CStr::from_ptr(toy_path as *const i8).to_str().unwrap() };
+pub fn toy_fs_exists(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    fs_result(&path, fs::exists(&path).map(|e| e as i64), 0)
+}
 
-    let entries: Vec<std::fs::DirEntry> =
-        fs::read_dir(path).unwrap().filter_map(|e| e.ok()).collect();
+///What is at path, None when there is nothing. Like fs::exists, a missing path is not a failure
+fn file_type(path: &str) -> io::Result<Option<fs::FileType>> {
+    match fs::metadata(path) {
+        Ok(m) => Ok(Some(m.file_type())),
+        Err(e) => match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_fs_is_dir(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let is_dir = file_type(&path).map(|t| t.is_some_and(|t| t.is_dir()) as i64);
+    fs_result(&path, is_dir, 0)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_fs_is_file(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let is_file = file_type(&path).map(|t| t.is_some_and(|t| t.is_file()) as i64);
+    fs_result(&path, is_file, 0)
+}
+
+///1 on success, recursive creates missing parents and accepts an existing directory
+#[unsafe(no_mangle)]
+pub fn toy_fs_mkdir(toy_path: ToyPtr, recursive: i64) -> i64 {
+    let path = read_str(toy_path);
+    let created = if recursive & 1 == 1 {
+        fs::create_dir_all(&path)
+    } else {
+        fs::create_dir(&path)
+    };
+    fs_result(&path, created.map(|_| 1), 0)
+}
+
+///1 on success
+#[unsafe(no_mangle)]
+pub fn toy_fs_remove_file(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    fs_result(&path, fs::remove_file(&path).map(|_| 1), 0)
+}
+
+///1 on success, without recursive the directory has to be empty
+#[unsafe(no_mangle)]
+pub fn toy_fs_remove_dir(toy_path: ToyPtr, recursive: i64) -> i64 {
+    let path = read_str(toy_path);
+    let removed = if recursive & 1 == 1 {
+        fs::remove_dir_all(&path)
+    } else {
+        fs::remove_dir(&path)
+    };
+    fs_result(&path, removed.map(|_| 1), 0)
+}
+
+///1 on success, replaces to when it is a file
+#[unsafe(no_mangle)]
+pub fn toy_fs_rename(from: ToyPtr, to: ToyPtr) -> i64 {
+    let from = read_str(from);
+    fs_result(&from, fs::rename(&from, read_str(to)).map(|_| 1), 0)
+}
+
+///1 on success, only copies files
+#[unsafe(no_mangle)]
+pub fn toy_fs_copy(from: ToyPtr, to: ToyPtr) -> i64 {
+    let from = read_str(from);
+    fs_result(&from, fs::copy(&from, read_str(to)).map(|_| 1), 0)
+}
+
+///The lines of a file without their line endings, empty on failure
+#[unsafe(no_mangle)]
+pub fn toy_fs_read_lines(toy_path: ToyPtr) -> ToyPtr {
+    let path = read_str(toy_path);
+    let lines = fs::File::open(&path).and_then(|f| BufReader::new(f).lines().collect());
+    new_toy_str_arr(&fs_result(&path, lines, vec![]))
+}
+
+static GLOBAL_READERS: OnceLock<Mutex<HashMap<i64, BufReader<fs::File>>>> = OnceLock::new();
+static NEXT_READER_HANDLE: AtomicI64 = AtomicI64::new(0);
+
+fn get_readers() -> &'static Mutex<HashMap<i64, BufReader<fs::File>>> {
+    GLOBAL_READERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+///Opens a file for reading line by line, returns the reader handle or -1 on failure
+#[unsafe(no_mangle)]
+pub fn toy_fs_open_reader(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let reader = fs::File::open(&path).map(|f| {
+        let handle = NEXT_READER_HANDLE.fetch_add(1, Ordering::Relaxed);
+        get_readers()
+            .lock()
+            .unwrap()
+            .insert(handle, BufReader::new(f));
+        handle
+    });
+    fs_result(&path, reader, -1)
+}
+
+///Reads up to and including the next newline, "" at the end of the file or on failure.
+///An empty line comes back as "\n"
+#[unsafe(no_mangle)]
+pub fn toy_fs_read_line(handle: i64) -> ToyPtr {
+    let mut readers = get_readers().lock().unwrap();
+    let line = match readers.get_mut(&handle) {
+        Some(reader) => {
+            let mut line = vec![];
+            reader
+                .read_until(b'\n', &mut line)
+                .map(|_| String::from_utf8_lossy(&line).into_owned())
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the reader does not exist or was closed",
+        )),
+    };
+    new_toy_str(&fs_result(&format!("reader {handle}"), line, String::new()))
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_fs_close_reader(handle: i64) {
+    drop(get_readers().lock().unwrap().remove(&handle));
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_fs_join(base: ToyPtr, child: ToyPtr) -> ToyPtr {
+    let joined = Path::new(&read_str(base)).join(read_str(child));
+    new_toy_str(&joined.to_string_lossy())
+}
+
+///The parent directory, "" when there is none
+#[unsafe(no_mangle)]
+pub fn toy_fs_dirname(toy_path: ToyPtr) -> ToyPtr {
+    let path = read_str(toy_path);
+    let parent = Path::new(&path).parent().map(|p| p.to_string_lossy());
+    new_toy_str(&parent.unwrap_or_default())
+}
+
+///The last component, "" when the path ends in ..
+#[unsafe(no_mangle)]
+pub fn toy_fs_basename(toy_path: ToyPtr) -> ToyPtr {
+    let path = read_str(toy_path);
+    let name = Path::new(&path).file_name().map(|n| n.to_string_lossy());
+    new_toy_str(&name.unwrap_or_default())
+}
+
+///The extension without its dot, "" when there is none
+#[unsafe(no_mangle)]
+pub fn toy_fs_extension(toy_path: ToyPtr) -> ToyPtr {
+    let path = read_str(toy_path);
+    let ext = Path::new(&path).extension().map(|e| e.to_string_lossy());
+    new_toy_str(&ext.unwrap_or_default())
+}
+
+///Files and folders directly inside path, an error leaves both empty
+fn dir_entries(path: &str) -> (Vec<String>, Vec<String>) {
+    let Some(entries) = fs_result(path, fs::read_dir(path).map(Some), None) else {
+        return (vec![], vec![]);
+    };
+    let entries: Vec<fs::DirEntry> = entries.filter_map(|e| e.ok()).collect();
 
     let files: Vec<String> = entries
         .iter()
@@ -71,32 +272,74 @@ pub fn toy_fs_read_dir(toy_path: ToyPtr) -> ToyPtr {
         .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
         .map(|e| e.file_name().to_string_lossy().into_owned())
         .collect();
+    (files, dirs)
+}
 
-    unsafe {
-        let toy_files = builtins::toy_malloc_arr(files.len() as i64, 4, 1);
-        let toy_folders = builtins::toy_malloc_arr(dirs.len() as i64, 4, 1);
+///Number of entries directly inside path, files and folders alike, -1 on failure
+#[unsafe(no_mangle)]
+pub fn toy_fs_get_file_count_in_dir(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let count = fs::read_dir(&path).map(|entries| entries.count() as i64);
+    fs_result(&path, count, -1)
+}
 
-        (*(toy_files as *mut builtins::ToyArr)).should_free_subelements = false;
-        (*(toy_folders as *mut builtins::ToyArr)).should_free_subelements = false;
+///Number of files directly inside path, folders are left out, -1 on failure
+#[unsafe(no_mangle)]
+pub fn toy_fs_count_files_in_dir(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let count = fs::read_dir(&path).map(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+            .count() as i64
+    });
+    fs_result(&path, count, -1)
+}
 
-        for (i, file) in files.iter().enumerate() {
-            let c = CString::new(file.as_str()).unwrap();
-            let ptr = builtins::toy_malloc(c.as_ptr() as i64);
-            builtins::toy_write_to_arr(toy_files, ptr, i as i64, 0);
-        }
+///Number of folders directly inside path, -1 on failure
+#[unsafe(no_mangle)]
+pub fn toy_fs_get_folder_count_in_dir(toy_path: ToyPtr) -> i64 {
+    let path = read_str(toy_path);
+    let count = fs::read_dir(&path).map(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .count() as i64
+    });
+    fs_result(&path, count, -1)
+}
 
-        for (i, dir) in dirs.iter().enumerate() {
-            let c = CString::new(dir.as_str()).unwrap();
-            let ptr = builtins::toy_malloc(c.as_ptr() as i64);
-            builtins::toy_write_to_arr(toy_folders, ptr, i as i64, 0);
-        }
+#[unsafe(no_mangle)]
+pub fn toy_fs_read_dir(toy_path: ToyPtr) -> ToyPtr {
+    let (files, dirs) = dir_entries(&read_str(toy_path));
 
-        let arr = builtins::toy_malloc_arr(2, 4, 2);
-        (*(arr as *mut builtins::ToyArr)).should_free_subelements = false;
+    let arr = builtins::toy_malloc_arr(2, 4, 2);
+    builtins::toy_write_to_arr(arr, new_toy_str_arr(&files), 0, 4, 0);
+    builtins::toy_write_to_arr(arr, new_toy_str_arr(&dirs), 1, 4, 0);
+    //freeing the result frees both lists and their strings
+    unsafe { (*(arr as *mut builtins::ToyArr)).should_free_subelements = true };
 
-        builtins::toy_write_to_arr(arr, toy_files, 0, 4);
-        builtins::toy_write_to_arr(arr, toy_folders, 1, 4);
+    return arr;
+}
 
-        return arr as ToyPtr;
-    }
+///The same text as FolderReadResults.to_str in std/fs.toy
+#[unsafe(no_mangle)]
+pub fn toy_fs_read_dir_to_str(toy_path: ToyPtr) -> ToyPtr {
+    let path = read_str(toy_path);
+    let (files, dirs) = dir_entries(&path);
+    let format = |items: &[String]| {
+        let arr = new_toy_str_arr(items);
+        let text = builtins::_toy_format(arr, ToyType::StrArr, 1);
+        let out = unsafe { CStr::from_ptr(text) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { drop(CString::from_raw(text)) };
+        builtins::toy_free_arr(arr);
+        out
+    };
+    new_toy_str(&format!(
+        " -- FOLDER READ RESULTS FOR {path} --\n[Files]\n{}\n[Folders]\n{}\n",
+        format(&files),
+        format(&dirs)
+    ))
 }
@@ -0,0 +1,388 @@
+use crate::ToyPtr;
+use crate::builtins;
+use crate::values::ToyType;
+use serde_json::{Map, Number, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+//json values cross into toy code as handles to a parsed document, so reading a field never parses
+//text again. A handle is a path into its document: get and at give handles into the same document
+//and append and set change it in place
+#[derive(Clone)]
+enum Step {
+    Key(String),
+    Idx(usize),
+}
+#[derive(Clone)]
+struct JsonRef {
+    doc: Arc<Mutex<Value>>,
+    path: Vec<Step>,
+}
+
+static GLOBAL_JSON: OnceLock<Mutex<HashMap<i64, JsonRef>>> = OnceLock::new();
+static NEXT_JSON_HANDLE: AtomicI64 = AtomicI64::new(0);
+static NULL: Value = Value::Null;
+
+//parse failures give -1 and leave a message for toy_json_parse_error, which every parse resets
+thread_local! {
+    static LAST_JSON_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn get_json() -> &'static Mutex<HashMap<i64, JsonRef>> {
+    GLOBAL_JSON.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn add_json(json: JsonRef) -> i64 {
+    let handle = NEXT_JSON_HANDLE.fetch_add(1, Ordering::Relaxed);
+    get_json().lock().unwrap().insert(handle, json);
+    handle
+}
+fn new_json(value: Value) -> i64 {
+    add_json(JsonRef {
+        doc: Arc::new(Mutex::new(value)),
+        path: vec![],
+    })
+}
+fn json_ref(handle: i64) -> JsonRef {
+    match get_json().lock().unwrap().get(&handle) {
+        Some(json) => json.clone(),
+        None => panic!("[ERROR] JSON value {handle} does not exist or was released"),
+    }
+}
+///A handle to the element or field step leads to from handle
+fn child(handle: i64, step: Step) -> i64 {
+    let mut json = json_ref(handle);
+    json.path.push(step);
+    add_json(json)
+}
+
+///Runs f on the value behind handle. The registry is only locked for the lookup, a path that no
+///longer leads anywhere, such as a missing key, gives null
+fn with_value<T>(handle: i64, f: impl FnOnce(&Value) -> T) -> T {
+    let json = json_ref(handle);
+    let doc = json.doc.lock().unwrap();
+    let mut value = &*doc;
+    for step in &json.path {
+        value = match (step, value) {
+            (Step::Key(key), Value::Object(fields)) => fields.get(key),
+            (Step::Idx(idx), Value::Array(items)) => items.get(*idx),
+            _ => None,
+        }
+        .unwrap_or(&NULL);
+    }
+    f(value)
+}
+fn with_value_mut<T>(handle: i64, f: impl FnOnce(&mut Value) -> T) -> T {
+    let json = json_ref(handle);
+    let mut doc = json.doc.lock().unwrap();
+    let mut value = &mut *doc;
+    for step in &json.path {
+        value = match (step, value) {
+            (Step::Key(key), Value::Object(fields)) => fields.get_mut(key),
+            (Step::Idx(idx), Value::Array(items)) => items.get_mut(*idx),
+            _ => None,
+        }
+        .unwrap_or_else(|| {
+            panic!("[ERROR] JSON value {handle} is not part of its document anymore")
+        });
+    }
+    f(value)
+}
+
+fn read_str<'a>(toy_str: ToyPtr) -> &'a [u8] {
+    unsafe { CStr::from_ptr(toy_str as *const i8) }.to_bytes()
+}
+fn new_toy_str(text: &str) -> ToyPtr {
+    let c_string = CString::new(text).unwrap();
+    builtins::toy_malloc(c_string.as_ptr() as i64)
+}
+fn to_toy_str(value: &Value) -> ToyPtr {
+    new_toy_str(&value.to_string())
+}
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+fn float_value(f: f64) -> Value {
+    //NaN and infinity have no JSON spelling
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+///Returns the handle of the parsed value, -1 when the text is not valid JSON
+#[unsafe(no_mangle)]
+pub fn toy_json_parse(toy_str: ToyPtr) -> i64 {
+    let (handle, error) = match serde_json::from_slice(read_str(toy_str)) {
+        Ok(value) => (new_json(value), String::new()),
+        Err(e) => (-1, e.to_string()),
+    };
+    LAST_JSON_ERROR.with(|last| *last.borrow_mut() = error);
+    handle
+}
+
+///Why the last parse on this thread failed, "" when it succeeded
+#[unsafe(no_mangle)]
+pub fn toy_json_parse_error() -> ToyPtr {
+    LAST_JSON_ERROR.with(|last| new_toy_str(&last.borrow()))
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_is_valid(toy_str: ToyPtr) -> i64 {
+    serde_json::from_slice::<Value>(read_str(toy_str)).is_ok() as i64
+}
+
+///Forgets the handle, the document goes away with the last handle into it
+#[unsafe(no_mangle)]
+pub fn toy_json_release(handle: i64) {
+    get_json().lock().unwrap().remove(&handle);
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_stringify(handle: i64) -> ToyPtr {
+    with_value(handle, to_toy_str)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_pretty(handle: i64) -> ToyPtr {
+    with_value(handle, |value| {
+        new_toy_str(&serde_json::to_string_pretty(value).unwrap())
+    })
+}
+
+///one of null, bool, number, string, array or object
+#[unsafe(no_mangle)]
+pub fn toy_json_kind(handle: i64) -> ToyPtr {
+    with_value(handle, |value| new_toy_str(kind(value)))
+}
+
+///Missing keys and non objects give null
+#[unsafe(no_mangle)]
+pub fn toy_json_get(handle: i64, toy_key: ToyPtr) -> i64 {
+    let key = String::from_utf8_lossy(read_str(toy_key)).into_owned();
+    child(handle, Step::Key(key))
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_has_key(handle: i64, toy_key: ToyPtr) -> i64 {
+    let key = String::from_utf8_lossy(read_str(toy_key));
+    with_value(handle, |value| value.get(key.as_ref()).is_some() as i64)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_at(handle: i64, idx: i64) -> i64 {
+    with_value(handle, |value| {
+        let Value::Array(items) = value else {
+            panic!("[ERROR] Expected a JSON array but found {}", kind(value));
+        };
+        if idx < 0 || idx as usize >= items.len() {
+            panic!(
+                "[ERROR] Index {idx} is out of bounds for a JSON array of length {}",
+                items.len()
+            );
+        }
+    });
+    child(handle, Step::Idx(idx as usize))
+}
+
+///Elements of an array, entries of an object, 0 for everything else
+#[unsafe(no_mangle)]
+pub fn toy_json_size(handle: i64) -> i64 {
+    with_value(handle, |value| match value {
+        Value::Array(items) => items.len() as i64,
+        Value::Object(fields) => fields.len() as i64,
+        _ => 0,
+    })
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_keys(handle: i64) -> ToyPtr {
+    with_value(handle, |value| {
+        let Value::Object(fields) = value else {
+            panic!("[ERROR] Expected a JSON object but found {}", kind(value));
+        };
+        let keys: Vec<ToyPtr> = fields.keys().map(|key| new_toy_str(key)).collect();
+        builtins::_toy_owned_str_arr(&keys)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_as_str(handle: i64) -> ToyPtr {
+    with_value(handle, |value| match value {
+        Value::String(s) => new_toy_str(s),
+        other => panic!("[ERROR] Expected a JSON string but found {}", kind(other)),
+    })
+}
+
+///Whole floats such as 3.0 are accepted, anything else that is not a number is an error
+#[unsafe(no_mangle)]
+pub fn toy_json_as_int(handle: i64) -> i64 {
+    with_value(handle, |value| match value.as_i64() {
+        Some(i) => i,
+        None => match value.as_f64() {
+            Some(f) if f.fract() == 0.0 => f as i64,
+            _ => panic!("[ERROR] Expected a JSON integer but found {value}"),
+        },
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_json_as_float(handle: i64) -> f64 {
+    with_value(handle, |value| match value.as_f64() {
+        Some(f) => f,
+        None => panic!("[ERROR] Expected a JSON number but found {}", kind(value)),
+    })
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_json_as_bool(handle: i64) -> i64 {
+    with_value(handle, |value| match value {
+        Value::Bool(b) => *b as i64,
+        other => panic!("[ERROR] Expected a JSON bool but found {}", kind(other)),
+    })
+}
+
+///A new JSON string holding s
+#[unsafe(no_mangle)]
+pub fn toy_json_from_str(toy_str: ToyPtr) -> i64 {
+    new_json(Value::String(
+        String::from_utf8_lossy(read_str(toy_str)).into_owned(),
+    ))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_json_from_float(value: f64) -> i64 {
+    new_json(float_value(value))
+}
+
+///Adds a copy of item to the end of the array and returns the array's handle
+#[unsafe(no_mangle)]
+pub fn toy_json_append(arr: i64, item: i64) -> i64 {
+    let item = with_value(item, Value::clone);
+    with_value_mut(arr, |value| match value {
+        Value::Array(items) => items.push(item),
+        other => panic!("[ERROR] Expected a JSON array but found {}", kind(other)),
+    });
+    arr
+}
+
+///Sets key of the object to a copy of item and returns the object's handle
+#[unsafe(no_mangle)]
+pub fn toy_json_set(obj: i64, toy_key: ToyPtr, item: i64) -> i64 {
+    let item = with_value(item, Value::clone);
+    let key = String::from_utf8_lossy(read_str(toy_key)).into_owned();
+    with_value_mut(obj, |value| match value {
+        Value::Object(fields) => {
+            fields.insert(key, item);
+        }
+        other => panic!("[ERROR] Expected a JSON object but found {}", kind(other)),
+    });
+    obj
+}
+
+///How a value passed to toy_json_encode is laid out, the compiler writes this as a string, see
+///AstToIrConverter::json_layout.
+///`s`, `i`, `f` and `b` are scalars, `M` is a map, `[T]` is an array of T and
+///`{name:T,...}` is a struct whose fields fill consecutive 8 byte slots in the listed order
+enum Layout {
+    Str,
+    Int,
+    Float,
+    Bool,
+    Map,
+    Arr(Box<Layout>),
+    Struct(Vec<(String, Layout)>),
+}
+impl Layout {
+    fn parse(text: &[u8], pos: &mut usize) -> Layout {
+        let c = text[*pos];
+        *pos += 1;
+        match c {
+            b's' => Layout::Str,
+            b'i' => Layout::Int,
+            b'f' => Layout::Float,
+            b'b' => Layout::Bool,
+            b'M' => Layout::Map,
+            b'[' => {
+                let elem = Layout::parse(text, pos);
+                *pos += 1; //]
+                Layout::Arr(Box::new(elem))
+            }
+            b'{' => {
+                let mut fields = Vec::new();
+                while text[*pos] != b'}' {
+                    let name_end = *pos + text[*pos..].iter().position(|&b| b == b':').unwrap();
+                    let name = String::from_utf8_lossy(&text[*pos..name_end]).into_owned();
+                    *pos = name_end + 1;
+                    fields.push((name, Layout::parse(text, pos)));
+                    if text[*pos] == b',' {
+                        *pos += 1;
+                    }
+                }
+                *pos += 1; //}
+                Layout::Struct(fields)
+            }
+            _ => unreachable!(), //compiler generated
+        }
+    }
+    fn from_toy_type(ty: &ToyType) -> Layout {
+        match ty {
+            ToyType::Str => Layout::Str,
+            ToyType::Float => Layout::Float,
+            ToyType::Bool => Layout::Bool,
+            _ => Layout::Int,
+        }
+    }
+    fn encode(&self, value: i64) -> Value {
+        match self {
+            Layout::Str if value == 0 => Value::Null,
+            Layout::Str => Value::String(String::from_utf8_lossy(read_str(value)).into_owned()),
+            Layout::Int => Value::from(value),
+            Layout::Float => float_value(f64::from_bits(value as u64)),
+            //bools can be stored as a single byte in an 8 byte slot
+            Layout::Bool => Value::Bool(value & 1 == 1),
+            Layout::Map => {
+                let (value_ty, entries) = crate::map::_toy_map_entries(value);
+                let value_layout = Layout::from_toy_type(&value_ty);
+                let fields: Map<String, Value> = entries
+                    .into_iter()
+                    .map(|(key, v)| (key, value_layout.encode(v)))
+                    .collect();
+                Value::Object(fields)
+            }
+            Layout::Arr(elem) => {
+                let len = builtins::toy_arrlen(value);
+                Value::Array(
+                    (0..len)
+                        .map(|i| elem.encode(builtins::toy_read_from_arr(value, i, 0)))
+                        .collect(),
+                )
+            }
+            Layout::Struct(fields) => {
+                let slots = value as *const i64;
+                let fields: Map<String, Value> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, layout))| {
+                        (name.clone(), layout.encode(unsafe { *slots.add(i) }))
+                    })
+                    .collect();
+                Value::Object(fields)
+            }
+        }
+    }
+}
+
+///Serializes any toy value to JSON text, layout describes the value, see Layout
+#[unsafe(no_mangle)]
+pub fn toy_json_encode(value: i64, toy_layout: ToyPtr) -> ToyPtr {
+    let layout = Layout::parse(read_str(toy_layout), &mut 0);
+    to_toy_str(&layout.encode(value))
+}
@@ -1,5 +1,8 @@
 mod fs;
+mod json;
 mod math;
+#[cfg(not(target_os = "wasi"))]
 mod net;
+mod str;
 mod sys;
 mod time;
@@ -1,120 +1,391 @@
 use crate::ToyPtr;
 use crate::builtins;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
 use std::os::raw::c_char;
-use std::sync::{Mutex, OnceLock, RwLock};
-use std::time::Duration;
-use tiny_http::{Request, Server};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Server};
 use ureq;
+///The body of a GET request, gives "" when no response was received and leaves the reason for
+///toy_net_socket_error
 #[unsafe(no_mangle)]
-
 pub extern "C" fn toy_net_get_url(url: ToyPtr) -> ToyPtr {
-    let url_str = unsafe { CStr::from_ptr(url as *const c_char) }
-        .to_str()
-        .unwrap();
+    let result = fetch("GET", &read_str(url), &[], "", -1).map(|response| response.body);
+    let body = socket_result(result.map_err(io::Error::other), String::new());
+    return new_toy_str(&body);
+}
 
-    let body: String = ureq::get(url_str)
-        .call()
-        .unwrap()
+///The result of toy_net_fetch, status is 0 and error is set when no response was received
+struct HttpResponse {
+    status: i64,
+    error: String,
+    body: String,
+    headers: Vec<String>,
+}
+
+static GLOBAL_RESPONSES: OnceLock<Mutex<HashMap<i64, HttpResponse>>> = OnceLock::new();
+static NEXT_RESPONSE_HANDLE: AtomicI64 = AtomicI64::new(0);
+
+fn get_responses() -> &'static Mutex<HashMap<i64, HttpResponse>> {
+    GLOBAL_RESPONSES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn with_response<T>(handle: i64, f: impl FnOnce(&HttpResponse) -> T) -> T {
+    let responses = get_responses().lock().unwrap();
+    match responses.get(&handle) {
+        Some(response) => f(response),
+        None => panic!("[ERROR] Http response {handle} does not exist or was already released"),
+    }
+}
+
+fn fetch(
+    method: &str,
+    url: &str,
+    headers: &[String],
+    body: &str,
+    timeout_ms: i64,
+) -> Result<HttpResponse, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        //4xx and 5xx are still responses, only failing to get one is an error
+        .http_status_as_error(false)
+        .timeout_global((timeout_ms >= 0).then(|| Duration::from_millis(timeout_ms as u64)))
+        .build()
+        .into();
+
+    let mut builder = ureq::http::Request::builder().method(method).uri(url);
+    for pair in headers.chunks(2) {
+        builder = builder.header(pair[0].as_str(), pair.get(1).map_or("", |v| v.as_str()));
+    }
+    let request = builder
+        .body(body.as_bytes().to_vec())
+        .map_err(|e| e.to_string())?;
+
+    let mut response = agent.run(request).map_err(|e| e.to_string())?;
+    let headers = response
+        .headers()
+        .iter()
+        .flat_map(|(name, value)| {
+            [
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            ]
+        })
+        .collect();
+    let body = response
         .body_mut()
         .read_to_string()
-        .unwrap();
+        .map_err(|e| e.to_string())?;
+    Ok(HttpResponse {
+        status: response.status().as_u16() as i64,
+        error: String::new(),
+        body,
+        headers,
+    })
+}
 
-    let c_string = CString::new(body).unwrap();
+///Sends a request and returns a response handle, failures are kept in the response rather than
+///panicking. headers is [name0, value0, ...], a negative timeout waits forever
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_fetch(
+    method: ToyPtr,
+    url: ToyPtr,
+    headers: ToyPtr,
+    body: ToyPtr,
+    timeout_ms: i64,
+) -> i64 {
+    let headers: Vec<String> = (0..builtins::toy_arrlen(headers))
+        .map(|i| read_str(builtins::toy_read_from_arr(headers, i, 0)))
+        .collect();
+    let response = fetch(
+        &read_str(method).to_uppercase(),
+        &read_str(url),
+        &headers,
+        &read_str(body),
+        timeout_ms,
+    )
+    .unwrap_or_else(|error| HttpResponse {
+        status: 0,
+        error,
+        body: String::new(),
+        headers: vec![],
+    });
+    let handle = NEXT_RESPONSE_HANDLE.fetch_add(1, Ordering::Relaxed);
+    get_responses().lock().unwrap().insert(handle, response);
+    handle
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_response_status(handle: i64) -> i64 {
+    with_response(handle, |response| response.status)
+}
 
-    return builtins::toy_malloc(c_string.as_ptr() as i64);
+///[error, body, header name0, header value0, ...]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_response_fields(handle: i64) -> ToyPtr {
+    with_response(handle, |response| {
+        let mut fields = vec![response.error.clone(), response.body.clone()];
+        fields.extend(response.headers.iter().cloned());
+        new_toy_str_arr(&fields)
+    })
 }
 
-static GLOBAL_SERVER_ARR: OnceLock<RwLock<Vec<Server>>> = OnceLock::new();
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_response_release(handle: i64) {
+    drop(get_responses().lock().unwrap().remove(&handle));
+}
+
+///Case insensitive lookup in [name0, value0, ...], missing headers give ""
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_find_header(headers: ToyPtr, name: ToyPtr) -> ToyPtr {
+    let name = read_str(name);
+    let len = builtins::toy_arrlen(headers);
+    let value = (0..len - 1)
+        .step_by(2)
+        .find(|&i| read_str(builtins::toy_read_from_arr(headers, i, 0)).eq_ignore_ascii_case(&name))
+        .map(|i| read_str(builtins::toy_read_from_arr(headers, i + 1, 0)))
+        .unwrap_or_default();
+    new_toy_str(&value)
+}
+
+fn read_str(toy_str: ToyPtr) -> String {
+    unsafe { CStr::from_ptr(toy_str as *const c_char) }
+        .to_string_lossy()
+        .into_owned()
+}
+fn new_toy_str(text: &str) -> ToyPtr {
+    //request data is untrusted, interior nulls would cut the string short anyway
+    let c_string = CString::new(text.replace('\0', "")).unwrap();
+    builtins::toy_malloc(c_string.as_ptr() as i64)
+}
+fn new_toy_str_arr(items: &[String]) -> ToyPtr {
+    let strs: Vec<ToyPtr> = items.iter().map(|item| new_toy_str(item)).collect();
+    builtins::_toy_owned_str_arr(&strs)
+}
 
-fn get_servers() -> &'static RwLock<Vec<Server>> {
+///A server and the accept timeout it was configured with
+struct HttpServer {
+    server: Server,
+    timeout_ms: i64,
+}
+
+//servers are shared so an accept can wait on one without holding the lock
+static GLOBAL_SERVER_ARR: OnceLock<RwLock<Vec<Arc<HttpServer>>>> = OnceLock::new();
+
+fn get_servers() -> &'static RwLock<Vec<Arc<HttpServer>>> {
     GLOBAL_SERVER_ARR.get_or_init(|| RwLock::new(vec![]))
 }
 
+///Returns the server handle, timeout is how long accepts wait in ms, negative waits forever
 #[unsafe(no_mangle)]
-pub extern "C" fn toy_net_configure_http_server(port: ToyPtr) -> ToyPtr {
-    let server = Server::http(&format!("0.0.0.0:{}", port)).unwrap();
+pub extern "C" fn toy_net_configure_http_server(port: i64, timeout: i64) -> i64 {
+    let server = match Server::http(format!("0.0.0.0:{}", port)) {
+        Ok(s) => s,
+        Err(e) => panic!("[ERROR] Could not start an http server on port {port}: {e}"),
+    };
     let mut servers = get_servers().write().unwrap();
-    servers.push(server);
+    servers.push(Arc::new(HttpServer {
+        server,
+        timeout_ms: timeout,
+    }));
     return (servers.len() - 1) as i64;
 }
 
-static GLOBAL_REQUEST: OnceLock<Mutex<Option<Request>>> = OnceLock::new();
+///An accepted request that has not been responded to yet. The body is read up front since
+///tiny_http only lets it be read once
+struct PendingRequest {
+    request: Request,
+    body: String,
+    response_headers: Vec<Header>,
+    ///the pattern of the route the request was dispatched to, "" before it is dispatched
+    route: String,
+}
+
+static GLOBAL_REQUESTS: OnceLock<Mutex<HashMap<i64, PendingRequest>>> = OnceLock::new();
+static NEXT_REQUEST_HANDLE: AtomicI64 = AtomicI64::new(0);
+///The request the handle-less api (read_request, write_response, close_client) works on
+static CURRENT_REQUEST: AtomicI64 = AtomicI64::new(-1);
 
-fn get_request() -> &'static Mutex<Option<Request>> {
-    GLOBAL_REQUEST.get_or_init(|| Mutex::new(None))
+fn get_requests() -> &'static Mutex<HashMap<i64, PendingRequest>> {
+    GLOBAL_REQUESTS.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn toy_net_connection_requested(server_handle: ToyPtr) -> ToyPtr {
-    let servers = get_servers().read().unwrap();
-    let server = &servers[server_handle as usize];
+fn with_request<T>(handle: i64, f: impl FnOnce(&mut PendingRequest) -> T) -> T {
+    let mut requests = get_requests().lock().unwrap();
+    match requests.get_mut(&handle) {
+        Some(pending) => f(pending),
+        None => panic!("[ERROR] Http request {handle} does not exist or was already answered"),
+    }
+}
 
-    return match server.recv_timeout(Duration::ZERO) {
-        Ok(Some(request)) => {
-            *get_request().lock().unwrap() = Some(request);
-            1
-        }
-        _ => 0,
+///Waits up to timeout_ms for a request, returns its handle or -1 on timeout. Negative timeouts
+///wait forever
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_accept(server_handle: i64, timeout_ms: i64) -> i64 {
+    let server = match get_servers().read().unwrap().get(server_handle as usize) {
+        Some(s) => Arc::clone(s),
+        None => panic!("[ERROR] Http server {server_handle} does not exist"),
+    };
+    let received = if timeout_ms < 0 {
+        server.server.recv().map(Some)
+    } else {
+        server
+            .server
+            .recv_timeout(Duration::from_millis(timeout_ms as u64))
+    };
+    let mut request = match received {
+        Ok(Some(request)) => request,
+        _ => return -1,
     };
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).unwrap_or(0);
+
+    let handle = NEXT_REQUEST_HANDLE.fetch_add(1, Ordering::Relaxed);
+    get_requests().lock().unwrap().insert(
+        handle,
+        PendingRequest {
+            request,
+            body,
+            response_headers: vec![],
+            route: String::new(),
+        },
+    );
+    handle
 }
-#[unsafe(no_mangle)]
-pub extern "C" fn toy_net_read_request() -> *mut ToyPtr {
-    let mut slot = get_request().lock().unwrap();
 
-    match slot.take() {
-        None => {
-            panic!("[ERROR] GLOBAL_REQUEST Undefined");
-        }
-        Some(mut request) => {
-            let method = request.method().to_string();
-            let path = request.url().to_string();
-            let ip = request
-                .remote_addr()
-                .map(|addr| addr.ip().to_string())
-                .unwrap_or_default();
-
-            let mut body = String::new();
-            request.as_reader().read_to_string(&mut body).unwrap_or(0);
-
-            *slot = Some(request);
-            let arr_ptr = builtins::toy_malloc_arr(4, 4, 1);
-
-            let ip_s = CString::new(ip).unwrap();
-            let method_s = CString::new(method).unwrap();
-            let path_s = CString::new(path).unwrap();
-            let body_s = CString::new(body).unwrap();
-            let ip_ptr = builtins::toy_malloc(ip_s.as_ptr() as i64);
-            let method_ptr = builtins::toy_malloc(method_s.as_ptr() as i64);
-            let path_ptr = builtins::toy_malloc(path_s.as_ptr() as i64);
-            let body_ptr = builtins::toy_malloc(body_s.as_ptr() as i64);
-
-            builtins::toy_write_to_arr(arr_ptr, ip_ptr, 0, 0);
-            builtins::toy_write_to_arr(arr_ptr, method_ptr, 1, 0);
-            builtins::toy_write_to_arr(arr_ptr, path_ptr, 2, 0);
-            builtins::toy_write_to_arr(arr_ptr, body_ptr, 3, 0);
-
-            return arr_ptr as *mut i64;
+fn header_is(header: &Header, name: &str) -> bool {
+    header.field.as_str().as_str().eq_ignore_ascii_case(name)
+}
+
+///Splits a url into its path and query string
+fn split_url(url: &str) -> (&str, &str) {
+    url.split_once('?').unwrap_or((url, ""))
+}
+
+///Decodes %XX escapes and + as a space, invalid escapes are kept as is
+fn url_decode(text: &str) -> String {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push(hi * 16 + lo);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
         }
+        i += 1;
     }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+///Decoded key value pairs of a query string, in order, keys without a value get ""
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (url_decode(key), url_decode(value))
+        })
+        .collect()
 }
 
+///[client ip, method, path, query string, body], the path has the query string removed
 #[unsafe(no_mangle)]
-pub extern "C" fn toy_net_close_client() {
-    let mut slot = get_request().lock().unwrap();
-    drop(slot.take());
+pub extern "C" fn toy_net_request_fields(handle: i64) -> ToyPtr {
+    with_request(handle, |pending| {
+        let request = &pending.request;
+        let (path, query) = split_url(request.url());
+        let ip = request
+            .remote_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        new_toy_str_arr(&[
+            ip,
+            request.method().to_string(),
+            url_decode(path),
+            query.to_string(),
+            pending.body.clone(),
+        ])
+    })
 }
 
+///Header names are case insensitive, missing headers give ""
 #[unsafe(no_mangle)]
-pub extern "C" fn toy_net_write_bytes(
+pub extern "C" fn toy_net_request_header(handle: i64, name: ToyPtr) -> ToyPtr {
+    let name = read_str(name);
+    with_request(handle, |pending| {
+        let value = pending
+            .request
+            .headers()
+            .iter()
+            .find(|h| header_is(h, &name))
+            .map(|h| h.value.to_string())
+            .unwrap_or_default();
+        new_toy_str(&value)
+    })
+}
+
+///Every header as [name0, value0, name1, value1, ...]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_request_headers(handle: i64) -> ToyPtr {
+    with_request(handle, |pending| {
+        let flat: Vec<String> = pending
+            .request
+            .headers()
+            .iter()
+            .flat_map(|h| [h.field.to_string(), h.value.to_string()])
+            .collect();
+        new_toy_str_arr(&flat)
+    })
+}
+
+///Every query parameter as [key0, value0, key1, value1, ...]
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_parse_query(query: ToyPtr) -> ToyPtr {
+    let flat: Vec<String> = parse_query(&read_str(query))
+        .into_iter()
+        .flat_map(|(key, value)| [key, value])
+        .collect();
+    new_toy_str_arr(&flat)
+}
+
+///Adds a header to the response that will be sent for this request
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_set_response_header(handle: i64, name: ToyPtr, value: ToyPtr) {
+    let (name, value) = (read_str(name), read_str(value));
+    let header = match Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+        Ok(h) => h,
+        Err(_) => panic!("[ERROR] Invalid http header {name}: {value}"),
+    };
+    with_request(handle, |pending| {
+        pending.response_headers.retain(|h| !header_is(h, &name));
+        pending.response_headers.push(header);
+    });
+}
+
+///Sends the response and releases the request, answered requests can not be used again
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_respond(
+    handle: i64,
     status_code: i64,
     content_type: ToyPtr,
     data: ToyPtr,
     size: i64,
 ) {
-    let mut slot = get_request().lock().unwrap();
-    let request = match slot.take() {
-        Some(r) => r,
+    let pending = match get_requests().lock().unwrap().remove(&handle) {
+        Some(p) => p,
         None => return,
     };
 
@@ -133,19 +404,182 @@ pub extern "C" fn toy_net_write_bytes(
         vec![]
     };
 
+    //headers set by the program replace the defaults
+    let mut headers: Vec<Header> = vec![
+        Header::from_bytes("Content-Type", ct.as_bytes()).unwrap(),
+        Header::from_bytes("Access-Control-Allow-Origin", b"*").unwrap(),
+        Header::from_bytes("Connection", b"close").unwrap(),
+    ];
+    headers.retain(|d| !pending.response_headers.iter().any(|h| h.field == d.field));
+    headers.extend(pending.response_headers);
+
+    let len = body_bytes.len();
     let response = tiny_http::Response::new(
         tiny_http::StatusCode(status_code as u16),
-        vec![
-            tiny_http::Header::from_bytes("Content-Type", ct.as_bytes()).unwrap(),
-            tiny_http::Header::from_bytes("Access-Control-Allow-Origin", b"*").unwrap(),
-            tiny_http::Header::from_bytes("Connection", b"close").unwrap(),
-        ],
+        headers,
         std::io::Cursor::new(body_bytes),
-        Some(size as usize),
+        Some(len),
         None,
     );
 
-    request.respond(response).unwrap_or(());
+    pending.request.respond(response).unwrap_or(());
+}
+
+///Sends a nul terminated body, its size is counted in bytes
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_respond_str(
+    handle: i64,
+    status_code: i64,
+    content_type: ToyPtr,
+    body: ToyPtr,
+) {
+    let size = if body != 0 {
+        unsafe { libc::strlen(body as *const c_char) as i64 }
+    } else {
+        0
+    };
+    toy_net_respond(handle, status_code, content_type, body, size);
+}
+
+///Drops the request without answering it, which closes the connection
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_close_request(handle: i64) {
+    drop(get_requests().lock().unwrap().remove(&handle));
+}
+
+///Path segments starting with : match any one segment and are captured, a trailing * matches
+///the rest of the path. Returns whether pattern matches path and the captures as
+///[name0, value0, ...]
+fn match_route(pattern: &str, path: &str) -> Option<Vec<String>> {
+    let pattern_parts: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+    let path_parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let mut params = vec![];
+    for (i, part) in pattern_parts.iter().enumerate() {
+        if *part == "*" && i == pattern_parts.len() - 1 {
+            return Some(params);
+        }
+        let segment = path_parts.get(i)?;
+        if let Some(name) = part.strip_prefix(':') {
+            if segment.is_empty() {
+                return None;
+            }
+            params.push(name.to_string());
+            params.push(segment.to_string());
+        } else if part != segment {
+            return None;
+        }
+    }
+    (pattern_parts.len() == path_parts.len()).then_some(params)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_route_matches(pattern: ToyPtr, path: ToyPtr) -> i64 {
+    match_route(&read_str(pattern), &read_str(path)).is_some() as i64
+}
+
+///The captures of a matching route as [name0, value0, ...], empty when it does not match
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_route_params(pattern: ToyPtr, path: ToyPtr) -> ToyPtr {
+    let params = match_route(&read_str(pattern), &read_str(path)).unwrap_or_default();
+    new_toy_str_arr(&params)
+}
+
+///Remembers the route pattern a request matched so its captures can be read by the handler
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_set_route(handle: i64, pattern: ToyPtr) {
+    with_request(handle, |pending| pending.route = read_str(pattern));
+}
+
+///The value of a ":name" segment of the route the request was dispatched to, "" when it has none
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_route_param(handle: i64, name: ToyPtr) -> ToyPtr {
+    let name = read_str(name);
+    let value = with_request(handle, |pending| {
+        let (path, _) = split_url(pending.request.url());
+        let params = match_route(&pending.route, &url_decode(path)).unwrap_or_default();
+        params
+            .chunks(2)
+            .find(|pair| pair[0] == name)
+            .map(|pair| pair[1].clone())
+            .unwrap_or_default()
+    });
+    new_toy_str(&value)
+}
+
+///Calls the handler of a route with the handle of the request it matched
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_call_handler(handler: extern "C" fn(i64), handle: i64) {
+    handler(handle);
+}
+
+//the handle-less api below works on the last request accepted by connection_requested, using the
+//last configured server
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_connection_requested() -> bool {
+    let (server_handle, timeout_ms) = {
+        let servers = get_servers().read().unwrap();
+        match servers.last() {
+            Some(s) => (servers.len() as i64 - 1, s.timeout_ms),
+            None => {
+                panic!("[ERROR] configure_http_server must be called before connection_requested")
+            }
+        }
+    };
+    let handle = toy_net_accept(server_handle, timeout_ms);
+    if handle == -1 {
+        return false;
+    }
+    //a request that was read but never answered is dropped, like the single slot used to do
+    toy_net_close_request(CURRENT_REQUEST.swap(handle, Ordering::Relaxed));
+    true
+}
+
+fn current_request() -> i64 {
+    let handle = CURRENT_REQUEST.load(Ordering::Relaxed);
+    if handle == -1 {
+        panic!("[ERROR] No http request, call connection_requested first");
+    }
+    handle
+}
+
+///[client ip, method, url, body], the url still has its query string
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_read_request() -> ToyPtr {
+    with_request(current_request(), |pending| {
+        let request = &pending.request;
+        let ip = request
+            .remote_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        new_toy_str_arr(&[
+            ip,
+            request.method().to_string(),
+            request.url().to_string(),
+            pending.body.clone(),
+        ])
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_close_client() {
+    toy_net_close_request(CURRENT_REQUEST.swap(-1, Ordering::Relaxed));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_write_bytes(
+    status_code: i64,
+    content_type: ToyPtr,
+    data: ToyPtr,
+    size: i64,
+) {
+    toy_net_respond(
+        CURRENT_REQUEST.swap(-1, Ordering::Relaxed),
+        status_code,
+        content_type,
+        data,
+        size,
+    );
 }
 
 #[unsafe(no_mangle)]
@@ -162,3 +596,431 @@ pub extern "C" fn toy_net_write_response(status_code: i64, content_type: ToyPtr,
 
     toy_net_write_bytes(status_code, ct, b, size);
 }
+
+//raw sockets. failures never panic, they return -1 or "" and leave a message for
+//toy_net_socket_error, which every socket call resets
+enum Socket {
+    Listener(TcpListener),
+    Stream(BufReader<TcpStream>),
+    ///The socket and the sender of the last datagram it received
+    Udp(UdpSocket, Option<SocketAddr>),
+}
+
+static GLOBAL_SOCKETS: OnceLock<Mutex<HashMap<i64, Arc<Mutex<Socket>>>>> = OnceLock::new();
+static NEXT_SOCKET_HANDLE: AtomicI64 = AtomicI64::new(0);
+
+thread_local! {
+    static LAST_SOCKET_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn get_sockets() -> &'static Mutex<HashMap<i64, Arc<Mutex<Socket>>>> {
+    GLOBAL_SOCKETS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn add_socket(socket: Socket) -> i64 {
+    let handle = NEXT_SOCKET_HANDLE.fetch_add(1, Ordering::Relaxed);
+    get_sockets()
+        .lock()
+        .unwrap()
+        .insert(handle, Arc::new(Mutex::new(socket)));
+    handle
+}
+
+///Records the outcome of a socket call, giving fallback on failure
+fn socket_result<T>(result: io::Result<T>, fallback: T) -> T {
+    let (value, error) = match result {
+        Ok(v) => (v, String::new()),
+        //read timeouts surface as WouldBlock on unix
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => (fallback, "timed out".to_string()),
+        Err(e) => (fallback, e.to_string()),
+    };
+    LAST_SOCKET_ERROR.with(|last| *last.borrow_mut() = error);
+    value
+}
+
+///Runs f on the socket behind handle. The registry is only locked for the lookup so a blocking
+///call on one socket does not hold up the others
+fn with_socket<T>(handle: i64, f: impl FnOnce(&mut Socket) -> io::Result<T>) -> io::Result<T> {
+    let socket = get_sockets().lock().unwrap().get(&handle).cloned();
+    match socket {
+        Some(socket) => f(&mut socket.lock().unwrap()),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("socket {handle} does not exist or was closed"),
+        )),
+    }
+}
+
+fn wrong_socket(expected: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("expected a {expected} socket"),
+    )
+}
+
+///None waits forever, Rust rejects a zero timeout so 0 becomes 1ms
+fn socket_timeout(timeout_ms: i64) -> Option<Duration> {
+    (timeout_ms >= 0).then(|| Duration::from_millis(timeout_ms.max(1) as u64))
+}
+
+///Reads never take more than this at once, however big max is. It is above the biggest udp datagram
+const MAX_READ: usize = 64 * 1024;
+
+fn read_len(max: i64) -> usize {
+    (max.max(0) as usize).min(MAX_READ)
+}
+
+fn not_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the data is not valid UTF-8, read it as bytes instead",
+    )
+}
+
+///The length of the UTF-8 character a byte starts, 0 when it can not start one
+fn utf8_width(byte: u8) -> usize {
+    match byte {
+        0x00..=0x7F => 1,
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 0,
+    }
+}
+
+///Takes up to max bytes of text off a connection. Only whole characters are taken, bytes that are
+///not UTF-8 are left on the connection so they can still be read as bytes
+fn read_text(reader: &mut BufReader<TcpStream>, max: usize) -> io::Result<String> {
+    let buf = reader.fill_buf()?;
+    let n = buf.len().min(max);
+    let (valid, is_cut_off) = match std::str::from_utf8(&buf[..n]) {
+        Ok(_) => (n, false),
+        Err(e) => (e.valid_up_to(), e.error_len().is_none()),
+    };
+    if valid > 0 || n == 0 {
+        let text = String::from_utf8(buf[..valid].to_vec()).unwrap();
+        reader.consume(valid);
+        return Ok(text);
+    }
+    //a character split over two packets, peek until the rest of it arrived so a timeout leaves the
+    //start of it in place
+    let width = utf8_width(buf[0]);
+    if !is_cut_off || width > max {
+        return Err(not_utf8());
+    }
+    let mut rest = vec![0; width - n];
+    loop {
+        match reader.get_ref().peek(&mut rest)? {
+            0 => return Err(not_utf8()),
+            got if got == rest.len() => break,
+            _ => std::thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    let mut char_bytes = vec![0; width];
+    reader.read_exact(&mut char_bytes)?;
+    String::from_utf8(char_bytes).map_err(|_| not_utf8())
+}
+
+fn read_bytes(reader: &mut BufReader<TcpStream>, max: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; max];
+    let n = reader.read(&mut buf)?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+///The bytes of an int[], every element has to be between 0 and 255
+fn arr_bytes(data: ToyPtr) -> io::Result<Vec<u8>> {
+    builtins::_toy_arr_values(data)
+        .into_iter()
+        .map(|b| {
+            u8::try_from(b).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{b} is not a byte, bytes are between 0 and 255"),
+                )
+            })
+        })
+        .collect()
+}
+
+fn resolve(host: &str, port: i64) -> io::Result<SocketAddr> {
+    (host, port as u16)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{host} did not resolve to an address"),
+            )
+        })
+}
+
+///The message of the last failed socket call on this thread, "" when it succeeded
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_socket_error() -> ToyPtr {
+    LAST_SOCKET_ERROR.with(|last| new_toy_str(&last.borrow()))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_listen(port: i64) -> i64 {
+    let listener = TcpListener::bind(("0.0.0.0", port as u16));
+    socket_result(listener.map(|l| add_socket(Socket::Listener(l))), -1)
+}
+
+///Returns the connection handle, -1 on failure or when nothing connected within timeout_ms.
+///Negative timeouts wait forever
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_accept(listener: i64, timeout_ms: i64) -> i64 {
+    let stream = with_socket(listener, |socket| {
+        let Socket::Listener(listener) = socket else {
+            return Err(wrong_socket("tcp listener"));
+        };
+        let Some(timeout) = socket_timeout(timeout_ms) else {
+            listener.set_nonblocking(false)?;
+            return listener.accept().map(|(stream, _)| stream);
+        };
+        //std has no accept timeout, so poll
+        listener.set_nonblocking(true)?;
+        let start = Instant::now();
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(stream);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if start.elapsed() >= timeout {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    });
+    socket_result(
+        stream.map(|s| add_socket(Socket::Stream(BufReader::new(s)))),
+        -1,
+    )
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_connect(host: ToyPtr, port: i64, timeout_ms: i64) -> i64 {
+    let stream = resolve(&read_str(host), port).and_then(|addr| match socket_timeout(timeout_ms) {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    });
+    socket_result(
+        stream.map(|s| add_socket(Socket::Stream(BufReader::new(s)))),
+        -1,
+    )
+}
+
+///Reads up to max bytes of text, "" when the peer closed the connection, the read failed or the
+///data is not UTF-8
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_read(conn: i64, max: i64) -> ToyPtr {
+    let data = with_socket(conn, |socket| {
+        let Socket::Stream(reader) = socket else {
+            return Err(wrong_socket("tcp connection"));
+        };
+        read_text(reader, read_len(max))
+    });
+    new_toy_str(&socket_result(data, String::new()))
+}
+
+///Reads up to max bytes as an int[] of byte values, empty when the peer closed the connection or
+///the read failed
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_read_bytes(conn: i64, max: i64) -> ToyPtr {
+    let data = with_socket(conn, |socket| {
+        let Socket::Stream(reader) = socket else {
+            return Err(wrong_socket("tcp connection"));
+        };
+        read_bytes(reader, read_len(max))
+    });
+    let bytes: Vec<i64> = socket_result(data, vec![])
+        .into_iter()
+        .map(i64::from)
+        .collect();
+    builtins::_toy_int_arr(&bytes)
+}
+
+///Reads up to and including the next newline, "" when the peer closed the connection or the
+///read failed. An empty line comes back as "\n"
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_read_line(conn: i64) -> ToyPtr {
+    let line = with_socket(conn, |socket| {
+        let Socket::Stream(reader) = socket else {
+            return Err(wrong_socket("tcp connection"));
+        };
+        let mut line = vec![];
+        reader.read_until(b'\n', &mut line)?;
+        String::from_utf8(line).map_err(|_| not_utf8())
+    });
+    new_toy_str(&socket_result(line, String::new()))
+}
+
+///Returns the number of bytes written, -1 on failure
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_write(conn: i64, data: ToyPtr) -> i64 {
+    let data = read_str(data);
+    let written = with_socket(conn, |socket| {
+        let Socket::Stream(reader) = socket else {
+            return Err(wrong_socket("tcp connection"));
+        };
+        reader.get_mut().write_all(data.as_bytes())?;
+        Ok(data.len() as i64)
+    });
+    socket_result(written, -1)
+}
+
+///Writes an int[] of byte values, returns the number of bytes written, -1 on failure
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_write_bytes(conn: i64, data: ToyPtr) -> i64 {
+    let written = arr_bytes(data).and_then(|data| {
+        with_socket(conn, |socket| {
+            let Socket::Stream(reader) = socket else {
+                return Err(wrong_socket("tcp connection"));
+            };
+            reader.get_mut().write_all(&data)?;
+            Ok(data.len() as i64)
+        })
+    });
+    socket_result(written, -1)
+}
+
+///"ip:port" of the other end of a connection
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_tcp_peer(conn: i64) -> ToyPtr {
+    let peer = with_socket(conn, |socket| match socket {
+        Socket::Stream(reader) => reader.get_ref().peer_addr().map(|a| a.to_string()),
+        _ => Err(wrong_socket("tcp connection")),
+    });
+    new_toy_str(&socket_result(peer, String::new()))
+}
+
+///How long reads on a tcp connection or udp socket wait, negative waits forever. Returns 1 on
+///success
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_set_read_timeout(handle: i64, timeout_ms: i64) -> i64 {
+    let timeout = socket_timeout(timeout_ms);
+    let set = with_socket(handle, |socket| match socket {
+        Socket::Stream(reader) => reader.get_ref().set_read_timeout(timeout),
+        Socket::Udp(udp, _) => udp.set_read_timeout(timeout),
+        Socket::Listener(_) => Err(wrong_socket("tcp connection or udp")),
+    });
+    socket_result(set.map(|_| 1), 0)
+}
+
+///The port a listener or udp socket is bound to, useful after binding port 0
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_local_port(handle: i64) -> i64 {
+    let addr = with_socket(handle, |socket| match socket {
+        Socket::Listener(listener) => listener.local_addr(),
+        Socket::Stream(reader) => reader.get_ref().local_addr(),
+        Socket::Udp(udp, _) => udp.local_addr(),
+    });
+    socket_result(addr.map(|a| a.port() as i64), -1)
+}
+
+///Closes any socket, closing twice is harmless
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_socket_close(handle: i64) {
+    drop(get_sockets().lock().unwrap().remove(&handle));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_bind(port: i64) -> i64 {
+    let udp = UdpSocket::bind(("0.0.0.0", port as u16));
+    socket_result(udp.map(|u| add_socket(Socket::Udp(u, None))), -1)
+}
+
+///Returns the number of bytes sent, -1 on failure
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_send(udp: i64, host: ToyPtr, port: i64, data: ToyPtr) -> i64 {
+    let (host, data) = (read_str(host), read_str(data));
+    let sent = with_socket(udp, |socket| {
+        let Socket::Udp(udp, _) = socket else {
+            return Err(wrong_socket("udp"));
+        };
+        udp.send_to(data.as_bytes(), resolve(&host, port)?)
+    });
+    socket_result(sent.map(|n| n as i64), -1)
+}
+
+///Sends an int[] of byte values, returns the number of bytes sent, -1 on failure
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_send_bytes(udp: i64, host: ToyPtr, port: i64, data: ToyPtr) -> i64 {
+    let host = read_str(host);
+    let sent = arr_bytes(data).and_then(|data| {
+        with_socket(udp, |socket| {
+            let Socket::Udp(udp, _) = socket else {
+                return Err(wrong_socket("udp"));
+            };
+            udp.send_to(&data, resolve(&host, port)?)
+        })
+    });
+    socket_result(sent.map(|n| n as i64), -1)
+}
+
+///Waits up to timeout_ms for a datagram and keeps its sender for toy_net_udp_sender_ip and
+///toy_net_udp_sender_port. Anything past max bytes is dropped
+fn recv_datagram(udp: i64, max: i64, timeout_ms: i64) -> io::Result<Vec<u8>> {
+    with_socket(udp, |socket| {
+        let Socket::Udp(udp, sender) = socket else {
+            return Err(wrong_socket("udp"));
+        };
+        *sender = None;
+        udp.set_read_timeout(socket_timeout(timeout_ms))?;
+        let mut buf = vec![0; read_len(max)];
+        let (n, from) = udp.recv_from(&mut buf)?;
+        *sender = Some(from);
+        buf.truncate(n);
+        Ok(buf)
+    })
+}
+
+///A datagram of at most max bytes of text, "" when nothing arrived or it is not UTF-8
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_recv(udp: i64, max: i64, timeout_ms: i64) -> ToyPtr {
+    let data = recv_datagram(udp, max, timeout_ms)
+        .and_then(|data| String::from_utf8(data).map_err(|_| not_utf8()));
+    new_toy_str(&socket_result(data, String::new()))
+}
+
+///A datagram of at most max bytes as an int[] of byte values, empty when nothing arrived
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_recv_bytes(udp: i64, max: i64, timeout_ms: i64) -> ToyPtr {
+    let data = recv_datagram(udp, max, timeout_ms);
+    let bytes: Vec<i64> = socket_result(data, vec![])
+        .into_iter()
+        .map(i64::from)
+        .collect();
+    builtins::_toy_int_arr(&bytes)
+}
+
+fn udp_sender(udp: i64) -> Option<SocketAddr> {
+    with_socket(udp, |socket| match socket {
+        Socket::Udp(_, sender) => Ok(*sender),
+        _ => Err(wrong_socket("udp")),
+    })
+    .ok()
+    .flatten()
+}
+
+///"" when the last recv got nothing
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_sender_ip(udp: i64) -> ToyPtr {
+    new_toy_str(
+        &udp_sender(udp)
+            .map(|a| a.ip().to_string())
+            .unwrap_or_default(),
+    )
+}
+
+///0 when the last recv got nothing
+#[unsafe(no_mangle)]
+pub extern "C" fn toy_net_udp_sender_port(udp: i64) -> i64 {
+    udp_sender(udp).map_or(0, |a| a.port() as i64)
+}
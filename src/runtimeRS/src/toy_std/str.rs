@@ -0,0 +1,188 @@
+use crate::ToyPtr;
+use crate::builtins;
+use std::borrow::Cow;
+use std::ffi::{CStr, CString};
+
+//indexes and lengths count code points, not bytes, so they agree with len()
+fn read_str<'a>(toy_str: ToyPtr) -> &'a [u8] {
+    unsafe { CStr::from_ptr(toy_str as *const i8) }.to_bytes()
+}
+///invalid UTF-8 from files or the network becomes U+FFFD instead of failing
+fn read_text<'a>(toy_str: ToyPtr) -> Cow<'a, str> {
+    String::from_utf8_lossy(read_str(toy_str))
+}
+///copies the bytes into a new toy string, the caller owns it
+fn new_toy_str(bytes: &[u8]) -> ToyPtr {
+    let c_string = CString::new(bytes).unwrap();
+    builtins::toy_malloc(c_string.as_ptr() as i64)
+}
+fn new_toy_str_arr(parts: &[&[u8]]) -> ToyPtr {
+    let strs: Vec<ToyPtr> = parts.iter().map(|part| new_toy_str(part)).collect();
+    builtins::_toy_owned_str_arr(&strs)
+}
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_split(toy_str: ToyPtr, toy_sep: ToyPtr) -> ToyPtr {
+    let s = read_str(toy_str);
+    let sep = read_str(toy_sep);
+    //an empty separator splits into single characters
+    if sep.is_empty() {
+        let text = read_text(toy_str);
+        let chars: Vec<String> = text.chars().map(String::from).collect();
+        let parts: Vec<&[u8]> = chars.iter().map(|c| c.as_bytes()).collect();
+        return new_toy_str_arr(&parts);
+    }
+    let mut parts: Vec<&[u8]> = Vec::new();
+    let mut rest = s;
+    while let Some(idx) = find_bytes(rest, sep) {
+        parts.push(&rest[..idx]);
+        rest = &rest[idx + sep.len()..];
+    }
+    parts.push(rest);
+    new_toy_str_arr(&parts)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_join(toy_arr: ToyPtr, toy_sep: ToyPtr) -> ToyPtr {
+    let sep = read_str(toy_sep);
+    let len = builtins::toy_arrlen(toy_arr);
+    let mut out: Vec<u8> = Vec::new();
+    for i in 0..len {
+        if i > 0 {
+            out.extend_from_slice(sep);
+        }
+        out.extend_from_slice(read_str(builtins::toy_read_from_arr(toy_arr, i, 0)));
+    }
+    new_toy_str(&out)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_trim(toy_str: ToyPtr) -> ToyPtr {
+    new_toy_str(read_text(toy_str).trim().as_bytes())
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_find(toy_str: ToyPtr, toy_needle: ToyPtr) -> i64 {
+    let s = read_str(toy_str);
+    match find_bytes(s, read_str(toy_needle)) {
+        Some(idx) => String::from_utf8_lossy(&s[..idx]).chars().count() as i64,
+        None => -1,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_replace(toy_str: ToyPtr, toy_from: ToyPtr, toy_to: ToyPtr) -> ToyPtr {
+    let s = read_str(toy_str);
+    let from = read_str(toy_from);
+    let to = read_str(toy_to);
+    if from.is_empty() {
+        return new_toy_str(s);
+    }
+    let mut out: Vec<u8> = Vec::new();
+    let mut rest = s;
+    while let Some(idx) = find_bytes(rest, from) {
+        out.extend_from_slice(&rest[..idx]);
+        out.extend_from_slice(to);
+        rest = &rest[idx + from.len()..];
+    }
+    out.extend_from_slice(rest);
+    new_toy_str(&out)
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_starts_with(toy_str: ToyPtr, toy_prefix: ToyPtr) -> i64 {
+    read_str(toy_str).starts_with(read_str(toy_prefix)) as i64
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_ends_with(toy_str: ToyPtr, toy_suffix: ToyPtr) -> i64 {
+    read_str(toy_str).ends_with(read_str(toy_suffix)) as i64
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_to_upper(toy_str: ToyPtr) -> ToyPtr {
+    new_toy_str(read_text(toy_str).to_uppercase().as_bytes())
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_to_lower(toy_str: ToyPtr) -> ToyPtr {
+    new_toy_str(read_text(toy_str).to_lowercase().as_bytes())
+}
+
+///s[start..end], end is exclusive
+#[unsafe(no_mangle)]
+pub fn toy_str_substring(toy_str: ToyPtr, start: i64, end: i64) -> ToyPtr {
+    let text = read_text(toy_str);
+    let len = text.chars().count() as i64;
+    if start < 0 || end < start || end > len {
+        panic!("[ERROR] Substring {start}..{end} is out of bounds for a string of length {len}");
+    }
+    let sub: String = text
+        .chars()
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .collect();
+    new_toy_str(sub.as_bytes())
+}
+
+#[unsafe(no_mangle)]
+pub fn toy_str_char_at(toy_str: ToyPtr, idx: i64) -> ToyPtr {
+    let text = read_text(toy_str);
+    let len = text.chars().count() as i64;
+    if idx < 0 || idx >= len {
+        panic!("[ERROR] Index {idx} is out of bounds for a string of length {len}");
+    }
+    let c = text.chars().nth(idx as usize).unwrap();
+    new_toy_str(c.to_string().as_bytes())
+}
+
+///Fills the template from args, `{}` takes the next arg, `{n}` takes arg n and `{{`/`}}` are literal braces
+#[unsafe(no_mangle)]
+pub fn toy_str_format(toy_template: ToyPtr, toy_args: ToyPtr) -> ToyPtr {
+    let template = read_str(toy_template);
+    let arg_count = builtins::toy_arrlen(toy_args);
+    let mut out: Vec<u8> = Vec::new();
+    let mut next_arg = 0;
+    let mut i = 0;
+    while i < template.len() {
+        let c = template[i];
+        if (c == b'{' || c == b'}') && template.get(i + 1) == Some(&c) {
+            out.push(c);
+            i += 2;
+            continue;
+        }
+        if c != b'{' {
+            out.push(c);
+            i += 1;
+            continue;
+        }
+        let Some(close) = template[i..].iter().position(|&b| b == b'}') else {
+            panic!("[ERROR] Unclosed {{ in format string");
+        };
+        let spec = &template[i + 1..i + close];
+        let arg_idx = if spec.is_empty() {
+            next_arg += 1;
+            next_arg - 1
+        } else {
+            match std::str::from_utf8(spec).ok().and_then(|s| s.parse::<i64>().ok()) {
+                Some(n) => n,
+                None => panic!(
+                    "[ERROR] Invalid format placeholder {{{}}}",
+                    String::from_utf8_lossy(spec)
+                ),
+            }
+        };
+        if arg_idx < 0 || arg_idx >= arg_count {
+            panic!("[ERROR] Format string uses argument {arg_idx} but only {arg_count} were given");
+        }
+        out.extend_from_slice(read_str(builtins::toy_read_from_arr(toy_args, arg_idx, 0)));
+        i += close + 1;
+    }
+    new_toy_str(&out)
+}
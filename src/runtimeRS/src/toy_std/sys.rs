@@ -8,9 +8,14 @@ use std::process::Command;
 fn toy_sys_exit(code: i64) {
     process::exit(code as i32);
 }
+///-1 on WASI, which has no processes
 #[unsafe(no_mangle)]
 fn toy_sys_get_pid() -> i64 {
-    return process::id() as i64;
+    if cfg!(target_os = "wasi") {
+        -1
+    } else {
+        process::id() as i64
+    }
 }
 
 #[unsafe(no_mangle)]
@@ -22,15 +27,17 @@ fn toy_sys_get_argv() -> ToyPtr {
     let arr = builtins::toy_malloc_arr(toy_sys_get_argc(), 4, 1);
     for i in 0..toy_sys_get_argc() {
         let val = unsafe { *GLOBAL_ARGV.offset(i as isize) };
-        builtins::toy_write_to_arr(arr, val as i64, i, 4);
+        builtins::toy_write_to_arr(arr, val as i64, i, 4, 0);
     }
     return arr;
 }
 
 #[unsafe(no_mangle)]
-fn toy_sys_get_os_name() -> i64 {
+fn toy_sys_get_os_name() -> ToyPtr {
     let s = if cfg!(windows) {
         "windows\0"
+    } else if cfg!(target_os = "wasi") {
+        "wasi\0"
     } else {
         "linux\0"
     };
@@ -54,7 +61,7 @@ fn toy_sys_invoke(code: ToyPtr, args: ToyPtr) -> i64 {
     let arg_count = builtins::toy_arrlen(args);
     for i in 0..arg_count {
         let s = unsafe {
-            CStr::from_ptr(builtins::toy_read_from_arr(args, i) as *const i8)
+            CStr::from_ptr(builtins::toy_read_from_arr(args, i, 0) as *const i8)
                 .to_str()
                 .unwrap()
         };
@@ -1,4 +1,4 @@
-use chrono::{Datelike, Local};
+use chrono::{DateTime, Datelike};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 #[unsafe(no_mangle)]
@@ -8,18 +8,27 @@ fn toy_time_ms_since_unix_epoch() -> i64 {
     let in_ms = since_the_epoch.as_millis();
     return in_ms as i64;
 }
+#[cfg(not(target_os = "wasi"))]
+fn now() -> DateTime<chrono::Local> {
+    chrono::Local::now()
+}
+//WASI has no time zones, dates there are UTC
+#[cfg(target_os = "wasi")]
+fn now() -> DateTime<chrono::Utc> {
+    chrono::Utc::now()
+}
 #[unsafe(no_mangle)]
 fn toy_time_current_year() -> i64 {
-    return Local::now().year() as i64;
+    return now().year() as i64;
 }
 #[unsafe(no_mangle)]
 fn toy_time_current_month() -> i64 {
-    let now = Local::now();
+    let now = now();
     return now.month() as i64;
 }
 #[unsafe(no_mangle)]
 fn toy_time_current_day() -> i64 {
-    return Local::now().day() as i64;
+    return now().day() as i64;
 }
 #[unsafe(no_mangle)]
 fn toy_time_sleep(ms: i64) {
@@ -10,6 +10,7 @@ pub enum ToyType {
     IntArr = 6,
     FloatArr = 7,
     Struct = 8,
+    Map = 9,
 }
 impl TryFrom<i64> for ToyType {
     type Error = i64;
@@ -24,6 +25,7 @@ impl TryFrom<i64> for ToyType {
             6 => Ok(ToyType::IntArr),
             7 => Ok(ToyType::FloatArr),
             8 => Ok(ToyType::Struct),
+            9 => Ok(ToyType::Map),
             _ => unreachable!()
         }
     }
@@ -0,0 +1,149 @@
+//! The functions runtimeRS exports, as listed by build.rs in runtime_exports.json, and the checks
+//! that keep `extern fn` declarations of them in line with their real signatures. A wrong declaration
+//! would otherwise only show up as a link error or as undefined behaviour at run time.
+use crate::{
+    errors::{Span, ToyError, ToyErrorType},
+    token::{ExternType, TypeTok},
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+static RUNTIME_EXPORTS_JSON: &str = include_str!(concat!(env!("OUT_DIR"), "/runtime_exports.json"));
+
+///A #[unsafe(no_mangle)] runtime function, params and ret are C types such as int64_t, double or
+///char*, or ToyPtr and ToyValue for the runtime's own aliases
+#[derive(Deserialize, Debug, Clone)]
+pub struct RuntimeExport {
+    pub name: String,
+    pub params: Vec<String>,
+    pub ret: String,
+}
+#[derive(Deserialize)]
+struct RuntimeExports {
+    functions: Vec<RuntimeExport>,
+}
+
+pub fn runtime_exports() -> &'static HashMap<String, RuntimeExport> {
+    static EXPORTS: OnceLock<HashMap<String, RuntimeExport>> = OnceLock::new();
+    EXPORTS.get_or_init(|| {
+        let exports: RuntimeExports = serde_json::from_str(RUNTIME_EXPORTS_JSON)
+            .expect("runtime_exports.json is generated by build.rs");
+        exports
+            .functions
+            .into_iter()
+            .map(|f| (f.name.clone(), f))
+            .collect()
+    })
+}
+
+///The C type an extern type is passed as, in the spelling runtime_exports.json uses
+fn c_type_of(ty: &ExternType) -> String {
+    let (base, ptr_depth) = match ty {
+        ExternType::c_int64_t(n) => ("int64_t", *n),
+        ExternType::c_char(n) => ("char", *n),
+        ExternType::c_void(n) => ("void", *n),
+        ExternType::c_double(n) => ("double", *n),
+        ExternType::c_int8_t(n) => ("int8_t", *n),
+        ExternType::c_int16_t(n) => ("int16_t", *n),
+        ExternType::c_int32_t(n) => ("int32_t", *n),
+        ExternType::c_uint8_t(n) => ("uint8_t", *n),
+        ExternType::c_uint16_t(n) => ("uint16_t", *n),
+        ExternType::c_uint32_t(n) => ("uint32_t", *n),
+        ExternType::c_uint64_t(n) => ("uint64_t", *n),
+        ExternType::c_float(n) => ("float", *n),
+        ExternType::c_bool(n) => ("bool", *n),
+        ExternType::c_struct(_, n) => ("struct", *n),
+        ExternType::c_fn(_, _) => return "fn".to_string(),
+    };
+    format!("{}{}", base, "*".repeat(ptr_depth as usize))
+}
+
+fn is_pointer(ty: &ExternType) -> bool {
+    c_type_of(ty).ends_with('*') || matches!(ty, ExternType::c_fn(_, _))
+}
+
+///Whether a value declared as `declared` can be passed where the runtime has `c_type`. Apart from
+///ToyPtr and void*, which any pointer may stand in for, and ToyValue, a value that may also be
+///an int, the types have to be the same
+fn accepts(c_type: &str, declared: &ExternType) -> bool {
+    match c_type {
+        "ToyPtr" | "void*" => is_pointer(declared),
+        "ToyValue" => is_pointer(declared) || *declared == ExternType::c_int64_t(0),
+        _ => c_type_of(declared) == c_type,
+    }
+}
+
+///Whether a Toy return type can hold what the runtime returns as `c_type`. Declaring no return
+///value is always fine, the value is ignored
+fn returns(c_type: &str, declared: &TypeTok) -> bool {
+    match declared {
+        TypeTok::Void => true,
+        TypeTok::Int => c_type == "int64_t" || c_type == "ToyValue",
+        TypeTok::Float => c_type == "double",
+        TypeTok::Bool => c_type == "bool",
+        _ => matches!(c_type, "ToyPtr" | "char*" | "void*"),
+    }
+}
+
+fn mismatch(message: String, span: Span) -> ToyError {
+    ToyError::new(ToyErrorType::ExternSignatureMismatch(message), span)
+}
+
+///Checks an extern declaration against the runtime export of the same name. c_ret is the C type
+///the return value was declared with, if it was given one instead of a Toy type
+pub fn validate_extern(
+    name: &str,
+    params: &[ExternType],
+    ret: &TypeTok,
+    c_ret: Option<&ExternType>,
+    span: Span,
+) -> Result<(), ToyError> {
+    let Some(export) = runtime_exports().get(name) else {
+        return Err(mismatch(
+            format!("{name} is not exported by the runtime"),
+            span,
+        ));
+    };
+    if export.params.len() != params.len() {
+        return Err(mismatch(
+            format!(
+                "{name} takes {} arguments in the runtime but is declared with {}",
+                export.params.len(),
+                params.len()
+            ),
+            span,
+        ));
+    }
+    for (i, (c_type, declared)) in export.params.iter().zip(params).enumerate() {
+        if !accepts(c_type, declared) {
+            return Err(mismatch(
+                format!(
+                    "argument {} of {name} is {c_type} in the runtime but is declared as {}",
+                    i + 1,
+                    declared.to_str()
+                ),
+                span,
+            ));
+        }
+    }
+    let ret_matches = match c_ret {
+        Some(ExternType::c_void(0)) => true,
+        Some(declared) => accepts(&export.ret, declared),
+        None => returns(&export.ret, ret),
+    };
+    if !ret_matches {
+        let declared = c_ret.map_or_else(|| format!("{:?}", ret), |c| c.to_str());
+        return Err(mismatch(
+            format!(
+                "{name} returns {} in the runtime but is declared to return {declared}",
+                export.ret
+            ),
+            span,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
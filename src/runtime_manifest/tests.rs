@@ -0,0 +1,121 @@
+use super::*;
+use crate::lexer::Lexer;
+use crate::parser::boxer::Boxer;
+use crate::parser::toy_box::TBox;
+use std::collections::BTreeMap;
+use std::fs;
+
+#[test]
+fn test_runtime_manifest_lists_exports() {
+    let sqrtf = &runtime_exports()["toy_math_sqrtf"];
+    assert_eq!(sqrtf.params, vec!["double".to_string()]);
+    assert_eq!(sqrtf.ret, "double");
+    let accept = &runtime_exports()["toy_net_accept"];
+    assert_eq!(accept.params.len(), 2);
+    assert_eq!(accept.ret, "int64_t");
+    //exported statics are not functions
+    assert!(!runtime_exports().contains_key("GLOBAL_ARGC"));
+    //only the library runtime compiles these in, programs link the default one
+    assert!(!runtime_exports().contains_key("toy_release"));
+    assert!(!runtime_exports().contains_key("toy_repl_store"));
+}
+
+#[test]
+fn test_runtime_manifest_keeps_exact_c_types() {
+    let read = &runtime_exports()["toy_read_from_arr"];
+    assert_eq!(read.params, vec!["ToyPtr", "int64_t", "ToyPtr"]);
+    let jit_run = &runtime_exports()["toy_jit_run"];
+    assert_eq!(jit_run.params, vec!["void*", "int64_t*"]);
+    assert_eq!(runtime_exports()["toy_arr_insert"].params[2], "ToyValue");
+    assert_eq!(
+        runtime_exports()["toy_net_connection_requested"].ret,
+        "bool"
+    );
+}
+
+#[test]
+fn test_runtime_manifest_spells_every_extern_type() {
+    let point = TypeTok::Struct(BTreeMap::from([
+        ("x".to_string(), Box::new(TypeTok::Float)),
+        ("y".to_string(), Box::new(TypeTok::Float)),
+    ]));
+    assert_eq!(c_type_of(&ExternType::c_int64_t(0)), "int64_t");
+    assert_eq!(c_type_of(&ExternType::c_uint8_t(0)), "uint8_t");
+    assert_eq!(c_type_of(&ExternType::c_char(2)), "char**");
+    assert_eq!(c_type_of(&ExternType::c_void(1)), "void*");
+    assert_eq!(c_type_of(&ExternType::c_struct(point.clone(), 0)), "struct");
+    assert!(is_pointer(&ExternType::c_struct(point, 1)));
+    let callback = ExternType::c_fn(vec![], Box::new(ExternType::c_void(0)));
+    assert!(is_pointer(&callback));
+    assert!(!is_pointer(&ExternType::c_bool(0)));
+}
+
+#[test]
+fn test_runtime_manifest_rejects_bad_externs() {
+    let check = |name: &str, params: &[ExternType], ret: TypeTok| {
+        validate_extern(name, params, &ret, None, Span::null_span())
+    };
+    let int = ExternType::c_int64_t(0);
+    let float = ExternType::c_double(0);
+    let str = ExternType::c_char(1);
+    let ptr = ExternType::c_void(1);
+    assert!(check("toy_net_accept", &[int.clone(), int.clone()], TypeTok::Int).is_ok());
+    //the result of a function can be ignored
+    assert!(check("toy_net_accept", &[int.clone(), int.clone()], TypeTok::Void).is_ok());
+    assert!(check("toy_fs_does_not_exist", &[str.clone()], TypeTok::Int).is_err());
+    assert!(check("toy_net_accept", &[int.clone()], TypeTok::Int).is_err());
+    assert!(check("toy_math_sqrtf", &[int.clone()], TypeTok::Float).is_err());
+    assert!(check("toy_math_sqrtf", &[float.clone()], TypeTok::Int).is_err());
+    assert!(check("toy_net_socket_close", &[int.clone()], TypeTok::Int).is_err());
+    //ints of another width and pointers in place of ints do not pass
+    let int32 = ExternType::c_int32_t(0);
+    assert!(check("toy_net_accept", &[int.clone(), int32], TypeTok::Int).is_err());
+    assert!(check("toy_net_accept", &[ptr.clone(), int.clone()], TypeTok::Int).is_err());
+    assert!(check("toy_net_connection_requested", &[], TypeTok::Int).is_err());
+    assert!(check("toy_net_connection_requested", &[], TypeTok::Bool).is_ok());
+    //a runtime pointer takes any pointer, an element of any type an int as well
+    assert!(check("toy_strlen", &[str.clone()], TypeTok::Int).is_ok());
+    assert!(check("toy_strlen", &[int.clone()], TypeTok::Int).is_err());
+    assert!(
+        check(
+            "toy_arr_index_of",
+            &[ptr.clone(), int.clone()],
+            TypeTok::Int
+        )
+        .is_ok()
+    );
+    assert!(check("toy_arr_index_of", &[ptr.clone(), float], TypeTok::Int).is_err());
+    //a pointer the runtime returns cannot come back as an int
+    assert!(check("toy_str_to_upper", &[str.clone()], TypeTok::Str).is_ok());
+    assert!(check("toy_str_to_upper", &[str.clone()], TypeTok::Int).is_err());
+    let c_ret = |ret: ExternType| {
+        validate_extern(
+            "toy_str_to_upper",
+            &[str.clone()],
+            &TypeTok::Any,
+            Some(&ret),
+            Span::null_span(),
+        )
+    };
+    assert!(c_ret(ExternType::c_char(1)).is_ok());
+    assert!(c_ret(ExternType::c_int64_t(0)).is_err());
+}
+
+#[test]
+fn test_runtime_manifest_matches_std() {
+    for entry in fs::read_dir("std").unwrap() {
+        let path = entry.unwrap().path();
+        let contents = fs::read_to_string(&path).unwrap();
+        let toks = Lexer::new().lex(contents).unwrap();
+        let boxes = Boxer::new().box_toks(toks).unwrap();
+        for tbox in boxes {
+            let params = tbox.get_extern_param_types();
+            if let TBox::ExternFuncDec(name, _, ret, c_ret, span) = tbox {
+                let name = name.get_var_name().unwrap();
+                if let Err(e) = validate_extern(&name, &params, &ret, c_ret.as_ref(), span) {
+                    panic!("{} {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
@@ -50,6 +50,8 @@ pub enum Token {
     Extern,
     ExternType(QualifiedExternType),
     Import,
+    ///`link "foo";` native library to link the program against
+    Link,
     Export,
     Interface,
     Implements,
@@ -98,13 +100,26 @@ impl SpannedToken {
     }
 }
 #[allow(nonstandard_style)]
-///types
+///types, the number is the pointer depth
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ExternType {
     c_int64_t(u64),
     c_char(u64),
     c_void(u64),
     c_double(u64),
+    c_int8_t(u64),
+    c_int16_t(u64),
+    c_int32_t(u64),
+    c_uint8_t(u64),
+    c_uint16_t(u64),
+    c_uint32_t(u64),
+    c_uint64_t(u64),
+    c_float(u64),
+    c_bool(u64),
+    ///a Toy struct, pointer depth 0 passes it by value (`byval Point`), 1 passes a pointer to it
+    c_struct(TypeTok, u64),
+    ///function pointer, the params and the return type (c_void for none) of the callback
+    c_fn(Vec<ExternType>, Box<ExternType>),
 }
 impl ExternType {
     pub fn from_type_name(word: &str) -> Option<Self> {
@@ -131,6 +146,15 @@ impl ExternType {
             .or_else(|| parse_ptr_depth(word, "c_char").map(Self::c_char))
             .or_else(|| parse_ptr_depth(word, "c_void").map(Self::c_void))
             .or_else(|| parse_ptr_depth(word, "c_double").map(Self::c_double))
+            .or_else(|| parse_ptr_depth(word, "c_int8_t").map(Self::c_int8_t))
+            .or_else(|| parse_ptr_depth(word, "c_int16_t").map(Self::c_int16_t))
+            .or_else(|| parse_ptr_depth(word, "c_int32_t").map(Self::c_int32_t))
+            .or_else(|| parse_ptr_depth(word, "c_uint8_t").map(Self::c_uint8_t))
+            .or_else(|| parse_ptr_depth(word, "c_uint16_t").map(Self::c_uint16_t))
+            .or_else(|| parse_ptr_depth(word, "c_uint32_t").map(Self::c_uint32_t))
+            .or_else(|| parse_ptr_depth(word, "c_uint64_t").map(Self::c_uint64_t))
+            .or_else(|| parse_ptr_depth(word, "c_float").map(Self::c_float))
+            .or_else(|| parse_ptr_depth(word, "c_bool").map(Self::c_bool))
     }
 
     pub fn to_str(&self) -> String {
@@ -139,6 +163,20 @@ impl ExternType {
             Self::c_char(n) => ("c_char", *n),
             Self::c_void(n) => ("c_void", *n),
             Self::c_double(n) => ("c_double", *n),
+            Self::c_int8_t(n) => ("c_int8_t", *n),
+            Self::c_int16_t(n) => ("c_int16_t", *n),
+            Self::c_int32_t(n) => ("c_int32_t", *n),
+            Self::c_uint8_t(n) => ("c_uint8_t", *n),
+            Self::c_uint16_t(n) => ("c_uint16_t", *n),
+            Self::c_uint32_t(n) => ("c_uint32_t", *n),
+            Self::c_uint64_t(n) => ("c_uint64_t", *n),
+            Self::c_float(n) => ("c_float", *n),
+            Self::c_bool(n) => ("c_bool", *n),
+            Self::c_struct(_, n) => ("c_struct", *n),
+            Self::c_fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_str()).collect();
+                return format!("fn({}): {}", params.join(", "), ret.to_str());
+            }
         };
 
         let mut out = base.to_string();
@@ -147,6 +185,18 @@ impl ExternType {
         }
         return out
     }
+
+    ///The extern type a Toy typed extern parameter is passed as
+    pub fn from_type_tok(ty: &TypeTok) -> Self {
+        match ty {
+            TypeTok::Int => ExternType::c_int64_t(0),
+            TypeTok::Float => ExternType::c_double(0),
+            TypeTok::Str => ExternType::c_char(1),
+            TypeTok::Bool => ExternType::c_bool(0),
+            TypeTok::Struct(_) => ExternType::c_struct(ty.clone(), 1),
+            _ => ExternType::c_void(1),
+        }
+    }
 }
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct QualifiedExternType {
@@ -188,6 +238,8 @@ pub enum TypeTok {
         BTreeMap<String, (Vec<TypeTok>, TypeTok)>,
         u64,
     ),
+    ///key type, value type, keys are str or int and values are scalars
+    Map(Box<TypeTok>, Box<TypeTok>),
 }
 
 impl Hash for TypeTok {
@@ -255,6 +307,11 @@ impl Hash for TypeTok {
                 y.hash(state);
                 n.hash(state);
             }
+            TypeTok::Map(k, v) => {
+                16.hash(state);
+                k.hash(state);
+                v.hash(state);
+            }
         }
     }
 }
@@ -276,6 +333,7 @@ impl TypeTok {
             Self::StructArr(_, _) => "StructArr".to_string(),
             Self::Interface(_, _) => "Interface".to_string(),
             Self::InterfaceArr(_, _, _) => "InterfaceArr".to_string(),
+            Self::Map(_, _) => "Map".to_string(),
         };
     }
 }
@@ -334,6 +392,7 @@ impl Token {
             Self::Extern => "Extern".to_string(),
             Self::ExternType(_) => "ExternType".to_string(),
             Self::Import => "Import".to_string(),
+            Self::Link => "Link".to_string(),
             Self::Export => "Export".to_string(),
             Self::Interface => "Interface".to_string(),
             Self::Implements => "Implements".to_string(),
@@ -421,6 +480,7 @@ impl Token {
                 Token::Extern => String::from("extern"),
                 Token::ExternType(et) => et.to_str(),
                 Token::Import => String::from("import"),
+                Token::Link => String::from("link"),
                 Token::Export => String::from("export"),
                 Token::Implements => String::from("implements"),
                 Token::Interface => String::from("interface"),